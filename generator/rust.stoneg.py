@@ -176,6 +176,7 @@ class RustBackend(RustHelperBackend):
             self.emit()
 
         self._impl_serde_for_struct(struct)
+        self._impl_validate_for_struct(struct)
 
         if self._is_error_type(struct):
             self._impl_error(struct)
@@ -211,6 +212,7 @@ class RustBackend(RustHelperBackend):
         self.emit()
 
         self._impl_serde_for_polymorphic_struct(struct)
+        self._impl_validate_for_enum(struct)
 
     def _emit_union(self, union: ir.Union) -> None:
         enum_name = self.enum_name(union)
@@ -241,6 +243,7 @@ class RustBackend(RustHelperBackend):
         self.emit()
 
         self._impl_serde_for_union(union)
+        self._impl_validate_for_enum(union)
 
         if self._is_error_type(union):
             self._impl_error(union)
@@ -796,6 +799,180 @@ class RustBackend(RustHelperBackend):
                                   '"cannot serialize \'Other\' variant"))')
         self.emit()
 
+    # Validation
+
+    def _impl_validate_for_struct(self, struct: ir.Struct) -> None:
+        checks = []
+        for field in struct.all_fields:
+            checks += self._validation_checks(
+                f'&self.{self.field_name(field)}', f'"{self.field_name(field)}"', field.data_type)
+        deprecated = any(field.deprecated for field in struct.all_fields)
+        self._emit_validate_impl(struct, checks, deprecated)
+
+    def _impl_validate_for_enum(self, typ: ir.DataType) -> None:
+        enum_name = self.enum_name(typ)
+        variants = self.get_enum_variants(typ)
+        cases = []
+        for variant in variants:
+            if isinstance(typ, ir.Struct):
+                # Subtypes of polymorphic structs are validated as a whole, like the struct itself.
+                inner = self._validation_checks('inner', '""', variant.data_type)
+            else:
+                inner = self._validation_checks(
+                    'inner', f'"{self.field_name(variant)}"', variant.data_type)
+            if inner:
+                cases.append((self.enum_variant_name(variant), inner))
+        checks = []
+        if len(cases) == 1 and (len(variants) > 1 or not self.is_closed_union(typ)):
+            variant_name, inner = cases[0]
+            checks.append(f'if let {enum_name}::{variant_name}(inner) = self {{')
+            checks += ['    ' + line for line in inner]
+            checks.append('}')
+        elif cases:
+            checks.append('match self {')
+            for variant_name, inner in cases:
+                checks.append(f'    {enum_name}::{variant_name}(inner) => {{')
+                checks += ['        ' + line for line in inner]
+                checks.append('    }')
+            if len(cases) < len(variants) or not self.is_closed_union(typ):
+                checks.append('    _ => (),')
+            checks.append('}')
+        deprecated = any(variant.deprecated for variant in variants)
+        self._emit_validate_impl(typ, checks, deprecated)
+
+    def _emit_validate_impl(self, typ: ir.DataType, checks: list[str], deprecated: bool) -> None:
+        with self.block(f'impl crate::validation::Validate for {self._rust_type(typ)}'):
+            if deprecated and checks:
+                self.emit('#[allow(deprecated)]')
+            with self.block('fn validate(&self) -> Result<(), crate::validation::ValidationError>'):
+                for line in checks:
+                    self.emit(line)
+                self.emit('Ok(())')
+        self.emit()
+
+    def _validation_checks(self, value: str, field: str, typ: ir.DataType, depth: int = 0) -> list[str]:
+        """
+        Rust statements which check the value of a reference expression `value` against the
+        constraints of its type, returning early with a ValidationError naming `field` (a `&str`
+        expression) if any are violated. Empty if the type has no constraints.
+        """
+        typ, _ = ir.unwrap_aliases(typ)
+        if isinstance(typ, ir.Nullable):
+            inner = self._validation_checks(f'inner{depth}', field, typ.data_type, depth + 1)
+            if not inner:
+                return []
+            return [f'if let Some(inner{depth}) = {value} {{'] \
+                + ['    ' + line for line in inner] \
+                + ['}']
+        elif isinstance(typ, ir.String):
+            checks = []
+            if typ.min_length is not None or typ.max_length is not None:
+                checks.append(
+                    f'crate::validation::check_string_length({field}, {value}, '
+                    f'{self._option_literal(typ.min_length)}, '
+                    f'{self._option_literal(typ.max_length)})?;')
+            if typ.pattern is not None:
+                checks.append(
+                    f'crate::validation::check_pattern({field}, {value}, r#"{typ.pattern}"#)?;')
+            return checks
+        elif ir.is_numeric_type(typ):
+            if typ.min_value is None and typ.max_value is None:
+                return []
+            # Bounds of float fields may be given as integers in the spec.
+            convert = float if ir.is_float_type(typ) else int
+            return [
+                f'crate::validation::check_range({field}, {self._deref(value)}, '
+                f'{self._option_literal(typ.min_value, convert)}, '
+                f'{self._option_literal(typ.max_value, convert)})?;']
+        elif isinstance(typ, ir.List):
+            checks = []
+            if typ.min_items is not None or typ.max_items is not None:
+                checks.append(
+                    f'crate::validation::check_list_length({field}, {value}, '
+                    f'{self._option_literal(typ.min_items)}, '
+                    f'{self._option_literal(typ.max_items)})?;')
+            inner = self._validation_checks(
+                f'item{depth}', self._indexed_field(field, f'i{depth}'), typ.data_type,
+                depth + 1)
+            if inner:
+                checks.append(
+                    f'for (i{depth}, item{depth}) in {value.removeprefix("&")}.iter().enumerate() {{')
+                checks += ['    ' + line for line in inner]
+                checks.append('}')
+            return checks
+        elif isinstance(typ, ir.Map):
+            inner = self._validation_checks(
+                f'value{depth}', self._indexed_field(field, f'key{depth}:?'),
+                typ.value_data_type, depth + 1)
+            if not inner:
+                return []
+            return [f'for (key{depth}, value{depth}) in {value} {{'] \
+                + ['    ' + line for line in inner] \
+                + ['}']
+        elif isinstance(typ, ir.UserDefined):
+            if not self._has_constraints(typ):
+                return []
+            if field == '""':
+                return [f'crate::validation::Validate::validate({value})?;']
+            return [f'crate::validation::Validate::validate({value}).map_err(|e| e.within({field}))?;']
+        else:
+            return []
+
+    def _has_constraints(self, typ: ir.DataType, visiting: Optional[set[int]] = None) -> bool:
+        """
+        Whether anything in the type, including the types of its fields, has any constraints, so
+        whether validating it can ever fail.
+        """
+        if visiting is None:
+            visiting = set()
+        typ, _ = ir.unwrap_aliases(typ)
+        if isinstance(typ, ir.Nullable):
+            return self._has_constraints(typ.data_type, visiting)
+        elif isinstance(typ, ir.String):
+            return typ.min_length is not None or typ.max_length is not None \
+                or typ.pattern is not None
+        elif ir.is_numeric_type(typ):
+            return typ.min_value is not None or typ.max_value is not None
+        elif isinstance(typ, ir.List):
+            return typ.min_items is not None or typ.max_items is not None \
+                or self._has_constraints(typ.data_type, visiting)
+        elif isinstance(typ, ir.Map):
+            return self._has_constraints(typ.value_data_type, visiting)
+        elif isinstance(typ, ir.UserDefined):
+            if id(typ) in visiting:
+                # Recursive types only have constraints if something else in them does.
+                return False
+            visiting.add(id(typ))
+            if isinstance(typ, ir.Struct) and not typ.has_enumerated_subtypes():
+                fields = typ.all_fields
+            else:
+                fields = self.get_enum_variants(typ)
+            return any(self._has_constraints(field.data_type, visiting) for field in fields)
+        else:
+            return False
+
+    @staticmethod
+    def _indexed_field(field: str, index: str) -> str:
+        """
+        A `&str` expression naming an element of a list or map field.
+        """
+        if field.startswith('"'):
+            return f'&format!("{field[1:-1]}[{{{index}}}]")'
+        return f'&format!("{{}}[{{{index}}}]", {field})'
+
+    @staticmethod
+    def _deref(value: str) -> str:
+        """
+        Dereference a reference expression, without leaving a `*&` in the output.
+        """
+        return value[1:] if value.startswith('&') else f'*{value}'
+
+    @staticmethod
+    def _option_literal(value: Optional[object], convert: type = int) -> str:
+        if value is None:
+            return 'None'
+        return f'Some({convert(value)!r})'
+
     # "extends" for structs means the subtype adds additional fields to the supertype, so we can
     # convert from the subtype to the supertype
     def _impl_from_for_struct(self, struct: ir.Struct, parent: ir.Struct) -> None:
@@ -167,7 +167,6 @@ where
             (None, None) => client.execute(req, Bytes::new()).await,
             (Some(params_body), _) => client.execute(req, params_body).await,
 
-            #[cfg(feature = "async_routes")]
             (None, Some(Body::Owned((body_bytes, ..)))) => client.execute(req, body_bytes).await,
 
             #[cfg(feature = "sync_routes")]
@@ -311,12 +310,11 @@ pub enum Body<'a> {
     #[cfg(feature = "sync_routes")]
     Borrowed(&'a [u8]),
 
-    #[cfg(feature = "async_routes")]
-    // PhantomData because otherwise if sync_routes is turned off, nothing uses the 'a lifetime
+    // PhantomData because otherwise if sync_routes is turned off, nothing uses the 'a lifetime.
+    // This isn't gated on async_routes because the helpers module uses it with sync clients too.
     Owned((Bytes, std::marker::PhantomData<&'a ()>)),
 }
 
-#[cfg(feature = "async_routes")]
 impl From<Bytes> for Body<'_> {
     fn from(value: Bytes) -> Self {
         Body::Owned((value, std::marker::PhantomData))
@@ -0,0 +1,330 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Uploading many small files at once using batched upload sessions.
+//!
+//! Uploading lots of files one at a time with [`upload`](crate::files::upload) commits each of
+//! them separately, and concurrent commits to the same namespace contend for a lock, which shows
+//! up as `too_many_write_operations` errors. Instead, [`upload_batch`] sends each file's content to
+//! its own upload session, and then commits up to [`MAX_BATCH_ENTRIES`] of them at a time with a
+//! single call to
+//! [`upload_session_finish_batch_v2`](crate::files::upload_session_finish_batch_v2).
+
+use super::{RETRY_BACKOFF, Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, FileMetadata, UploadSessionAppendArg, UploadSessionAppendError,
+    UploadSessionCursor, UploadSessionFinishArg, UploadSessionFinishBatchArg,
+    UploadSessionFinishBatchResultEntry, UploadSessionFinishError, UploadSessionStartBatchArg,
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use std::future::Future;
+
+/// The most files which can be committed in a single batch. This is a Dropbox limit.
+pub const MAX_BATCH_ENTRIES: usize = 1000;
+
+/// A file to upload as part of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchUploadEntry {
+    /// Where and how to commit the file.
+    pub commit: CommitInfo,
+
+    /// The file's content. Each file must be sent in a single request, so this can't be larger
+    /// than 150 MiB.
+    pub data: Bytes,
+}
+
+impl BatchUploadEntry {
+    /// Make a new entry with the given commit info and content.
+    pub fn new(commit: CommitInfo, data: impl Into<Bytes>) -> Self {
+        Self {
+            commit,
+            data: data.into(),
+        }
+    }
+}
+
+/// Options controlling how [`upload_batch`] uploads files.
+#[derive(Debug, Clone)]
+pub struct BatchUploadOptions {
+    parallelism: usize,
+    max_retries: u32,
+}
+
+impl Default for BatchUploadOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 8,
+            max_retries: 3,
+        }
+    }
+}
+
+impl BatchUploadOptions {
+    /// How many files' content to upload at the same time. Defaults to 8.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error, and how many more
+    /// times to try committing an entry which fails with
+    /// [`UploadSessionFinishError::TooManyWriteOperations`]. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+}
+
+/// The outcome of uploading one file in a batch.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // the success case is by far the most common
+pub enum BatchUploadResult {
+    /// The file was uploaded and committed.
+    Success(FileMetadata),
+
+    /// Uploading the file's content failed, even after retrying, so it wasn't committed.
+    AppendFailed(Error<UploadSessionAppendError>),
+
+    /// Committing the file failed. This is the failure case of
+    /// [`UploadSessionFinishBatchResultEntry`].
+    FinishFailed(UploadSessionFinishError),
+}
+
+/// An error which stopped a batch upload part way through, because starting or committing a whole
+/// batch failed.
+#[derive(thiserror::Error, Debug)]
+#[error("batch upload failed: {source}")]
+pub struct BatchUploadError {
+    /// One result for each entry, in the same order as they were given, for the entries whose
+    /// outcome is known. Entries which were committed in earlier batches, or in an earlier attempt
+    /// at the batch which failed, have their results here; the rest are `None`, and can be
+    /// uploaded again.
+    pub results: Vec<Option<BatchUploadResult>>,
+
+    /// The error which stopped the upload.
+    pub source: Error,
+}
+
+impl From<UploadSessionFinishBatchResultEntry> for BatchUploadResult {
+    fn from(entry: UploadSessionFinishBatchResultEntry) -> Self {
+        match entry {
+            UploadSessionFinishBatchResultEntry::Success(metadata) => Self::Success(metadata),
+            UploadSessionFinishBatchResultEntry::Failure(e) => Self::FinishFailed(e),
+        }
+    }
+}
+
+if_feature! { "sync_routes",
+    /// Upload many files in batches, using a sync HTTP client.
+    ///
+    /// Returns one result for each entry, in the same order as they were given.
+    /// An error is only returned if starting or committing a whole batch fails, and it holds the
+    /// results of the entries which were dealt with before that.
+    pub fn upload_batch(
+        client: &impl crate::client_trait::UserAuthClient,
+        entries: Vec<BatchUploadEntry>,
+        options: &BatchUploadOptions,
+    ) -> Result<Vec<BatchUploadResult>, BatchUploadError> {
+        super::block_on_sync(upload_batch_impl(
+            client,
+            entries,
+            options,
+            Sleeper::Blocking,
+            |sessions| {
+                std::future::ready(super::parallel_map(
+                    sessions,
+                    options.parallelism,
+                    |(session_id, data)| {
+                        super::block_on_sync(append(
+                            client,
+                            session_id,
+                            data,
+                            options,
+                            Sleeper::Blocking,
+                        ))
+                    },
+                ))
+            },
+        ))
+    }
+}
+
+/// Upload many files in batches.
+///
+/// Returns one result for each entry, in the same order as they were given.
+/// An error is only returned if starting or committing a whole batch fails, and it holds the
+/// results of the entries which were dealt with before that.
+pub async fn upload_batch_async(
+    client: &impl UserAuthClient,
+    entries: Vec<BatchUploadEntry>,
+    options: &BatchUploadOptions,
+) -> Result<Vec<BatchUploadResult>, BatchUploadError> {
+    upload_batch_impl(client, entries, options, Sleeper::Async, |sessions| {
+        futures::stream::iter(sessions)
+            .map(|(session_id, data)| append(client, session_id, data, options, Sleeper::Async))
+            .buffered(options.parallelism)
+            .collect::<Vec<_>>()
+    })
+    .await
+}
+
+/// Does the work of uploading the batches. The only difference between sync and async is how the
+/// content of each file is uploaded in parallel, which is done by `append_all`, given pairs of
+/// session ID and content.
+async fn upload_batch_impl<F, Fut>(
+    client: &impl UserAuthClient,
+    entries: Vec<BatchUploadEntry>,
+    options: &BatchUploadOptions,
+    sleeper: Sleeper,
+    append_all: F,
+) -> Result<Vec<BatchUploadResult>, BatchUploadError>
+where
+    F: Fn(Vec<(String, Bytes)>) -> Fut,
+    Fut: Future<Output = Vec<Result<(), Error<UploadSessionAppendError>>>>,
+{
+    let total = entries.len();
+    let mut results = Vec::with_capacity(total);
+    let mut entries = entries.into_iter().peekable();
+    while entries.peek().is_some() {
+        let batch = entries.by_ref().take(MAX_BATCH_ENTRIES).collect::<Vec<_>>();
+        let mut batch_results = (0..batch.len()).map(|_| None).collect::<Vec<_>>();
+        let outcome = upload_one_batch(
+            client,
+            batch,
+            &mut batch_results,
+            options,
+            sleeper,
+            &append_all,
+        )
+        .await;
+        results.extend(batch_results);
+        if let Err(source) = outcome {
+            results.resize_with(total, || None);
+            return Err(BatchUploadError { results, source });
+        }
+    }
+    Ok(results
+        .into_iter()
+        .map(|result| result.expect("every entry should have a result"))
+        .collect())
+}
+
+/// Upload and commit one batch of at most [`MAX_BATCH_ENTRIES`] entries, and put the outcome of
+/// each one into the corresponding index of `results`.
+async fn upload_one_batch<F, Fut>(
+    client: &impl UserAuthClient,
+    batch: Vec<BatchUploadEntry>,
+    results: &mut [Option<BatchUploadResult>],
+    options: &BatchUploadOptions,
+    sleeper: Sleeper,
+    append_all: &F,
+) -> Result<(), Error>
+where
+    F: Fn(Vec<(String, Bytes)>) -> Fut,
+    Fut: Future<Output = Vec<Result<(), Error<UploadSessionAppendError>>>>,
+{
+    let arg = UploadSessionStartBatchArg::new(batch.len() as u64);
+    let session_ids = with_retry(options.max_retries, sleeper, || {
+        routes::files::upload_session_start_batch(client, &arg)
+    })
+    .await?
+    .session_ids;
+    if session_ids.len() != batch.len() {
+        return Err(Error::UnexpectedResponse(format!(
+            "asked for {} upload sessions, got {}",
+            batch.len(),
+            session_ids.len()
+        )));
+    }
+
+    let appended = append_all(
+        session_ids
+            .iter()
+            .cloned()
+            .zip(batch.iter().map(|entry| entry.data.clone()))
+            .collect(),
+    )
+    .await;
+
+    let mut to_finish = vec![];
+    for (i, ((session_id, entry), appended)) in
+        session_ids.into_iter().zip(batch).zip(appended).enumerate()
+    {
+        match appended {
+            Ok(()) => {
+                let cursor = UploadSessionCursor::new(session_id, entry.data.len() as u64);
+                to_finish.push((i, UploadSessionFinishArg::new(cursor, entry.commit)));
+            }
+            Err(e) => results[i] = Some(BatchUploadResult::AppendFailed(e)),
+        }
+    }
+
+    finish(client, to_finish, results, options, sleeper).await
+}
+
+/// Upload the entire content of a file to its session, and close it.
+async fn append(
+    client: &impl UserAuthClient,
+    session_id: String,
+    data: Bytes,
+    options: &BatchUploadOptions,
+    sleeper: Sleeper,
+) -> Result<(), Error<UploadSessionAppendError>> {
+    let arg = UploadSessionAppendArg::new(UploadSessionCursor::new(session_id, 0)).with_close(true);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::upload_session_append_v2(client, &arg, data.clone())
+    })
+    .await
+}
+
+/// Commit the given sessions, and put the outcome of each one into the corresponding index of
+/// `results`. Entries which fail due to lock contention are tried again.
+async fn finish(
+    client: &impl UserAuthClient,
+    mut pending: Vec<(usize, UploadSessionFinishArg)>,
+    results: &mut [Option<BatchUploadResult>],
+    options: &BatchUploadOptions,
+    sleeper: Sleeper,
+) -> Result<(), Error> {
+    let mut attempts = 0;
+    while !pending.is_empty() {
+        let arg =
+            UploadSessionFinishBatchArg::new(pending.iter().map(|(_, arg)| arg.clone()).collect());
+        let entries = with_retry(options.max_retries, sleeper, || {
+            routes::files::upload_session_finish_batch_v2(client, &arg)
+        })
+        .await?
+        .entries;
+        if entries.len() != pending.len() {
+            return Err(Error::UnexpectedResponse(format!(
+                "committed {} entries, got {} results",
+                pending.len(),
+                entries.len()
+            )));
+        }
+
+        let mut retry = vec![];
+        for ((i, arg), entry) in pending.into_iter().zip(entries) {
+            match entry {
+                UploadSessionFinishBatchResultEntry::Failure(
+                    UploadSessionFinishError::TooManyWriteOperations,
+                ) if attempts < options.max_retries => retry.push((i, arg)),
+                entry => results[i] = Some(entry.into()),
+            }
+        }
+
+        if !retry.is_empty() {
+            attempts += 1;
+            warn!(
+                "{} entries failed due to too many write operations; retrying ({attempts}/{})",
+                retry.len(),
+                options.max_retries,
+            );
+            sleeper.sleep(RETRY_BACKOFF * attempts).await;
+        }
+        pending = retry;
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Computing Dropbox content hashes.
+//!
+//! The [`content_hash`](crate::files::FileMetadata::content_hash) of a file is calculated by
+//! splitting its content into 4 MiB blocks, taking the SHA-256 hash of each block, and then taking
+//! the SHA-256 hash of all of those hashes concatenated together. It is given in lowercase hex.
+//! See <https://www.dropbox.com/developers/reference/content-hash> for details.
+
+use ring::digest::{Context, Digest, SHA256};
+use std::io;
+
+/// The size of the blocks the content is split into for hashing.
+pub const HASH_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Calculates a content hash incrementally, as the content is fed in.
+///
+/// This also implements [`Write`](io::Write), so it can be used with [`io::copy`].
+#[derive(Clone)]
+pub struct ContentHasher {
+    overall: Context,
+    block: Context,
+    block_len: usize,
+}
+
+impl std::fmt::Debug for ContentHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentHasher").finish_non_exhaustive()
+    }
+}
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentHasher {
+    /// Make a new hasher with no content.
+    pub fn new() -> Self {
+        Self {
+            overall: Context::new(&SHA256),
+            block: Context::new(&SHA256),
+            block_len: 0,
+        }
+    }
+
+    /// Add some content to the hash.
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = data.len().min(HASH_BLOCK_SIZE - self.block_len);
+            self.block.update(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == HASH_BLOCK_SIZE {
+                let block = std::mem::replace(&mut self.block, Context::new(&SHA256));
+                self.overall.update(block.finish().as_ref());
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Get the content hash of everything added so far.
+    pub fn finish(mut self) -> String {
+        if self.block_len != 0 {
+            self.overall.update(self.block.finish().as_ref());
+        }
+        to_hex(self.overall.finish())
+    }
+}
+
+impl io::Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Calculate the content hash of the given data.
+pub fn content_hash(data: &[u8]) -> String {
+    let mut hasher = ContentHasher::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Hash a single block. Along with [`combine_block_hashes`], this lets blocks be hashed out of
+/// order, or in parallel.
+#[cfg(feature = "dbx_files")]
+pub(crate) fn block_hash(block: &[u8]) -> Digest {
+    debug_assert!(block.len() <= HASH_BLOCK_SIZE);
+    ring::digest::digest(&SHA256, block)
+}
+
+/// Get the content hash from the hashes of each block, in order.
+#[cfg(feature = "dbx_files")]
+pub(crate) fn combine_block_hashes<'a>(hashes: impl IntoIterator<Item = &'a Digest>) -> String {
+    let mut overall = Context::new(&SHA256);
+    for hash in hashes {
+        overall.update(hash.as_ref());
+    }
+    to_hex(overall.finish())
+}
+
+fn to_hex(digest: Digest) -> String {
+    digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,249 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Copying files and folders between accounts, without transferring their content.
+//!
+//! A copy reference, from `files/copy_reference/get`, can be saved into another account which is
+//! linked to the same app with `files/copy_reference/save`, which copies the file or folder on the
+//! server. [`copy_between_accounts`] does this using a client for each account, fetching each
+//! reference just before it's saved so that it can't expire in between, and fetching a new one if
+//! it's rejected anyway. Folders with too many files to copy in one go are recreated, and their
+//! contents copied one by one.
+//!
+//! To copy between two members of a team, use two [member views](super::member) of the team
+//! client.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CreateFolderArg, CreateFolderError, GetCopyReferenceArg, GetCopyReferenceError, ListFolderArg,
+    ListFolderContinueArg, ListFolderContinueError, ListFolderError, Metadata,
+    SaveCopyReferenceArg, SaveCopyReferenceError,
+};
+use futures::future::BoxFuture;
+
+/// Options controlling how [`copy_between_accounts`] copies.
+#[derive(Debug, Clone)]
+pub struct CrossAccountCopyOptions {
+    max_retries: u32,
+}
+
+impl Default for CrossAccountCopyOptions {
+    fn default() -> Self {
+        Self { max_retries: 3 }
+    }
+}
+
+impl CrossAccountCopyOptions {
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+}
+
+/// An error copying between accounts. Each variant has the path, in the source account for
+/// source operations and the destination account for destination operations, which it's about.
+///
+/// If a folder was being copied piece by piece, whatever was copied before the error is left in
+/// place.
+#[derive(thiserror::Error, Debug)]
+pub enum CrossAccountCopyError {
+    /// Getting a copy reference from the source account failed.
+    #[error("error getting copy reference for {path}: {source}")]
+    GetReference {
+        /// The path in the source account.
+        path: String,
+        /// The error.
+        source: Error<GetCopyReferenceError>,
+    },
+
+    /// Saving a copy reference into the destination account failed.
+    #[error("error saving copy reference to {path}: {source}")]
+    SaveReference {
+        /// The path in the destination account.
+        path: String,
+        /// The error.
+        source: Error<SaveCopyReferenceError>,
+    },
+
+    /// Listing a source folder which was too big to copy in one go failed.
+    #[error("error listing {path}: {source}")]
+    ListFolder {
+        /// The path in the source account.
+        path: String,
+        /// The error.
+        source: Error<ListFolderError>,
+    },
+
+    /// Listing more of a source folder which was too big to copy in one go failed.
+    #[error("error listing {path}: {source}")]
+    ListFolderContinue {
+        /// The path in the source account.
+        path: String,
+        /// The error.
+        source: Error<ListFolderContinueError>,
+    },
+
+    /// Creating a destination folder, to copy the contents of a source folder into, failed.
+    #[error("error creating folder {path}: {source}")]
+    CreateFolder {
+        /// The path in the destination account.
+        path: String,
+        /// The error.
+        source: Error<CreateFolderError>,
+    },
+}
+
+if_feature! { "sync_routes",
+    /// Copy a file or folder from one account to another, using sync HTTP clients. Returns the
+    /// metadata of the copy in the destination account.
+    ///
+    /// Both clients must belong to the same app.
+    pub fn copy_between_accounts(
+        source: &impl crate::client_trait::UserAuthClient,
+        source_path: &str,
+        destination: &impl crate::client_trait::UserAuthClient,
+        destination_path: &str,
+        options: &CrossAccountCopyOptions,
+    ) -> Result<Metadata, CrossAccountCopyError> {
+        let copier = Copier {
+            source,
+            destination,
+            options,
+            sleeper: Sleeper::Blocking,
+        };
+        super::block_on_sync(copier.copy(source_path.to_owned(), destination_path.to_owned()))
+    }
+}
+
+/// Copy a file or folder from one account to another. Returns the metadata of the copy in the
+/// destination account.
+///
+/// Both clients must belong to the same app.
+pub async fn copy_between_accounts_async(
+    source: &impl UserAuthClient,
+    source_path: &str,
+    destination: &impl UserAuthClient,
+    destination_path: &str,
+    options: &CrossAccountCopyOptions,
+) -> Result<Metadata, CrossAccountCopyError> {
+    let copier = Copier {
+        source,
+        destination,
+        options,
+        sleeper: Sleeper::Async,
+    };
+    copier
+        .copy(source_path.to_owned(), destination_path.to_owned())
+        .await
+}
+
+struct Copier<'a, S, D> {
+    source: &'a S,
+    destination: &'a D,
+    options: &'a CrossAccountCopyOptions,
+    sleeper: Sleeper,
+}
+
+impl<S: UserAuthClient, D: UserAuthClient> Copier<'_, S, D> {
+    /// Copy one file or folder. Boxed, since it recurses for big folders.
+    fn copy(
+        &self,
+        from: String,
+        to: String,
+    ) -> BoxFuture<'_, Result<Metadata, CrossAccountCopyError>> {
+        Box::pin(async move {
+            let mut fresh = true;
+            loop {
+                let get_arg = GetCopyReferenceArg::new(from.clone());
+                let reference = with_retry(self.options.max_retries, self.sleeper, || {
+                    routes::files::copy_reference_get(self.source, &get_arg)
+                })
+                .await
+                .map_err(|source| CrossAccountCopyError::GetReference {
+                    path: from.clone(),
+                    source,
+                })?;
+
+                let save_arg = SaveCopyReferenceArg::new(reference.copy_reference, to.clone());
+                match with_retry(self.options.max_retries, self.sleeper, || {
+                    routes::files::copy_reference_save(self.destination, &save_arg)
+                })
+                .await
+                {
+                    Ok(result) => return Ok(result.metadata),
+                    // Probably expired, though it shouldn't have; try once more with a new one.
+                    Err(Error::Api(SaveCopyReferenceError::InvalidCopyReference)) if fresh => {
+                        fresh = false;
+                    }
+                    Err(Error::Api(SaveCopyReferenceError::TooManyFiles))
+                        if matches!(reference.metadata, Metadata::Folder(_)) =>
+                    {
+                        return self.copy_contents(from, to).await;
+                    }
+                    Err(source) => {
+                        return Err(CrossAccountCopyError::SaveReference { path: to, source });
+                    }
+                }
+            }
+        })
+    }
+
+    /// Copy a folder which is too big to copy in one go, by creating it and copying each thing in
+    /// it.
+    async fn copy_contents(
+        &self,
+        from: String,
+        to: String,
+    ) -> Result<Metadata, CrossAccountCopyError> {
+        debug!("{from} is too big to copy at once; copying its contents one by one");
+        let create_arg = CreateFolderArg::new(to.clone());
+        let folder = with_retry(self.options.max_retries, self.sleeper, || {
+            routes::files::create_folder_v2(self.destination, &create_arg)
+        })
+        .await
+        .map_err(|source| CrossAccountCopyError::CreateFolder {
+            path: to.clone(),
+            source,
+        })?
+        .metadata;
+
+        let arg = ListFolderArg::new(from.clone());
+        let mut result = with_retry(self.options.max_retries, self.sleeper, || {
+            routes::files::list_folder(self.source, &arg)
+        })
+        .await
+        .map_err(|source| CrossAccountCopyError::ListFolder {
+            path: from.clone(),
+            source,
+        })?;
+        loop {
+            for entry in result.entries {
+                let (name, path) = match entry {
+                    Metadata::File(file) => (file.name, file.path_lower),
+                    Metadata::Folder(folder) => (folder.name, folder.path_lower),
+                    Metadata::Deleted(_) => continue,
+                };
+                // The source path could be an ID, so it can't always be joined with the name.
+                let path = path.unwrap_or_else(|| format!("{}/{name}", from.trim_end_matches('/')));
+                self.copy(path, format!("{}/{name}", to.trim_end_matches('/')))
+                    .await?;
+            }
+            if !result.has_more {
+                return Ok(Metadata::Folder(folder));
+            }
+            let arg = ListFolderContinueArg::new(result.cursor);
+            result = with_retry(self.options.max_retries, self.sleeper, || {
+                routes::files::list_folder_continue(self.source, &arg)
+            })
+            .await
+            .map_err(|source| CrossAccountCopyError::ListFolderContinue {
+                path: from.clone(),
+                source,
+            })?;
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Downloads which survive dropped connections.
+//!
+//! A download of a large file over a flaky connection is likely to be interrupted partway through.
+//! The readers in this module keep track of how many bytes have been delivered, and if reading the
+//! response fails, or it ends early, transparently reissue the request starting from where it left
+//! off. The file's revision is pinned from the first response, so the content can't change
+//! between requests.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{DownloadArg, DownloadError, FileMetadata};
+use futures::AsyncRead;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+/// Options controlling how downloads are retried.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub(crate) max_retries: u32,
+    pub(crate) max_reconnects: u32,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            max_reconnects: 5,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// How many times in a row to reconnect after reading the response fails, without any data
+    /// being read in between, before giving up and returning the error. Defaults to 5.
+    pub fn with_max_reconnects(mut self, value: u32) -> Self {
+        self.max_reconnects = value;
+        self
+    }
+}
+
+/// Which part of the file is being downloaded, and how far along it is.
+#[derive(Debug, Clone)]
+struct Progress {
+    /// The file's revision, which further requests are pinned to.
+    rev: String,
+
+    /// The offset in the file of the next byte to be read.
+    position: u64,
+
+    /// The offset in the file where the download ends (exclusive).
+    end: u64,
+
+    /// How many times reading has failed since the last successful read.
+    failures: u32,
+}
+
+impl Progress {
+    /// Work out the absolute range being downloaded, given the requested range and the file's
+    /// metadata. The range arguments are interpreted as in [`download`](crate::files::download).
+    fn new(metadata: &FileMetadata, range_start: Option<u64>, range_end: Option<u64>) -> Self {
+        let size = metadata.size;
+        let (position, end) = match (range_start, range_end) {
+            (Some(start), Some(end)) => (start, end.saturating_add(1)),
+            (Some(start), None) => (start, size),
+            // Only an end means "this many bytes from the end".
+            (None, Some(suffix)) => (size.saturating_sub(suffix), size),
+            (None, None) => (0, size),
+        };
+        Self {
+            rev: metadata.rev.clone(),
+            position: position.min(size),
+            end: end.min(size),
+            failures: 0,
+        }
+    }
+
+    /// The argument to request the rest of the file from where it left off.
+    fn resume_args(&self) -> (DownloadArg, Option<u64>, Option<u64>) {
+        (
+            DownloadArg::new(format!("rev:{}", self.rev)),
+            Some(self.position),
+            Some(self.end - 1),
+        )
+    }
+
+    /// Record the result of a read into a non-empty buffer. Returns the result to pass on to the
+    /// caller, or, if the read failed and should be retried, the error which caused it.
+    fn record_read(
+        &mut self,
+        result: io::Result<usize>,
+        max_reconnects: u32,
+    ) -> Result<io::Result<usize>, io::Error> {
+        let e = match result {
+            Ok(0) => io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("download ended early at offset {}", self.position),
+            ),
+            Ok(n) => {
+                self.position += n as u64;
+                self.failures = 0;
+                return Ok(Ok(n));
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(Err(e)),
+            Err(e) => e,
+        };
+        if self.failures >= max_reconnects {
+            return Ok(Err(e));
+        }
+        self.failures += 1;
+        warn!(
+            "download of rev {} failed at offset {}: {e}; reconnecting ({}/{max_reconnects})",
+            self.rev, self.position, self.failures
+        );
+        Err(e)
+    }
+
+    fn is_done(&self) -> bool {
+        self.position >= self.end
+    }
+}
+
+fn missing_body<E>() -> Error<E> {
+    Error::UnexpectedResponse("download response has no body".to_owned())
+}
+
+if_feature! { "sync_routes",
+    /// A reader over a file being downloaded, which reconnects if the download is interrupted,
+    /// using a sync HTTP client.
+    pub struct ResumableDownload<'a, C> {
+        client: &'a C,
+        options: DownloadOptions,
+        metadata: FileMetadata,
+        progress: Progress,
+        body: Box<dyn io::Read>,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::UserAuthClient> ResumableDownload<'a, C> {
+    /// Start downloading a file. The arguments are the same as those of
+    /// [`download`](crate::files::download).
+    pub fn new(
+        client: &'a C,
+        arg: &DownloadArg,
+        range_start: Option<u64>,
+        range_end: Option<u64>,
+        options: DownloadOptions,
+    ) -> Result<Self, Error<DownloadError>> {
+        let response = Self::request(client, &options, arg, range_start, range_end)?;
+        let progress = Progress::new(&response.result, range_start, range_end);
+        Ok(Self {
+            client,
+            options,
+            metadata: response.result,
+            progress,
+            body: response.body.ok_or_else(missing_body)?,
+        })
+    }
+
+    fn request(
+        client: &C,
+        options: &DownloadOptions,
+        arg: &DownloadArg,
+        range_start: Option<u64>,
+        range_end: Option<u64>,
+    ) -> Result<crate::client_trait::HttpRequestResult<FileMetadata>, Error<DownloadError>> {
+        super::block_on_sync(with_retry(options.max_retries, Sleeper::Blocking, || {
+            std::future::ready(crate::sync_routes::files::download(
+                client,
+                arg,
+                range_start,
+                range_end,
+            ))
+        }))
+    }
+
+    /// The metadata of the file being downloaded.
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.metadata
+    }
+
+    /// The offset in the file of the next byte to be read.
+    pub fn position(&self) -> u64 {
+        self.progress.position
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> io::Read for ResumableDownload<'_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if buf.is_empty() || self.progress.is_done() {
+                return Ok(0);
+            }
+            let result = self.body.read(buf);
+            match self
+                .progress
+                .record_read(result, self.options.max_reconnects)
+            {
+                Ok(result) => return result,
+                Err(_) => {
+                    let (arg, start, end) = self.progress.resume_args();
+                    let response = Self::request(self.client, &self.options, &arg, start, end)
+                        .map_err(io::Error::other)?;
+                    self.body = response
+                        .body
+                        .ok_or_else(|| io::Error::other(missing_body::<DownloadError>()))?;
+                }
+            }
+        }
+    }
+}
+
+type Body = Box<dyn AsyncRead + Send + Unpin>;
+
+enum State {
+    Reading(Body),
+    Reconnecting(Pin<Box<dyn Future<Output = Result<Body, Error<DownloadError>>> + Send>>),
+}
+
+/// A reader over a file being downloaded, which reconnects if the download is interrupted.
+pub struct AsyncResumableDownload<C> {
+    client: Arc<C>,
+    options: DownloadOptions,
+    metadata: FileMetadata,
+    progress: Progress,
+    state: State,
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncResumableDownload<C> {
+    /// Start downloading a file. The arguments are the same as those of
+    /// [`download`](crate::files::download).
+    pub async fn new(
+        client: Arc<C>,
+        arg: &DownloadArg,
+        range_start: Option<u64>,
+        range_end: Option<u64>,
+        options: DownloadOptions,
+    ) -> Result<Self, Error<DownloadError>> {
+        let response = with_retry(options.max_retries, Sleeper::Async, || {
+            routes::files::download(client.as_ref(), arg, range_start, range_end)
+        })
+        .await?;
+        let progress = Progress::new(&response.result, range_start, range_end);
+        Ok(Self {
+            client,
+            options,
+            metadata: response.result,
+            progress,
+            state: State::Reading(response.body.ok_or_else(missing_body)?),
+        })
+    }
+
+    /// The metadata of the file being downloaded.
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.metadata
+    }
+
+    /// The offset in the file of the next byte to be read.
+    pub fn position(&self) -> u64 {
+        self.progress.position
+    }
+
+    fn reconnect(&self) -> State {
+        let client = Arc::clone(&self.client);
+        let max_retries = self.options.max_retries;
+        let (arg, start, end) = self.progress.resume_args();
+        State::Reconnecting(Box::pin(async move {
+            let response = with_retry(max_retries, Sleeper::Async, || {
+                routes::files::download(client.as_ref(), &arg, start, end)
+            })
+            .await?;
+            response.body.ok_or_else(missing_body)
+        }))
+    }
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncRead for AsyncResumableDownload<C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if buf.is_empty() || this.progress.is_done() {
+                return Poll::Ready(Ok(0));
+            }
+            match &mut this.state {
+                State::Reading(body) => {
+                    let result = ready!(Pin::new(body).poll_read(cx, buf));
+                    match this
+                        .progress
+                        .record_read(result, this.options.max_reconnects)
+                    {
+                        Ok(result) => return Poll::Ready(result),
+                        Err(_) => this.state = this.reconnect(),
+                    }
+                }
+                State::Reconnecting(future) => match ready!(future.as_mut().poll(cx)) {
+                    Ok(body) => this.state = State::Reading(body),
+                    Err(e) => return Poll::Ready(Err(io::Error::other(e))),
+                },
+            }
+        }
+    }
+}
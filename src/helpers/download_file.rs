@@ -0,0 +1,387 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Downloading a file into a local file, safely.
+//!
+//! [`download_to_file`] never leaves a partially downloaded or corrupt file behind: the download
+//! is written to a temporary file next to the destination, checked against the size and content
+//! hash in the file's metadata, flushed to disk, and only then renamed over the destination. The
+//! local file's modification time is set to the file's `client_modified` time. Downloads which are
+//! interrupted reconnect, as described in [`download`](super::download).
+
+use super::content_hash::ContentHasher;
+use super::download::{AsyncResumableDownload, DownloadOptions};
+use super::{BlockingSpawnFn, Sleeper, routes, run_blocking, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    DownloadArg, DownloadError, FileMetadata, GetMetadataArg, GetMetadataError, Metadata,
+};
+use futures::AsyncReadExt;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Options controlling how [`download_to_file`] downloads a file.
+#[derive(Clone, Default)]
+pub struct DownloadFileOptions {
+    skip_unchanged: bool,
+    download: DownloadOptions,
+    spawner: Option<Arc<BlockingSpawnFn>>,
+}
+
+impl fmt::Debug for DownloadFileOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadFileOptions")
+            .field("skip_unchanged", &self.skip_unchanged)
+            .field("download", &self.download)
+            .field("spawner", &self.spawner.is_some())
+            .finish()
+    }
+}
+
+impl DownloadFileOptions {
+    /// Whether to skip the download if the local file already has the same content. This costs
+    /// an extra request, and reading the local file if it has the same size. Defaults to false.
+    pub fn with_skip_unchanged(mut self, value: bool) -> Self {
+        self.skip_unchanged = value;
+        self
+    }
+
+    /// Options controlling how the download is retried.
+    pub fn with_download_options(mut self, value: DownloadOptions) -> Self {
+        self.download = value;
+        self
+    }
+
+    /// A function which runs a blocking closure on a thread where that's allowed, such as
+    /// `|f| { tokio::task::spawn_blocking(f); }`. [`download_to_file_async`] uses it for reading
+    /// and writing the local file. Without one, that's done on the task awaiting the download,
+    /// which blocks it. The sync [`download_to_file`] doesn't use it.
+    pub fn with_blocking_spawner(
+        mut self,
+        value: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static,
+    ) -> Self {
+        self.spawner = Some(Arc::new(value));
+        self
+    }
+}
+
+/// An error downloading a file.
+///
+/// The destination is left untouched when this is returned.
+#[derive(thiserror::Error, Debug)]
+pub enum DownloadFileError {
+    /// Looking up the file's metadata failed.
+    #[error("error getting file metadata: {0}")]
+    Metadata(#[source] Error<GetMetadataError>),
+
+    /// The path doesn't refer to a file.
+    #[error("path is not a file")]
+    NotAFile,
+
+    /// Starting the download failed, even after retrying.
+    #[error("error downloading file: {0}")]
+    Download(#[source] Error<DownloadError>),
+
+    /// Reading the download, or reading or writing local files, failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The download ended before the whole file was received, or went on past its end.
+    #[error("size mismatch: expected {expected} bytes, got {actual}")]
+    SizeMismatch {
+        /// The size from the file's metadata.
+        expected: u64,
+        /// How many bytes were downloaded.
+        actual: u64,
+    },
+
+    /// The downloaded content doesn't match the file's content hash.
+    #[error("content hash mismatch: expected {expected}, got {actual}")]
+    HashMismatch {
+        /// The content hash from the file's metadata.
+        expected: String,
+        /// The content hash of the data which was downloaded.
+        actual: String,
+    },
+}
+
+/// What happened to the file.
+#[derive(Debug, Clone)]
+pub enum DownloadFileOutcome {
+    /// The file was downloaded.
+    Downloaded(FileMetadata),
+
+    /// The local file already had the same content, so it was left alone.
+    Unchanged(FileMetadata),
+}
+
+impl DownloadFileOutcome {
+    /// The metadata of the file on Dropbox, whether it was downloaded or not.
+    pub fn metadata(&self) -> &FileMetadata {
+        match self {
+            Self::Downloaded(metadata) | Self::Unchanged(metadata) => metadata,
+        }
+    }
+}
+
+if_feature! { "sync_routes",
+    /// Download a file from Dropbox to the given local path, using a sync HTTP client. An
+    /// existing file at the local path is replaced.
+    pub fn download_to_file(
+        client: &impl crate::client_trait::UserAuthClient,
+        dropbox_path: &str,
+        local_path: impl AsRef<Path>,
+        options: &DownloadFileOptions,
+    ) -> Result<DownloadFileOutcome, DownloadFileError> {
+        let local_path = local_path.as_ref();
+        let arg = match super::block_on_sync(unchanged(
+            client,
+            dropbox_path,
+            local_path,
+            options,
+            Sleeper::Blocking,
+        ))? {
+            Check::Unchanged(metadata) => return Ok(DownloadFileOutcome::Unchanged(*metadata)),
+            Check::Download(arg) => arg,
+        };
+        let mut reader = super::download::ResumableDownload::new(
+            client,
+            &arg,
+            None,
+            None,
+            options.download.clone(),
+        )
+        .map_err(DownloadFileError::Download)?;
+        let mut temp = TempFile::create(local_path)?;
+        io::copy(&mut reader, &mut temp)?;
+        let metadata = reader.metadata().clone();
+        temp.persist(&metadata)?;
+        Ok(DownloadFileOutcome::Downloaded(metadata))
+    }
+}
+
+/// Download a file from Dropbox to the given local path. An existing file at the local path is
+/// replaced.
+///
+/// The local file is read and written through the
+/// [blocking spawner](DownloadFileOptions::with_blocking_spawner) from the options, if there is
+/// one, and on the current task otherwise.
+pub async fn download_to_file_async<C: UserAuthClient + Send + 'static>(
+    client: Arc<C>,
+    dropbox_path: &str,
+    local_path: impl AsRef<Path>,
+    options: &DownloadFileOptions,
+) -> Result<DownloadFileOutcome, DownloadFileError> {
+    let local_path = local_path.as_ref();
+    let arg = match unchanged(
+        client.as_ref(),
+        dropbox_path,
+        local_path,
+        options,
+        Sleeper::Async,
+    )
+    .await?
+    {
+        Check::Unchanged(metadata) => return Ok(DownloadFileOutcome::Unchanged(*metadata)),
+        Check::Download(arg) => arg,
+    };
+    let mut reader =
+        AsyncResumableDownload::new(client, &arg, None, None, options.download.clone())
+            .await
+            .map_err(DownloadFileError::Download)?;
+    let spawner = options.spawner.as_deref();
+    let destination = local_path.to_owned();
+    let mut temp = run_blocking(Sleeper::Async, spawner, move || {
+        TempFile::create(&destination)
+    })
+    .await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        (temp, buf) = run_blocking(Sleeper::Async, spawner, move || {
+            temp.write_all(&buf[..n])?;
+            io::Result::Ok((temp, buf))
+        })
+        .await?;
+    }
+    let metadata = reader.metadata().clone();
+    let persisted = metadata.clone();
+    run_blocking(Sleeper::Async, spawner, move || temp.persist(&persisted)).await?;
+    Ok(DownloadFileOutcome::Downloaded(metadata))
+}
+
+/// Whether the file needs downloading.
+enum Check {
+    /// The local file already has the same content as this file.
+    Unchanged(Box<FileMetadata>),
+    /// Download the file using this argument.
+    Download(DownloadArg),
+}
+
+/// If skipping unchanged files is enabled, look up the remote file, and check whether the local
+/// one has the same content. If it doesn't, the download is pinned to the revision looked up.
+async fn unchanged(
+    client: &impl UserAuthClient,
+    dropbox_path: &str,
+    local_path: &Path,
+    options: &DownloadFileOptions,
+    sleeper: Sleeper,
+) -> Result<Check, DownloadFileError> {
+    if !options.skip_unchanged {
+        return Ok(Check::Download(DownloadArg::new(dropbox_path.to_owned())));
+    }
+    let arg = GetMetadataArg::new(dropbox_path.to_owned());
+    let metadata = match with_retry(options.download.max_retries, sleeper, || {
+        routes::files::get_metadata(client, &arg)
+    })
+    .await
+    .map_err(DownloadFileError::Metadata)?
+    {
+        Metadata::File(metadata) => metadata,
+        Metadata::Folder(_) | Metadata::Deleted(_) => return Err(DownloadFileError::NotAFile),
+    };
+    let download = DownloadArg::new(format!("rev:{}", metadata.rev));
+    let Some(remote_hash) = metadata.content_hash.clone() else {
+        return Ok(Check::Download(download));
+    };
+    let (local_path, size) = (local_path.to_owned(), metadata.size);
+    let same = run_blocking(sleeper, options.spawner.as_deref(), move || {
+        has_content(&local_path, size, &remote_hash)
+    })
+    .await?;
+    if same {
+        Ok(Check::Unchanged(Box::new(metadata)))
+    } else {
+        Ok(Check::Download(download))
+    }
+}
+
+/// Whether the local file exists, and has the given size and content hash.
+fn has_content(path: &Path, size: u64, content_hash: &str) -> io::Result<bool> {
+    let mut local = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if local.metadata()?.len() != size {
+        return Ok(false);
+    }
+    let mut hasher = ContentHasher::new();
+    io::copy(&mut local, &mut hasher)?;
+    Ok(hasher.finish() == content_hash)
+}
+
+/// A temporary file next to the destination, which is deleted when dropped unless it's been
+/// persisted. Everything written to it is hashed.
+pub(super) struct TempFile {
+    file: File,
+    path: PathBuf,
+    destination: PathBuf,
+    hasher: ContentHasher,
+    size: u64,
+    persisted: bool,
+}
+
+impl TempFile {
+    pub(super) fn create(destination: &Path) -> io::Result<Self> {
+        // Distinguishes temporary files created by this process at the same time.
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let name = destination.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{destination:?} is not a file path"),
+            )
+        })?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}-{}.download",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let path = destination.with_file_name(temp_name);
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok(Self {
+            file,
+            path,
+            destination: destination.to_owned(),
+            hasher: ContentHasher::new(),
+            size: 0,
+            persisted: false,
+        })
+    }
+
+    /// Check what was written against the file's metadata, and if it matches, set the
+    /// modification time, flush it to disk and rename it over the destination.
+    pub(super) fn persist(mut self, metadata: &FileMetadata) -> Result<(), DownloadFileError> {
+        if self.size != metadata.size {
+            return Err(DownloadFileError::SizeMismatch {
+                expected: metadata.size,
+                actual: self.size,
+            });
+        }
+        let actual = std::mem::take(&mut self.hasher).finish();
+        match &metadata.content_hash {
+            Some(expected) if *expected != actual => {
+                return Err(DownloadFileError::HashMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+            _ => (),
+        }
+        if let Some(time) = super::parse_dropbox_timestamp(&metadata.client_modified) {
+            self.file.set_modified(time)?;
+        }
+        Ok(self.finish()?)
+    }
+
+    /// Flush the file to disk and rename it over the destination, without checking it against
+    /// anything, for content which was checked some other way.
+    pub(super) fn finish(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.path, &self.destination)?;
+        self.persisted = true;
+        // Try to make the rename itself durable too. The file is in place by now, so failing to
+        // isn't an error.
+        #[cfg(unix)]
+        if let Some(dir) = self.destination.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+        Ok(())
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Making sure a file or folder has a shared link with the given settings.
+//!
+//! `sharing/create_shared_link_with_settings` fails if the file or folder already has a link, and
+//! the existing link might not have the settings asked for. [`ensure_shared_link`] creates a link
+//! if there isn't one, and otherwise finds the existing one and changes whichever of its settings
+//! differ from the ones asked for, reporting what it changed. Calling it again with the same
+//! settings does nothing.
+//!
+//! An audience which a team or shared folder policy overrides, or doesn't allow, is left as it is,
+//! rather than being asked for again every time.
+//!
+//! Passwords can't be read back, so a password is only set on a link which doesn't already require
+//! one, unless [`EnsureSharedLinkOptions::with_reset_password`] asks for it to be set every time.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::sharing::{
+    CreateSharedLinkWithSettingsArg, CreateSharedLinkWithSettingsError, LinkAccessLevel,
+    LinkAudience, LinkPermissions, ListSharedLinksArg, ListSharedLinksError,
+    ModifySharedLinkSettingsArgs, ModifySharedLinkSettingsError, RequestedLinkAccessLevel,
+    RequestedVisibility, SharedLinkAlreadyExistsMetadata, SharedLinkMetadata, SharedLinkSettings,
+};
+
+/// Options controlling how [`ensure_shared_link`] reconciles a link's settings.
+#[derive(Debug, Clone)]
+pub struct EnsureSharedLinkOptions {
+    remove_expiration: bool,
+    reset_password: bool,
+    max_retries: u32,
+}
+
+impl Default for EnsureSharedLinkOptions {
+    fn default() -> Self {
+        Self {
+            remove_expiration: false,
+            reset_password: false,
+            max_retries: 3,
+        }
+    }
+}
+
+impl EnsureSharedLinkOptions {
+    /// Whether to remove an existing link's expiry if the settings don't give one. By default, a
+    /// setting which isn't given is left as it is.
+    pub fn with_remove_expiration(mut self, value: bool) -> Self {
+        self.remove_expiration = value;
+        self
+    }
+
+    /// Whether to set the password given in the settings even if the link already requires one,
+    /// which is then reported as changed every time. By default, the password of a link which
+    /// already requires one is left as it is, since there's no way to tell whether it differs.
+    pub fn with_reset_password(mut self, value: bool) -> Self {
+        self.reset_password = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+}
+
+/// A setting of an existing link which [`ensure_shared_link`] changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedLinkChange {
+    /// The expiry was set, changed or removed.
+    Expiry,
+    /// The audience was changed.
+    Audience,
+    /// Whether a password is required was changed.
+    RequirePassword,
+    /// The password was set, on a link which didn't require one before, or on any link if
+    /// [`EnsureSharedLinkOptions::with_reset_password`] is set.
+    Password,
+    /// The access level was changed.
+    Access,
+    /// Whether the link can be downloaded was changed.
+    AllowDownload,
+}
+
+/// What [`ensure_shared_link`] did.
+#[derive(Debug, Clone)]
+pub enum EnsureSharedLinkOutcome {
+    /// There was no link, so one was created.
+    Created(SharedLinkMetadata),
+
+    /// There was a link with the settings asked for already.
+    Unchanged(SharedLinkMetadata),
+
+    /// There was a link, and some of its settings were changed.
+    Updated {
+        /// The link's metadata after the changes.
+        metadata: SharedLinkMetadata,
+        /// What was changed.
+        changes: Vec<SharedLinkChange>,
+    },
+}
+
+impl EnsureSharedLinkOutcome {
+    /// The link's metadata.
+    pub fn metadata(&self) -> &SharedLinkMetadata {
+        match self {
+            Self::Created(metadata) | Self::Unchanged(metadata) => metadata,
+            Self::Updated { metadata, .. } => metadata,
+        }
+    }
+}
+
+/// An error making sure a file or folder has a shared link.
+#[derive(thiserror::Error, Debug)]
+pub enum EnsureSharedLinkError {
+    /// Creating the link failed.
+    #[error("error creating a shared link to {path}: {source}")]
+    Create {
+        /// The path of the file or folder.
+        path: String,
+        /// The error, boxed since it can hold the metadata of an existing link.
+        source: Box<Error<CreateSharedLinkWithSettingsError>>,
+    },
+
+    /// Listing the file or folder's links, to find the existing one, failed.
+    #[error("error listing shared links to {path}: {source}")]
+    List {
+        /// The path of the file or folder.
+        path: String,
+        /// The error.
+        source: Error<ListSharedLinksError>,
+    },
+
+    /// Changing the existing link's settings failed.
+    #[error("error changing the settings of {url}: {source}")]
+    Modify {
+        /// The link's URL.
+        url: String,
+        /// The error.
+        source: Error<ModifySharedLinkSettingsError>,
+    },
+
+    /// The existing link is of a kind this version of the SDK doesn't know about, so its settings
+    /// can't be checked.
+    #[error("shared link to {0} is of an unknown kind")]
+    UnknownLinkType(String),
+}
+
+if_feature! { "sync_routes",
+    /// Make sure the file or folder at `path` has a shared link with the given settings, using a
+    /// sync HTTP client. See [`ensure_shared_link_async`].
+    pub fn ensure_shared_link(
+        client: &impl crate::client_trait::UserAuthClient,
+        path: &str,
+        settings: &SharedLinkSettings,
+        options: &EnsureSharedLinkOptions,
+    ) -> Result<EnsureSharedLinkOutcome, EnsureSharedLinkError> {
+        super::block_on_sync(ensure(client, path, settings, options, Sleeper::Blocking))
+    }
+}
+
+/// Make sure the file or folder at `path` has a shared link with the given settings.
+///
+/// If it has no link, one is created with the settings. Otherwise, the settings which are given
+/// are compared with the existing link's, and any which differ are changed; settings which aren't
+/// given are left as they are. The `Max` and `Default` access levels, and the deprecated requested
+/// visibility, depend on things which can't be checked, so they're only used when creating a
+/// link. A password given is only sent if the link doesn't require one yet, or if
+/// [`EnsureSharedLinkOptions::with_reset_password`] is set.
+pub async fn ensure_shared_link_async(
+    client: &impl UserAuthClient,
+    path: &str,
+    settings: &SharedLinkSettings,
+    options: &EnsureSharedLinkOptions,
+) -> Result<EnsureSharedLinkOutcome, EnsureSharedLinkError> {
+    ensure(client, path, settings, options, Sleeper::Async).await
+}
+
+async fn ensure(
+    client: &impl UserAuthClient,
+    path: &str,
+    settings: &SharedLinkSettings,
+    options: &EnsureSharedLinkOptions,
+    sleeper: Sleeper,
+) -> Result<EnsureSharedLinkOutcome, EnsureSharedLinkError> {
+    let create_arg =
+        CreateSharedLinkWithSettingsArg::new(path.to_owned()).with_settings(settings.clone());
+    let mut fresh = true;
+    let existing = loop {
+        let error = match with_retry(options.max_retries, sleeper, || {
+            routes::sharing::create_shared_link_with_settings(client, &create_arg)
+        })
+        .await
+        {
+            Ok(metadata) => return Ok(EnsureSharedLinkOutcome::Created(metadata)),
+            Err(Error::Api(CreateSharedLinkWithSettingsError::SharedLinkAlreadyExists(Some(
+                SharedLinkAlreadyExistsMetadata::Metadata(metadata),
+            )))) => break metadata,
+            Err(
+                error @ Error::Api(CreateSharedLinkWithSettingsError::SharedLinkAlreadyExists(_)),
+            ) => error,
+            Err(source) => {
+                return Err(EnsureSharedLinkError::Create {
+                    path: path.to_owned(),
+                    source: Box::new(source),
+                });
+            }
+        };
+        // The error doesn't say what the existing link is, so look for it.
+        if let Some(metadata) = find_link(client, path, options, sleeper).await? {
+            break metadata;
+        }
+        // It was removed in the meantime; try once more to create one.
+        if !fresh {
+            return Err(EnsureSharedLinkError::Create {
+                path: path.to_owned(),
+                source: Box::new(error),
+            });
+        }
+        fresh = false;
+    };
+
+    let (url, permissions, expires) = match &existing {
+        SharedLinkMetadata::File(file) => (&file.url, &file.link_permissions, &file.expires),
+        SharedLinkMetadata::Folder(folder) => {
+            (&folder.url, &folder.link_permissions, &folder.expires)
+        }
+        SharedLinkMetadata::Other => {
+            return Err(EnsureSharedLinkError::UnknownLinkType(path.to_owned()));
+        }
+    };
+    let (update, changes, remove_expiration) =
+        differences(settings, permissions, expires.as_deref(), options);
+    if changes.is_empty() {
+        return Ok(EnsureSharedLinkOutcome::Unchanged(existing));
+    }
+
+    debug!("changing {changes:?} of the shared link to {path}");
+    let modify_arg = ModifySharedLinkSettingsArgs::new(url.clone(), update)
+        .with_remove_expiration(remove_expiration);
+    let metadata = with_retry(options.max_retries, sleeper, || {
+        routes::sharing::modify_shared_link_settings(client, &modify_arg)
+    })
+    .await
+    .map_err(|source| EnsureSharedLinkError::Modify {
+        url: url.clone(),
+        source,
+    })?;
+    Ok(EnsureSharedLinkOutcome::Updated { metadata, changes })
+}
+
+/// Find the link to exactly the given path, if there is one.
+async fn find_link(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &EnsureSharedLinkOptions,
+    sleeper: Sleeper,
+) -> Result<Option<SharedLinkMetadata>, EnsureSharedLinkError> {
+    let mut arg = ListSharedLinksArg::default()
+        .with_path(path.to_owned())
+        .with_direct_only(true);
+    loop {
+        let result = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_shared_links(client, &arg)
+        })
+        .await
+        .map_err(|source| EnsureSharedLinkError::List {
+            path: path.to_owned(),
+            source,
+        })?;
+        if let Some(link) = result.links.into_iter().next() {
+            return Ok(Some(link));
+        }
+        match result.cursor {
+            Some(cursor) if result.has_more => arg.cursor = Some(cursor),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Work out which of the desired settings an existing link doesn't have. Returns the settings to
+/// change, what they are, and whether to remove the expiry.
+fn differences(
+    desired: &SharedLinkSettings,
+    permissions: &LinkPermissions,
+    expires: Option<&str>,
+    options: &EnsureSharedLinkOptions,
+) -> (SharedLinkSettings, Vec<SharedLinkChange>, bool) {
+    let mut update = SharedLinkSettings::default();
+    let mut changes = Vec::new();
+    let mut remove_expiration = false;
+
+    match &desired.expires {
+        Some(time) if expires != Some(time.as_str()) => {
+            update.expires = Some(time.clone());
+            changes.push(SharedLinkChange::Expiry);
+        }
+        None if options.remove_expiration && expires.is_some() => {
+            remove_expiration = true;
+            changes.push(SharedLinkChange::Expiry);
+        }
+        _ => (),
+    }
+
+    if let Some(audience) = &desired.audience {
+        // A team or shared folder policy can make the effective audience differ from the one the
+        // link asks for, and asking for it again wouldn't change that.
+        let requested = matches!(
+            (&permissions.requested_visibility, audience),
+            (Some(RequestedVisibility::Public), LinkAudience::Public)
+                | (Some(RequestedVisibility::TeamOnly), LinkAudience::Team)
+        );
+        let disallowed = permissions
+            .audience_options
+            .iter()
+            .flatten()
+            .any(|option| option.audience == *audience && !option.allowed);
+        if !requested && permissions.effective_audience.as_ref() != Some(audience) {
+            if disallowed {
+                warn!("the link's audience can't be changed to {audience:?}: a policy forbids it");
+            } else {
+                update.audience = Some(audience.clone());
+                changes.push(SharedLinkChange::Audience);
+            }
+        }
+    }
+
+    let requires_password = permissions.require_password.unwrap_or(false);
+    if let Some(require) = desired.require_password {
+        if requires_password != require {
+            update.require_password = Some(require);
+            changes.push(SharedLinkChange::RequirePassword);
+        }
+    }
+
+    if let Some(password) = &desired.link_password {
+        // Whether the existing password matches can't be checked.
+        if options.reset_password || !requires_password {
+            update.link_password = Some(password.clone());
+            changes.push(SharedLinkChange::Password);
+        }
+    }
+
+    let access_differs = match (&desired.access, &permissions.link_access_level) {
+        (Some(RequestedLinkAccessLevel::Viewer), Some(LinkAccessLevel::Viewer))
+        | (Some(RequestedLinkAccessLevel::Editor), Some(LinkAccessLevel::Editor)) => false,
+        (Some(RequestedLinkAccessLevel::Viewer | RequestedLinkAccessLevel::Editor), _) => true,
+        // Nothing asked for, or a level which depends on the caller's own access.
+        _ => false,
+    };
+    if access_differs {
+        update.access = desired.access.clone();
+        changes.push(SharedLinkChange::Access);
+    }
+
+    if let Some(allow) = desired.allow_download {
+        if permissions.allow_download != allow {
+            update.allow_download = Some(allow);
+            changes.push(SharedLinkChange::AllowDownload);
+        }
+    }
+
+    (update, changes, remove_expiration)
+}
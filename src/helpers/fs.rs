@@ -0,0 +1,589 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! A file system-like interface to the files in a user's Dropbox.
+//!
+//! [`DropboxFs`] and [`AsyncDropboxFs`] have the familiar operations of [`std::fs`] (listing
+//! folders, reading and writing files, renaming, copying and deleting) mapped onto the routes of
+//! the `files` namespace, and report errors as [`io::Error`]s with the closest matching
+//! [`io::ErrorKind`], so that code written against a local file system can work with Dropbox with
+//! few changes. The original API error is kept as the inner error.
+//!
+//! A few things behave differently from a local file system:
+//!  * Paths are Dropbox paths, like `/Photos/beach.jpg`, or IDs. See
+//!    [`DropboxPath`](super::path::DropboxPath).
+//!  * Renaming or copying onto an existing file fails instead of replacing it.
+//!  * A file which is being written isn't visible until the writer is closed, and then its whole
+//!    content appears at once.
+
+use super::path::DropboxPath;
+use super::remote_file::{AsyncRemoteFile, RemoteFileOptions};
+use super::writer::{AsyncDropboxWriter, WriterOptions};
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, CreateFolderArg, CreateFolderError, DeleteArg, DeleteError, DownloadError,
+    GetMetadataArg, GetMetadataError, ListFolderArg, ListFolderContinueArg,
+    ListFolderContinueError, ListFolderError, LookupError, Metadata, RelocationArg,
+    RelocationError, UploadError, UploadSessionAppendError, UploadSessionFinishError,
+    UploadSessionStartError, WriteConflictError, WriteError, WriteMode,
+};
+use std::io;
+use std::sync::Arc;
+
+/// Options for a [`DropboxFs`] or [`AsyncDropboxFs`].
+#[derive(Debug, Clone)]
+pub struct FsOptions {
+    max_retries: u32,
+    remote_file: RemoteFileOptions,
+}
+
+impl Default for FsOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            remote_file: RemoteFileOptions::default(),
+        }
+    }
+}
+
+impl FsOptions {
+    /// How many times to retry a request which fails with a transient error. Rate-limited
+    /// requests are always retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// How files opened for reading are fetched and cached. See [`RemoteFileOptions`].
+    pub fn with_remote_file_options(mut self, value: RemoteFileOptions) -> Self {
+        self.remote_file = value;
+        self
+    }
+
+    fn writer(&self) -> WriterOptions {
+        WriterOptions::default().with_max_retries(self.max_retries)
+    }
+}
+
+/// The [`io::ErrorKind`] which best matches an API error.
+pub(super) trait ErrorKind {
+    fn kind(&self) -> io::ErrorKind;
+}
+
+impl ErrorKind for LookupError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            LookupError::MalformedPath(_) => io::ErrorKind::InvalidInput,
+            LookupError::NotFound => io::ErrorKind::NotFound,
+            LookupError::NotFile => io::ErrorKind::IsADirectory,
+            LookupError::NotFolder => io::ErrorKind::NotADirectory,
+            LookupError::RestrictedContent => io::ErrorKind::PermissionDenied,
+            LookupError::UnsupportedContentType => io::ErrorKind::Unsupported,
+            LookupError::Locked => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for WriteError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            WriteError::MalformedPath(_) => io::ErrorKind::InvalidInput,
+            WriteError::Conflict(WriteConflictError::FileAncestor) => io::ErrorKind::NotADirectory,
+            WriteError::Conflict(_) => io::ErrorKind::AlreadyExists,
+            WriteError::NoWritePermission
+            | WriteError::TeamFolder
+            | WriteError::AccessRestricted => io::ErrorKind::PermissionDenied,
+            WriteError::InsufficientSpace => io::ErrorKind::StorageFull,
+            WriteError::DisallowedName => io::ErrorKind::InvalidInput,
+            WriteError::TooManyWriteOperations => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for DownloadError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            DownloadError::Path(e) => e.kind(),
+            DownloadError::UnsupportedFile => io::ErrorKind::Unsupported,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for GetMetadataError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            GetMetadataError::Path(e) => e.kind(),
+        }
+    }
+}
+
+impl ErrorKind for ListFolderError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            ListFolderError::Path(e) => e.kind(),
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for ListFolderContinueError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            ListFolderContinueError::Path(e) => e.kind(),
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for CreateFolderError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            CreateFolderError::Path(e) => e.kind(),
+        }
+    }
+}
+
+impl ErrorKind for DeleteError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            DeleteError::PathLookup(e) => e.kind(),
+            DeleteError::PathWrite(e) => e.kind(),
+            DeleteError::TooManyWriteOperations => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for RelocationError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            RelocationError::FromLookup(e) => e.kind(),
+            RelocationError::FromWrite(e) | RelocationError::To(e) => e.kind(),
+            RelocationError::CantMoveFolderIntoItself
+            | RelocationError::DuplicatedOrNestedPaths => io::ErrorKind::InvalidInput,
+            RelocationError::InsufficientQuota => io::ErrorKind::StorageFull,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for UploadError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            UploadError::Path(e) => e.reason.kind(),
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for UploadSessionFinishError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            UploadSessionFinishError::Path(e) => e.kind(),
+            UploadSessionFinishError::TooManyWriteOperations => io::ErrorKind::ResourceBusy,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKind for UploadSessionStartError {
+    fn kind(&self) -> io::ErrorKind {
+        io::ErrorKind::Other
+    }
+}
+
+impl ErrorKind for UploadSessionAppendError {
+    fn kind(&self) -> io::ErrorKind {
+        io::ErrorKind::Other
+    }
+}
+
+/// The [`io::ErrorKind`] which best matches an error from a route.
+pub(super) fn error_kind<E: ErrorKind>(e: &Error<E>) -> io::ErrorKind {
+    match e {
+        Error::Api(e) => e.kind(),
+        Error::BadRequest(_) => io::ErrorKind::InvalidInput,
+        Error::Authentication(_) | Error::AccessDenied(_) => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    }
+}
+
+/// Convert an error from a route into an [`io::Error`] wrapping it.
+pub(super) fn io_error<E>(e: Error<E>) -> io::Error
+where
+    E: ErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    io::Error::new(error_kind(&e), e)
+}
+
+/// How a file created for writing is committed: replacing any existing file.
+fn create_commit(path: &str) -> io::Result<CommitInfo> {
+    Ok(CommitInfo::new(parse_path(path)?).with_mode(WriteMode::Overwrite))
+}
+
+/// Check and normalize a path.
+fn parse_path(path: &str) -> io::Result<String> {
+    DropboxPath::parse(path)
+        .map(String::from)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+async fn read_dir(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<Vec<Metadata>> {
+    let arg = ListFolderArg::new(parse_path(path)?);
+    let mut result = with_retry(options.max_retries, sleeper, || {
+        routes::files::list_folder(client, &arg)
+    })
+    .await
+    .map_err(io_error)?;
+    let mut entries = std::mem::take(&mut result.entries);
+    while result.has_more {
+        let arg = ListFolderContinueArg::new(result.cursor);
+        result = with_retry(options.max_retries, sleeper, || {
+            routes::files::list_folder_continue(client, &arg)
+        })
+        .await
+        .map_err(io_error)?;
+        entries.append(&mut result.entries);
+    }
+    Ok(entries)
+}
+
+async fn metadata(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<Metadata> {
+    let arg = GetMetadataArg::new(parse_path(path)?);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::get_metadata(client, &arg)
+    })
+    .await
+    .map_err(io_error)
+}
+
+async fn rename(
+    client: &impl UserAuthClient,
+    from: &str,
+    to: &str,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<Metadata> {
+    let arg = RelocationArg::new(parse_path(from)?, parse_path(to)?);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::move_v2(client, &arg)
+    })
+    .await
+    .map(|result| result.metadata)
+    .map_err(io_error)
+}
+
+async fn copy(
+    client: &impl UserAuthClient,
+    from: &str,
+    to: &str,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<Metadata> {
+    let arg = RelocationArg::new(parse_path(from)?, parse_path(to)?);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::copy_v2(client, &arg)
+    })
+    .await
+    .map(|result| result.metadata)
+    .map_err(io_error)
+}
+
+/// Delete a file or folder, checking first that it's the expected one of the two.
+async fn remove(
+    client: &impl UserAuthClient,
+    path: &str,
+    folder: bool,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<()> {
+    match metadata(client, path, options, sleeper).await? {
+        Metadata::Folder(_) if !folder => {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{path:?} is a folder"),
+            ));
+        }
+        Metadata::File(_) if folder => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{path:?} is a file"),
+            ));
+        }
+        _ => (),
+    }
+    let arg = DeleteArg::new(parse_path(path)?);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::delete_v2(client, &arg)
+    })
+    .await
+    .map(|_| ())
+    .map_err(io_error)
+}
+
+async fn create_dir_all(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &FsOptions,
+    sleeper: Sleeper,
+) -> io::Result<()> {
+    let path = parse_path(path)?;
+    if path.is_empty() {
+        return Ok(());
+    }
+    // Creating a folder creates any missing parents as well.
+    let arg = CreateFolderArg::new(path);
+    match with_retry(options.max_retries, sleeper, || {
+        routes::files::create_folder_v2(client, &arg)
+    })
+    .await
+    {
+        Ok(_)
+        | Err(Error::Api(CreateFolderError::Path(WriteError::Conflict(
+            WriteConflictError::Folder,
+        )))) => Ok(()),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+if_feature! { "sync_routes",
+    /// A file system-like interface to a user's Dropbox, using a sync HTTP client.
+    #[derive(Debug)]
+    pub struct DropboxFs<'a, C> {
+        client: &'a C,
+        options: FsOptions,
+    }
+
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::UserAuthClient> DropboxFs<'a, C> {
+    /// Create a file system over the Dropbox of the given client's user.
+    pub fn new(client: &'a C, options: FsOptions) -> Self {
+        Self { client, options }
+    }
+
+    /// List the contents of a folder, like [`std::fs::read_dir`].
+    pub fn read_dir(&self, path: impl AsRef<str>) -> io::Result<Vec<Metadata>> {
+        super::block_on_sync(read_dir(
+            self.client,
+            path.as_ref(),
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Get the metadata of a file or folder, like [`std::fs::metadata`].
+    pub fn metadata(&self, path: impl AsRef<str>) -> io::Result<Metadata> {
+        super::block_on_sync(metadata(
+            self.client,
+            path.as_ref(),
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Open a file for reading, like [`std::fs::File::open`]. See
+    /// [`RemoteFile`](super::remote_file::RemoteFile).
+    pub fn open(&self, path: impl AsRef<str>) -> io::Result<super::remote_file::RemoteFile<'a, C>> {
+        super::remote_file::RemoteFile::open(
+            self.client,
+            &parse_path(path.as_ref())?,
+            self.options.remote_file.clone(),
+        )
+        .map_err(io_error)
+    }
+
+    /// Create a file for writing, like [`std::fs::File::create`]. If the file already exists, it's
+    /// replaced when the writer is closed.
+    pub fn create(&self, path: impl AsRef<str>) -> io::Result<super::writer::DropboxWriter<'a, C>> {
+        Ok(super::writer::DropboxWriter::new(
+            self.client,
+            create_commit(path.as_ref())?,
+            self.options.writer(),
+        ))
+    }
+
+    /// Move a file or folder, like [`std::fs::rename`], returning its new metadata.
+    pub fn rename(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> io::Result<Metadata> {
+        super::block_on_sync(rename(
+            self.client,
+            from.as_ref(),
+            to.as_ref(),
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Copy a file or folder, like [`std::fs::copy`], returning the metadata of the copy.
+    pub fn copy(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> io::Result<Metadata> {
+        super::block_on_sync(copy(
+            self.client,
+            from.as_ref(),
+            to.as_ref(),
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Delete a file, like [`std::fs::remove_file`].
+    pub fn remove_file(&self, path: impl AsRef<str>) -> io::Result<()> {
+        super::block_on_sync(remove(
+            self.client,
+            path.as_ref(),
+            false,
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Delete a folder and everything in it, like [`std::fs::remove_dir_all`].
+    pub fn remove_dir_all(&self, path: impl AsRef<str>) -> io::Result<()> {
+        super::block_on_sync(remove(
+            self.client,
+            path.as_ref(),
+            true,
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Create a folder and any missing parents, like [`std::fs::create_dir_all`].
+    pub fn create_dir_all(&self, path: impl AsRef<str>) -> io::Result<()> {
+        super::block_on_sync(create_dir_all(
+            self.client,
+            path.as_ref(),
+            &self.options,
+            Sleeper::Blocking,
+        ))
+    }
+}
+
+/// A file system-like interface to a user's Dropbox.
+pub struct AsyncDropboxFs<C> {
+    client: Arc<C>,
+    options: FsOptions,
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncDropboxFs<C> {
+    /// Create a file system over the Dropbox of the given client's user.
+    pub fn new(client: Arc<C>, options: FsOptions) -> Self {
+        Self { client, options }
+    }
+
+    /// List the contents of a folder, like [`std::fs::read_dir`].
+    pub async fn read_dir(&self, path: impl AsRef<str>) -> io::Result<Vec<Metadata>> {
+        read_dir(
+            self.client.as_ref(),
+            path.as_ref(),
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Get the metadata of a file or folder, like [`std::fs::metadata`].
+    pub async fn metadata(&self, path: impl AsRef<str>) -> io::Result<Metadata> {
+        metadata(
+            self.client.as_ref(),
+            path.as_ref(),
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Open a file for reading, like [`std::fs::File::open`]. See [`AsyncRemoteFile`].
+    pub async fn open(&self, path: impl AsRef<str>) -> io::Result<AsyncRemoteFile<C>> {
+        AsyncRemoteFile::open(
+            Arc::clone(&self.client),
+            &parse_path(path.as_ref())?,
+            self.options.remote_file.clone(),
+        )
+        .await
+        .map_err(io_error)
+    }
+
+    /// Create a file for writing, like [`std::fs::File::create`]. If the file already exists, it's
+    /// replaced when the writer is closed.
+    pub fn create(&self, path: impl AsRef<str>) -> io::Result<AsyncDropboxWriter<C>> {
+        Ok(AsyncDropboxWriter::new(
+            Arc::clone(&self.client),
+            create_commit(path.as_ref())?,
+            self.options.writer(),
+        ))
+    }
+
+    /// Move a file or folder, like [`std::fs::rename`], returning its new metadata.
+    pub async fn rename(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> io::Result<Metadata> {
+        rename(
+            self.client.as_ref(),
+            from.as_ref(),
+            to.as_ref(),
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Copy a file or folder, like [`std::fs::copy`], returning the metadata of the copy.
+    pub async fn copy(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> io::Result<Metadata> {
+        copy(
+            self.client.as_ref(),
+            from.as_ref(),
+            to.as_ref(),
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Delete a file, like [`std::fs::remove_file`].
+    pub async fn remove_file(&self, path: impl AsRef<str>) -> io::Result<()> {
+        remove(
+            self.client.as_ref(),
+            path.as_ref(),
+            false,
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Delete a folder and everything in it, like [`std::fs::remove_dir_all`].
+    pub async fn remove_dir_all(&self, path: impl AsRef<str>) -> io::Result<()> {
+        remove(
+            self.client.as_ref(),
+            path.as_ref(),
+            true,
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Create a folder and any missing parents, like [`std::fs::create_dir_all`].
+    pub async fn create_dir_all(&self, path: impl AsRef<str>) -> io::Result<()> {
+        create_dir_all(
+            self.client.as_ref(),
+            path.as_ref(),
+            &self.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+}
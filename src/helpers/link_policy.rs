@@ -0,0 +1,644 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Finding and fixing risky shared links across a team.
+//!
+//! Using a team client, this goes through the shared links of every active member of the team,
+//! acting as each of them, and checks each link against a [`LinkPolicy`]: whether it's public,
+//! whether it has no expiry, and whether it's stale. Each kind of risk has its own fix: restricting
+//! the link to the team, making it expire, or revoking it. Like [restoring](super::restore), this
+//! happens in two steps:
+//!
+//! 1. [`plan_link_policy`] lists the members and their links. Nothing is changed, so the
+//!    [`LinkPolicyPlan`] can be reviewed first, as a dry run; it displays as one line per link
+//!    which needs fixing.
+//! 2. [`enforce_link_policy`] fixes the links as planned, and reports what happened to each of
+//!    them, with the time it was done, as an audit log.
+//!
+//! The API doesn't say when a link was created or last used, so a link is stale when the file it
+//! links to hasn't been modified for longer than the given age, however recently the link was
+//! made. Links to folders are never stale.
+
+use super::member::AsyncMemberClient;
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::{TeamAuthClient, UserAuthClient};
+use crate::client_trait_common::TeamSelect;
+use crate::types::sharing::{
+    LinkAudience, ListSharedLinksArg, ListSharedLinksError, ModifySharedLinkSettingsArgs,
+    ModifySharedLinkSettingsError, ResolvedVisibility, RevokeSharedLinkArg, RevokeSharedLinkError,
+    SharedLinkMetadata, SharedLinkSettings,
+};
+use crate::types::team::{
+    MembersListArg, MembersListContinueArg, MembersListContinueError, MembersListError,
+    TeamMemberStatus,
+};
+use futures::{StreamExt, TryStreamExt};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// A function called with the report of each action as it's finished.
+type ProgressFn = dyn Fn(&LinkActionReport) + Send + Sync;
+
+/// Options controlling how [`plan_link_policy`] and [`enforce_link_policy`] work.
+#[derive(Clone)]
+pub struct LinkPolicyOptions {
+    parallelism: usize,
+    max_retries: u32,
+    progress: Option<Arc<ProgressFn>>,
+}
+
+impl Default for LinkPolicyOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            max_retries: 3,
+            progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for LinkPolicyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkPolicyOptions")
+            .field("parallelism", &self.parallelism)
+            .field("max_retries", &self.max_retries)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl LinkPolicyOptions {
+    /// How many members' links to list, or links to fix, at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function to call with the report of each action as [`enforce_link_policy`] finishes it,
+    /// for example to write an audit log as it goes.
+    pub fn with_progress(mut self, f: impl Fn(&LinkActionReport) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    fn report(
+        &self,
+        finding: LinkFinding,
+        outcome: Result<(), LinkActionError>,
+    ) -> LinkActionReport {
+        let report = LinkActionReport {
+            time: super::dropbox_timestamp(SystemTime::now()),
+            finding,
+            outcome,
+        };
+        if let Some(progress) = &self.progress {
+            progress(&report);
+        }
+        report
+    }
+}
+
+/// How to fix a link which breaks a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFix {
+    /// Only let members of the team use the link.
+    RestrictToTeam,
+
+    /// Make the link expire this long after the policy is planned, unless it expires sooner
+    /// already.
+    Expire(Duration),
+
+    /// Revoke the link.
+    Revoke,
+}
+
+/// Which links are risky, and how to fix them. No rules are set by default.
+#[derive(Debug, Clone, Default)]
+pub struct LinkPolicy {
+    public: Option<LinkFix>,
+    no_expiry: Option<LinkFix>,
+    unmodified: Option<(Duration, LinkFix)>,
+}
+
+impl LinkPolicy {
+    /// Fix links which anyone can use.
+    pub fn with_public_links(mut self, fix: LinkFix) -> Self {
+        self.public = Some(fix);
+        self
+    }
+
+    /// Fix links which never expire.
+    pub fn with_links_without_expiry(mut self, fix: LinkFix) -> Self {
+        self.no_expiry = Some(fix);
+        self
+    }
+
+    /// Fix links to files which haven't been modified for longer than `max_age`, which are
+    /// reported as [`LinkRisk::Stale`]. This goes by the file's modification time, not the link's
+    /// age, which the API doesn't give, so a link made yesterday to an old file is stale too.
+    /// Links to folders are never stale.
+    pub fn with_links_to_unmodified_files(mut self, max_age: Duration, fix: LinkFix) -> Self {
+        self.unmodified = Some((max_age, fix));
+        self
+    }
+}
+
+/// A rule which a link breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkRisk {
+    /// Anyone can use the link.
+    Public,
+
+    /// The link never expires.
+    NoExpiry,
+
+    /// The link is to a file which hasn't been modified for longer than the age given to
+    /// [`LinkPolicy::with_links_to_unmodified_files`], going by the file's server modification
+    /// time. When the link was made or last used isn't known, so this says nothing about the link
+    /// itself: a new link to an old file is stale, and an old link to a recently modified file
+    /// isn't. Links to folders are never stale.
+    Stale,
+}
+
+impl fmt::Display for LinkRisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Public => "public",
+            Self::NoExpiry => "no expiry",
+            Self::Stale => "stale",
+        })
+    }
+}
+
+/// What to do to a link which breaks the policy. When a link breaks several rules, their fixes
+/// are combined: revoking it if any rule says to, otherwise making all the changes at once, with
+/// the soonest expiry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAction {
+    /// Change the link's settings.
+    Modify {
+        /// Whether to restrict the link to members of the team.
+        restrict_to_team: bool,
+        /// When to make the link expire, as a Dropbox timestamp.
+        expires: Option<String>,
+    },
+
+    /// Revoke the link.
+    Revoke,
+}
+
+impl fmt::Display for LinkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Modify {
+                restrict_to_team,
+                expires,
+            } => {
+                if *restrict_to_team {
+                    f.write_str("restrict to team")?;
+                }
+                if let Some(expires) = expires {
+                    let and = if *restrict_to_team { " and " } else { "" };
+                    write!(f, "{and}expire at {expires}")?;
+                }
+                Ok(())
+            }
+            Self::Revoke => f.write_str("revoke"),
+        }
+    }
+}
+
+/// A link which breaks the policy.
+#[derive(Debug, Clone)]
+pub struct LinkFinding {
+    /// The team member ID of the member who owns the link.
+    pub team_member_id: String,
+
+    /// The member's email address.
+    pub email: String,
+
+    /// The link's URL.
+    pub url: String,
+
+    /// The lowercased path of what the link is to, if the member can see it.
+    pub path: Option<String>,
+
+    /// The rules the link breaks.
+    pub risks: Vec<LinkRisk>,
+
+    /// How it will be fixed.
+    pub action: LinkAction,
+}
+
+impl fmt::Display for LinkFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (", self.email, self.url)?;
+        for (i, risk) in self.risks.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{sep}{risk}")?;
+        }
+        write!(f, "): {}", self.action)
+    }
+}
+
+/// Every link which breaks the policy, as found by [`plan_link_policy`].
+#[derive(Debug, Clone, Default)]
+pub struct LinkPolicyPlan {
+    /// The links, grouped by member, in the order the team's members are listed. Links which
+    /// break rules but which the rules' fixes wouldn't change, such as a link which already
+    /// expires sooner, are left out.
+    pub findings: Vec<LinkFinding>,
+}
+
+impl fmt::Display for LinkPolicyPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What happened to a single link.
+#[derive(Debug)]
+pub struct LinkActionReport {
+    /// When the action finished, as a Dropbox timestamp.
+    pub time: String,
+
+    /// The link and what was done to it.
+    pub finding: LinkFinding,
+
+    /// Whether it was done.
+    pub outcome: Result<(), LinkActionError>,
+}
+
+impl fmt::Display for LinkActionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}: {}",
+            self.time, self.finding.email, self.finding.url, self.finding.action
+        )?;
+        match &self.outcome {
+            Ok(()) => f.write_str(": done"),
+            Err(e) => write!(f, ": failed: {e}"),
+        }
+    }
+}
+
+/// The outcome of enforcing a link policy.
+#[derive(Debug, Default)]
+pub struct LinkPolicyReport {
+    /// What happened to each link, in the same order as the plan.
+    pub actions: Vec<LinkActionReport>,
+}
+
+impl LinkPolicyReport {
+    /// The actions which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &LinkActionReport> {
+        self.actions.iter().filter(|report| report.outcome.is_err())
+    }
+
+    /// Write the report as an audit log of JSON objects, one per line, with the time, member,
+    /// link, risks, action and outcome of each action.
+    pub fn write_audit_log(&self, mut writer: impl Write) -> io::Result<()> {
+        for report in &self.actions {
+            let finding = &report.finding;
+            let record = serde_json::json!({
+                "time": report.time,
+                "team_member_id": finding.team_member_id,
+                "email": finding.email,
+                "url": finding.url,
+                "path": finding.path,
+                "risks": finding.risks.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "action": finding.action.to_string(),
+                "error": report.outcome.as_ref().err().map(ToString::to_string),
+            });
+            serde_json::to_writer(&mut writer, &record).map_err(io::Error::from)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// An error which stopped a link policy from being planned.
+#[derive(thiserror::Error, Debug)]
+pub enum LinkPolicyPlanError {
+    /// Listing the team's members failed.
+    #[error("error listing team members: {0}")]
+    ListMembers(#[source] Error<MembersListError>),
+
+    /// Listing more of the team's members failed.
+    #[error("error listing team members: {0}")]
+    ListMembersContinue(#[source] Error<MembersListContinueError>),
+
+    /// Listing a member's shared links failed.
+    #[error("error listing shared links of team member {team_member_id}: {source}")]
+    ListLinks {
+        /// The member's team member ID.
+        team_member_id: String,
+        /// The error.
+        #[source]
+        source: Error<ListSharedLinksError>,
+    },
+}
+
+/// An error fixing a single link.
+#[derive(thiserror::Error, Debug)]
+pub enum LinkActionError {
+    /// Changing the link's settings failed.
+    #[error("error changing link settings: {0}")]
+    Modify(#[source] Error<ModifySharedLinkSettingsError>),
+
+    /// Revoking the link failed.
+    #[error("error revoking link: {0}")]
+    Revoke(#[source] Error<RevokeSharedLinkError>),
+}
+
+if_feature! { "sync_routes",
+    /// Find the links in a team which break a policy, using a sync HTTP client. Nothing is
+    /// changed.
+    pub fn plan_link_policy<C: crate::client_trait::TeamAuthClient>(
+        client: &C,
+        policy: &LinkPolicy,
+        options: &LinkPolicyOptions,
+    ) -> Result<LinkPolicyPlan, LinkPolicyPlanError> {
+        let sleeper = Sleeper::Blocking;
+        let now = SystemTime::now();
+        let members = super::block_on_sync(active_members(client, options, sleeper))?;
+        let mut findings = Vec::new();
+        for member_findings in super::parallel_map(members, options.parallelism, |member| {
+            let member_client =
+                super::member::MemberClient::new(client, TeamSelect::User(member.id.clone()));
+            super::block_on_sync(find(&member_client, &member, policy, now, options, sleeper))
+        }) {
+            findings.extend(member_findings?);
+        }
+        Ok(LinkPolicyPlan { findings })
+    }
+
+    /// Fix the links in a plan, using a sync HTTP client.
+    pub fn enforce_link_policy<C: crate::client_trait::TeamAuthClient>(
+        client: &C,
+        plan: LinkPolicyPlan,
+        options: &LinkPolicyOptions,
+    ) -> LinkPolicyReport {
+        let actions = super::parallel_map(plan.findings, options.parallelism, |finding| {
+            let member_client = super::member::MemberClient::new(
+                client,
+                TeamSelect::User(finding.team_member_id.clone()),
+            );
+            let outcome =
+                super::block_on_sync(apply(&member_client, &finding, options, Sleeper::Blocking));
+            options.report(finding, outcome)
+        });
+        LinkPolicyReport { actions }
+    }
+}
+
+/// Find the links in a team which break a policy. Nothing is changed.
+pub async fn plan_link_policy_async<C: TeamAuthClient + Send>(
+    client: &Arc<C>,
+    policy: &LinkPolicy,
+    options: &LinkPolicyOptions,
+) -> Result<LinkPolicyPlan, LinkPolicyPlanError> {
+    let sleeper = Sleeper::Async;
+    let now = SystemTime::now();
+    let members = active_members(client.as_ref(), options, sleeper).await?;
+    let findings: Vec<Vec<_>> = futures::stream::iter(members)
+        .map(|member| async move {
+            let member_client =
+                AsyncMemberClient::new(Arc::clone(client), TeamSelect::User(member.id.clone()));
+            find(&member_client, &member, policy, now, options, sleeper).await
+        })
+        .buffered(options.parallelism)
+        .try_collect()
+        .await?;
+    Ok(LinkPolicyPlan {
+        findings: findings.into_iter().flatten().collect(),
+    })
+}
+
+/// Fix the links in a plan.
+pub async fn enforce_link_policy_async<C: TeamAuthClient + Send>(
+    client: &Arc<C>,
+    plan: LinkPolicyPlan,
+    options: &LinkPolicyOptions,
+) -> LinkPolicyReport {
+    let actions = futures::stream::iter(plan.findings)
+        .map(|finding| async move {
+            let member_client = AsyncMemberClient::new(
+                Arc::clone(client),
+                TeamSelect::User(finding.team_member_id.clone()),
+            );
+            let outcome = apply(&member_client, &finding, options, Sleeper::Async).await;
+            options.report(finding, outcome)
+        })
+        .buffered(options.parallelism)
+        .collect()
+        .await;
+    LinkPolicyReport { actions }
+}
+
+/// An active member of the team.
+struct Member {
+    id: String,
+    email: String,
+}
+
+async fn active_members(
+    client: &impl TeamAuthClient,
+    options: &LinkPolicyOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<Member>, LinkPolicyPlanError> {
+    let arg = MembersListArg::default();
+    let mut result = with_retry(options.max_retries, sleeper, || {
+        routes::team::members_list_v2(client, &arg)
+    })
+    .await
+    .map_err(LinkPolicyPlanError::ListMembers)?;
+    let mut members = Vec::new();
+    loop {
+        // Only active members can be acted as, and only they can use their links.
+        members.extend(
+            result
+                .members
+                .into_iter()
+                .map(|member| member.profile)
+                .filter(|profile| profile.status == TeamMemberStatus::Active)
+                .map(|profile| Member {
+                    id: profile.team_member_id,
+                    email: profile.email,
+                }),
+        );
+        if !result.has_more {
+            return Ok(members);
+        }
+        let arg = MembersListContinueArg::new(result.cursor);
+        result = with_retry(options.max_retries, sleeper, || {
+            routes::team::members_list_continue_v2(client, &arg)
+        })
+        .await
+        .map_err(LinkPolicyPlanError::ListMembersContinue)?;
+    }
+}
+
+/// Find a member's links which break the policy.
+async fn find(
+    client: &impl UserAuthClient,
+    member: &Member,
+    policy: &LinkPolicy,
+    now: SystemTime,
+    options: &LinkPolicyOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<LinkFinding>, LinkPolicyPlanError> {
+    let mut arg = ListSharedLinksArg::default();
+    let mut findings = Vec::new();
+    loop {
+        let result = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_shared_links(client, &arg)
+        })
+        .await
+        .map_err(|source| LinkPolicyPlanError::ListLinks {
+            team_member_id: member.id.clone(),
+            source,
+        })?;
+        findings.extend(
+            result
+                .links
+                .into_iter()
+                .filter_map(|link| evaluate(link, member, policy, now)),
+        );
+        match result.cursor {
+            Some(cursor) if result.has_more => arg.cursor = Some(cursor),
+            _ => return Ok(findings),
+        }
+    }
+}
+
+/// Check a link against the policy, and work out how to fix it if it breaks any rules.
+fn evaluate(
+    link: SharedLinkMetadata,
+    member: &Member,
+    policy: &LinkPolicy,
+    now: SystemTime,
+) -> Option<LinkFinding> {
+    let (url, path, permissions, expires, modified) = match link {
+        SharedLinkMetadata::File(file) => (
+            file.url,
+            file.path_lower,
+            file.link_permissions,
+            file.expires,
+            Some(file.server_modified),
+        ),
+        SharedLinkMetadata::Folder(folder) => (
+            folder.url,
+            folder.path_lower,
+            folder.link_permissions,
+            folder.expires,
+            None,
+        ),
+        SharedLinkMetadata::Other => return None,
+    };
+    let public = match &permissions.effective_audience {
+        Some(audience) => *audience == LinkAudience::Public,
+        None => permissions.resolved_visibility == Some(ResolvedVisibility::Public),
+    };
+    let stale = |max_age: Duration| {
+        modified
+            .as_deref()
+            .and_then(super::parse_dropbox_timestamp)
+            .is_some_and(|modified| modified + max_age < now)
+    };
+
+    let mut risks = Vec::new();
+    let mut fixes = Vec::new();
+    if let Some(fix) = policy.public.filter(|_| public) {
+        risks.push(LinkRisk::Public);
+        fixes.push(fix);
+    }
+    if let Some(fix) = policy.no_expiry.filter(|_| expires.is_none()) {
+        risks.push(LinkRisk::NoExpiry);
+        fixes.push(fix);
+    }
+    if let Some((_, fix)) = policy.unmodified.filter(|&(max_age, _)| stale(max_age)) {
+        risks.push(LinkRisk::Stale);
+        fixes.push(fix);
+    }
+
+    let action = if fixes.contains(&LinkFix::Revoke) {
+        LinkAction::Revoke
+    } else {
+        let restrict_to_team = public && fixes.contains(&LinkFix::RestrictToTeam);
+        let expires = fixes
+            .iter()
+            .filter_map(|fix| match fix {
+                LinkFix::Expire(after) => Some(super::dropbox_timestamp(now + *after)),
+                _ => None,
+            })
+            .min()
+            // Timestamps compare as strings. Don't put off an expiry which is already sooner.
+            .filter(|time| expires.as_ref().is_none_or(|expires| time < expires));
+        if !restrict_to_team && expires.is_none() {
+            return None;
+        }
+        LinkAction::Modify {
+            restrict_to_team,
+            expires,
+        }
+    };
+    Some(LinkFinding {
+        team_member_id: member.id.clone(),
+        email: member.email.clone(),
+        url,
+        path,
+        risks,
+        action,
+    })
+}
+
+/// Fix a link, acting as the member who owns it.
+async fn apply(
+    client: &impl UserAuthClient,
+    finding: &LinkFinding,
+    options: &LinkPolicyOptions,
+    sleeper: Sleeper,
+) -> Result<(), LinkActionError> {
+    match &finding.action {
+        LinkAction::Modify {
+            restrict_to_team,
+            expires,
+        } => {
+            let mut settings = SharedLinkSettings::default();
+            if *restrict_to_team {
+                settings = settings.with_audience(LinkAudience::Team);
+            }
+            settings.expires.clone_from(expires);
+            let arg = ModifySharedLinkSettingsArgs::new(finding.url.clone(), settings);
+            with_retry(options.max_retries, sleeper, || {
+                routes::sharing::modify_shared_link_settings(client, &arg)
+            })
+            .await
+            .map(|_| ())
+            .map_err(LinkActionError::Modify)
+        }
+        LinkAction::Revoke => {
+            let arg = RevokeSharedLinkArg::new(finding.url.clone());
+            with_retry(options.max_retries, sleeper, || {
+                routes::sharing::revoke_shared_link(client, &arg)
+            })
+            .await
+            .map_err(LinkActionError::Revoke)
+        }
+    }
+}
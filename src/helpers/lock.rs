@@ -0,0 +1,464 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Holding locks on files for the duration of a scope.
+//!
+//! [`FileLockGuard`] and [`AsyncFileLockGuard`] lock one or more files using
+//! `files/lock_file_batch`, and unlock them again when they're released or dropped. Locking is all
+//! or nothing: if some of the files can't be locked, the ones that were locked are unlocked again,
+//! and the error lists which files failed and why. Optionally, files which are locked by someone
+//! else can be waited for, up to a deadline.
+//!
+//! [`get_lock_info`] finds out who holds the locks on a set of files, without changing them.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    FileLockContent, FileLockMetadata, LockFileArg, LockFileBatchArg, LockFileError,
+    LockFileResultEntry, Metadata, SingleUserLock, UnlockFileArg, UnlockFileBatchArg,
+};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type SpawnFn = dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync;
+
+/// Options controlling how files are locked by a [`FileLockGuard`] or [`AsyncFileLockGuard`].
+#[derive(Clone)]
+pub struct LockOptions {
+    wait: Option<Duration>,
+    retry_interval: Duration,
+    max_retries: u32,
+    spawner: Option<Arc<SpawnFn>>,
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self {
+            wait: None,
+            retry_interval: Duration::from_secs(5),
+            max_retries: 3,
+            spawner: None,
+        }
+    }
+}
+
+impl fmt::Debug for LockOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockOptions")
+            .field("wait", &self.wait)
+            .field("retry_interval", &self.retry_interval)
+            .field("max_retries", &self.max_retries)
+            .field("spawner", &self.spawner.is_some())
+            .finish()
+    }
+}
+
+impl LockOptions {
+    /// How long to keep trying to lock files which are locked by someone else, before giving up.
+    /// Defaults to not waiting at all.
+    pub fn with_wait(mut self, value: Duration) -> Self {
+        self.wait = Some(value);
+        self
+    }
+
+    /// How long to wait between attempts to lock files which are locked by someone else, when
+    /// [waiting](Self::with_wait) for them. Defaults to 5 seconds.
+    pub fn with_retry_interval(mut self, value: Duration) -> Self {
+        self.retry_interval = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function which runs a future in the background, such as `tokio::spawn`. An
+    /// [`AsyncFileLockGuard`] which is dropped without being released uses it to unlock its files,
+    /// since that can't be done from within `drop`. Without one, the files stay locked.
+    pub fn with_spawner(
+        mut self,
+        value: impl Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static,
+    ) -> Self {
+        self.spawner = Some(Arc::new(value));
+        self
+    }
+}
+
+/// A file which couldn't be locked or unlocked.
+#[derive(Debug, Clone)]
+pub struct LockFailure {
+    /// The path of the file, as given.
+    pub path: String,
+
+    /// Why it couldn't be locked or unlocked.
+    pub error: LockFileError,
+}
+
+impl LockFailure {
+    /// Who holds the lock on the file, if it couldn't be locked because someone else has it.
+    pub fn holder(&self) -> Option<&SingleUserLock> {
+        match &self.error {
+            LockFileError::LockConflict(conflict) => match &conflict.lock.content {
+                FileLockContent::SingleUser(lock) => Some(lock),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether trying again later might succeed.
+    fn is_temporary(&self) -> bool {
+        matches!(
+            self.error,
+            LockFileError::LockConflict(_) | LockFileError::TooManyWriteOperations
+        )
+    }
+}
+
+/// An error locking or unlocking files.
+#[derive(thiserror::Error, Debug)]
+pub enum LockError {
+    /// The request as a whole failed.
+    #[error("error locking or unlocking files: {0}")]
+    Request(#[source] Error<LockFileError>),
+
+    /// Some of the files couldn't be locked or unlocked. This is never empty.
+    #[error(
+        "{} file(s) couldn't be locked or unlocked, including {}: {}",
+        .0.len(), .0[0].path, .0[0].error
+    )]
+    Entries(Vec<LockFailure>),
+}
+
+if_feature! { "sync_routes",
+    /// Locks on a set of files, which are unlocked when the guard is dropped, using a sync HTTP
+    /// client.
+    ///
+    /// Dropping the guard ignores any errors unlocking the files, so call
+    /// [`release`](Self::release) to find out whether it succeeded.
+    pub struct FileLockGuard<'a, C: crate::client_trait::UserAuthClient> {
+        client: &'a C,
+        locks: Locks,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> fmt::Debug for FileLockGuard<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLockGuard")
+            .field("locks", &self.locks)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::UserAuthClient> FileLockGuard<'a, C> {
+    /// Lock all of the given files, or none of them.
+    pub fn acquire(
+        client: &'a C,
+        paths: Vec<String>,
+        options: LockOptions,
+    ) -> Result<Self, LockError> {
+        let metadata = super::block_on_sync(lock(client, &paths, &options, Sleeper::Blocking))?;
+        Ok(Self {
+            client,
+            locks: Locks {
+                paths,
+                metadata,
+                options,
+                released: false,
+            },
+        })
+    }
+
+    /// The paths of the locked files, as given.
+    pub fn paths(&self) -> &[String] {
+        &self.locks.paths
+    }
+
+    /// The metadata of each locked file, as of when it was locked, in the same order as the paths.
+    pub fn metadata(&self) -> &[Metadata] {
+        &self.locks.metadata
+    }
+
+    /// Unlock the files.
+    pub fn release(mut self) -> Result<(), LockError> {
+        self.locks.released = true;
+        super::block_on_sync(unlock(
+            self.client,
+            &self.locks.paths,
+            &self.locks.options,
+            Sleeper::Blocking,
+        ))
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> Drop for FileLockGuard<'_, C> {
+    fn drop(&mut self) {
+        // Unlike committing a file, unlocking is just as appropriate when unwinding from a panic.
+        if !self.locks.released {
+            if let Err(e) = super::block_on_sync(unlock(
+                self.client,
+                &self.locks.paths,
+                &self.locks.options,
+                Sleeper::Blocking,
+            )) {
+                warn!("failed to unlock files: {e}");
+            }
+        }
+    }
+}
+
+/// Locks on a set of files, which should be unlocked using [`release`](Self::release).
+///
+/// If the guard is dropped without being released, the files are unlocked in the background using
+/// the [spawner](LockOptions::with_spawner) from its options, if there is one. Otherwise, they stay
+/// locked until they're unlocked some other way.
+pub struct AsyncFileLockGuard<C: UserAuthClient + Send + Sync + 'static> {
+    client: Arc<C>,
+    locks: Locks,
+}
+
+impl<C: UserAuthClient + Send + Sync + 'static> fmt::Debug for AsyncFileLockGuard<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncFileLockGuard")
+            .field("locks", &self.locks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: UserAuthClient + Send + Sync + 'static> AsyncFileLockGuard<C> {
+    /// Lock all of the given files, or none of them.
+    pub async fn acquire(
+        client: Arc<C>,
+        paths: Vec<String>,
+        options: LockOptions,
+    ) -> Result<Self, LockError> {
+        let metadata = lock(client.as_ref(), &paths, &options, Sleeper::Async).await?;
+        Ok(Self {
+            client,
+            locks: Locks {
+                paths,
+                metadata,
+                options,
+                released: false,
+            },
+        })
+    }
+
+    /// The paths of the locked files, as given.
+    pub fn paths(&self) -> &[String] {
+        &self.locks.paths
+    }
+
+    /// The metadata of each locked file, as of when it was locked, in the same order as the paths.
+    pub fn metadata(&self) -> &[Metadata] {
+        &self.locks.metadata
+    }
+
+    /// Unlock the files.
+    pub async fn release(mut self) -> Result<(), LockError> {
+        self.locks.released = true;
+        unlock(
+            self.client.as_ref(),
+            &self.locks.paths,
+            &self.locks.options,
+            Sleeper::Async,
+        )
+        .await
+    }
+}
+
+impl<C: UserAuthClient + Send + Sync + 'static> Drop for AsyncFileLockGuard<C> {
+    fn drop(&mut self) {
+        if self.locks.released {
+            return;
+        }
+        let Some(spawner) = self.locks.options.spawner.clone() else {
+            warn!(
+                "file lock guard dropped without being released; {} file(s) are still locked",
+                self.locks.paths.len(),
+            );
+            return;
+        };
+        let client = self.client.clone();
+        let paths = std::mem::take(&mut self.locks.paths);
+        let options = self.locks.options.clone();
+        spawner(Box::pin(async move {
+            if let Err(e) = unlock(client.as_ref(), &paths, &options, Sleeper::Async).await {
+                warn!("failed to unlock files: {e}");
+            }
+        }));
+    }
+}
+
+/// What a guard holds, and whether it has been released.
+#[derive(Debug)]
+struct Locks {
+    paths: Vec<String>,
+    metadata: Vec<Metadata>,
+    options: LockOptions,
+    released: bool,
+}
+
+if_feature! { "sync_routes",
+    /// Find out whether each of the given files is locked, and by whom, using a sync HTTP client.
+    ///
+    /// The results are in the same order as the paths. A file which isn't locked has no lock info.
+    pub fn get_lock_info(
+        client: &impl crate::client_trait::UserAuthClient,
+        paths: Vec<String>,
+        options: &LockOptions,
+    ) -> Result<Vec<Result<Option<FileLockMetadata>, LockFileError>>, Error<LockFileError>> {
+        super::block_on_sync(get_lock_info_impl(client, paths, options, Sleeper::Blocking))
+    }
+}
+
+/// Find out whether each of the given files is locked, and by whom.
+///
+/// The results are in the same order as the paths. A file which isn't locked has no lock info.
+pub async fn get_lock_info_async(
+    client: &impl UserAuthClient,
+    paths: Vec<String>,
+    options: &LockOptions,
+) -> Result<Vec<Result<Option<FileLockMetadata>, LockFileError>>, Error<LockFileError>> {
+    get_lock_info_impl(client, paths, options, Sleeper::Async).await
+}
+
+async fn get_lock_info_impl(
+    client: &impl UserAuthClient,
+    paths: Vec<String>,
+    options: &LockOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<Result<Option<FileLockMetadata>, LockFileError>>, Error<LockFileError>> {
+    let count = paths.len();
+    let arg = LockFileBatchArg::new(paths.into_iter().map(LockFileArg::new).collect());
+    let entries = with_retry(options.max_retries, sleeper, || {
+        routes::files::get_file_lock_batch(client, &arg)
+    })
+    .await?
+    .entries;
+    check_count(entries.len(), count)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            LockFileResultEntry::Success(result) => Ok(match result.metadata {
+                Metadata::File(file) => file.file_lock_info,
+                _ => None,
+            }),
+            LockFileResultEntry::Failure(e) => Err(e),
+        })
+        .collect())
+}
+
+/// Lock all the files, waiting for ones locked by someone else if the options say to.
+async fn lock(
+    client: &impl UserAuthClient,
+    paths: &[String],
+    options: &LockOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<Metadata>, LockError> {
+    let deadline = options.wait.map(|wait| Instant::now() + wait);
+    let arg = LockFileBatchArg::new(paths.iter().cloned().map(LockFileArg::new).collect());
+    loop {
+        let entries = with_retry(options.max_retries, sleeper, || {
+            routes::files::lock_file_batch(client, &arg)
+        })
+        .await
+        .map_err(LockError::Request)?
+        .entries;
+        if let Err(e) = check_count(entries.len(), paths.len()) {
+            // There's no telling which files were locked.
+            let _ = unlock(client, paths, options, sleeper).await;
+            return Err(LockError::Request(e));
+        }
+
+        let mut locked = Vec::new();
+        let mut metadata = Vec::new();
+        let mut failures = Vec::new();
+        for (path, entry) in paths.iter().zip(entries) {
+            match entry {
+                LockFileResultEntry::Success(result) => {
+                    locked.push(path.clone());
+                    metadata.push(result.metadata);
+                }
+                LockFileResultEntry::Failure(error) => failures.push(LockFailure {
+                    path: path.clone(),
+                    error,
+                }),
+            }
+        }
+        if failures.is_empty() {
+            return Ok(metadata);
+        }
+
+        // Don't hold on to some of the locks while waiting for the rest: anything else locking
+        // the same files in a different order would end up waiting for us forever.
+        if !locked.is_empty() {
+            if let Err(e) = unlock(client, &locked, options, sleeper).await {
+                warn!("failed to unlock files after only some of them could be locked: {e}");
+            }
+        }
+        let can_wait =
+            deadline.is_some_and(|deadline| Instant::now() + options.retry_interval <= deadline);
+        if !can_wait || !failures.iter().all(LockFailure::is_temporary) {
+            return Err(LockError::Entries(failures));
+        }
+        debug!(
+            "{} file(s) are locked by someone else, including {}; waiting",
+            failures.len(),
+            failures[0].path,
+        );
+        sleeper.sleep(options.retry_interval).await;
+    }
+}
+
+async fn unlock(
+    client: &impl UserAuthClient,
+    paths: &[String],
+    options: &LockOptions,
+    sleeper: Sleeper,
+) -> Result<(), LockError> {
+    let arg = UnlockFileBatchArg::new(paths.iter().cloned().map(UnlockFileArg::new).collect());
+    let entries = with_retry(options.max_retries, sleeper, || {
+        routes::files::unlock_file_batch(client, &arg)
+    })
+    .await
+    .map_err(LockError::Request)?
+    .entries;
+    check_count(entries.len(), paths.len()).map_err(LockError::Request)?;
+    let failures = paths
+        .iter()
+        .zip(entries)
+        .filter_map(|(path, entry)| match entry {
+            LockFileResultEntry::Success(_) => None,
+            LockFileResultEntry::Failure(error) => Some(LockFailure {
+                path: path.clone(),
+                error,
+            }),
+        })
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(LockError::Entries(failures))
+    }
+}
+
+fn check_count(got: usize, expected: usize) -> Result<(), Error<LockFileError>> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(Error::UnexpectedResponse(format!(
+            "expected {expected} lock results, got {got}"
+        )))
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Acting as a team member using a team client.
+//!
+//! A client with team authorization can call user routes on behalf of a team member, by selecting
+//! the member's context. The default clients do this with a setter, which means one client per
+//! member. [`MemberClient`] and [`AsyncMemberClient`] instead wrap a shared team client, and are
+//! user clients for one member of the team, so a single team client can be used as several
+//! members at once, for example as both sides of a
+//! [cross-account copy](super::cross_account).
+
+use crate::Error;
+use crate::async_client_trait::{self, HttpRequestResultRaw};
+use crate::client_trait_common::TeamSelect;
+use bytes::Bytes;
+use std::future::Future;
+use std::sync::Arc;
+
+if_feature! { "sync_routes",
+    /// A sync team client acting as one member of the team.
+    #[derive(Debug)]
+    pub struct MemberClient<'a, C> {
+        client: &'a C,
+        select: TeamSelect,
+    }
+}
+
+// Not derived, so that C doesn't need to be Clone.
+#[cfg(feature = "sync_routes")]
+impl<C> Clone for MemberClient<'_, C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client,
+            select: self.select.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::TeamAuthClient> MemberClient<'a, C> {
+    /// Act as the given member of the client's team. Use [`TeamSelect::User`] for a team member's
+    /// ID, or [`TeamSelect::Admin`] for a team admin's, which also grants access to team folders.
+    pub fn new(client: &'a C, select: TeamSelect) -> Self {
+        Self { client, select }
+    }
+
+    /// The member being acted as.
+    pub fn select(&self) -> &TeamSelect {
+        &self.select
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::TeamAuthClient> crate::client_trait::HttpClient
+    for MemberClient<'_, C>
+{
+    type Request = C::Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<crate::client_trait::HttpRequestResultRaw, Error> {
+        self.client.execute(request, body)
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        self.client.new_request(url)
+    }
+
+    fn update_token(&self, old_token: Arc<String>) -> Result<bool, Error> {
+        self.client.update_token(old_token)
+    }
+
+    fn token(&self) -> Option<Arc<String>> {
+        self.client.token()
+    }
+
+    fn path_root(&self) -> Option<&str> {
+        self.client.path_root()
+    }
+
+    fn team_select(&self) -> Option<&TeamSelect> {
+        Some(&self.select)
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::TeamAuthClient> crate::client_trait::UserAuthClient
+    for MemberClient<'_, C>
+{
+}
+
+/// An async team client acting as one member of the team.
+#[derive(Debug)]
+pub struct AsyncMemberClient<C> {
+    client: Arc<C>,
+    select: TeamSelect,
+}
+
+// Not derived, so that C doesn't need to be Clone.
+impl<C> Clone for AsyncMemberClient<C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            select: self.select.clone(),
+        }
+    }
+}
+
+impl<C: async_client_trait::TeamAuthClient + Send> AsyncMemberClient<C> {
+    /// Act as the given member of the client's team. Use [`TeamSelect::User`] for a team member's
+    /// ID, or [`TeamSelect::Admin`] for a team admin's, which also grants access to team folders.
+    pub fn new(client: Arc<C>, select: TeamSelect) -> Self {
+        Self { client, select }
+    }
+
+    /// The member being acted as.
+    pub fn select(&self) -> &TeamSelect {
+        &self.select
+    }
+}
+
+impl<C: async_client_trait::TeamAuthClient + Send> async_client_trait::HttpClient
+    for AsyncMemberClient<C>
+{
+    type Request = C::Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: Bytes,
+    ) -> impl Future<Output = Result<HttpRequestResultRaw, Error>> + Send {
+        self.client.execute(request, body)
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        self.client.new_request(url)
+    }
+
+    fn update_token(
+        &self,
+        old_token: Arc<String>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send {
+        self.client.update_token(old_token)
+    }
+
+    fn token(&self) -> Option<Arc<String>> {
+        self.client.token()
+    }
+
+    fn path_root(&self) -> Option<&str> {
+        self.client.path_root()
+    }
+
+    fn team_select(&self) -> Option<&TeamSelect> {
+        Some(&self.select)
+    }
+}
+
+impl<C: async_client_trait::TeamAuthClient + Send> async_client_trait::UserAuthClient
+    for AsyncMemberClient<C>
+{
+}
@@ -0,0 +1,540 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Making a shared folder's members match a list, as configuration.
+//!
+//! Given the members a shared folder should have, and their access levels, this works out what
+//! needs to change in two steps, like [restoring](super::restore) does:
+//!
+//! 1. [`plan_folder_membership`] lists the folder's current members and compares them with the
+//!    desired ones. Nothing is changed, so the [`MembershipPlan`] can be reviewed first, as a dry
+//!    run; it displays as one line per change.
+//! 2. [`execute_folder_membership`] adds, updates and removes members as planned, waiting for each
+//!    removal to finish, and reports what happened to each of them.
+//!
+//! Only access given directly on the folder can be changed. Members who have access through a
+//! parent folder, and aren't listed, are left alone; listed ones who should have more access than
+//! they inherit are given it directly, and ones who should have less are left alone too, since
+//! access can't be lowered below what's inherited. The folder's owner is never changed.
+
+use super::{JOB_POLL_INTERVAL, Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::dbx_async::{LaunchResultBase, PollArg, PollError};
+use crate::types::sharing::{
+    AccessLevel, AddFolderMemberArg, AddFolderMemberError, AddMember, InviteeInfo,
+    ListFolderMembersArgs, ListFolderMembersContinueArg, ListFolderMembersContinueError,
+    MemberSelector, RemoveFolderMemberArg, RemoveFolderMemberError, RemoveMemberJobStatus,
+    SharedFolderAccessError, UpdateFolderMemberArg, UpdateFolderMemberError,
+};
+use std::fmt;
+use std::sync::Arc;
+
+/// A function called with the report of each change as it's finished.
+type ProgressFn = dyn Fn(&ChangeReport) + Send + Sync;
+
+/// Options controlling how [`execute_folder_membership`] makes changes.
+#[derive(Clone)]
+pub struct FolderMembershipOptions {
+    quiet: bool,
+    custom_message: Option<String>,
+    leave_a_copy: bool,
+    max_retries: u32,
+    progress: Option<Arc<ProgressFn>>,
+}
+
+impl Default for FolderMembershipOptions {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            custom_message: None,
+            leave_a_copy: false,
+            max_retries: 3,
+            progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for FolderMembershipOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FolderMembershipOptions")
+            .field("quiet", &self.quiet)
+            .field("custom_message", &self.custom_message)
+            .field("leave_a_copy", &self.leave_a_copy)
+            .field("max_retries", &self.max_retries)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl FolderMembershipOptions {
+    /// Whether to add members without notifying them. Defaults to false.
+    pub fn with_quiet(mut self, value: bool) -> Self {
+        self.quiet = value;
+        self
+    }
+
+    /// A message to include in the invitations sent to new members.
+    pub fn with_custom_message(mut self, value: impl Into<String>) -> Self {
+        self.custom_message = Some(value.into());
+        self
+    }
+
+    /// Whether removed members keep a copy of the folder's contents. Defaults to false.
+    pub fn with_leave_a_copy(mut self, value: bool) -> Self {
+        self.leave_a_copy = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function to call with the report of each change as [`execute_folder_membership`]
+    /// finishes it.
+    pub fn with_progress(mut self, f: impl Fn(&ChangeReport) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    fn report(&self, change: MembershipChange, outcome: Result<(), ChangeError>) -> ChangeReport {
+        let report = ChangeReport { change, outcome };
+        if let Some(progress) = &self.progress {
+            progress(&report);
+        }
+        report
+    }
+}
+
+/// A member a shared folder should have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderMember {
+    /// Who the member is: a user's or group's Dropbox ID, or an email address.
+    pub member: MemberSelector,
+
+    /// The access they should have.
+    pub access: AccessLevel,
+}
+
+impl FolderMember {
+    /// A member with the given access.
+    pub fn new(member: MemberSelector, access: AccessLevel) -> Self {
+        Self { member, access }
+    }
+}
+
+/// An error which stopped a membership change from being planned.
+#[derive(thiserror::Error, Debug)]
+pub enum MembershipPlanError {
+    /// Starting to list the folder's members, with `list_folder_members`, failed.
+    #[error("error listing folder members: {0}")]
+    ListMembers(#[source] Error<SharedFolderAccessError>),
+
+    /// Getting the next page of the folder's members, with `list_folder_members/continue`, failed.
+    #[error("error listing folder members: {0}")]
+    ListMembersContinue(#[source] Error<ListFolderMembersContinueError>),
+}
+
+/// An error making a single change to a folder's members.
+#[derive(thiserror::Error, Debug)]
+pub enum ChangeError {
+    /// Adding the member failed.
+    #[error("error adding member: {0}")]
+    Add(#[source] Error<AddFolderMemberError>),
+
+    /// Changing the member's access failed.
+    #[error("error changing member's access: {0}")]
+    Update(#[source] Error<UpdateFolderMemberError>),
+
+    /// Removing the member failed.
+    #[error("error removing member: {0}")]
+    Remove(#[source] Error<RemoveFolderMemberError>),
+
+    /// Checking on the member's removal failed, so it's not known whether it finished.
+    #[error("error checking on member's removal: {0}")]
+    RemoveStatus(#[source] Error<PollError>),
+}
+
+/// A change to a folder's members.
+#[derive(Debug, Clone)]
+pub enum MembershipChange {
+    /// Add a new member, or give one access directly which they only had through a parent folder.
+    Add {
+        /// Who to add.
+        member: MemberSelector,
+        /// The access to give them.
+        access: AccessLevel,
+    },
+
+    /// Change the access of a member.
+    Update {
+        /// Who to change.
+        member: MemberSelector,
+        /// Their current access.
+        from: AccessLevel,
+        /// The access to give them.
+        to: AccessLevel,
+    },
+
+    /// Remove a member who isn't listed.
+    Remove {
+        /// Who to remove.
+        member: MemberSelector,
+        /// Their current access.
+        access: AccessLevel,
+    },
+}
+
+impl MembershipChange {
+    /// The member being changed.
+    pub fn member(&self) -> &MemberSelector {
+        match self {
+            Self::Add { member, .. }
+            | Self::Update { member, .. }
+            | Self::Remove { member, .. } => member,
+        }
+    }
+}
+
+impl fmt::Display for MembershipChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = super::sharing_audit::access_level_name;
+        match self {
+            Self::Add { member, access } => {
+                write!(f, "add {} as {}", Selector(member), name(access))
+            }
+            Self::Update { member, from, to } => write!(
+                f,
+                "change {} from {} to {}",
+                Selector(member),
+                name(from),
+                name(to)
+            ),
+            Self::Remove { member, access } => {
+                write!(f, "remove {} ({})", Selector(member), name(access))
+            }
+        }
+    }
+}
+
+/// Displays a member selector as the ID or email address it holds.
+struct Selector<'a>(&'a MemberSelector);
+
+impl fmt::Display for Selector<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            MemberSelector::DropboxId(id) => f.write_str(id),
+            MemberSelector::Email(email) => f.write_str(email),
+            MemberSelector::Other => f.write_str("unknown member"),
+        }
+    }
+}
+
+/// Everything which needs changing to make a folder's members match, as worked out by
+/// [`plan_folder_membership`].
+#[derive(Debug, Clone)]
+pub struct MembershipPlan {
+    /// The folder's shared folder ID.
+    pub shared_folder_id: String,
+
+    /// The changes, with additions first, then updates, then removals. Empty if the members
+    /// already match.
+    pub changes: Vec<MembershipChange>,
+}
+
+impl fmt::Display for MembershipPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What happened to a single change.
+#[derive(Debug)]
+pub struct ChangeReport {
+    /// The change.
+    pub change: MembershipChange,
+
+    /// Whether it was made.
+    pub outcome: Result<(), ChangeError>,
+}
+
+/// The outcome of changing a folder's members.
+#[derive(Debug, Default)]
+pub struct MembershipReport {
+    /// What happened to each change, in the same order as the plan.
+    pub changes: Vec<ChangeReport>,
+}
+
+impl MembershipReport {
+    /// The changes which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &ChangeReport> {
+        self.changes.iter().filter(|report| report.outcome.is_err())
+    }
+}
+
+if_feature! { "sync_routes",
+    /// Work out what needs changing to give a shared folder the given members, using a sync HTTP
+    /// client. Nothing is changed.
+    pub fn plan_folder_membership(
+        client: &impl crate::client_trait::UserAuthClient,
+        shared_folder_id: &str,
+        desired: &[FolderMember],
+        options: &FolderMembershipOptions,
+    ) -> Result<MembershipPlan, MembershipPlanError> {
+        super::block_on_sync(plan(client, shared_folder_id, desired, options, Sleeper::Blocking))
+    }
+
+    /// Make the changes to a folder's members, using a sync HTTP client.
+    pub fn execute_folder_membership(
+        client: &impl crate::client_trait::UserAuthClient,
+        plan: MembershipPlan,
+        options: &FolderMembershipOptions,
+    ) -> MembershipReport {
+        super::block_on_sync(execute(client, plan, options, Sleeper::Blocking))
+    }
+}
+
+/// Work out what needs changing to give a shared folder the given members. Nothing is changed.
+pub async fn plan_folder_membership_async(
+    client: &impl UserAuthClient,
+    shared_folder_id: &str,
+    desired: &[FolderMember],
+    options: &FolderMembershipOptions,
+) -> Result<MembershipPlan, MembershipPlanError> {
+    plan(client, shared_folder_id, desired, options, Sleeper::Async).await
+}
+
+/// Make the changes to a folder's members.
+pub async fn execute_folder_membership_async(
+    client: &impl UserAuthClient,
+    plan: MembershipPlan,
+    options: &FolderMembershipOptions,
+) -> MembershipReport {
+    execute(client, plan, options, Sleeper::Async).await
+}
+
+/// A current member of the folder.
+struct Current {
+    /// How to refer to them in changes.
+    selector: MemberSelector,
+    /// Dropbox IDs and email addresses they can be listed by, with emails in lower case.
+    ids: Vec<String>,
+    access: AccessLevel,
+    inherited: bool,
+}
+
+impl Current {
+    fn matches(&self, member: &MemberSelector) -> bool {
+        match member {
+            MemberSelector::DropboxId(id) => self.ids.contains(id),
+            MemberSelector::Email(email) => self.ids.contains(&email.to_lowercase()),
+            MemberSelector::Other => false,
+        }
+    }
+}
+
+async fn plan(
+    client: &impl UserAuthClient,
+    shared_folder_id: &str,
+    desired: &[FolderMember],
+    options: &FolderMembershipOptions,
+    sleeper: Sleeper,
+) -> Result<MembershipPlan, MembershipPlanError> {
+    let current = current_members(client, shared_folder_id, options, sleeper).await?;
+    let mut listed = vec![false; current.len()];
+    let (mut adds, mut updates, mut removes) = (Vec::new(), Vec::new(), Vec::new());
+    for wanted in desired {
+        let found = current
+            .iter()
+            .enumerate()
+            .filter(|(_, member)| member.matches(&wanted.member))
+            .inspect(|&(i, _)| listed[i] = true)
+            .map(|(_, member)| member)
+            .collect::<Vec<_>>();
+        // A member can have both inherited and direct access; the direct access is what changes.
+        let direct = found.iter().find(|member| !member.inherited);
+        match direct {
+            Some(member) if member.access == AccessLevel::Owner => (),
+            Some(member) if member.access != wanted.access => {
+                updates.push(MembershipChange::Update {
+                    member: member.selector.clone(),
+                    from: member.access.clone(),
+                    to: wanted.access.clone(),
+                })
+            }
+            Some(_) => (),
+            // What they inherit is at least what they should have, and can't be taken away here.
+            None if found
+                .iter()
+                .any(|member| rank(&member.access) >= rank(&wanted.access)) => {}
+            None => adds.push(MembershipChange::Add {
+                member: wanted.member.clone(),
+                access: wanted.access.clone(),
+            }),
+        }
+    }
+    for (member, listed) in current.into_iter().zip(listed) {
+        if !listed && !member.inherited && member.access != AccessLevel::Owner {
+            removes.push(MembershipChange::Remove {
+                member: member.selector,
+                access: member.access,
+            });
+        }
+    }
+    adds.extend(updates);
+    adds.extend(removes);
+    Ok(MembershipPlan {
+        shared_folder_id: shared_folder_id.to_owned(),
+        changes: adds,
+    })
+}
+
+/// How much an access level allows, for comparing them.
+fn rank(access: &AccessLevel) -> u8 {
+    match access {
+        AccessLevel::Owner => 5,
+        AccessLevel::Editor => 4,
+        AccessLevel::Viewer => 3,
+        AccessLevel::ViewerNoComment => 2,
+        AccessLevel::Traverse => 1,
+        _ => 0,
+    }
+}
+
+async fn current_members(
+    client: &impl UserAuthClient,
+    shared_folder_id: &str,
+    options: &FolderMembershipOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<Current>, MembershipPlanError> {
+    let arg = ListFolderMembersArgs::new(shared_folder_id.to_owned());
+    let mut members = with_retry(options.max_retries, sleeper, || {
+        routes::sharing::list_folder_members(client, &arg)
+    })
+    .await
+    .map_err(MembershipPlanError::ListMembers)?;
+    let mut current = Vec::new();
+    loop {
+        for user in members.users {
+            let mut ids = vec![user.user.account_id.clone(), user.user.email.to_lowercase()];
+            ids.extend(user.user.team_member_id);
+            current.push(Current {
+                selector: MemberSelector::DropboxId(user.user.account_id),
+                ids,
+                access: user.access_type,
+                inherited: user.is_inherited,
+            });
+        }
+        for group in members.groups {
+            current.push(Current {
+                selector: MemberSelector::DropboxId(group.group.group_id.clone()),
+                ids: vec![group.group.group_id],
+                access: group.access_type,
+                inherited: group.is_inherited,
+            });
+        }
+        for invitee in members.invitees {
+            let InviteeInfo::Email(email) = invitee.invitee else {
+                continue;
+            };
+            let mut ids = vec![email.to_lowercase()];
+            ids.extend(invitee.user.map(|user| user.account_id));
+            current.push(Current {
+                selector: MemberSelector::Email(email),
+                ids,
+                access: invitee.access_type,
+                inherited: invitee.is_inherited,
+            });
+        }
+        let Some(cursor) = members.cursor else {
+            return Ok(current);
+        };
+        let arg = ListFolderMembersContinueArg::new(cursor);
+        members = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_folder_members_continue(client, &arg)
+        })
+        .await
+        .map_err(MembershipPlanError::ListMembersContinue)?;
+    }
+}
+
+async fn execute(
+    client: &impl UserAuthClient,
+    plan: MembershipPlan,
+    options: &FolderMembershipOptions,
+    sleeper: Sleeper,
+) -> MembershipReport {
+    // One at a time: these all change the same folder, and a failure shouldn't affect the others.
+    let mut changes = Vec::new();
+    for change in plan.changes {
+        let outcome = apply(client, &plan.shared_folder_id, &change, options, sleeper).await;
+        changes.push(options.report(change, outcome));
+    }
+    MembershipReport { changes }
+}
+
+async fn apply(
+    client: &impl UserAuthClient,
+    shared_folder_id: &str,
+    change: &MembershipChange,
+    options: &FolderMembershipOptions,
+    sleeper: Sleeper,
+) -> Result<(), ChangeError> {
+    match change {
+        MembershipChange::Add { member, access } => {
+            let member = AddMember::new(member.clone()).with_access_level(access.clone());
+            let mut arg = AddFolderMemberArg::new(shared_folder_id.to_owned(), vec![member])
+                .with_quiet(options.quiet);
+            arg.custom_message.clone_from(&options.custom_message);
+            with_retry(options.max_retries, sleeper, || {
+                routes::sharing::add_folder_member(client, &arg)
+            })
+            .await
+            .map_err(ChangeError::Add)
+        }
+        MembershipChange::Update { member, to, .. } => {
+            let arg =
+                UpdateFolderMemberArg::new(shared_folder_id.to_owned(), member.clone(), to.clone());
+            with_retry(options.max_retries, sleeper, || {
+                routes::sharing::update_folder_member(client, &arg)
+            })
+            .await
+            .map(|_| ())
+            .map_err(ChangeError::Update)
+        }
+        MembershipChange::Remove { member, .. } => {
+            let arg = RemoveFolderMemberArg::new(
+                shared_folder_id.to_owned(),
+                member.clone(),
+                options.leave_a_copy,
+            );
+            let LaunchResultBase::AsyncJobId(id) = with_retry(options.max_retries, sleeper, || {
+                routes::sharing::remove_folder_member(client, &arg)
+            })
+            .await
+            .map_err(ChangeError::Remove)?;
+            let arg = PollArg::new(id);
+            loop {
+                sleeper.sleep(JOB_POLL_INTERVAL).await;
+                match with_retry(options.max_retries, sleeper, || {
+                    routes::sharing::check_remove_member_job_status(client, &arg)
+                })
+                .await
+                .map_err(ChangeError::RemoveStatus)?
+                {
+                    RemoveMemberJobStatus::InProgress => (),
+                    RemoveMemberJobStatus::Complete(_) => return Ok(()),
+                    RemoveMemberJobStatus::Failed(e) => {
+                        return Err(ChangeError::Remove(Error::Api(e)));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Higher-level helpers built on top of the generated API routes.
+//!
+//! The routes in [`sync_routes`](crate::sync_routes) and [`async_routes`](crate::async_routes)
+//! map one-to-one onto Dropbox API endpoints. The helpers in this module combine several of them
+//! to accomplish common tasks which otherwise every app ends up reimplementing, like uploading a
+//! large file in parallel, or retrying a request that got rate-limited.
+//!
+//! Each helper is written against the async HTTP client traits. Where it makes sense, a sync
+//! counterpart is provided as well (when the `sync_routes` feature is enabled) which works with
+//! the sync HTTP clients. These follow the same convention as
+//! [`Authorization::obtain_access_token`](crate::oauth2::Authorization::obtain_access_token): the
+//! async version has an `_async` suffix, and the sync version doesn't.
+
+#[cfg(feature = "dbx_files")]
+use crate::Error;
+#[cfg(feature = "dbx_files")]
+use std::future::Future;
+#[cfg(feature = "dbx_files")]
+use std::time::{Duration, SystemTime};
+
+// The routes and the shared machinery below are only used by helpers for the files namespace and
+// those which build on it.
+#[cfg(feature = "dbx_files")]
+mod routes;
+
+pub mod content_hash;
+pub mod member;
+pub mod path;
+
+if_feature! { "dbx_files",
+    pub mod batch_upload;
+    pub mod cross_account;
+    pub mod download;
+    pub mod download_file;
+    pub mod fs;
+    pub mod lock;
+    pub mod parallel_download;
+    pub mod remote_file;
+    pub mod restore;
+    pub mod search;
+    pub mod thumbnails;
+    pub mod update;
+    pub mod upload_directory;
+    pub mod upload_file;
+    pub mod upload_session;
+    pub mod writer;
+}
+
+if_feature! { "dbx_sharing",
+    pub mod ensure_link;
+    pub mod membership;
+    pub mod shared_link;
+    pub mod sharing_audit;
+}
+
+#[cfg(all(feature = "dbx_sharing", feature = "dbx_team"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "dbx_sharing", feature = "dbx_team"))))]
+pub mod link_policy;
+
+if_feature! { "object_store", pub mod object_store; }
+
+if_feature! { "zip", pub mod zip; }
+
+/// How long to wait before retrying after a transient error, multiplied by the number of failures
+/// so far.
+#[cfg(feature = "dbx_files")]
+pub(crate) const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How long to wait between checks on the status of an asynchronous job.
+#[cfg(feature = "dbx_files")]
+pub(crate) const JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The largest file which can be uploaded in a single request. This is a Dropbox limit.
+#[cfg(feature = "dbx_files")]
+pub(crate) const MAX_SINGLE_UPLOAD: u64 = 150 * 1024 * 1024;
+
+/// How a helper should wait before retrying a request.
+///
+/// Sync helpers drive the async implementation to completion in a single poll, so they can't use a
+/// timer future and must block the thread instead.
+#[cfg(feature = "dbx_files")]
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Sleeper {
+    /// Block the current thread.
+    #[cfg_attr(not(feature = "sync_routes"), allow(dead_code))]
+    Blocking,
+
+    /// Wait using a runtime-agnostic timer future.
+    Async,
+}
+
+#[cfg(feature = "dbx_files")]
+impl Sleeper {
+    pub(crate) async fn sleep(self, duration: Duration) {
+        match self {
+            Sleeper::Blocking => std::thread::sleep(duration),
+            Sleeper::Async => futures_timer::Delay::new(duration).await,
+        }
+    }
+}
+
+/// A function which runs a blocking closure somewhere blocking is allowed, given by the caller of
+/// an async helper which reads or writes local files.
+#[cfg(feature = "dbx_files")]
+pub(crate) type BlockingSpawnFn = dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync;
+
+/// Run blocking work, such as local file I/O. Async helpers hand it to the spawner, if they were
+/// given one, and wait for the result; otherwise, and in sync helpers, it runs right here.
+#[cfg(feature = "dbx_files")]
+pub(crate) async fn run_blocking<T, E>(
+    sleeper: Sleeper,
+    spawner: Option<&BlockingSpawnFn>,
+    f: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<std::io::Error> + Send + 'static,
+{
+    let (Sleeper::Async, Some(spawner)) = (sleeper, spawner) else {
+        return f();
+    };
+    let (tx, rx) = futures::channel::oneshot::channel();
+    spawner(Box::new(move || {
+        let _ = tx.send(f());
+    }));
+    rx.await.unwrap_or_else(|_| {
+        Err(std::io::Error::other("blocking task was dropped before it finished").into())
+    })
+}
+
+/// Run a request, retrying it if it fails with a transient error: a server error or an error from
+/// the HTTP client, up to `max_retries` times. Rate-limited requests are retried after the delay
+/// the server asks for, and don't count against `max_retries`.
+#[cfg(feature = "dbx_files")]
+pub(crate) async fn with_retry<T, E, F, Fut>(
+    max_retries: u32,
+    sleeper: Sleeper,
+    f: F,
+) -> Result<T, Error<E>>
+where
+    E: std::error::Error,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error<E>>>,
+{
+    with_retry_counted(max_retries, sleeper, f).await.0
+}
+
+/// Like [`with_retry`], but also returns how many times the request was retried after a transient
+/// error. A request which was retried may have gone through on an earlier attempt, even though
+/// its response was lost.
+#[cfg(feature = "dbx_files")]
+pub(crate) async fn with_retry_counted<T, E, F, Fut>(
+    max_retries: u32,
+    sleeper: Sleeper,
+    mut f: F,
+) -> (Result<T, Error<E>>, u32)
+where
+    E: std::error::Error,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error<E>>>,
+{
+    let mut failures = 0;
+    loop {
+        match f().await {
+            Err(Error::RateLimited {
+                reason,
+                retry_after_seconds,
+            }) => {
+                // too_many_write_operations errors don't specify a delay; don't hammer the server.
+                let secs = retry_after_seconds.max(1);
+                warn!("rate-limited ({reason}), waiting {secs} seconds");
+                sleeper.sleep(Duration::from_secs(u64::from(secs))).await;
+            }
+            Err(e @ (Error::ServerError(_) | Error::HttpClient(_))) if failures < max_retries => {
+                failures += 1;
+                warn!("{e}; retrying ({failures}/{max_retries})");
+                sleeper.sleep(RETRY_BACKOFF * failures).await;
+            }
+            other => return (other, failures),
+        }
+    }
+}
+
+/// Format a time as a Dropbox timestamp, like `2025-01-31T12:34:56Z`. Dropbox timestamps have
+/// this fixed format, so they can be compared as strings. Times before 1970 are clamped to it.
+#[cfg(feature = "dbx_files")]
+pub(crate) fn dropbox_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since the epoch to a civil date. See
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parse a Dropbox timestamp, like `2025-01-31T12:34:56Z`, the inverse of [`dropbox_timestamp`].
+/// Returns `None` if it doesn't have that format, or is before 1970.
+#[cfg(feature = "dbx_files")]
+pub(crate) fn parse_dropbox_timestamp(time: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| -> Option<u64> {
+        let digits = time.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let separators = [
+        (4, b'-'),
+        (7, b'-'),
+        (10, b'T'),
+        (13, b':'),
+        (16, b':'),
+        (19, b'Z'),
+    ];
+    if time.len() != 20 || separators.iter().any(|&(i, c)| time.as_bytes()[i] != c) {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Convert a civil date to days since the epoch. See
+    // <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let yoe = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Where to write something with the given `/`-separated relative path under `dest`, or `None` if
+/// its path could refer to something outside `dest`.
+#[cfg(any(feature = "zip", feature = "dbx_sharing"))]
+pub(crate) fn local_path(dest: &std::path::Path, name: &str) -> Option<std::path::PathBuf> {
+    if name.starts_with('/') {
+        return None;
+    }
+    let mut path = dest.to_owned();
+    for component in name.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => return None,
+            // Backslashes are path separators on Windows, and colons start a drive letter or an
+            // alternate data stream, so either could escape `dest` there.
+            _ if component.contains('\\') || (cfg!(windows) && component.contains(':')) => {
+                return None;
+            }
+            _ => path.push(component),
+        }
+    }
+    Some(path)
+}
+
+/// Drive a future created from a sync HTTP client to completion.
+#[cfg(all(feature = "dbx_files", feature = "sync_routes"))]
+pub(crate) fn block_on_sync<T>(f: impl Future<Output = T>) -> T {
+    use futures::FutureExt;
+    f.now_or_never()
+        .expect("sync client future should resolve immediately")
+}
+
+/// Call `f` on each item using up to `parallelism` threads, and return the results in the same
+/// order as the items.
+#[cfg(all(feature = "dbx_files", feature = "sync_routes"))]
+pub(crate) fn parallel_map<T: Send, R: Send>(
+    items: Vec<T>,
+    parallelism: usize,
+    f: impl Fn(T) -> R + Sync,
+) -> Vec<R> {
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let len = items.len();
+    let items = items
+        .into_iter()
+        .map(|item| Mutex::new(Some(item)))
+        .collect::<Vec<_>>();
+    let results = (0..len).map(|_| Mutex::new(None)).collect::<Vec<_>>();
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        for _ in 0..parallelism.clamp(1, len.max(1)) {
+            s.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let item = item.lock().unwrap().take().unwrap();
+                    *results[i].lock().unwrap() = Some(f(item));
+                }
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
@@ -0,0 +1,698 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! An [`ObjectStore`] backed by Dropbox, so that libraries built on the `object_store` crate can
+//! read and write files in Dropbox like they do in S3 or on a local disk.
+//!
+//! [`DropboxObjectStore`] keeps objects as files under a root folder: the object
+//! `data/2024/01.parquet` in a store rooted at `/Pipelines` is the file
+//! `/Pipelines/data/2024/01.parquet`.
+//!
+//! The [`e_tag`](ObjectMeta::e_tag) of an object is the file's
+//! [content hash](super::content_hash), and its [`version`](ObjectMeta::version) is the file's
+//! revision. A version can be given to [`GetOptions::version`] to read an older revision of a
+//! file, and either one to [`PutMode::Update`] to only replace a file if it hasn't changed.
+//!
+//! A few things behave differently from other stores:
+//!  * Dropbox has real folders. They're created as needed when objects are written, but aren't
+//!    removed when the last object in them is deleted, so they can show up as empty common
+//!    prefixes in [`ObjectStore::list_with_delimiter`].
+//!  * Copying or renaming onto an existing object deletes it first, so the two steps aren't
+//!    atomic.
+//!  * Tags and attributes are ignored.
+//!  * Aborting a multipart upload doesn't make any request: the upload session is left to expire
+//!    on its own.
+//!  * A multipart upload replaces any existing object, like in other stores. To create or update
+//!    the object instead, put a [`PutMode`] in [`PutMultipartOptions::extensions`].
+
+use super::fs::{ErrorKind, error_kind};
+use super::path::{DropboxPath, PathKind};
+use super::upload_session::{BLOCK_SIZE, UploadOptions, UploadSession, UploadSessionError};
+use super::{MAX_SINGLE_UPLOAD, Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, DeleteArg, DeleteError, DownloadArg, FileMetadata, GetMetadataArg, ListFolderArg,
+    ListFolderContinueArg, ListFolderError, LookupError, Metadata, RelocationArg, RelocationError,
+    UploadArg, WriteConflictError, WriteError, WriteMode,
+};
+use ::object_store::path::Path;
+use ::object_store::{
+    Attributes, CopyMode, CopyOptions, GetOptions, GetRange, GetResult, GetResultPayload,
+    ListResult, MultipartUpload, ObjectMeta, ObjectStore, PutMode, PutMultipartOptions, PutOptions,
+    PutPayload, PutResult, RenameOptions, RenameTargetMode, Result, UpdateVersion, UploadPart,
+};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::stream::BoxStream;
+use futures::{AsyncRead, AsyncReadExt, StreamExt, TryStreamExt};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// The name of the store in errors.
+const STORE: &str = "Dropbox";
+
+/// How much of a download is read at a time.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Options for a [`DropboxObjectStore`].
+#[derive(Debug, Clone)]
+pub struct ObjectStoreOptions {
+    max_retries: u32,
+    upload: UploadOptions,
+}
+
+impl Default for ObjectStoreOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            upload: UploadOptions::default(),
+        }
+    }
+}
+
+impl ObjectStoreOptions {
+    /// How many times to retry a request which fails with a transient error. Rate-limited
+    /// requests are always retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// How objects too large for a single request, and multipart uploads, are sent through
+    /// upload sessions. See [`UploadOptions`].
+    pub fn with_upload_options(mut self, value: UploadOptions) -> Self {
+        self.upload = value;
+        self
+    }
+}
+
+/// An [`ObjectStore`] of the files under a folder in a user's Dropbox.
+pub struct DropboxObjectStore<C> {
+    client: Arc<C>,
+    root: DropboxPath,
+    options: ObjectStoreOptions,
+}
+
+impl<C> Clone for DropboxObjectStore<C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            root: self.root.clone(),
+            options: self.options.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for DropboxObjectStore<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropboxObjectStore")
+            .field("root", &self.root)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> fmt::Display for DropboxObjectStore<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dropbox({}/)", self.root)
+    }
+}
+
+impl<C: UserAuthClient + Send + Sync + 'static> DropboxObjectStore<C> {
+    /// Create a store of the files under `root`, which can be [`DropboxPath::root`] to use the
+    /// whole Dropbox. The root has to be a path rather than an ID, because the locations of
+    /// listed objects are worked out from their paths; an `id:`, `ns:` or `rev:` root is an error.
+    pub fn new(client: Arc<C>, root: DropboxPath, options: ObjectStoreOptions) -> Result<Self> {
+        if root.kind() != PathKind::Path {
+            return Err(::object_store::Error::Generic {
+                store: STORE,
+                source: format!("the root must be a path, not {root:?}").into(),
+            });
+        }
+        Ok(Self {
+            client,
+            root,
+            options,
+        })
+    }
+
+    /// The Dropbox path of an object.
+    fn path(&self, location: &Path) -> String {
+        if location.as_ref().is_empty() {
+            self.root.as_str().to_owned()
+        } else {
+            format!("{}/{location}", self.root)
+        }
+    }
+
+    /// The location of a listed file or folder, from its path.
+    fn location(&self, path_display: Option<&str>) -> Result<Path> {
+        let relative = path_display
+            .unwrap_or_default()
+            .split('/')
+            .skip(1 + self.root.segments().count())
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(Path::parse(relative)?)
+    }
+
+    /// The metadata of a file, by its Dropbox path or revision. Folders aren't objects, so they're
+    /// reported as not found.
+    async fn file_metadata(&self, location: &Path, path: String) -> Result<FileMetadata> {
+        let arg = GetMetadataArg::new(path);
+        let metadata = with_retry(self.options.max_retries, Sleeper::Async, || {
+            routes::files::get_metadata(self.client.as_ref(), &arg)
+        })
+        .await
+        .map_err(|e| store_error(location, e))?;
+        match metadata {
+            Metadata::File(file) => Ok(file),
+            _ => Err(::object_store::Error::NotFound {
+                path: location.to_string(),
+                source: "not a file".into(),
+            }),
+        }
+    }
+
+    /// The revision of the file which a conditional update should replace.
+    /// The write mode for a put mode, and whether it's an update of a given version.
+    async fn write_mode(&self, location: &Path, mode: PutMode) -> Result<(WriteMode, bool)> {
+        Ok(match mode {
+            PutMode::Overwrite => (WriteMode::Overwrite, false),
+            PutMode::Create => (WriteMode::Add, false),
+            PutMode::Update(version) => (
+                WriteMode::Update(self.update_rev(location, version).await?),
+                true,
+            ),
+        })
+    }
+
+    async fn update_rev(&self, location: &Path, version: UpdateVersion) -> Result<String> {
+        if let Some(rev) = version.version {
+            return Ok(rev);
+        }
+        let precondition = |source: String| ::object_store::Error::Precondition {
+            path: location.to_string(),
+            source: source.into(),
+        };
+        let Some(e_tag) = version.e_tag else {
+            return Err(precondition(
+                "an update needs an e_tag or a version".to_owned(),
+            ));
+        };
+        let file = match self.file_metadata(location, self.path(location)).await {
+            Ok(file) => file,
+            Err(::object_store::Error::NotFound { .. }) => {
+                return Err(precondition("object not found".to_owned()));
+            }
+            Err(e) => return Err(e),
+        };
+        match file.content_hash {
+            Some(hash) if hash == e_tag => Ok(file.rev),
+            hash => Err(precondition(format!(
+                "{e_tag} does not match {}",
+                hash.as_deref().unwrap_or("*")
+            ))),
+        }
+    }
+
+    /// List a folder, returning the first page of entries and the cursor for the next one, if
+    /// there's more. A folder which doesn't exist is listed as empty.
+    async fn list_folder(
+        &self,
+        prefix: &Path,
+        recursive: bool,
+    ) -> Result<(Vec<Metadata>, Option<String>)> {
+        let arg = ListFolderArg::new(self.path(prefix)).with_recursive(recursive);
+        match with_retry(self.options.max_retries, Sleeper::Async, || {
+            routes::files::list_folder(self.client.as_ref(), &arg)
+        })
+        .await
+        {
+            Ok(result) => Ok((result.entries, result.has_more.then_some(result.cursor))),
+            Err(Error::Api(ListFolderError::Path(
+                LookupError::NotFound | LookupError::NotFolder,
+            ))) => Ok((vec![], None)),
+            Err(e) => Err(store_error(prefix, e)),
+        }
+    }
+
+    /// Get the next page of a folder listing.
+    async fn list_folder_continue(
+        &self,
+        prefix: &Path,
+        cursor: String,
+    ) -> Result<(Vec<Metadata>, Option<String>)> {
+        let arg = ListFolderContinueArg::new(cursor);
+        let result = with_retry(self.options.max_retries, Sleeper::Async, || {
+            routes::files::list_folder_continue(self.client.as_ref(), &arg)
+        })
+        .await
+        .map_err(|e| store_error(prefix, e))?;
+        Ok((result.entries, result.has_more.then_some(result.cursor)))
+    }
+
+    /// The objects among listed entries.
+    fn objects(&self, entries: Vec<Metadata>) -> Result<Vec<ObjectMeta>> {
+        entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Metadata::File(file) => Some(file),
+                _ => None,
+            })
+            .map(|file| object_meta(self.location(file.path_display.as_deref())?, &file))
+            .collect()
+    }
+
+    /// Delete a file, checking that it's a file first, because deleting a folder would delete
+    /// everything in it. A location which isn't a file is treated as already deleted.
+    async fn delete_file(&self, location: &Path) -> Result<()> {
+        let file = match self.file_metadata(location, self.path(location)).await {
+            Ok(file) => file,
+            Err(::object_store::Error::NotFound { .. }) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let arg = DeleteArg::new(self.path(location)).with_parent_rev(file.rev);
+        match with_retry(self.options.max_retries, Sleeper::Async, || {
+            routes::files::delete_v2(self.client.as_ref(), &arg)
+        })
+        .await
+        {
+            Ok(_) | Err(Error::Api(DeleteError::PathLookup(LookupError::NotFound))) => Ok(()),
+            Err(e) => Err(store_error(location, e)),
+        }
+    }
+
+    /// Copy or move a file. The API doesn't replace an existing file at the destination, so with
+    /// `overwrite` set, one is deleted first.
+    async fn relocate(&self, from: &Path, to: &Path, overwrite: bool, keep: bool) -> Result<()> {
+        let arg = RelocationArg::new(self.path(from), self.path(to));
+        let relocate = || {
+            with_retry(self.options.max_retries, Sleeper::Async, || async {
+                if keep {
+                    routes::files::copy_v2(self.client.as_ref(), &arg).await
+                } else {
+                    routes::files::move_v2(self.client.as_ref(), &arg).await
+                }
+            })
+        };
+        match relocate().await {
+            Ok(_) => Ok(()),
+            Err(Error::Api(RelocationError::To(WriteError::Conflict(
+                WriteConflictError::File,
+            )))) if overwrite => {
+                self.delete_file(to).await?;
+                relocate()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| store_error(from, e))
+            }
+            Err(e) => Err(store_error(from, e)),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: UserAuthClient + Send + Sync + 'static> ObjectStore for DropboxObjectStore<C> {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        let (mode, update) = self.write_mode(location, opts.mode).await?;
+        let path = self.path(location);
+        let data = Bytes::from(payload);
+        let result = if data.len() as u64 <= MAX_SINGLE_UPLOAD {
+            let arg = UploadArg::new(path)
+                .with_mode(mode)
+                .with_strict_conflict(true);
+            with_retry(self.options.max_retries, Sleeper::Async, || {
+                routes::files::upload(self.client.as_ref(), &arg, data.clone())
+            })
+            .await
+            .map_err(|e| store_error(location, e))
+        } else {
+            let commit = CommitInfo::new(path)
+                .with_mode(mode)
+                .with_strict_conflict(true);
+            match UploadSession::start_async(self.client.as_ref(), self.options.upload.clone())
+                .await
+            {
+                Ok(session) => session
+                    .upload_async(self.client.as_ref(), futures::io::Cursor::new(data), commit)
+                    .await
+                    .map_err(|e| upload_error(location, e)),
+                Err(e) => Err(store_error(location, e)),
+            }
+        };
+        put_outcome(result, update)
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOptions,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let mode = opts.extensions.get::<PutMode>().cloned();
+        let (mode, update) = self
+            .write_mode(location, mode.unwrap_or(PutMode::Overwrite))
+            .await?;
+        let session = UploadSession::start_async(self.client.as_ref(), self.options.upload.clone())
+            .await
+            .map_err(|e| store_error(location, e))?;
+        Ok(Box::new(DropboxMultipartUpload {
+            client: Arc::clone(&self.client),
+            session: Arc::new(session),
+            chunk_size: self.options.upload.chunk_size(),
+            location: location.clone(),
+            path: self.path(location),
+            mode,
+            update,
+            offset: 0,
+            buffer: BytesMut::new(),
+        }))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let path = match &options.version {
+            Some(rev) => format!("rev:{rev}"),
+            None => self.path(location),
+        };
+        let range_error = |e| ::object_store::Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        };
+
+        let (file, body) = if options.head {
+            (self.file_metadata(location, path).await?, None)
+        } else {
+            let (start, end) = match &options.range {
+                None => (None, None),
+                Some(range @ GetRange::Bounded(r)) => {
+                    range.is_valid().map_err(range_error)?;
+                    (Some(r.start), Some(r.end - 1))
+                }
+                Some(GetRange::Offset(offset)) => (Some(*offset), None),
+                Some(GetRange::Suffix(len)) => (None, Some(*len)),
+            };
+            let arg = DownloadArg::new(path);
+            let response = with_retry(self.options.max_retries, Sleeper::Async, || {
+                routes::files::download(self.client.as_ref(), &arg, start, end)
+            })
+            .await
+            .map_err(|e| store_error(location, e))?;
+            let body = response
+                .body
+                .ok_or_else(|| ::object_store::Error::Generic {
+                    store: STORE,
+                    source: "download response has no body".into(),
+                })?;
+            (response.result, Some(body))
+        };
+
+        let meta = object_meta(location.clone(), &file)?;
+        options.check_preconditions(&meta)?;
+        let range = match &options.range {
+            Some(range) => range.as_range(meta.size).map_err(range_error)?,
+            None => 0..meta.size,
+        };
+        let payload = match body {
+            Some(body) => byte_stream(body),
+            None => futures::stream::empty().boxed(),
+        };
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(payload),
+            meta,
+            range,
+            attributes: Attributes::new(),
+        })
+    }
+
+    fn delete_stream(
+        &self,
+        locations: BoxStream<'static, Result<Path>>,
+    ) -> BoxStream<'static, Result<Path>> {
+        // Deletes are done one at a time, as concurrent writes to the same Dropbox tend to fail
+        // with too_many_write_operations.
+        let store = self.clone();
+        locations
+            .and_then(move |location| {
+                let store = store.clone();
+                async move {
+                    store.delete_file(&location).await?;
+                    Ok(location)
+                }
+            })
+            .boxed()
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, Result<ObjectMeta>> {
+        enum Page {
+            First,
+            Next(String),
+            Done,
+        }
+        let store = self.clone();
+        let prefix = prefix.cloned().unwrap_or_default();
+        futures::stream::try_unfold(Page::First, move |page| {
+            let store = store.clone();
+            let prefix = prefix.clone();
+            async move {
+                let (entries, cursor) = match page {
+                    Page::First => store.list_folder(&prefix, true).await?,
+                    Page::Next(cursor) => store.list_folder_continue(&prefix, cursor).await?,
+                    Page::Done => return Ok::<_, ::object_store::Error>(None),
+                };
+                let objects = store.objects(entries)?;
+                let next = cursor.map_or(Page::Done, Page::Next);
+                Ok(Some((
+                    futures::stream::iter(objects.into_iter().map(Ok)),
+                    next,
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let prefix = prefix.cloned().unwrap_or_default();
+        let (mut entries, mut cursor) = self.list_folder(&prefix, false).await?;
+        while let Some(next) = cursor {
+            let (more, next) = self.list_folder_continue(&prefix, next).await?;
+            entries.extend(more);
+            cursor = next;
+        }
+
+        let mut common_prefixes = vec![];
+        for entry in &entries {
+            if let Metadata::Folder(folder) = entry {
+                common_prefixes.push(self.location(folder.path_display.as_deref())?);
+            }
+        }
+        Ok(ListResult {
+            common_prefixes,
+            objects: self.objects(entries)?,
+        })
+    }
+
+    async fn copy_opts(&self, from: &Path, to: &Path, options: CopyOptions) -> Result<()> {
+        let overwrite = matches!(options.mode, CopyMode::Overwrite);
+        self.relocate(from, to, overwrite, true).await
+    }
+
+    async fn rename_opts(&self, from: &Path, to: &Path, options: RenameOptions) -> Result<()> {
+        let overwrite = matches!(options.target_mode, RenameTargetMode::Overwrite);
+        self.relocate(from, to, overwrite, false).await
+    }
+}
+
+/// A multipart upload through a concurrent upload session.
+///
+/// Parts can be any size, but all requests to the session except the last have to be a multiple
+/// of [`BLOCK_SIZE`], so each part sends as many whole blocks as it can, and keeps the rest for
+/// the next one. The offset of each part is fixed when it's added, so parts can be sent in any
+/// order.
+struct DropboxMultipartUpload<C> {
+    client: Arc<C>,
+    session: Arc<UploadSession>,
+    chunk_size: usize,
+    location: Path,
+    path: String,
+    /// How to commit the file, and whether that's an update of a given version.
+    mode: WriteMode,
+    update: bool,
+    offset: u64,
+    buffer: BytesMut,
+}
+
+impl<C> fmt::Debug for DropboxMultipartUpload<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropboxMultipartUpload")
+            .field("session", &self.session)
+            .field("location", &self.location)
+            .field("offset", &self.offset)
+            .field("buffered", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<C: UserAuthClient + Send + Sync + 'static> MultipartUpload for DropboxMultipartUpload<C> {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        for chunk in data.iter() {
+            self.buffer.extend_from_slice(chunk);
+        }
+        let len = self.buffer.len() / BLOCK_SIZE * BLOCK_SIZE;
+        let data = self.buffer.split_to(len).freeze();
+        let offset = self.offset;
+        self.offset += len as u64;
+
+        let client = Arc::clone(&self.client);
+        let session = Arc::clone(&self.session);
+        let chunk_size = self.chunk_size;
+        let location = self.location.clone();
+        Box::pin(async move {
+            let mut start = 0;
+            while start < data.len() {
+                let end = data.len().min(start + chunk_size);
+                session
+                    .append(
+                        client.as_ref(),
+                        offset + start as u64,
+                        data.slice(start..end),
+                        false,
+                        Sleeper::Async,
+                    )
+                    .await
+                    .map_err(|e| store_error(&location, e))?;
+                start = end;
+            }
+            Ok(())
+        })
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let data = std::mem::take(&mut self.buffer).freeze();
+        let commit = CommitInfo::new(self.path.clone())
+            .with_mode(self.mode.clone())
+            .with_strict_conflict(true);
+        let result = self
+            .session
+            .finish(
+                self.client.as_ref(),
+                self.offset,
+                data,
+                commit,
+                None,
+                Sleeper::Async,
+            )
+            .await
+            .map_err(|e| upload_error(&self.location, e));
+        put_outcome(result, self.update)
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The metadata of a file as an object.
+fn object_meta(location: Path, file: &FileMetadata) -> Result<ObjectMeta> {
+    let last_modified = chrono::DateTime::parse_from_rfc3339(&file.server_modified)
+        .map_err(|e| ::object_store::Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        })?
+        .to_utc();
+    Ok(ObjectMeta {
+        location,
+        last_modified,
+        size: file.size,
+        e_tag: file.content_hash.clone(),
+        version: Some(file.rev.clone()),
+    })
+}
+
+fn put_result(file: &FileMetadata) -> PutResult {
+    PutResult {
+        e_tag: file.content_hash.clone(),
+        version: Some(file.rev.clone()),
+    }
+}
+
+/// Convert an error from a route into the closest matching store error.
+fn store_error<E>(location: &Path, e: Error<E>) -> ::object_store::Error
+where
+    E: ErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    let kind = error_kind(&e);
+    let path = location.to_string();
+    match (kind, e) {
+        (_, e @ Error::Authentication(_)) => ::object_store::Error::Unauthenticated {
+            path,
+            source: Box::new(e),
+        },
+        (io::ErrorKind::NotFound, e) => ::object_store::Error::NotFound {
+            path,
+            source: Box::new(e),
+        },
+        (io::ErrorKind::AlreadyExists, e) => ::object_store::Error::AlreadyExists {
+            path,
+            source: Box::new(e),
+        },
+        (io::ErrorKind::PermissionDenied, e) => ::object_store::Error::PermissionDenied {
+            path,
+            source: Box::new(e),
+        },
+        (_, e) => ::object_store::Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        },
+    }
+}
+
+/// The result of a put, given the uploaded file or the error, and whether it was an update.
+fn put_outcome(result: Result<FileMetadata>, update: bool) -> Result<PutResult> {
+    match result {
+        Ok(file) => Ok(put_result(&file)),
+        // A conflict with an update means the file was changed since the given version.
+        Err(::object_store::Error::AlreadyExists { path, source }) if update => {
+            Err(::object_store::Error::Precondition { path, source })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn upload_error(location: &Path, e: UploadSessionError) -> ::object_store::Error {
+    match e {
+        UploadSessionError::Append(e) => store_error(location, e),
+        UploadSessionError::Finish(e) => store_error(location, e),
+        e @ UploadSessionError::Io(_) => ::object_store::Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        },
+    }
+}
+
+/// Read a download as a stream of chunks.
+fn byte_stream(body: Box<dyn AsyncRead + Unpin + Send>) -> BoxStream<'static, Result<Bytes>> {
+    futures::stream::try_unfold(body, |mut body| async move {
+        let mut buf = vec![0; READ_SIZE];
+        let len = body
+            .read(&mut buf)
+            .await
+            .map_err(|e| ::object_store::Error::Generic {
+                store: STORE,
+                source: Box::new(e),
+            })?;
+        if len == 0 {
+            return Ok(None);
+        }
+        buf.truncate(len);
+        Ok(Some((Bytes::from(buf), body)))
+    })
+    .boxed()
+}
@@ -0,0 +1,340 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Downloading a large file over several connections at once.
+//!
+//! A single connection is often limited to far less than the available bandwidth, particularly
+//! over high-latency links. [`download_parallel`] splits a file into ranges and downloads them
+//! concurrently, writing each one directly into place in a local file. All the ranges are pinned
+//! to the same revision of the file, and each one reconnects if it gets interrupted, as described
+//! in [`download`](super::download). When it's done, the file's content hash is checked.
+
+use super::content_hash::{HASH_BLOCK_SIZE, block_hash, combine_block_hashes};
+use super::download::{AsyncResumableDownload, DownloadOptions};
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    DownloadArg, DownloadError, FileMetadata, GetMetadataArg, GetMetadataError, Metadata,
+};
+use futures::{AsyncReadExt, StreamExt, TryStreamExt};
+use ring::digest::Digest;
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Options controlling how [`download_parallel`] downloads a file.
+#[derive(Clone)]
+pub struct ParallelDownloadOptions {
+    parallelism: usize,
+    blocks_per_range: usize,
+    verify: bool,
+    download: DownloadOptions,
+    progress: Option<Arc<dyn Fn(u64) + Send + Sync>>,
+}
+
+impl Default for ParallelDownloadOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 8,
+            blocks_per_range: 4,
+            verify: true,
+            download: DownloadOptions::default(),
+            progress: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ParallelDownloadOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParallelDownloadOptions")
+            .field("parallelism", &self.parallelism)
+            .field("blocks_per_range", &self.blocks_per_range)
+            .field("verify", &self.verify)
+            .field("download", &self.download)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl ParallelDownloadOptions {
+    /// How many ranges to download at the same time. Each one holds a buffer of one
+    /// [`HASH_BLOCK_SIZE`] block in memory. Defaults to 8.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many [`HASH_BLOCK_SIZE`] blocks to download in each range. Defaults to 4.
+    pub fn with_blocks_per_range(mut self, value: usize) -> Self {
+        self.blocks_per_range = value.max(1);
+        self
+    }
+
+    /// Whether to check the content hash of the downloaded file against the one in its metadata.
+    /// Defaults to true.
+    pub fn with_verify(mut self, value: bool) -> Self {
+        self.verify = value;
+        self
+    }
+
+    /// How each range should be retried if it fails. See [`DownloadOptions`].
+    pub fn with_download_options(mut self, value: DownloadOptions) -> Self {
+        self.download = value;
+        self
+    }
+
+    /// A function to call whenever a block has been written to the file, with the total number of
+    /// bytes written so far. Because ranges are downloaded in parallel, this isn't the offset of a
+    /// contiguous prefix of the file.
+    pub fn with_progress(mut self, f: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    fn range_size(&self) -> u64 {
+        (HASH_BLOCK_SIZE * self.blocks_per_range) as u64
+    }
+}
+
+/// An error that occurred while downloading a file in parallel.
+///
+/// The content of the local file is unspecified when this is returned.
+#[derive(thiserror::Error, Debug)]
+pub enum ParallelDownloadError {
+    /// Looking up the file's metadata failed.
+    #[error("error getting file metadata: {0}")]
+    Metadata(#[source] Error<GetMetadataError>),
+
+    /// The path doesn't refer to a file.
+    #[error("path is not a file")]
+    NotAFile,
+
+    /// Starting the download of a range failed, even after retrying.
+    #[error("error downloading file: {0}")]
+    Download(#[source] Error<DownloadError>),
+
+    /// Reading the download, or writing to the local file, failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The downloaded content doesn't match the file's content hash.
+    #[error("content hash mismatch: expected {expected}, got {actual}")]
+    HashMismatch {
+        /// The content hash from the file's metadata.
+        expected: String,
+        /// The content hash of the data which was downloaded.
+        actual: String,
+    },
+}
+
+if_feature! { "sync_routes",
+    /// Download a file over several connections at once into the given local file, using a sync
+    /// HTTP client. Returns the metadata of the file which was downloaded.
+    ///
+    /// The local file is truncated or extended to the size of the remote file, and must be open
+    /// for writing.
+    pub fn download_parallel(
+        client: &impl crate::client_trait::UserAuthClient,
+        path: &str,
+        file: &File,
+        options: &ParallelDownloadOptions,
+    ) -> Result<FileMetadata, ParallelDownloadError> {
+        use std::io::Read;
+
+        let metadata = super::block_on_sync(get_file_metadata(
+            client,
+            path,
+            options,
+            Sleeper::Blocking,
+        ))?;
+        let target = Target::new(file, &metadata, options)?;
+        let hashes = super::parallel_map(target.ranges(), options.parallelism, |(start, end)| {
+            let mut reader = super::download::ResumableDownload::new(
+                client,
+                &target.arg,
+                Some(start),
+                Some(end - 1),
+                options.download.clone(),
+            )
+            .map_err(ParallelDownloadError::Download)?;
+            let mut block = vec![0; HASH_BLOCK_SIZE];
+            let mut hashes = vec![];
+            for (offset, len) in blocks(start, end) {
+                reader.read_exact(&mut block[..len])?;
+                hashes.push(target.write_block(offset, &block[..len])?);
+            }
+            Ok(hashes)
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, ParallelDownloadError>>()?;
+        target.finish(metadata, hashes)
+    }
+}
+
+/// Download a file over several connections at once into the given local file. Returns the
+/// metadata of the file which was downloaded.
+///
+/// The local file is truncated or extended to the size of the remote file, and must be open for
+/// writing. Note that writes to it are done synchronously.
+pub async fn download_parallel_async<C: UserAuthClient + Send + 'static>(
+    client: Arc<C>,
+    path: &str,
+    file: &File,
+    options: &ParallelDownloadOptions,
+) -> Result<FileMetadata, ParallelDownloadError> {
+    let metadata = get_file_metadata(client.as_ref(), path, options, Sleeper::Async).await?;
+    let target = Target::new(file, &metadata, options)?;
+    let hashes = futures::stream::iter(target.ranges())
+        .map(|(start, end)| {
+            let client = Arc::clone(&client);
+            let target = &target;
+            async move {
+                let mut reader = AsyncResumableDownload::new(
+                    client,
+                    &target.arg,
+                    Some(start),
+                    Some(end - 1),
+                    options.download.clone(),
+                )
+                .await
+                .map_err(ParallelDownloadError::Download)?;
+                let mut block = vec![0; HASH_BLOCK_SIZE];
+                let mut hashes = vec![];
+                for (offset, len) in blocks(start, end) {
+                    reader.read_exact(&mut block[..len]).await?;
+                    hashes.push(target.write_block(offset, &block[..len])?);
+                }
+                Ok::<_, ParallelDownloadError>(hashes)
+            }
+        })
+        .buffered(options.parallelism)
+        .try_collect::<Vec<_>>()
+        .await?;
+    target.finish(metadata, hashes)
+}
+
+async fn get_file_metadata(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &ParallelDownloadOptions,
+    sleeper: Sleeper,
+) -> Result<FileMetadata, ParallelDownloadError> {
+    let arg = GetMetadataArg::new(path.to_owned());
+    match with_retry(options.download.max_retries, sleeper, || {
+        routes::files::get_metadata(client, &arg)
+    })
+    .await
+    .map_err(ParallelDownloadError::Metadata)?
+    {
+        Metadata::File(metadata) => Ok(metadata),
+        Metadata::Folder(_) | Metadata::Deleted(_) => Err(ParallelDownloadError::NotAFile),
+    }
+}
+
+/// The local file being downloaded into, and the state shared between ranges.
+struct Target<'a> {
+    file: &'a File,
+    options: &'a ParallelDownloadOptions,
+    /// The argument for downloading ranges of the file, pinned to the revision.
+    arg: DownloadArg,
+    size: u64,
+    written: AtomicU64,
+}
+
+impl<'a> Target<'a> {
+    fn new(
+        file: &'a File,
+        metadata: &FileMetadata,
+        options: &'a ParallelDownloadOptions,
+    ) -> io::Result<Self> {
+        file.set_len(metadata.size)?;
+        Ok(Self {
+            file,
+            options,
+            arg: DownloadArg::new(format!("rev:{}", metadata.rev)),
+            size: metadata.size,
+            written: AtomicU64::new(0),
+        })
+    }
+
+    /// The start and end (exclusive) of each range to download. Each range is a multiple of the
+    /// hash block size, so that blocks can be hashed as they arrive.
+    fn ranges(&self) -> Vec<(u64, u64)> {
+        let range_size = self.options.range_size();
+        (0..self.size)
+            .step_by(range_size as usize)
+            .map(|start| (start, (start + range_size).min(self.size)))
+            .collect()
+    }
+
+    /// Write a block into place and return its hash.
+    fn write_block(&self, offset: u64, block: &[u8]) -> io::Result<Digest> {
+        write_all_at(self.file, block, offset)?;
+        let written = self.written.fetch_add(block.len() as u64, Ordering::SeqCst);
+        if let Some(progress) = &self.options.progress {
+            progress(written + block.len() as u64);
+        }
+        Ok(block_hash(block))
+    }
+
+    /// Check the content hash, given the hashes of each block of each range, in order.
+    fn finish(
+        &self,
+        metadata: FileMetadata,
+        hashes: Vec<Vec<Digest>>,
+    ) -> Result<FileMetadata, ParallelDownloadError> {
+        let Some(expected) = metadata
+            .content_hash
+            .as_ref()
+            .filter(|_| self.options.verify)
+        else {
+            return Ok(metadata);
+        };
+        let actual = combine_block_hashes(hashes.iter().flatten());
+        if *expected != actual {
+            return Err(ParallelDownloadError::HashMismatch {
+                expected: expected.clone(),
+                actual,
+            });
+        }
+        Ok(metadata)
+    }
+}
+
+/// The offset and length of each hash block in a range.
+fn blocks(start: u64, end: u64) -> impl Iterator<Item = (u64, usize)> {
+    (start..end)
+        .step_by(HASH_BLOCK_SIZE)
+        .map(move |offset| (offset, (end - offset).min(HASH_BLOCK_SIZE as u64) as usize))
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn write_all_at(_file: &File, _buf: &[u8], _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "positioned writes are not supported on this platform",
+    ))
+}
@@ -0,0 +1,302 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! A validated Dropbox path.
+//!
+//! The path arguments of the API routes are plain strings, so mistakes like a missing leading
+//! slash or a `..` segment only show up as a `malformed_path` error from the server.
+//! [`DropboxPath`] checks and normalizes paths up front, and has the usual operations for building
+//! them. It converts into a `String`, so it can be passed anywhere a path argument is expected:
+//!
+//! ```
+//! # fn main() -> Result<(), dropbox_sdk::helpers::path::PathError> {
+//! use dropbox_sdk::helpers::path::DropboxPath;
+//!
+//! let dir = DropboxPath::parse("/Photos/2024/")?;
+//! let file = dir.join("beach.jpg")?;
+//! assert_eq!(file.as_str(), "/Photos/2024/beach.jpg");
+//! assert_eq!(file.parent(), Some(dir));
+//!
+//! # #[cfg(feature = "dbx_files")] {
+//! let arg = dropbox_sdk::files::DownloadArg::new(file.into());
+//! assert_eq!(arg.path, "/Photos/2024/beach.jpg");
+//! # }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// The longest a single path component can be, in characters. This is a Dropbox limit.
+const MAX_COMPONENT_LEN: usize = 255;
+
+/// The different forms a [`DropboxPath`] can take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathKind {
+    /// A path from the root of the user's Dropbox, like `/Photos/beach.jpg`, or the root itself,
+    /// which is the empty string.
+    Path,
+
+    /// A file or folder ID, optionally followed by a path relative to it, like
+    /// `id:a4ayc_80_OEAAAAAAAAAYa/beach.jpg`.
+    Id,
+
+    /// A specific revision of a file, like `rev:a1c10ce0dd78`. These can only be read from.
+    Rev,
+
+    /// A namespace ID, optionally followed by a path relative to it, like `ns:1234/beach.jpg`.
+    Namespace,
+}
+
+/// An error parsing or building a [`DropboxPath`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// A path from the root must start with a slash.
+    #[error("path must start with '/': {0:?}")]
+    MissingLeadingSlash(String),
+
+    /// A `..` segment would go above the root of the path.
+    #[error("path goes above its root: {0:?}")]
+    EscapesRoot(String),
+
+    /// A path component contains a control character.
+    #[error("path component contains invalid character {1:?}: {0:?}")]
+    InvalidCharacter(String, char),
+
+    /// A path component is longer than 255 characters.
+    #[error("path component is too long: {0:?}")]
+    ComponentTooLong(String),
+
+    /// An `id:`, `rev:` or `ns:` path has an invalid ID.
+    #[error("invalid ID in path: {0:?}")]
+    InvalidId(String),
+
+    /// A `rev:` path was used as the base of a join; revisions are of files, not folders.
+    #[error("can't join onto a revision: {0:?}")]
+    JoinOntoRev(String),
+}
+
+/// A validated, normalized Dropbox path.
+///
+/// Paths from the root are normalized by collapsing repeated slashes, removing trailing slashes
+/// and `.` segments, and resolving `..` segments. The root itself is the empty string, as the API
+/// expects.
+///
+/// Comparison and hashing are case-insensitive in the path components, as Dropbox paths are, and
+/// match the `path_lower` field of metadata. The IDs of `id:`, `rev:` and `ns:` paths are compared
+/// exactly, as they are case-sensitive. The original case is preserved by
+/// [`as_str`](Self::as_str).
+#[derive(Debug, Clone)]
+pub struct DropboxPath {
+    path: String,
+    lower: String,
+    kind: PathKind,
+    /// The length of the `id:`/`ns:` prefix, before the relative path, if any.
+    prefix_len: usize,
+}
+
+impl DropboxPath {
+    /// The root of the user's Dropbox.
+    pub fn root() -> Self {
+        Self::new(String::new(), PathKind::Path, 0)
+    }
+
+    /// Parse and normalize a path in any of the forms described by [`PathKind`].
+    pub fn parse(s: &str) -> Result<Self, PathError> {
+        if let Some(rev) = s.strip_prefix("rev:") {
+            if rev.len() < 9 || !rev.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(PathError::InvalidId(s.to_owned()));
+            }
+            return Ok(Self::new(s.to_owned(), PathKind::Rev, s.len()));
+        }
+        for (prefix, kind) in [("id:", PathKind::Id), ("ns:", PathKind::Namespace)] {
+            if let Some(rest) = s.strip_prefix(prefix) {
+                let (id, relative) = rest.split_once('/').unwrap_or((rest, ""));
+                let valid = match kind {
+                    PathKind::Namespace => !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()),
+                    _ => !id.is_empty(),
+                };
+                if !valid {
+                    return Err(PathError::InvalidId(s.to_owned()));
+                }
+                let prefix_len = prefix.len() + id.len();
+                return Self::build(&s[..prefix_len], kind, &[], relative, s);
+            }
+        }
+        if !s.is_empty() && !s.starts_with('/') {
+            return Err(PathError::MissingLeadingSlash(s.to_owned()));
+        }
+        Self::build("", PathKind::Path, &[], s, s)
+    }
+
+    /// Append a relative path to this one. The relative path may contain several components, and
+    /// `.` and `..` segments, but may not go above the root of this path.
+    pub fn join(&self, relative: &str) -> Result<Self, PathError> {
+        if self.kind == PathKind::Rev {
+            return Err(PathError::JoinOntoRev(self.path.clone()));
+        }
+        let segments = self.segments().collect::<Vec<_>>();
+        Self::build(
+            &self.path[..self.prefix_len],
+            self.kind,
+            &segments,
+            relative,
+            relative,
+        )
+    }
+
+    /// The path of the parent folder, or `None` if this is a root, a bare ID, or a revision.
+    pub fn parent(&self) -> Option<Self> {
+        let relative = &self.path[self.prefix_len..];
+        let (parent, _) = relative.rsplit_once('/')?;
+        let len = self.prefix_len + parent.len();
+        Some(Self::new(
+            self.path[..len].to_owned(),
+            self.kind,
+            self.prefix_len,
+        ))
+    }
+
+    /// The last component of the path, or `None` if this is a root, a bare ID, or a revision.
+    pub fn file_name(&self) -> Option<&str> {
+        self.segments().next_back()
+    }
+
+    /// The components of the path after the root or ID, if any.
+    pub fn segments(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.path[self.prefix_len..]
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+    }
+
+    /// Which form the path takes.
+    pub fn kind(&self) -> PathKind {
+        self.kind
+    }
+
+    /// Whether this is the root of the user's Dropbox.
+    pub fn is_root(&self) -> bool {
+        self.kind == PathKind::Path && self.path.is_empty()
+    }
+
+    /// The path, in its original case.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The path with its components in lowercase, as in the `path_lower` field of metadata. The ID
+    /// of an `id:`, `rev:` or `ns:` path is left as it is.
+    pub fn to_lower(&self) -> &str {
+        &self.lower
+    }
+
+    fn new(path: String, kind: PathKind, prefix_len: usize) -> Self {
+        Self {
+            lower: format!(
+                "{}{}",
+                &path[..prefix_len],
+                path[prefix_len..].to_lowercase()
+            ),
+            path,
+            kind,
+            prefix_len,
+        }
+    }
+
+    /// Build a path from a prefix, some already-validated segments, and a relative path to
+    /// normalize onto the end of them. `original` is used in error messages.
+    fn build(
+        prefix: &str,
+        kind: PathKind,
+        base: &[&str],
+        relative: &str,
+        original: &str,
+    ) -> Result<Self, PathError> {
+        let mut segments = base.to_vec();
+        for segment in relative.split('/') {
+            match segment {
+                "" | "." => (),
+                ".." => {
+                    if segments.pop().is_none() {
+                        return Err(PathError::EscapesRoot(original.to_owned()));
+                    }
+                }
+                segment => {
+                    if let Some(c) = segment.chars().find(|c| c.is_control()) {
+                        return Err(PathError::InvalidCharacter(original.to_owned(), c));
+                    }
+                    if segment.chars().count() > MAX_COMPONENT_LEN {
+                        return Err(PathError::ComponentTooLong(original.to_owned()));
+                    }
+                    segments.push(segment);
+                }
+            }
+        }
+        let mut path = prefix.to_owned();
+        for segment in segments {
+            path.push('/');
+            path.push_str(segment);
+        }
+        Ok(Self::new(path, kind, prefix.len()))
+    }
+}
+
+impl PartialEq for DropboxPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.lower == other.lower
+    }
+}
+
+impl Eq for DropboxPath {}
+
+impl Hash for DropboxPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lower.hash(state);
+    }
+}
+
+impl fmt::Display for DropboxPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl AsRef<str> for DropboxPath {
+    fn as_ref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl FromStr for DropboxPath {
+    type Err = PathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<&str> for DropboxPath {
+    type Error = PathError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for DropboxPath {
+    type Error = PathError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+/// All the path types in the API, like [`files::ReadPath`](crate::files::ReadPath) and
+/// [`files::WritePathOrId`](crate::files::WritePathOrId), are aliases of `String`, so this allows
+/// a `DropboxPath` to be used as any of them.
+impl From<DropboxPath> for String {
+    fn from(path: DropboxPath) -> Self {
+        path.path
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Random access to a file on Dropbox without downloading all of it.
+//!
+//! Many libraries, like zip readers or media parsers, need to read a file with
+//! [`Read`](io::Read) + [`Seek`](io::Seek), or their async equivalents. [`RemoteFile`] and
+//! [`AsyncRemoteFile`] provide these by fetching the parts of the file which are read using range
+//! requests. Data is fetched in blocks, a few blocks ahead of what is being read, and recently
+//! used blocks are cached, so that reading sequentially or re-reading nearby data doesn't need a
+//! request every time.
+//!
+//! The revision of the file is pinned when it is opened, so its content can't change while it's
+//! being read.
+
+use super::download::{AsyncResumableDownload, DownloadOptions};
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    DownloadArg, FileMetadata, GetMetadataArg, GetMetadataError, LookupError, Metadata,
+};
+use bytes::Bytes;
+use futures::{AsyncRead, AsyncReadExt, AsyncSeek};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+/// Options controlling how a remote file is fetched and cached.
+#[derive(Debug, Clone)]
+pub struct RemoteFileOptions {
+    block_size: usize,
+    read_ahead: usize,
+    cache_blocks: usize,
+    download: DownloadOptions,
+}
+
+impl Default for RemoteFileOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 1024 * 1024,
+            read_ahead: 4,
+            cache_blocks: 16,
+            download: DownloadOptions::default(),
+        }
+    }
+}
+
+impl RemoteFileOptions {
+    /// The size of the blocks the file is fetched and cached in. Defaults to 1 MiB.
+    pub fn with_block_size(mut self, value: usize) -> Self {
+        self.block_size = value.max(1);
+        self
+    }
+
+    /// How many blocks to fetch in a single request when a block which isn't cached is read,
+    /// including that block. Defaults to 4.
+    pub fn with_read_ahead(mut self, value: usize) -> Self {
+        self.read_ahead = value.max(1);
+        self
+    }
+
+    /// How many blocks to keep cached. This is always at least the read-ahead. Defaults to 16.
+    pub fn with_cache_blocks(mut self, value: usize) -> Self {
+        self.cache_blocks = value;
+        self
+    }
+
+    /// How requests should be retried if they fail. See [`DownloadOptions`].
+    pub fn with_download_options(mut self, value: DownloadOptions) -> Self {
+        self.download = value;
+        self
+    }
+}
+
+/// The least recently used blocks of a file, by block index. The most recently used is last.
+#[derive(Debug)]
+struct BlockCache {
+    capacity: usize,
+    blocks: VecDeque<(u64, Bytes)>,
+}
+
+impl BlockCache {
+    fn get(&mut self, index: u64) -> Option<Bytes> {
+        let i = self.blocks.iter().position(|(idx, _)| *idx == index)?;
+        let entry = self.blocks.remove(i).expect("index should be in bounds");
+        let block = entry.1.clone();
+        self.blocks.push_back(entry);
+        Some(block)
+    }
+
+    fn contains(&self, index: u64) -> bool {
+        self.blocks.iter().any(|(idx, _)| *idx == index)
+    }
+
+    fn insert(&mut self, index: u64, block: Bytes) {
+        self.blocks.push_back((index, block));
+        while self.blocks.len() > self.capacity {
+            self.blocks.pop_front();
+        }
+    }
+}
+
+/// The state shared by the sync and async variants: everything except the client.
+#[derive(Debug)]
+struct State {
+    metadata: FileMetadata,
+    arg: DownloadArg,
+    options: RemoteFileOptions,
+    position: u64,
+    cache: BlockCache,
+}
+
+impl State {
+    fn new(metadata: FileMetadata, options: RemoteFileOptions) -> Self {
+        Self {
+            arg: DownloadArg::new(format!("rev:{}", metadata.rev)),
+            cache: BlockCache {
+                capacity: options.cache_blocks.max(options.read_ahead),
+                blocks: VecDeque::new(),
+            },
+            metadata,
+            options,
+            position: 0,
+        }
+    }
+
+    fn block_size(&self) -> u64 {
+        self.options.block_size as u64
+    }
+
+    /// Read from the cache at the current position. Returns `None` if the block isn't cached.
+    fn read_cached(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if buf.is_empty() || self.position >= self.metadata.size {
+            return Some(0);
+        }
+        let index = self.position / self.block_size();
+        let block = self.cache.get(index)?;
+        let start = (self.position - index * self.block_size()) as usize;
+        let n = buf.len().min(block.len() - start);
+        buf[..n].copy_from_slice(&block[start..start + n]);
+        self.position += n as u64;
+        Some(n)
+    }
+
+    /// The range to fetch (start and exclusive end) to satisfy a read at the current position:
+    /// the block containing it, plus any following blocks up to the read-ahead which aren't
+    /// already cached.
+    fn missing_range(&self) -> (u64, u64) {
+        let first = self.position / self.block_size();
+        let blocks = (1..self.options.read_ahead as u64)
+            .take_while(|i| !self.cache.contains(first + i))
+            .count() as u64
+            + 1;
+        let start = first * self.block_size();
+        (
+            start,
+            (start + blocks * self.block_size()).min(self.metadata.size),
+        )
+    }
+
+    /// Split fetched data starting at the given offset into blocks and cache them.
+    fn insert(&mut self, start: u64, data: Vec<u8>) {
+        let data = Bytes::from(data);
+        let offsets = (0..data.len()).step_by(self.options.block_size);
+        for (index, offset) in (start / self.block_size()..).zip(offsets) {
+            let end = (offset + self.options.block_size).min(data.len());
+            self.cache.insert(index, data.slice(offset..end));
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.metadata.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match new {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Look up the metadata of the file at the given path, failing with [`LookupError::NotFile`] if
+/// it's not a file.
+async fn file_metadata(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &RemoteFileOptions,
+    sleeper: Sleeper,
+) -> Result<FileMetadata, Error<GetMetadataError>> {
+    let arg = GetMetadataArg::new(path.to_owned());
+    match with_retry(options.download.max_retries, sleeper, || {
+        routes::files::get_metadata(client, &arg)
+    })
+    .await?
+    {
+        Metadata::File(metadata) => Ok(metadata),
+        Metadata::Folder(_) | Metadata::Deleted(_) => {
+            Err(Error::Api(GetMetadataError::Path(LookupError::NotFile)))
+        }
+    }
+}
+
+if_feature! { "sync_routes",
+    /// A file on Dropbox which can be read from any position, using a sync HTTP client.
+    #[derive(Debug)]
+    pub struct RemoteFile<'a, C> {
+        client: &'a C,
+        state: State,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::UserAuthClient> RemoteFile<'a, C> {
+    /// Open the file at the given path. This looks up its metadata, but doesn't fetch any of its
+    /// content yet.
+    pub fn open(
+        client: &'a C,
+        path: &str,
+        options: RemoteFileOptions,
+    ) -> Result<Self, Error<GetMetadataError>> {
+        let metadata =
+            super::block_on_sync(file_metadata(client, path, &options, Sleeper::Blocking))?;
+        Ok(Self::from_metadata(client, metadata, options))
+    }
+
+    /// Open a file whose metadata is already known.
+    pub fn from_metadata(
+        client: &'a C,
+        metadata: FileMetadata,
+        options: RemoteFileOptions,
+    ) -> Self {
+        Self {
+            client,
+            state: State::new(metadata, options),
+        }
+    }
+
+    /// The metadata of the file.
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.state.metadata
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> io::Read for RemoteFile<'_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(n) = self.state.read_cached(buf) {
+            return Ok(n);
+        }
+        let (start, end) = self.state.missing_range();
+        let mut reader = super::download::ResumableDownload::new(
+            self.client,
+            &self.state.arg,
+            Some(start),
+            Some(end - 1),
+            self.state.options.download.clone(),
+        )
+        .map_err(io::Error::other)?;
+        let mut data = vec![0; (end - start) as usize];
+        reader.read_exact(&mut data)?;
+        self.state.insert(start, data);
+        Ok(self
+            .state
+            .read_cached(buf)
+            .expect("block should have just been cached"))
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> io::Seek for RemoteFile<'_, C> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.state.seek(pos)
+    }
+}
+
+type Fetch = Pin<Box<dyn Future<Output = io::Result<(u64, Vec<u8>)>> + Send>>;
+
+/// A file on Dropbox which can be read from any position.
+pub struct AsyncRemoteFile<C> {
+    client: Arc<C>,
+    state: State,
+    fetch: Option<Fetch>,
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncRemoteFile<C> {
+    /// Open the file at the given path. This looks up its metadata, but doesn't fetch any of its
+    /// content yet.
+    pub async fn open(
+        client: Arc<C>,
+        path: &str,
+        options: RemoteFileOptions,
+    ) -> Result<Self, Error<GetMetadataError>> {
+        let metadata = file_metadata(client.as_ref(), path, &options, Sleeper::Async).await?;
+        Ok(Self::from_metadata(client, metadata, options))
+    }
+
+    /// Open a file whose metadata is already known.
+    pub fn from_metadata(
+        client: Arc<C>,
+        metadata: FileMetadata,
+        options: RemoteFileOptions,
+    ) -> Self {
+        Self {
+            client,
+            state: State::new(metadata, options),
+            fetch: None,
+        }
+    }
+
+    /// The metadata of the file.
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.state.metadata
+    }
+
+    fn start_fetch(&self) -> Fetch {
+        let client = Arc::clone(&self.client);
+        let arg = self.state.arg.clone();
+        let options = self.state.options.download.clone();
+        let (start, end) = self.state.missing_range();
+        Box::pin(async move {
+            let mut reader =
+                AsyncResumableDownload::new(client, &arg, Some(start), Some(end - 1), options)
+                    .await
+                    .map_err(io::Error::other)?;
+            let mut data = vec![0; (end - start) as usize];
+            reader.read_exact(&mut data).await?;
+            Ok((start, data))
+        })
+    }
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncRead for AsyncRemoteFile<C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(fetch) = &mut this.fetch {
+                let result = ready!(fetch.as_mut().poll(cx));
+                this.fetch = None;
+                let (start, data) = result?;
+                this.state.insert(start, data);
+            }
+            if let Some(n) = this.state.read_cached(buf) {
+                return Poll::Ready(Ok(n));
+            }
+            this.fetch = Some(this.start_fetch());
+        }
+    }
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncSeek for AsyncRemoteFile<C> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        Poll::Ready(self.get_mut().state.seek(pos))
+    }
+}
@@ -0,0 +1,491 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Rolling a folder back to how it was at an earlier time.
+//!
+//! Dropbox keeps the earlier revisions of files, and of deleted files, for a while. This uses them
+//! to undo changes made to a folder since a given time, for example by ransomware or a script gone
+//! wrong, in two steps:
+//!
+//! 1. [`plan_restore`] lists the folder recursively, including deleted files, and for each file
+//!    which was modified or deleted since the time, finds the revision of it which was current then
+//!    using [`list_revisions`](crate::files::list_revisions). Nothing is changed, so the
+//!    [`RestorePlan`] can be reviewed first, as a dry run.
+//! 2. [`execute_restore`] restores each of those revisions with
+//!    [`restore`](crate::files::restore), and reports what happened to each of them.
+//!
+//! Files created since the time are left alone, unless
+//! [`RestoreOptions::with_delete_new_files`] is set. Deleted folders come back when any file in
+//! them is restored, but empty ones aren't recreated.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    DeleteArg, DeleteError, FileMetadata, ListFolderArg, ListFolderContinueArg,
+    ListFolderContinueError, ListFolderError, ListRevisionsArg, ListRevisionsError, LookupError,
+    Metadata, RestoreArg, RestoreError,
+};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// The most revisions which can be listed in a single request. This is a Dropbox limit.
+const MAX_REVISIONS: u64 = 100;
+
+/// A function called with the report of each action as it's finished.
+type ProgressFn = dyn Fn(&ActionReport) + Send + Sync;
+
+/// Options controlling how [`plan_restore`] and [`execute_restore`] work.
+#[derive(Clone)]
+pub struct RestoreOptions {
+    parallelism: usize,
+    delete_new_files: bool,
+    max_retries: u32,
+    progress: Option<Arc<ProgressFn>>,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            delete_new_files: false,
+            max_retries: 3,
+            progress: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for RestoreOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RestoreOptions")
+            .field("parallelism", &self.parallelism)
+            .field("delete_new_files", &self.delete_new_files)
+            .field("max_retries", &self.max_retries)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl RestoreOptions {
+    /// How many files to look up or restore at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// Whether to delete files which didn't exist at the time being restored to. Defaults to
+    /// false.
+    pub fn with_delete_new_files(mut self, value: bool) -> Self {
+        self.delete_new_files = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function to call with the report of each action as [`execute_restore`] finishes it.
+    /// Actions are carried out concurrently, so they finish in no particular order.
+    pub fn with_progress(mut self, f: impl Fn(&ActionReport) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    fn report(&self, action: RestoreAction, outcome: ActionOutcome) -> ActionReport {
+        let report = ActionReport { action, outcome };
+        if let Some(progress) = &self.progress {
+            progress(&report);
+        }
+        report
+    }
+}
+
+/// An error which stopped a restore from being planned.
+#[derive(thiserror::Error, Debug)]
+pub enum PlanError {
+    /// Starting to list the folder, with `list_folder`, failed.
+    #[error("error listing folder: {0}")]
+    ListFolder(#[source] Error<ListFolderError>),
+
+    /// Getting the next page of the folder's listing, with `list_folder/continue`, failed.
+    #[error("error listing folder: {0}")]
+    ListFolderContinue(#[source] Error<ListFolderContinueError>),
+
+    /// Listing the revisions of a file failed.
+    #[error("error listing revisions of {path:?}: {error}")]
+    ListRevisions {
+        /// The path of the file.
+        path: String,
+
+        /// The error.
+        #[source]
+        error: Error<ListRevisionsError>,
+    },
+}
+
+/// An error carrying out a single action of a restore.
+#[derive(thiserror::Error, Debug)]
+pub enum ActionError {
+    /// Restoring the file failed.
+    #[error("error restoring file: {0}")]
+    Restore(#[source] Error<RestoreError>),
+
+    /// Deleting the file failed. If it was modified since the restore was planned, this fails
+    /// with a conflict.
+    #[error("error deleting file: {0}")]
+    Delete(#[source] Error<DeleteError>),
+}
+
+/// What needs doing to a single file to restore it.
+#[derive(Debug, Clone)]
+pub enum RestoreAction {
+    /// The file has been modified since; restore the earlier revision.
+    Revert {
+        /// The path of the file.
+        path: String,
+
+        /// The revision which was current at the time.
+        revision: FileMetadata,
+    },
+
+    /// The file has been deleted since; restore the last revision it had before the time.
+    Undelete {
+        /// The path of the file.
+        path: String,
+
+        /// The revision which was current at the time.
+        revision: FileMetadata,
+    },
+
+    /// The file didn't exist at the time; delete it. Only planned if
+    /// [`RestoreOptions::with_delete_new_files`] is set.
+    Delete {
+        /// The path of the file.
+        path: String,
+
+        /// The current revision. The file isn't deleted if it's changed since.
+        current: FileMetadata,
+    },
+}
+
+impl RestoreAction {
+    /// The path of the file.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Revert { path, .. } | Self::Undelete { path, .. } | Self::Delete { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+/// Everything which needs doing to restore a folder, as worked out by [`plan_restore`].
+#[derive(Debug, Clone)]
+pub struct RestorePlan {
+    /// The time the folder is being restored to, as a Dropbox timestamp.
+    pub time: String,
+
+    /// What needs doing to each file which changed since the time, in order of path. Files which
+    /// didn't change aren't included.
+    pub actions: Vec<RestoreAction>,
+}
+
+impl RestorePlan {
+    fn new(
+        time: String,
+        actions: Vec<Result<Option<RestoreAction>, PlanError>>,
+    ) -> Result<Self, PlanError> {
+        let mut actions = actions
+            .into_iter()
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>, _>>()?;
+        actions.sort_by_key(|action| action.path().to_lowercase());
+        Ok(Self { time, actions })
+    }
+}
+
+/// What happened when carrying out an action.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // the success case is by far the most common
+pub enum ActionOutcome {
+    /// The file was restored, and this is its new metadata.
+    Restored(FileMetadata),
+
+    /// The file was deleted.
+    Deleted,
+
+    /// The action failed.
+    Failed(ActionError),
+}
+
+/// What happened to a single file.
+#[derive(Debug)]
+pub struct ActionReport {
+    /// What was done to the file.
+    pub action: RestoreAction,
+
+    /// What happened.
+    pub outcome: ActionOutcome,
+}
+
+/// The outcome of carrying out a restore.
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    /// What happened to each file, in the same order as the actions of the plan.
+    pub actions: Vec<ActionReport>,
+}
+
+if_feature! { "sync_routes",
+    /// Work out what needs doing to restore a folder to how it was at the given time, using a
+    /// sync HTTP client. Nothing is changed.
+    pub fn plan_restore(
+        client: &impl crate::client_trait::UserAuthClient,
+        path: &str,
+        time: SystemTime,
+        options: &RestoreOptions,
+    ) -> Result<RestorePlan, PlanError> {
+        let time = super::dropbox_timestamp(time);
+        let candidates =
+            super::block_on_sync(candidates(client, path, &time, options, Sleeper::Blocking))?;
+        let actions = super::parallel_map(candidates, options.parallelism, |candidate| {
+            super::block_on_sync(plan_file(client, candidate, &time, options, Sleeper::Blocking))
+        });
+        RestorePlan::new(time, actions)
+    }
+
+    /// Carry out a restore, using a sync HTTP client.
+    pub fn execute_restore(
+        client: &impl crate::client_trait::UserAuthClient,
+        plan: RestorePlan,
+        options: &RestoreOptions,
+    ) -> RestoreReport {
+        let actions = super::parallel_map(plan.actions, options.parallelism, |action| {
+            let outcome = super::block_on_sync(execute(client, &action, options, Sleeper::Blocking));
+            options.report(action, outcome)
+        });
+        RestoreReport { actions }
+    }
+}
+
+/// Work out what needs doing to restore a folder to how it was at the given time. Nothing is
+/// changed.
+pub async fn plan_restore_async(
+    client: &impl UserAuthClient,
+    path: &str,
+    time: SystemTime,
+    options: &RestoreOptions,
+) -> Result<RestorePlan, PlanError> {
+    let time = super::dropbox_timestamp(time);
+    let candidates = candidates(client, path, &time, options, Sleeper::Async).await?;
+    let actions = futures::stream::iter(candidates)
+        .map(|candidate| plan_file(client, candidate, &time, options, Sleeper::Async))
+        .buffered(options.parallelism)
+        .collect::<Vec<_>>()
+        .await;
+    RestorePlan::new(time, actions)
+}
+
+/// Carry out a restore.
+pub async fn execute_restore_async(
+    client: &impl UserAuthClient,
+    plan: RestorePlan,
+    options: &RestoreOptions,
+) -> RestoreReport {
+    let actions = futures::stream::iter(plan.actions)
+        .map(|action| async move {
+            let outcome = execute(client, &action, options, Sleeper::Async).await;
+            options.report(action, outcome)
+        })
+        .buffered(options.parallelism)
+        .collect()
+        .await;
+    RestoreReport { actions }
+}
+
+/// A file which might have changed since the time being restored to.
+#[allow(clippy::large_enum_variant)] // most candidates are live files
+enum Candidate {
+    /// A file which exists now.
+    Live(FileMetadata),
+
+    /// A file, or maybe a folder, which has been deleted, by path.
+    Deleted(String),
+}
+
+/// List the folder, and return the files in it which might have changed since the time.
+async fn candidates(
+    client: &impl UserAuthClient,
+    path: &str,
+    time: &str,
+    options: &RestoreOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<Candidate>, PlanError> {
+    let mut files = HashMap::new();
+    let mut deleted = HashMap::new();
+    let arg = ListFolderArg::new(path.to_owned())
+        .with_recursive(true)
+        .with_include_deleted(true);
+    let mut result = with_retry(options.max_retries, sleeper, || {
+        routes::files::list_folder(client, &arg)
+    })
+    .await
+    .map_err(PlanError::ListFolder)?;
+    loop {
+        for entry in result.entries {
+            match entry {
+                Metadata::File(file) => {
+                    if let Some(path) = file.path_lower.clone() {
+                        files.insert(path, file);
+                    }
+                }
+                Metadata::Deleted(entry) => {
+                    if let Some(path) = entry.path_lower {
+                        deleted.insert(path, entry.path_display.unwrap_or(entry.name));
+                    }
+                }
+                Metadata::Folder(_) => (),
+            }
+        }
+        if !result.has_more {
+            break;
+        }
+        let arg = ListFolderContinueArg::new(result.cursor);
+        result = with_retry(options.max_retries, sleeper, || {
+            routes::files::list_folder_continue(client, &arg)
+        })
+        .await
+        .map_err(PlanError::ListFolderContinue)?;
+    }
+
+    // A file modified before the time has been the same since. A deleted file may have been
+    // recreated since, in which case it's live.
+    deleted.retain(|path, _| !files.contains_key(path));
+    Ok(files
+        .into_values()
+        .filter(|file| file.server_modified.as_str() > time)
+        .map(Candidate::Live)
+        .chain(deleted.into_values().map(Candidate::Deleted))
+        .collect())
+}
+
+/// Work out what needs doing to a single file, if anything.
+async fn plan_file(
+    client: &impl UserAuthClient,
+    candidate: Candidate,
+    time: &str,
+    options: &RestoreOptions,
+    sleeper: Sleeper,
+) -> Result<Option<RestoreAction>, PlanError> {
+    let (path, current) = match candidate {
+        Candidate::Live(file) => (
+            file.path_display
+                .clone()
+                .unwrap_or_else(|| file.name.clone()),
+            Some(file),
+        ),
+        Candidate::Deleted(path) => (path, None),
+    };
+    let revision = match revision_at(client, &path, time, options, sleeper).await {
+        Ok(revision) => revision,
+        // Deleted folders are listed too, and have no revisions.
+        Err(Error::Api(ListRevisionsError::Path(LookupError::NotFile))) if current.is_none() => {
+            return Ok(None);
+        }
+        Err(error) => return Err(PlanError::ListRevisions { path, error }),
+    };
+    Ok(match (current, revision) {
+        (Some(current), Some(revision)) if !same_content(&current, &revision) => {
+            Some(RestoreAction::Revert { path, revision })
+        }
+        (Some(current), None) if options.delete_new_files => {
+            Some(RestoreAction::Delete { path, current })
+        }
+        (None, Some(revision)) => Some(RestoreAction::Undelete { path, revision }),
+        _ => None,
+    })
+}
+
+/// Whether two revisions of a file have the same content. Restoring a file makes a new revision
+/// of it, so this compares content hashes where possible.
+fn same_content(a: &FileMetadata, b: &FileMetadata) -> bool {
+    match (&a.content_hash, &b.content_hash) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.rev == b.rev,
+    }
+}
+
+/// Find the revision of a file which was current at the time, or `None` if it didn't exist then.
+async fn revision_at(
+    client: &impl UserAuthClient,
+    path: &str,
+    time: &str,
+    options: &RestoreOptions,
+    sleeper: Sleeper,
+) -> Result<Option<FileMetadata>, Error<ListRevisionsError>> {
+    let mut arg = ListRevisionsArg::new(path.to_owned()).with_limit(MAX_REVISIONS);
+    loop {
+        let result = with_retry(options.max_retries, sleeper, || {
+            routes::files::list_revisions(client, &arg)
+        })
+        .await?;
+        // If it was deleted before the time, and not recreated since, it didn't exist then.
+        if result
+            .server_deleted
+            .as_deref()
+            .is_some_and(|deleted| deleted <= time)
+        {
+            return Ok(None);
+        }
+        // Revisions are listed newest first.
+        let oldest = result.entries.last().map(|entry| entry.rev.clone());
+        if let Some(revision) = result
+            .entries
+            .into_iter()
+            .find(|entry| entry.server_modified.as_str() <= time)
+        {
+            return Ok(Some(revision));
+        }
+        match oldest {
+            Some(rev) if result.has_more => arg = arg.with_before_rev(rev),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Carry out a single action.
+async fn execute(
+    client: &impl UserAuthClient,
+    action: &RestoreAction,
+    options: &RestoreOptions,
+    sleeper: Sleeper,
+) -> ActionOutcome {
+    match action {
+        RestoreAction::Revert { path, revision } | RestoreAction::Undelete { path, revision } => {
+            let arg = RestoreArg::new(path.clone(), revision.rev.clone());
+            match with_retry(options.max_retries, sleeper, || {
+                routes::files::restore(client, &arg)
+            })
+            .await
+            {
+                Ok(metadata) => ActionOutcome::Restored(metadata),
+                Err(e) => ActionOutcome::Failed(ActionError::Restore(e)),
+            }
+        }
+        RestoreAction::Delete { path, current } => {
+            let arg = DeleteArg::new(path.clone()).with_parent_rev(current.rev.clone());
+            match with_retry(options.max_retries, sleeper, || {
+                routes::files::delete_v2(client, &arg)
+            })
+            .await
+            {
+                Ok(_) => ActionOutcome::Deleted,
+                Err(e) => ActionOutcome::Failed(ActionError::Delete(e)),
+            }
+        }
+    }
+}
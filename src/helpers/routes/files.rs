@@ -0,0 +1,89 @@
+use crate::types::files::*;
+
+routes! {
+    rpc Api "files/copy_reference/get"
+        fn copy_reference_get(UserAuthClient, GetCopyReferenceArg)
+            -> GetCopyReferenceResult, GetCopyReferenceError;
+    rpc Api "files/copy_reference/save"
+        fn copy_reference_save(UserAuthClient, SaveCopyReferenceArg)
+            -> SaveCopyReferenceResult, SaveCopyReferenceError;
+    rpc Api "files/copy_v2"
+        fn copy_v2(UserAuthClient, RelocationArg) -> RelocationResult, RelocationError;
+    rpc Api "files/create_folder_batch"
+        fn create_folder_batch(UserAuthClient, CreateFolderBatchArg)
+            -> CreateFolderBatchLaunch, crate::NoError;
+    rpc Api "files/create_folder_batch/check"
+        fn create_folder_batch_check(UserAuthClient, crate::types::dbx_async::PollArg)
+            -> CreateFolderBatchJobStatus, crate::types::dbx_async::PollError;
+    rpc Api "files/create_folder_v2"
+        fn create_folder_v2(UserAuthClient, CreateFolderArg)
+            -> CreateFolderResult, CreateFolderError;
+    rpc Api "files/delete_v2"
+        fn delete_v2(UserAuthClient, DeleteArg) -> DeleteResult, DeleteError;
+    rpc Api "files/get_file_lock_batch"
+        fn get_file_lock_batch(UserAuthClient, LockFileBatchArg)
+            -> LockFileBatchResult, LockFileError;
+    rpc Api "files/get_metadata"
+        fn get_metadata(UserAuthClient, GetMetadataArg) -> Metadata, GetMetadataError;
+    rpc Content "files/get_thumbnail_batch"
+        fn get_thumbnail_batch(UserAuthClient, GetThumbnailBatchArg)
+            -> GetThumbnailBatchResult, GetThumbnailBatchError;
+    download Content "files/get_thumbnail_v2"
+        fn get_thumbnail_v2(UserAuthClient, ThumbnailV2Arg) -> PreviewResult, ThumbnailV2Error;
+    rpc Api "files/list_folder"
+        fn list_folder(UserAuthClient, ListFolderArg) -> ListFolderResult, ListFolderError;
+    rpc Api "files/list_folder/continue"
+        fn list_folder_continue(UserAuthClient, ListFolderContinueArg)
+            -> ListFolderResult, ListFolderContinueError;
+    rpc Api "files/list_revisions"
+        fn list_revisions(UserAuthClient, ListRevisionsArg)
+            -> ListRevisionsResult, ListRevisionsError;
+    rpc Api "files/lock_file_batch"
+        fn lock_file_batch(UserAuthClient, LockFileBatchArg) -> LockFileBatchResult, LockFileError;
+    rpc Api "files/move_v2"
+        fn move_v2(UserAuthClient, RelocationArg) -> RelocationResult, RelocationError;
+    rpc Api "files/restore"
+        fn restore(UserAuthClient, RestoreArg) -> FileMetadata, RestoreError;
+    rpc Api "files/search/continue_v2"
+        fn search_continue_v2(UserAuthClient, SearchV2ContinueArg)
+            -> SearchV2Result, SearchError;
+    rpc Api "files/search_v2"
+        fn search_v2(UserAuthClient, SearchV2Arg) -> SearchV2Result, SearchError;
+    rpc Api "files/unlock_file_batch"
+        fn unlock_file_batch(UserAuthClient, UnlockFileBatchArg)
+            -> LockFileBatchResult, LockFileError;
+    upload Content "files/upload"
+        fn upload(UserAuthClient, UploadArg) -> FileMetadata, UploadError;
+    download Content "files/download"
+        fn download(UserAuthClient, DownloadArg) -> FileMetadata, DownloadError;
+    upload Content "files/upload_session/start"
+        fn upload_session_start(UserAuthClient, UploadSessionStartArg)
+            -> UploadSessionStartResult, UploadSessionStartError;
+    upload Content "files/upload_session/append_v2"
+        fn upload_session_append_v2(UserAuthClient, UploadSessionAppendArg)
+            -> (), UploadSessionAppendError;
+    rpc Api "files/upload_session/start_batch"
+        fn upload_session_start_batch(UserAuthClient, UploadSessionStartBatchArg)
+            -> UploadSessionStartBatchResult, crate::NoError;
+    upload Content "files/upload_session/finish"
+        fn upload_session_finish(UserAuthClient, UploadSessionFinishArg)
+            -> FileMetadata, UploadSessionFinishError;
+    rpc Api "files/upload_session/finish_batch_v2"
+        fn upload_session_finish_batch_v2(UserAuthClient, UploadSessionFinishBatchArg)
+            -> UploadSessionFinishBatchResult, crate::NoError;
+}
+
+#[cfg(feature = "zip")]
+routes! {
+    download Content "files/download_zip"
+        fn download_zip(UserAuthClient, DownloadZipArg) -> DownloadZipResult, DownloadZipError;
+}
+
+#[cfg(feature = "dbx_sharing")]
+routes! {
+    rpc Api "files/list_folder"
+        fn list_folder_app_auth(AppAuthClient, ListFolderArg) -> ListFolderResult, ListFolderError;
+    rpc Api "files/list_folder/continue"
+        fn list_folder_continue_app_auth(AppAuthClient, ListFolderContinueArg)
+            -> ListFolderResult, ListFolderContinueError;
+}
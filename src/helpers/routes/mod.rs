@@ -0,0 +1,81 @@
+//! Typed wrappers around the API routes used by the helpers.
+//!
+//! The generated async routes are only compiled when the `async_routes` feature is enabled, but
+//! the helpers need them regardless of which routes are exported, so the ones that are needed are
+//! redefined here. The signatures match the generated async routes.
+
+/// Define wrappers for routes. Each one is given as
+///
+/// ```text
+/// <style> <endpoint> "<route name>" fn <name>(<client trait>, <arg type>) -> <result type>, <error type>;
+/// ```
+macro_rules! routes {
+    ($(
+        $style:ident $endpoint:ident $route:literal
+            fn $name:ident($client:ident, $arg:ty) -> $ret:ty, $err:ty;
+    )*) => {
+        $(
+            routes!(@route $style $endpoint $route $name $client $arg, $ret, $err);
+        )*
+    };
+    (@route rpc $endpoint:ident $route:literal $name:ident $client:ident $arg:ty, $ret:ty, $err:ty) => {
+        pub(crate) fn $name<'a>(
+            client: &'a impl crate::async_client_trait::$client,
+            arg: &'a $arg,
+        ) -> impl std::future::Future<Output = Result<$ret, crate::Error<$err>>> + Send + 'a {
+            crate::client_helpers::request(
+                client,
+                crate::client_trait_common::Endpoint::$endpoint,
+                crate::client_trait_common::Style::Rpc,
+                $route,
+                arg,
+                None,
+            )
+        }
+    };
+    (@route upload $endpoint:ident $route:literal $name:ident $client:ident $arg:ty, $ret:ty, $err:ty) => {
+        pub(crate) fn $name<'a>(
+            client: &'a impl crate::async_client_trait::$client,
+            arg: &'a $arg,
+            body: bytes::Bytes,
+        ) -> impl std::future::Future<Output = Result<$ret, crate::Error<$err>>> + Send + 'a {
+            crate::client_helpers::request(
+                client,
+                crate::client_trait_common::Endpoint::$endpoint,
+                crate::client_trait_common::Style::Upload,
+                $route,
+                arg,
+                Some(crate::client_helpers::Body::from(body)),
+            )
+        }
+    };
+    (@route download $endpoint:ident $route:literal $name:ident $client:ident $arg:ty, $ret:ty, $err:ty) => {
+        pub(crate) fn $name<'a>(
+            client: &'a impl crate::async_client_trait::$client,
+            arg: &'a $arg,
+            range_start: Option<u64>,
+            range_end: Option<u64>,
+        ) -> impl std::future::Future<
+            Output = Result<crate::async_client_trait::HttpRequestResult<$ret>, crate::Error<$err>>,
+        > + Send
+               + 'a {
+            crate::client_helpers::request_with_body(
+                client,
+                crate::client_trait_common::Endpoint::$endpoint,
+                crate::client_trait_common::Style::Download,
+                $route,
+                arg,
+                None,
+                range_start,
+                range_end,
+            )
+        }
+    };
+}
+
+if_feature! { "dbx_files", pub(crate) mod files; }
+
+if_feature! { "dbx_sharing", pub(crate) mod sharing; }
+
+#[cfg(all(feature = "dbx_sharing", feature = "dbx_team"))]
+pub(crate) mod team;
@@ -0,0 +1,64 @@
+use crate::types::sharing::*;
+
+routes! {
+    rpc Api "sharing/add_folder_member"
+        fn add_folder_member(UserAuthClient, AddFolderMemberArg) -> (), AddFolderMemberError;
+    rpc Api "sharing/check_remove_member_job_status"
+        fn check_remove_member_job_status(UserAuthClient, crate::types::dbx_async::PollArg)
+            -> RemoveMemberJobStatus, crate::types::dbx_async::PollError;
+    rpc Api "sharing/create_shared_link_with_settings"
+        fn create_shared_link_with_settings(UserAuthClient, CreateSharedLinkWithSettingsArg)
+            -> SharedLinkMetadata, CreateSharedLinkWithSettingsError;
+    download Content "sharing/get_shared_link_file"
+        fn get_shared_link_file(UserAuthClient, GetSharedLinkFileArg)
+            -> SharedLinkMetadata, GetSharedLinkFileError;
+    download Content "sharing/get_shared_link_file"
+        fn get_shared_link_file_app_auth(AppAuthClient, GetSharedLinkFileArg)
+            -> SharedLinkMetadata, GetSharedLinkFileError;
+    rpc Api "sharing/list_file_members/batch"
+        fn list_file_members_batch(UserAuthClient, ListFileMembersBatchArg)
+            -> Vec<ListFileMembersBatchResult>, SharingUserError;
+    rpc Api "sharing/list_file_members/continue"
+        fn list_file_members_continue(UserAuthClient, ListFileMembersContinueArg)
+            -> SharedFileMembers, ListFileMembersContinueError;
+    rpc Api "sharing/list_folder_members"
+        fn list_folder_members(UserAuthClient, ListFolderMembersArgs)
+            -> SharedFolderMembers, SharedFolderAccessError;
+    rpc Api "sharing/list_folder_members/continue"
+        fn list_folder_members_continue(UserAuthClient, ListFolderMembersContinueArg)
+            -> SharedFolderMembers, ListFolderMembersContinueError;
+    rpc Api "sharing/list_folders"
+        fn list_folders(UserAuthClient, ListFoldersArgs) -> ListFoldersResult, crate::NoError;
+    rpc Api "sharing/list_folders/continue"
+        fn list_folders_continue(UserAuthClient, ListFoldersContinueArg)
+            -> ListFoldersResult, ListFoldersContinueError;
+    rpc Api "sharing/list_mountable_folders"
+        fn list_mountable_folders(UserAuthClient, ListFoldersArgs)
+            -> ListFoldersResult, crate::NoError;
+    rpc Api "sharing/list_mountable_folders/continue"
+        fn list_mountable_folders_continue(UserAuthClient, ListFoldersContinueArg)
+            -> ListFoldersResult, ListFoldersContinueError;
+    rpc Api "sharing/list_received_files"
+        fn list_received_files(UserAuthClient, ListFilesArg) -> ListFilesResult, SharingUserError;
+    rpc Api "sharing/list_received_files/continue"
+        fn list_received_files_continue(UserAuthClient, ListFilesContinueArg)
+            -> ListFilesResult, ListFilesContinueError;
+    rpc Api "sharing/list_shared_links"
+        fn list_shared_links(UserAuthClient, ListSharedLinksArg)
+            -> ListSharedLinksResult, ListSharedLinksError;
+    rpc Api "sharing/modify_shared_link_settings"
+        fn modify_shared_link_settings(UserAuthClient, ModifySharedLinkSettingsArgs)
+            -> SharedLinkMetadata, ModifySharedLinkSettingsError;
+    rpc Api "sharing/remove_folder_member"
+        fn remove_folder_member(UserAuthClient, RemoveFolderMemberArg)
+            -> crate::types::dbx_async::LaunchResultBase, RemoveFolderMemberError;
+    rpc Api "sharing/update_folder_member"
+        fn update_folder_member(UserAuthClient, UpdateFolderMemberArg)
+            -> MemberAccessLevelResult, UpdateFolderMemberError;
+}
+
+#[cfg(feature = "dbx_team")]
+routes! {
+    rpc Api "sharing/revoke_shared_link"
+        fn revoke_shared_link(UserAuthClient, RevokeSharedLinkArg) -> (), RevokeSharedLinkError;
+}
@@ -0,0 +1,10 @@
+use crate::types::team::*;
+
+routes! {
+    rpc Api "team/members/list_v2"
+        fn members_list_v2(TeamAuthClient, MembersListArg)
+            -> MembersListV2Result, MembersListError;
+    rpc Api "team/members/list/continue_v2"
+        fn members_list_continue_v2(TeamAuthClient, MembersListContinueArg)
+            -> MembersListV2Result, MembersListContinueError;
+}
@@ -0,0 +1,344 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Searching for files, following result pages automatically.
+//!
+//! [`SearchQuery`] builds the arguments to `files/search_v2`, and runs the search as an iterator
+//! (with a sync HTTP client) or a stream (with an async one) of [`SearchHit`]s, fetching more pages
+//! of results with `files/search/continue_v2` as needed. Each hit has the metadata of the matching
+//! file or folder, and the parts of its name which matched the query, as [`Highlighted`] text.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    FileCategory, FileStatus, HighlightSpan, Metadata, MetadataV2, SearchError,
+    SearchMatchFieldOptions, SearchMatchTypeV2, SearchOptions, SearchOrderBy, SearchV2Arg,
+    SearchV2ContinueArg,
+};
+use futures::{Stream, TryStreamExt};
+use std::fmt;
+use std::ops::Range;
+
+/// The most results the server returns in one page.
+const MAX_PAGE_SIZE: u64 = 1000;
+
+/// A search for files and folders, built up using the `with_*` methods, and run using
+/// [`run`](Self::run) or [`run_async`](Self::run_async).
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    query: String,
+    options: SearchOptions,
+    include_highlights: bool,
+    max_retries: u32,
+}
+
+impl SearchQuery {
+    /// Search for the given text. By default, the whole Dropbox is searched for files and folders
+    /// which aren't deleted, matching on both names and content.
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            options: SearchOptions::default(),
+            include_highlights: true,
+            max_retries: 3,
+        }
+    }
+
+    /// Only search within the given folder.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.options.path = Some(path.into());
+        self
+    }
+
+    /// Only search for files in the given category. Can be given more than once, to search for
+    /// files in any of them. Not supported when searching for deleted files.
+    pub fn with_category(mut self, category: FileCategory) -> Self {
+        self.options
+            .file_categories
+            .get_or_insert_with(Vec::new)
+            .push(category);
+        self
+    }
+
+    /// Only search for files with the given extension, with or without the leading dot. Can be
+    /// given more than once, to search for files with any of them. Not supported when searching
+    /// for deleted files.
+    pub fn with_extension(mut self, extension: impl AsRef<str>) -> Self {
+        let extension = extension.as_ref();
+        self.options
+            .file_extensions
+            .get_or_insert_with(Vec::new)
+            .push(extension.strip_prefix('.').unwrap_or(extension).to_owned());
+        self
+    }
+
+    /// Whether to only match the query against file and folder names, and not their content.
+    /// Defaults to false.
+    pub fn with_filename_only(mut self, value: bool) -> Self {
+        self.options.filename_only = value;
+        self
+    }
+
+    /// Whether to search for deleted files, instead of ones which currently exist. Defaults to
+    /// false.
+    pub fn with_deleted(mut self, value: bool) -> Self {
+        self.options.file_status = if value {
+            FileStatus::Deleted
+        } else {
+            FileStatus::Active
+        };
+        self
+    }
+
+    /// What order to return results in. Defaults to relevance.
+    pub fn with_order_by(mut self, value: SearchOrderBy) -> Self {
+        self.options.order_by = Some(value);
+        self
+    }
+
+    /// How many results to fetch per request, up to 1000. Defaults to 100.
+    pub fn with_page_size(mut self, value: u64) -> Self {
+        self.options.max_results = value.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+
+    /// Whether to find out which parts of each result's name matched the query, in
+    /// [`SearchHit::highlights`]. Defaults to true.
+    pub fn with_highlights(mut self, value: bool) -> Self {
+        self.include_highlights = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// Run the search as a stream of results, fetching more pages as they're needed.
+    ///
+    /// The stream ends after the first error.
+    pub fn run_async<'a>(
+        &'a self,
+        client: &'a impl UserAuthClient,
+    ) -> impl Stream<Item = Result<SearchHit, Error<SearchError>>> + 'a {
+        Box::pin(
+            futures::stream::try_unfold(Page::First, move |page| async move {
+                if let Page::Done = page {
+                    return Ok::<_, Error<SearchError>>(None);
+                }
+                let (hits, next) = self.fetch(client, page, Sleeper::Async).await?;
+                Ok(Some((
+                    futures::stream::iter(hits.into_iter().map(Ok)),
+                    next,
+                )))
+            })
+            .try_flatten(),
+        )
+    }
+
+    /// Fetch a page of results, and return them along with what to fetch next.
+    async fn fetch(
+        &self,
+        client: &impl UserAuthClient,
+        page: Page,
+        sleeper: Sleeper,
+    ) -> Result<(Vec<SearchHit>, Page), Error<SearchError>> {
+        let result = match page {
+            Page::First => {
+                let arg = SearchV2Arg::new(self.query.clone())
+                    .with_options(self.options.clone())
+                    .with_match_field_options(
+                        SearchMatchFieldOptions::default()
+                            .with_include_highlights(self.include_highlights),
+                    );
+                with_retry(self.max_retries, sleeper, || {
+                    routes::files::search_v2(client, &arg)
+                })
+                .await?
+            }
+            Page::Next(cursor) => {
+                let arg = SearchV2ContinueArg::new(cursor);
+                with_retry(self.max_retries, sleeper, || {
+                    routes::files::search_continue_v2(client, &arg)
+                })
+                .await?
+            }
+            Page::Done => return Ok((Vec::new(), Page::Done)),
+        };
+
+        let next = match result.cursor {
+            Some(cursor) if result.has_more => Page::Next(cursor),
+            _ => Page::Done,
+        };
+        let hits = result
+            .matches
+            .into_iter()
+            .filter_map(|m| match m.metadata {
+                MetadataV2::Metadata(metadata) => Some(SearchHit {
+                    metadata,
+                    match_type: m.match_type,
+                    highlights: m.highlight_spans.map(Highlighted::from_spans),
+                }),
+                MetadataV2::Other => {
+                    debug!("skipping search result of an unknown type");
+                    None
+                }
+            })
+            .collect();
+        Ok((hits, next))
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl SearchQuery {
+    /// Run the search using a sync HTTP client, as an iterator of results which fetches more pages
+    /// as they're needed.
+    ///
+    /// The iterator ends after the first error.
+    pub fn run<'a, C: crate::client_trait::UserAuthClient>(
+        &'a self,
+        client: &'a C,
+    ) -> SearchResults<'a, C> {
+        SearchResults {
+            client,
+            query: self,
+            hits: std::collections::VecDeque::new(),
+            next: Page::First,
+        }
+    }
+}
+
+/// Which page of results to fetch next.
+enum Page {
+    First,
+    Next(String),
+    Done,
+}
+
+if_feature! { "sync_routes",
+    /// An iterator over the results of a search, returned by [`SearchQuery::run`].
+    pub struct SearchResults<'a, C: crate::client_trait::UserAuthClient> {
+        client: &'a C,
+        query: &'a SearchQuery,
+        hits: std::collections::VecDeque<SearchHit>,
+        next: Page,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> fmt::Debug for SearchResults<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchResults")
+            .field("query", &self.query)
+            .field("hits", &self.hits)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> Iterator for SearchResults<'_, C> {
+    type Item = Result<SearchHit, Error<SearchError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(hit) = self.hits.pop_front() {
+                return Some(Ok(hit));
+            }
+            let page = std::mem::replace(&mut self.next, Page::Done);
+            if let Page::Done = page {
+                return None;
+            }
+            match super::block_on_sync(self.query.fetch(self.client, page, Sleeper::Blocking)) {
+                Ok((hits, next)) => {
+                    self.hits = hits.into();
+                    self.next = next;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// A file or folder which matched a search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// The metadata of the file or folder.
+    pub metadata: Metadata,
+
+    /// What matched the query, if the server said.
+    pub match_type: Option<SearchMatchTypeV2>,
+
+    /// The name of the file or folder, with the parts which matched the query marked. This is only
+    /// present if [highlights](SearchQuery::with_highlights) were requested.
+    pub highlights: Option<Highlighted>,
+}
+
+/// Text with some parts of it highlighted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Highlighted {
+    text: String,
+    ranges: Vec<Range<usize>>,
+}
+
+impl Highlighted {
+    /// Put the text back together from the spans returned by the server, merging adjacent
+    /// highlighted spans.
+    pub fn from_spans(spans: Vec<HighlightSpan>) -> Self {
+        let mut result = Self::default();
+        for span in spans {
+            let start = result.text.len();
+            result.text.push_str(&span.highlight_str);
+            let end = result.text.len();
+            if !span.is_highlighted || start == end {
+                continue;
+            }
+            match result.ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => result.ranges.push(start..end),
+            }
+        }
+        result
+    }
+
+    /// The whole text, without any markers.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The byte ranges of the text which are highlighted, in order.
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// The parts of the text which are highlighted, in order.
+    pub fn highlighted(&self) -> impl Iterator<Item = &str> {
+        self.ranges.iter().map(|range| &self.text[range.clone()])
+    }
+
+    /// The text with each highlighted part surrounded by `open` and `close`, for example `"**"`
+    /// and `"**"` for Markdown, or `"<mark>"` and `"</mark>"` for HTML.
+    pub fn annotate(&self, open: &str, close: &str) -> String {
+        let mut result =
+            String::with_capacity(self.text.len() + self.ranges.len() * (open.len() + close.len()));
+        let mut pos = 0;
+        for range in &self.ranges {
+            result.push_str(&self.text[pos..range.start]);
+            result.push_str(open);
+            result.push_str(&self.text[range.clone()]);
+            result.push_str(close);
+            pos = range.end;
+        }
+        result.push_str(&self.text[pos..]);
+        result
+    }
+}
+
+impl fmt::Display for Highlighted {
+    /// Shows the text without any markers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
@@ -0,0 +1,573 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Listing and downloading the contents of a shared link to a folder.
+//!
+//! `files/list_folder` can list a folder within a shared link, but not recursively, and the
+//! entries it returns don't have paths. [`list_shared_link`] walks the whole folder, working out
+//! each file's path within the link, and [`download_shared_link`] downloads the files into a local
+//! folder with the same layout, using `sharing/get_shared_link_file`. Each file is written
+//! atomically and checked against its content hash, as with
+//! [`download_to_file`](super::download_file).
+//!
+//! Links with a password are supported, and the files can be filtered using glob patterns. Both
+//! functions have `_app_auth` variants, which only need an app key and secret rather than a user's
+//! authorization.
+
+use super::download_file::{DownloadFileError, TempFile};
+use super::{BlockingSpawnFn, Sleeper, routes, run_blocking, with_retry};
+use crate::Error;
+use crate::async_client_trait::{AppAuthClient, HttpRequestResult, UserAuthClient};
+use crate::types::files::{
+    FileMetadata, ListFolderArg, ListFolderContinueArg, ListFolderContinueError, ListFolderError,
+    ListFolderResult, Metadata, SharedLink,
+};
+use crate::types::sharing::{GetSharedLinkFileArg, GetSharedLinkFileError, SharedLinkMetadata};
+use futures::{AsyncReadExt, StreamExt, TryStreamExt};
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Options controlling which files in a shared link are listed or downloaded, and how.
+#[derive(Clone)]
+pub struct SharedLinkOptions {
+    password: Option<String>,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    parallelism: usize,
+    max_retries: u32,
+    spawner: Option<Arc<BlockingSpawnFn>>,
+}
+
+impl Default for SharedLinkOptions {
+    fn default() -> Self {
+        Self {
+            password: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            parallelism: 4,
+            max_retries: 3,
+            spawner: None,
+        }
+    }
+}
+
+impl fmt::Debug for SharedLinkOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedLinkOptions")
+            .field("password", &self.password)
+            .field("include", &self.include)
+            .field("exclude", &self.exclude)
+            .field("parallelism", &self.parallelism)
+            .field("max_retries", &self.max_retries)
+            .field("spawner", &self.spawner.is_some())
+            .finish()
+    }
+}
+
+impl SharedLinkOptions {
+    /// The link's password, if it has one.
+    pub fn with_password(mut self, value: impl Into<String>) -> Self {
+        self.password = Some(value.into());
+        self
+    }
+
+    /// Only include files whose path within the link matches the given glob pattern. Can be given
+    /// more than once, to include files matching any of them. By default, all files are included.
+    ///
+    /// Paths are relative to the link, like `reports/2024/q1.pdf`. In patterns, `*` matches
+    /// anything except a `/`, `**` matches any number of folders, and `?` matches any one
+    /// character except a `/`, so `**/*.pdf` matches every PDF. Matching ignores case, as Dropbox
+    /// does.
+    pub fn with_include(mut self, pattern: &str) -> Self {
+        self.include.push(Glob::new(pattern));
+        self
+    }
+
+    /// Leave out files and folders whose path within the link matches the given glob pattern, with
+    /// the same syntax as [`with_include`](Self::with_include). Can be given more than once.
+    /// Folders which are left out aren't listed at all.
+    pub fn with_exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(Glob::new(pattern));
+        self
+    }
+
+    /// How many files to download at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function which runs a blocking closure on a thread where that's allowed, such as
+    /// `|f| { tokio::task::spawn_blocking(f); }`. The async download functions create and write
+    /// the local files through it, rather than on the tasks downloading them. The sync ones don't
+    /// use it.
+    pub fn with_blocking_spawner(
+        mut self,
+        value: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static,
+    ) -> Self {
+        self.spawner = Some(Arc::new(value));
+        self
+    }
+
+    fn includes(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(path)))
+            && !self.excludes(path)
+    }
+
+    fn excludes(&self, path: &str) -> bool {
+        self.exclude.iter().any(|glob| glob.matches(path))
+    }
+
+    fn shared_link(&self, url: &str) -> SharedLink {
+        let link = SharedLink::new(url.to_owned());
+        match &self.password {
+            Some(password) => link.with_password(password.clone()),
+            None => link,
+        }
+    }
+}
+
+/// A file in a shared link.
+#[derive(Debug, Clone)]
+pub struct SharedLinkFile {
+    /// The file's path within the link, with no leading slash, like `reports/2024/q1.pdf`.
+    pub path: String,
+
+    /// The file's metadata. Paths in it are only set if the link is to something in the caller's
+    /// own Dropbox.
+    pub metadata: FileMetadata,
+}
+
+/// An error listing or downloading the contents of a shared link.
+#[derive(thiserror::Error, Debug)]
+pub enum SharedLinkCrawlError {
+    /// Listing a folder in the link failed.
+    #[error("error listing {path:?}: {source}")]
+    ListFolder {
+        /// The folder's path within the link.
+        path: String,
+        /// The error.
+        source: Error<ListFolderError>,
+    },
+
+    /// Listing more of a folder in the link failed.
+    #[error("error listing {path:?}: {source}")]
+    ListFolderContinue {
+        /// The folder's path within the link.
+        path: String,
+        /// The error.
+        source: Error<ListFolderContinueError>,
+    },
+
+    /// Downloading a file failed.
+    #[error("error downloading {path:?}: {source}")]
+    Download {
+        /// The file's path within the link.
+        path: String,
+        /// The error.
+        source: Error<GetSharedLinkFileError>,
+    },
+
+    /// A file's path would put it outside the destination folder.
+    #[error("unsafe path in shared link: {0:?}")]
+    UnsafePath(String),
+
+    /// Writing a downloaded file failed, or it didn't match its metadata.
+    #[error("error writing {path:?}: {source}")]
+    Write {
+        /// Where the file was being written.
+        path: PathBuf,
+        /// The error.
+        source: DownloadFileError,
+    },
+}
+
+if_feature! { "sync_routes",
+    /// List the files in a shared link to a folder, recursively, using a sync HTTP client.
+    pub fn list_shared_link(
+        client: &impl crate::client_trait::UserAuthClient,
+        url: &str,
+        options: &SharedLinkOptions,
+    ) -> Result<Vec<SharedLinkFile>, SharedLinkCrawlError> {
+        super::block_on_sync(list(&User(client), url, options, Sleeper::Blocking))
+    }
+
+    /// List the files in a shared link to a folder, recursively, using a sync HTTP client with
+    /// app authentication.
+    pub fn list_shared_link_app_auth(
+        client: &impl crate::client_trait::AppAuthClient,
+        url: &str,
+        options: &SharedLinkOptions,
+    ) -> Result<Vec<SharedLinkFile>, SharedLinkCrawlError> {
+        super::block_on_sync(list(&App(client), url, options, Sleeper::Blocking))
+    }
+
+    /// Download the files in a shared link to a folder into the given local folder, using a sync
+    /// HTTP client. Returns the local paths of the files, which are replaced if they exist.
+    pub fn download_shared_link(
+        client: &impl crate::client_trait::UserAuthClient,
+        url: &str,
+        dest: impl AsRef<Path>,
+        options: &SharedLinkOptions,
+    ) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+        download_sync(&User(client), url, dest.as_ref(), options)
+    }
+
+    /// Download the files in a shared link to a folder into the given local folder, using a sync
+    /// HTTP client with app authentication. Returns the local paths of the files, which are
+    /// replaced if they exist.
+    pub fn download_shared_link_app_auth(
+        client: &impl crate::client_trait::AppAuthClient,
+        url: &str,
+        dest: impl AsRef<Path>,
+        options: &SharedLinkOptions,
+    ) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+        download_sync(&App(client), url, dest.as_ref(), options)
+    }
+}
+
+/// List the files in a shared link to a folder, recursively.
+pub async fn list_shared_link_async(
+    client: &impl UserAuthClient,
+    url: &str,
+    options: &SharedLinkOptions,
+) -> Result<Vec<SharedLinkFile>, SharedLinkCrawlError> {
+    list(&User(client), url, options, Sleeper::Async).await
+}
+
+/// List the files in a shared link to a folder, recursively, using app authentication.
+pub async fn list_shared_link_app_auth_async(
+    client: &impl AppAuthClient,
+    url: &str,
+    options: &SharedLinkOptions,
+) -> Result<Vec<SharedLinkFile>, SharedLinkCrawlError> {
+    list(&App(client), url, options, Sleeper::Async).await
+}
+
+/// Download the files in a shared link to a folder into the given local folder. Returns the local
+/// paths of the files, which are replaced if they exist.
+///
+/// The local files are written using the
+/// [blocking spawner](SharedLinkOptions::with_blocking_spawner), if one was given.
+pub async fn download_shared_link_async(
+    client: &impl UserAuthClient,
+    url: &str,
+    dest: impl AsRef<Path>,
+    options: &SharedLinkOptions,
+) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+    download(&User(client), url, dest.as_ref(), options).await
+}
+
+/// Download the files in a shared link to a folder into the given local folder, using app
+/// authentication. Returns the local paths of the files, which are replaced if they exist.
+///
+/// The local files are written using the
+/// [blocking spawner](SharedLinkOptions::with_blocking_spawner), if one was given.
+pub async fn download_shared_link_app_auth_async(
+    client: &impl AppAuthClient,
+    url: &str,
+    dest: impl AsRef<Path>,
+    options: &SharedLinkOptions,
+) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+    download(&App(client), url, dest.as_ref(), options).await
+}
+
+/// The routes used, with either user or app authentication.
+trait LinkClient: Sync {
+    fn list_folder<'a>(
+        &'a self,
+        arg: &'a ListFolderArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderError>>> + Send + 'a;
+
+    fn list_folder_continue<'a>(
+        &'a self,
+        arg: &'a ListFolderContinueArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderContinueError>>> + Send + 'a;
+
+    fn get_file<'a>(
+        &'a self,
+        arg: &'a GetSharedLinkFileArg,
+    ) -> impl Future<
+        Output = Result<HttpRequestResult<SharedLinkMetadata>, Error<GetSharedLinkFileError>>,
+    > + Send
+    + 'a;
+}
+
+struct User<'a, C>(&'a C);
+
+impl<C: UserAuthClient> LinkClient for User<'_, C> {
+    fn list_folder<'a>(
+        &'a self,
+        arg: &'a ListFolderArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderError>>> + Send + 'a {
+        routes::files::list_folder(self.0, arg)
+    }
+
+    fn list_folder_continue<'a>(
+        &'a self,
+        arg: &'a ListFolderContinueArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderContinueError>>> + Send + 'a
+    {
+        routes::files::list_folder_continue(self.0, arg)
+    }
+
+    fn get_file<'a>(
+        &'a self,
+        arg: &'a GetSharedLinkFileArg,
+    ) -> impl Future<
+        Output = Result<HttpRequestResult<SharedLinkMetadata>, Error<GetSharedLinkFileError>>,
+    > + Send
+    + 'a {
+        routes::sharing::get_shared_link_file(self.0, arg, None, None)
+    }
+}
+
+struct App<'a, C>(&'a C);
+
+impl<C: AppAuthClient> LinkClient for App<'_, C> {
+    fn list_folder<'a>(
+        &'a self,
+        arg: &'a ListFolderArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderError>>> + Send + 'a {
+        routes::files::list_folder_app_auth(self.0, arg)
+    }
+
+    fn list_folder_continue<'a>(
+        &'a self,
+        arg: &'a ListFolderContinueArg,
+    ) -> impl Future<Output = Result<ListFolderResult, Error<ListFolderContinueError>>> + Send + 'a
+    {
+        routes::files::list_folder_continue_app_auth(self.0, arg)
+    }
+
+    fn get_file<'a>(
+        &'a self,
+        arg: &'a GetSharedLinkFileArg,
+    ) -> impl Future<
+        Output = Result<HttpRequestResult<SharedLinkMetadata>, Error<GetSharedLinkFileError>>,
+    > + Send
+    + 'a {
+        routes::sharing::get_shared_link_file_app_auth(self.0, arg, None, None)
+    }
+}
+
+/// List the files in the link which the options include, in order of their paths.
+async fn list(
+    client: &impl LinkClient,
+    url: &str,
+    options: &SharedLinkOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharedLinkFile>, SharedLinkCrawlError> {
+    let mut files = Vec::new();
+    // Paths within the link of the folders still to list, starting from the root.
+    let mut folders = vec![String::new()];
+    while let Some(folder) = folders.pop() {
+        let arg = ListFolderArg::new(if folder.is_empty() {
+            String::new()
+        } else {
+            format!("/{folder}")
+        })
+        .with_shared_link(options.shared_link(url));
+        let mut result = with_retry(options.max_retries, sleeper, || client.list_folder(&arg))
+            .await
+            .map_err(|source| SharedLinkCrawlError::ListFolder {
+                path: folder.clone(),
+                source,
+            })?;
+        loop {
+            for entry in result.entries {
+                let name = match &entry {
+                    Metadata::File(file) => &file.name,
+                    Metadata::Folder(folder) => &folder.name,
+                    Metadata::Deleted(_) => continue,
+                };
+                let path = if folder.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{folder}/{name}")
+                };
+                match entry {
+                    Metadata::File(metadata) if options.includes(&path) => {
+                        files.push(SharedLinkFile { path, metadata });
+                    }
+                    Metadata::Folder(_) if !options.excludes(&path) => folders.push(path),
+                    _ => debug!("skipping {path:?}"),
+                }
+            }
+            if !result.has_more {
+                break;
+            }
+            let arg = ListFolderContinueArg::new(result.cursor);
+            result = with_retry(options.max_retries, sleeper, || {
+                client.list_folder_continue(&arg)
+            })
+            .await
+            .map_err(|source| SharedLinkCrawlError::ListFolderContinue {
+                path: folder.clone(),
+                source,
+            })?;
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+#[cfg(feature = "sync_routes")]
+fn download_sync(
+    client: &impl LinkClient,
+    url: &str,
+    dest: &Path,
+    options: &SharedLinkOptions,
+) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+    let files = super::block_on_sync(list(client, url, options, Sleeper::Blocking))?;
+    super::parallel_map(files, options.parallelism, |file| {
+        super::block_on_sync(download_file(
+            client,
+            url,
+            &file,
+            dest,
+            options,
+            Sleeper::Blocking,
+        ))
+    })
+    .into_iter()
+    .collect()
+}
+
+async fn download(
+    client: &impl LinkClient,
+    url: &str,
+    dest: &Path,
+    options: &SharedLinkOptions,
+) -> Result<Vec<PathBuf>, SharedLinkCrawlError> {
+    let files = list(client, url, options, Sleeper::Async).await?;
+    futures::stream::iter(&files)
+        .map(|file| download_file(client, url, file, dest, options, Sleeper::Async))
+        .buffered(options.parallelism)
+        .try_collect()
+        .await
+}
+
+/// Download one file from the link to its place under `dest`.
+async fn download_file(
+    client: &impl LinkClient,
+    url: &str,
+    file: &SharedLinkFile,
+    dest: &Path,
+    options: &SharedLinkOptions,
+    sleeper: Sleeper,
+) -> Result<PathBuf, SharedLinkCrawlError> {
+    let local = super::local_path(dest, &file.path)
+        .ok_or_else(|| SharedLinkCrawlError::UnsafePath(file.path.clone()))?;
+    let write_error = |source: DownloadFileError| SharedLinkCrawlError::Write {
+        path: local.clone(),
+        source,
+    };
+    let download_error = |source| SharedLinkCrawlError::Download {
+        path: file.path.clone(),
+        source,
+    };
+
+    let mut arg = GetSharedLinkFileArg::new(url.to_owned()).with_path(format!("/{}", file.path));
+    arg.link_password.clone_from(&options.password);
+    let response = with_retry(options.max_retries, sleeper, || client.get_file(&arg))
+        .await
+        .map_err(download_error)?;
+    let mut body = response.body.ok_or_else(|| {
+        download_error(Error::UnexpectedResponse(
+            "download response has no body".to_owned(),
+        ))
+    })?;
+
+    let spawner = options.spawner.as_deref();
+    let destination = local.clone();
+    let mut temp = run_blocking(sleeper, spawner, move || {
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        TempFile::create(&destination)
+    })
+    .await
+    .map_err(|e| write_error(e.into()))?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = body
+            .read(&mut buf)
+            .await
+            .map_err(|e| write_error(e.into()))?;
+        if n == 0 {
+            break;
+        }
+        (temp, buf) = run_blocking(sleeper, spawner, move || {
+            temp.write_all(&buf[..n])?;
+            io::Result::Ok((temp, buf))
+        })
+        .await
+        .map_err(|e| write_error(e.into()))?;
+    }
+    let metadata = file.metadata.clone();
+    run_blocking(sleeper, spawner, move || temp.persist(&metadata))
+        .await
+        .map_err(write_error)?;
+    debug!("downloaded {:?}", file.path);
+    Ok(local)
+}
+
+/// A glob pattern, matched against `/`-separated paths, ignoring case.
+#[derive(Debug, Clone)]
+struct Glob(Vec<char>);
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        Self(pattern.to_lowercase().chars().collect())
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let path = path.to_lowercase().chars().collect::<Vec<_>>();
+        glob_match(&self.0, &path)
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no folders at all.
+            if let ['/', after @ ..] = rest {
+                if glob_match(after, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => glob_match(rest, text),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] if c == p => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,699 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! A report of everything an account shares, and with whom.
+//!
+//! The sharing routes each cover one part of the picture: shared folders, their members, files
+//! shared with the account, their members, and shared links. [`audit_sharing`] calls all of them,
+//! following their cursors, and flattens the results into a [`SharingAuditReport`] with one entry
+//! per grant of access: who can reach which folder or file, at what access level, whether the
+//! access is inherited from a parent folder, and whether it reaches outside the account's team.
+//! The report can be written out as CSV or JSON.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::sharing::{
+    AccessLevel, GroupMembershipInfo, InviteeInfo, InviteeMembershipInfo, LinkAccessLevel,
+    LinkAudience, ListFileMembersBatchArg, ListFileMembersContinueArg,
+    ListFileMembersContinueError, ListFileMembersIndividualResult, ListFilesArg,
+    ListFilesContinueArg, ListFilesContinueError, ListFolderMembersArgs,
+    ListFolderMembersContinueArg, ListFolderMembersContinueError, ListFoldersArgs,
+    ListFoldersContinueArg, ListFoldersContinueError, ListSharedLinksArg, ListSharedLinksError,
+    SharedFileMembers, SharedFileMetadata, SharedFolderAccessError, SharedFolderMembers,
+    SharedFolderMetadata, SharedLinkMetadata, SharingUserError, UserInfo,
+};
+use futures::{StreamExt, TryStreamExt};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// The most files `sharing/list_file_members/batch` accepts at once.
+const FILE_MEMBERS_BATCH_SIZE: usize = 100;
+
+/// Options controlling how [`audit_sharing`] gathers its report.
+#[derive(Debug, Clone)]
+pub struct SharingAuditOptions {
+    parallelism: usize,
+    max_retries: u32,
+}
+
+impl Default for SharingAuditOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            max_retries: 3,
+        }
+    }
+}
+
+impl SharingAuditOptions {
+    /// How many folders' or batches of files' members to list at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+}
+
+/// What kind of thing is shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedItemKind {
+    /// A shared folder, or a folder with a shared link.
+    Folder,
+    /// A shared file, or a file with a shared link.
+    File,
+}
+
+/// Who has been given access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Grantee {
+    /// A Dropbox user.
+    User {
+        /// The user's account ID.
+        account_id: String,
+        /// The user's email address.
+        email: String,
+        /// The user's display name.
+        display_name: String,
+    },
+
+    /// A group.
+    Group {
+        /// The group's ID.
+        group_id: String,
+        /// The group's name.
+        name: String,
+    },
+
+    /// Someone who has been invited by email, but hasn't joined yet.
+    Invitee {
+        /// The address they were invited at.
+        email: String,
+    },
+
+    /// Anyone with a shared link.
+    Link {
+        /// The link's URL.
+        url: String,
+        /// Who can use the link, if the server said.
+        audience: Option<LinkAudience>,
+    },
+}
+
+/// One grant of access to a shared folder or file.
+#[derive(Debug, Clone)]
+pub struct SharingAuditEntry {
+    /// Whether a folder or a file is shared.
+    pub kind: SharedItemKind,
+
+    /// The shared folder ID of a folder, the ID of a file, or the URL of a link with no ID.
+    pub id: String,
+
+    /// The folder or file's name.
+    pub name: String,
+
+    /// The folder or file's path in the account, if it's in it.
+    pub path: Option<String>,
+
+    /// Who has access.
+    pub grantee: Grantee,
+
+    /// What access they have.
+    pub access: AccessLevel,
+
+    /// Whether the access comes from a parent folder rather than being given directly.
+    pub inherited: bool,
+
+    /// Whether the grantee is outside the account's team. For an account which isn't on a team,
+    /// every other user is outside it. Invitees whose account isn't known, and links which anyone
+    /// can use, count as outside.
+    pub external: bool,
+}
+
+/// Everything an account shares. See the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct SharingAuditReport {
+    /// The grants of access, with shared folders first, then shared files, then shared links.
+    pub entries: Vec<SharingAuditEntry>,
+}
+
+impl SharingAuditReport {
+    /// The grants of access which reach outside the account's team.
+    pub fn external(&self) -> impl Iterator<Item = &SharingAuditEntry> {
+        self.entries.iter().filter(|entry| entry.external)
+    }
+
+    /// Write the report as CSV, with a header row, one row per entry, and a column per field of
+    /// the entry and its grantee. For links, the grantee's ID is the URL and its name is the
+    /// audience, like `public` or `team`. Fields starting with a character which would make a
+    /// spreadsheet treat them as a formula, like `=`, are prefixed with `'`.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "kind,id,name,path,grantee_type,grantee_id,grantee_name,grantee_email,access,\
+             inherited,external"
+        )?;
+        for entry in &self.entries {
+            let row = row(entry);
+            let fields = row.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+            writeln!(writer, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// Write the report as a JSON array, with an object per entry, with the same fields as the
+    /// CSV columns.
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let [
+                    kind,
+                    id,
+                    name,
+                    path,
+                    grantee_type,
+                    grantee_id,
+                    grantee_name,
+                    email,
+                    access,
+                    ..,
+                ] = row(entry);
+                serde_json::json!({
+                    "kind": kind,
+                    "id": id,
+                    "name": name,
+                    "path": entry.path.as_ref().map(|_| path),
+                    "grantee_type": grantee_type,
+                    "grantee_id": grantee_id,
+                    "grantee_name": grantee_name,
+                    "grantee_email": (!email.is_empty()).then_some(email),
+                    "access": access,
+                    "inherited": entry.inherited,
+                    "external": entry.external,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer_pretty(writer, &entries).map_err(io::Error::from)
+    }
+}
+
+/// An error gathering a sharing audit report.
+#[derive(thiserror::Error, Debug)]
+pub enum SharingAuditError {
+    /// Listing shared folders failed.
+    #[error("error listing shared folders: {0}")]
+    ListFolders(#[source] Error<crate::NoError>),
+
+    /// Listing more shared folders failed.
+    #[error("error listing shared folders: {0}")]
+    ListFoldersContinue(#[source] Error<ListFoldersContinueError>),
+
+    /// Listing a shared folder's members failed.
+    #[error("error listing members of shared folder {shared_folder_id}: {source}")]
+    FolderMembers {
+        /// The folder's shared folder ID.
+        shared_folder_id: String,
+        /// The error.
+        source: Error<SharedFolderAccessError>,
+    },
+
+    /// Listing more of a shared folder's members failed.
+    #[error("error listing members of shared folder {shared_folder_id}: {source}")]
+    FolderMembersContinue {
+        /// The folder's shared folder ID.
+        shared_folder_id: String,
+        /// The error.
+        source: Error<ListFolderMembersContinueError>,
+    },
+
+    /// Listing the files shared with the account failed.
+    #[error("error listing shared files: {0}")]
+    ListFiles(#[source] Error<SharingUserError>),
+
+    /// Listing more of the files shared with the account failed.
+    #[error("error listing shared files: {0}")]
+    ListFilesContinue(#[source] Error<ListFilesContinueError>),
+
+    /// Listing the members of a batch of shared files failed.
+    #[error("error listing members of shared files: {0}")]
+    FileMembers(#[source] Error<SharingUserError>),
+
+    /// Listing more of a shared file's members failed.
+    #[error("error listing members of shared file {file}: {source}")]
+    FileMembersContinue {
+        /// The file's ID.
+        file: String,
+        /// The error.
+        source: Error<ListFileMembersContinueError>,
+    },
+
+    /// Listing shared links failed.
+    #[error("error listing shared links: {0}")]
+    SharedLinks(#[source] Error<ListSharedLinksError>),
+}
+
+if_feature! { "sync_routes",
+    /// Gather a report of everything an account shares, using a sync HTTP client.
+    pub fn audit_sharing(
+        client: &impl crate::client_trait::UserAuthClient,
+        options: &SharingAuditOptions,
+    ) -> Result<SharingAuditReport, SharingAuditError> {
+        let sleeper = Sleeper::Blocking;
+        let mut entries = Vec::new();
+        let folders = super::block_on_sync(list_folders(client, options, sleeper))?;
+        for members in super::parallel_map(folders, options.parallelism, |folder| {
+            super::block_on_sync(folder_members(client, &folder, options, sleeper))
+        }) {
+            entries.extend(members?);
+        }
+        let files = super::block_on_sync(list_files(client, options, sleeper))?;
+        for members in super::parallel_map(batches(files), options.parallelism, |batch| {
+            super::block_on_sync(file_members(client, &batch, options, sleeper))
+        }) {
+            entries.extend(members?);
+        }
+        entries.extend(super::block_on_sync(links(client, options, sleeper))?);
+        Ok(SharingAuditReport { entries })
+    }
+}
+
+/// Gather a report of everything an account shares.
+pub async fn audit_sharing_async(
+    client: &impl UserAuthClient,
+    options: &SharingAuditOptions,
+) -> Result<SharingAuditReport, SharingAuditError> {
+    let sleeper = Sleeper::Async;
+    let mut entries = Vec::new();
+    let folders = list_folders(client, options, sleeper).await?;
+    let members: Vec<Vec<_>> = futures::stream::iter(&folders)
+        .map(|folder| folder_members(client, folder, options, sleeper))
+        .buffered(options.parallelism)
+        .try_collect()
+        .await?;
+    entries.extend(members.into_iter().flatten());
+    let files = list_files(client, options, sleeper).await?;
+    let members: Vec<Vec<_>> = futures::stream::iter(batches(files))
+        .map(|batch| async move { file_members(client, &batch, options, sleeper).await })
+        .buffered(options.parallelism)
+        .try_collect()
+        .await?;
+    entries.extend(members.into_iter().flatten());
+    entries.extend(links(client, options, sleeper).await?);
+    Ok(SharingAuditReport { entries })
+}
+
+/// List the shared folders the account is a member of, whether they're mounted or not.
+async fn list_folders(
+    client: &impl UserAuthClient,
+    options: &SharingAuditOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharedFolderMetadata>, SharingAuditError> {
+    let arg = ListFoldersArgs::default();
+    let mut folders = Vec::new();
+    for mountable in [false, true] {
+        let mut result = with_retry(options.max_retries, sleeper, || async {
+            if mountable {
+                routes::sharing::list_mountable_folders(client, &arg).await
+            } else {
+                routes::sharing::list_folders(client, &arg).await
+            }
+        })
+        .await
+        .map_err(SharingAuditError::ListFolders)?;
+        loop {
+            folders.extend(result.entries);
+            let Some(cursor) = result.cursor else {
+                break;
+            };
+            let arg = ListFoldersContinueArg::new(cursor);
+            result = with_retry(options.max_retries, sleeper, || async {
+                if mountable {
+                    routes::sharing::list_mountable_folders_continue(client, &arg).await
+                } else {
+                    routes::sharing::list_folders_continue(client, &arg).await
+                }
+            })
+            .await
+            .map_err(SharingAuditError::ListFoldersContinue)?;
+        }
+    }
+    // A folder can be in both lists while it's being mounted or unmounted.
+    let mut seen = std::collections::HashSet::new();
+    folders.retain(|folder| seen.insert(folder.shared_folder_id.clone()));
+    Ok(folders)
+}
+
+async fn folder_members(
+    client: &impl UserAuthClient,
+    folder: &SharedFolderMetadata,
+    options: &SharingAuditOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharingAuditEntry>, SharingAuditError> {
+    let item = Item {
+        kind: SharedItemKind::Folder,
+        id: folder.shared_folder_id.clone(),
+        name: folder.name.clone(),
+        path: folder.path_display.clone(),
+    };
+    let arg = ListFolderMembersArgs::new(folder.shared_folder_id.clone());
+    let mut members: SharedFolderMembers = with_retry(options.max_retries, sleeper, || {
+        routes::sharing::list_folder_members(client, &arg)
+    })
+    .await
+    .map_err(|source| SharingAuditError::FolderMembers {
+        shared_folder_id: item.id.clone(),
+        source,
+    })?;
+    let mut entries = Vec::new();
+    loop {
+        for user in members.users {
+            entries.push(item.user(&user.user, user.access_type, user.is_inherited));
+        }
+        entries.extend(members.groups.iter().map(|group| item.group(group)));
+        entries.extend(members.invitees.iter().map(|invitee| item.invitee(invitee)));
+        let Some(cursor) = members.cursor else {
+            return Ok(entries);
+        };
+        let arg = ListFolderMembersContinueArg::new(cursor);
+        members = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_folder_members_continue(client, &arg)
+        })
+        .await
+        .map_err(|source| SharingAuditError::FolderMembersContinue {
+            shared_folder_id: item.id.clone(),
+            source,
+        })?;
+    }
+}
+
+/// List the files which have been shared with the account.
+async fn list_files(
+    client: &impl UserAuthClient,
+    options: &SharingAuditOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharedFileMetadata>, SharingAuditError> {
+    let arg = ListFilesArg::default();
+    let mut result = with_retry(options.max_retries, sleeper, || {
+        routes::sharing::list_received_files(client, &arg)
+    })
+    .await
+    .map_err(SharingAuditError::ListFiles)?;
+    let mut files = Vec::new();
+    loop {
+        files.extend(result.entries);
+        let Some(cursor) = result.cursor else {
+            return Ok(files);
+        };
+        let arg = ListFilesContinueArg::new(cursor);
+        result = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_received_files_continue(client, &arg)
+        })
+        .await
+        .map_err(SharingAuditError::ListFilesContinue)?;
+    }
+}
+
+fn batches(files: Vec<SharedFileMetadata>) -> Vec<Vec<SharedFileMetadata>> {
+    let mut batches = Vec::new();
+    let mut files = files.into_iter().peekable();
+    while files.peek().is_some() {
+        batches.push(files.by_ref().take(FILE_MEMBERS_BATCH_SIZE).collect());
+    }
+    batches
+}
+
+async fn file_members(
+    client: &impl UserAuthClient,
+    files: &[SharedFileMetadata],
+    options: &SharingAuditOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharingAuditEntry>, SharingAuditError> {
+    let items = files
+        .iter()
+        .map(|file| {
+            let item = Item {
+                kind: SharedItemKind::File,
+                id: file.id.clone(),
+                name: file.name.clone(),
+                path: file.path_display.clone(),
+            };
+            (file.id.clone(), item)
+        })
+        .collect::<HashMap<_, _>>();
+    let arg = ListFileMembersBatchArg::new(files.iter().map(|file| file.id.clone()).collect());
+    let results = with_retry(options.max_retries, sleeper, || {
+        routes::sharing::list_file_members_batch(client, &arg)
+    })
+    .await
+    .map_err(SharingAuditError::FileMembers)?;
+
+    let mut entries = Vec::new();
+    for result in results {
+        let Some(item) = items.get(&result.file) else {
+            warn!("unexpected file in members batch: {}", result.file);
+            continue;
+        };
+        let mut members: SharedFileMembers = match result.result {
+            ListFileMembersIndividualResult::Result(result) => result.members,
+            // Most likely it's stopped being shared since it was listed.
+            other => {
+                warn!("skipping shared file {}: {other:?}", item.id);
+                continue;
+            }
+        };
+        loop {
+            for user in members.users {
+                entries.push(item.user(&user.user, user.access_type, user.is_inherited));
+            }
+            entries.extend(members.groups.iter().map(|group| item.group(group)));
+            entries.extend(members.invitees.iter().map(|invitee| item.invitee(invitee)));
+            let Some(cursor) = members.cursor else {
+                break;
+            };
+            let arg = ListFileMembersContinueArg::new(cursor);
+            members = with_retry(options.max_retries, sleeper, || {
+                routes::sharing::list_file_members_continue(client, &arg)
+            })
+            .await
+            .map_err(|source| SharingAuditError::FileMembersContinue {
+                file: item.id.clone(),
+                source,
+            })?;
+        }
+    }
+    Ok(entries)
+}
+
+/// List the account's shared links.
+async fn links(
+    client: &impl UserAuthClient,
+    options: &SharingAuditOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<SharingAuditEntry>, SharingAuditError> {
+    let mut arg = ListSharedLinksArg::default();
+    let mut entries = Vec::new();
+    loop {
+        let result = with_retry(options.max_retries, sleeper, || {
+            routes::sharing::list_shared_links(client, &arg)
+        })
+        .await
+        .map_err(SharingAuditError::SharedLinks)?;
+        for link in result.links {
+            let (kind, url, name, id, path, permissions) = match link {
+                SharedLinkMetadata::File(file) => (
+                    SharedItemKind::File,
+                    file.url,
+                    file.name,
+                    file.id,
+                    file.path_lower,
+                    file.link_permissions,
+                ),
+                SharedLinkMetadata::Folder(folder) => (
+                    SharedItemKind::Folder,
+                    folder.url,
+                    folder.name,
+                    folder.id,
+                    folder.path_lower,
+                    folder.link_permissions,
+                ),
+                SharedLinkMetadata::Other => {
+                    warn!("skipping shared link of an unknown kind");
+                    continue;
+                }
+            };
+            let access = match permissions.link_access_level {
+                Some(LinkAccessLevel::Viewer) => AccessLevel::Viewer,
+                Some(LinkAccessLevel::Editor) => AccessLevel::Editor,
+                _ => AccessLevel::Other,
+            };
+            let external = matches!(
+                permissions.effective_audience,
+                Some(LinkAudience::Public) | None
+            );
+            entries.push(SharingAuditEntry {
+                kind,
+                id: id.unwrap_or_else(|| url.clone()),
+                name,
+                path,
+                grantee: Grantee::Link {
+                    url,
+                    audience: permissions.effective_audience,
+                },
+                access,
+                inherited: false,
+                external,
+            });
+        }
+        match result.cursor {
+            Some(cursor) if result.has_more => arg.cursor = Some(cursor),
+            _ => return Ok(entries),
+        }
+    }
+}
+
+/// The folder or file which entries are about.
+struct Item {
+    kind: SharedItemKind,
+    id: String,
+    name: String,
+    path: Option<String>,
+}
+
+impl Item {
+    fn entry(
+        &self,
+        grantee: Grantee,
+        access: AccessLevel,
+        inherited: bool,
+        external: bool,
+    ) -> SharingAuditEntry {
+        SharingAuditEntry {
+            kind: self.kind,
+            id: self.id.clone(),
+            name: self.name.clone(),
+            path: self.path.clone(),
+            grantee,
+            access,
+            inherited,
+            external,
+        }
+    }
+
+    fn user(&self, user: &UserInfo, access: AccessLevel, inherited: bool) -> SharingAuditEntry {
+        let grantee = Grantee::User {
+            account_id: user.account_id.clone(),
+            email: user.email.clone(),
+            display_name: user.display_name.clone(),
+        };
+        self.entry(grantee, access, inherited, !user.same_team)
+    }
+
+    fn group(&self, group: &GroupMembershipInfo) -> SharingAuditEntry {
+        let grantee = Grantee::Group {
+            group_id: group.group.group_id.clone(),
+            name: group.group.group_name.clone(),
+        };
+        self.entry(
+            grantee,
+            group.access_type.clone(),
+            group.is_inherited,
+            !group.group.same_team,
+        )
+    }
+
+    fn invitee(&self, invitee: &InviteeMembershipInfo) -> SharingAuditEntry {
+        let email = match &invitee.invitee {
+            InviteeInfo::Email(email) => email.clone(),
+            InviteeInfo::Other => String::new(),
+        };
+        let external = !invitee.user.as_ref().is_some_and(|user| user.same_team);
+        self.entry(
+            Grantee::Invitee { email },
+            invitee.access_type.clone(),
+            invitee.is_inherited,
+            external,
+        )
+    }
+}
+
+/// An entry's fields as text, in the order of the CSV columns.
+fn row(entry: &SharingAuditEntry) -> [String; 11] {
+    let kind = match entry.kind {
+        SharedItemKind::Folder => "folder",
+        SharedItemKind::File => "file",
+    };
+    let (grantee_type, grantee_id, grantee_name, email) = match &entry.grantee {
+        Grantee::User {
+            account_id,
+            email,
+            display_name,
+        } => (
+            "user",
+            account_id.clone(),
+            display_name.clone(),
+            email.clone(),
+        ),
+        Grantee::Group { group_id, name } => {
+            ("group", group_id.clone(), name.clone(), String::new())
+        }
+        Grantee::Invitee { email } => ("invitee", email.clone(), String::new(), email.clone()),
+        Grantee::Link { url, audience } => {
+            let audience = match audience {
+                Some(LinkAudience::Public) => "public",
+                Some(LinkAudience::Team) => "team",
+                Some(LinkAudience::NoOne) => "no_one",
+                _ => "other",
+            };
+            ("link", url.clone(), audience.to_owned(), String::new())
+        }
+    };
+    [
+        kind.to_owned(),
+        entry.id.clone(),
+        entry.name.clone(),
+        entry.path.clone().unwrap_or_default(),
+        grantee_type.to_owned(),
+        grantee_id,
+        grantee_name,
+        email,
+        access_level_name(&entry.access).to_owned(),
+        entry.inherited.to_string(),
+        entry.external.to_string(),
+    ]
+}
+
+/// The API's name for an access level, like `viewer_no_comment`.
+pub(super) fn access_level_name(access: &AccessLevel) -> &'static str {
+    match access {
+        AccessLevel::Owner => "owner",
+        AccessLevel::Editor => "editor",
+        AccessLevel::Viewer => "viewer",
+        AccessLevel::ViewerNoComment => "viewer_no_comment",
+        AccessLevel::Traverse => "traverse",
+        AccessLevel::NoAccess => "no_access",
+        _ => "other",
+    }
+}
+
+/// Quote a CSV field if it needs it. Fields which a spreadsheet would take as a formula, like a
+/// name starting with `=`, are prefixed with `'` so that they're shown as text instead.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{field}")
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Getting thumbnails of many files at once.
+//!
+//! [`get_thumbnail_batch`](crate::files::get_thumbnail_batch) returns the thumbnails of up to
+//! [`MAX_BATCH_ENTRIES`] files in a single request, encoded as base64 strings. [`get_thumbnails`]
+//! takes any number of paths, splits them into batches which are requested concurrently, and
+//! decodes the results.
+//!
+//! An entry which fails in a batch with an error that might not happen on its own, like a
+//! conversion error, or all the entries of a batch which fails as a whole, are fetched again one
+//! at a time with [`get_thumbnail_v2`](crate::files::get_thumbnail_v2). Errors which are a
+//! property of the file, like it not existing or not being an image, are returned as they are.
+
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    FileMetadata, GetThumbnailBatchArg, GetThumbnailBatchResultEntry, PathOrLink, ThumbnailArg,
+    ThumbnailError, ThumbnailFormat, ThumbnailMode, ThumbnailQuality, ThumbnailSize,
+    ThumbnailV2Arg, ThumbnailV2Error,
+};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::{AsyncReadExt, StreamExt};
+
+/// The most thumbnails which can be requested in a single batch. This is a Dropbox limit.
+pub const MAX_BATCH_ENTRIES: usize = 25;
+
+/// Options controlling which thumbnails [`get_thumbnails`] gets, and how.
+#[derive(Debug, Clone)]
+pub struct ThumbnailOptions {
+    format: ThumbnailFormat,
+    size: ThumbnailSize,
+    mode: ThumbnailMode,
+    quality: ThumbnailQuality,
+    parallelism: usize,
+    max_retries: u32,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            format: ThumbnailFormat::Jpeg,
+            size: ThumbnailSize::W64h64,
+            mode: ThumbnailMode::Strict,
+            quality: ThumbnailQuality::Quality80,
+            parallelism: 4,
+            max_retries: 3,
+        }
+    }
+}
+
+impl ThumbnailOptions {
+    /// The image format of the thumbnails. Defaults to JPEG.
+    pub fn with_format(mut self, value: ThumbnailFormat) -> Self {
+        self.format = value;
+        self
+    }
+
+    /// The size of the thumbnails. Defaults to 64 by 64 pixels.
+    pub fn with_size(mut self, value: ThumbnailSize) -> Self {
+        self.size = value;
+        self
+    }
+
+    /// How the images are resized to fit the thumbnail size. Defaults to
+    /// [`ThumbnailMode::Strict`].
+    pub fn with_mode(mut self, value: ThumbnailMode) -> Self {
+        self.mode = value;
+        self
+    }
+
+    /// The quality of JPEG thumbnails. Defaults to 80%.
+    pub fn with_quality(mut self, value: ThumbnailQuality) -> Self {
+        self.quality = value;
+        self
+    }
+
+    /// How many batches to request at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error. Rate-limited
+    /// requests are always retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    fn batch_arg(&self, path: &str) -> ThumbnailArg {
+        ThumbnailArg::new(path.to_owned())
+            .with_format(self.format.clone())
+            .with_size(self.size.clone())
+            .with_mode(self.mode.clone())
+            .with_quality(self.quality.clone())
+    }
+
+    fn single_arg(&self, path: &str) -> ThumbnailV2Arg {
+        ThumbnailV2Arg::new(PathOrLink::Path(path.to_owned()))
+            .with_format(self.format.clone())
+            .with_size(self.size.clone())
+            .with_mode(self.mode.clone())
+            .with_quality(self.quality.clone())
+    }
+}
+
+/// The thumbnail of a file.
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    /// The metadata of the file.
+    pub metadata: FileMetadata,
+
+    /// The thumbnail image, in the requested format.
+    pub data: Vec<u8>,
+}
+
+if_feature! { "sync_routes",
+    /// Get the thumbnails of any number of files, using a sync HTTP client.
+    ///
+    /// Returns one result for each path, in the same order as they were given.
+    pub fn get_thumbnails(
+        client: &impl crate::client_trait::UserAuthClient,
+        paths: Vec<String>,
+        options: &ThumbnailOptions,
+    ) -> Vec<Result<Thumbnail, Error<ThumbnailV2Error>>> {
+        super::parallel_map(batches(paths), options.parallelism, |batch| {
+            super::block_on_sync(get_batch(client, batch, options, Sleeper::Blocking))
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Get the thumbnails of any number of files.
+///
+/// Returns one result for each path, in the same order as they were given.
+pub async fn get_thumbnails_async(
+    client: &impl UserAuthClient,
+    paths: Vec<String>,
+    options: &ThumbnailOptions,
+) -> Vec<Result<Thumbnail, Error<ThumbnailV2Error>>> {
+    futures::stream::iter(batches(paths))
+        .map(|batch| get_batch(client, batch, options, Sleeper::Async))
+        .buffered(options.parallelism)
+        .flat_map(futures::stream::iter)
+        .collect()
+        .await
+}
+
+/// Split the paths into batches which can each be requested at once.
+fn batches(paths: Vec<String>) -> Vec<Vec<String>> {
+    let mut paths = paths.into_iter().peekable();
+    let mut batches = vec![];
+    while paths.peek().is_some() {
+        batches.push(paths.by_ref().take(MAX_BATCH_ENTRIES).collect());
+    }
+    batches
+}
+
+/// Get a batch of thumbnails, falling back to getting entries which failed one at a time.
+async fn get_batch(
+    client: &impl UserAuthClient,
+    paths: Vec<String>,
+    options: &ThumbnailOptions,
+    sleeper: Sleeper,
+) -> Vec<Result<Thumbnail, Error<ThumbnailV2Error>>> {
+    let arg = GetThumbnailBatchArg::new(paths.iter().map(|path| options.batch_arg(path)).collect());
+    let entries: Vec<Option<_>> = match with_retry(options.max_retries, sleeper, || {
+        routes::files::get_thumbnail_batch(client, &arg)
+    })
+    .await
+    {
+        Ok(result) if result.entries.len() == paths.len() => {
+            result.entries.into_iter().map(batch_result).collect()
+        }
+        Ok(result) => {
+            warn!(
+                "asked for {} thumbnails, got {}; getting them one at a time",
+                paths.len(),
+                result.entries.len()
+            );
+            paths.iter().map(|_| None).collect()
+        }
+        Err(e) => {
+            warn!("thumbnail batch failed: {e}; getting them one at a time");
+            paths.iter().map(|_| None).collect()
+        }
+    };
+
+    let mut results = Vec::with_capacity(paths.len());
+    for (path, entry) in paths.into_iter().zip(entries) {
+        results.push(match entry {
+            Some(result) => result,
+            None => get_single(client, &path, options, sleeper).await,
+        });
+    }
+    results
+}
+
+/// The result of one entry of a batch, or `None` if it should be tried again on its own.
+fn batch_result(
+    entry: GetThumbnailBatchResultEntry,
+) -> Option<Result<Thumbnail, Error<ThumbnailV2Error>>> {
+    match entry {
+        GetThumbnailBatchResultEntry::Success(result) => match STANDARD.decode(&result.thumbnail) {
+            Ok(data) => Some(Ok(Thumbnail {
+                metadata: result.metadata,
+                data,
+            })),
+            Err(e) => {
+                warn!(
+                    "bad thumbnail data for {:?}: {e}",
+                    result.metadata.path_display
+                );
+                None
+            }
+        },
+        GetThumbnailBatchResultEntry::Failure(ThumbnailError::ConversionError)
+        | GetThumbnailBatchResultEntry::Other => None,
+        GetThumbnailBatchResultEntry::Failure(e) => Some(Err(Error::Api(match e {
+            ThumbnailError::Path(e) => ThumbnailV2Error::Path(e),
+            ThumbnailError::UnsupportedExtension => ThumbnailV2Error::UnsupportedExtension,
+            ThumbnailError::UnsupportedImage => ThumbnailV2Error::UnsupportedImage,
+            ThumbnailError::EncryptedContent => ThumbnailV2Error::EncryptedContent,
+            ThumbnailError::ConversionError => ThumbnailV2Error::ConversionError,
+        }))),
+    }
+}
+
+/// Get a single thumbnail.
+async fn get_single(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &ThumbnailOptions,
+    sleeper: Sleeper,
+) -> Result<Thumbnail, Error<ThumbnailV2Error>> {
+    let arg = options.single_arg(path);
+    let response = with_retry(options.max_retries, sleeper, || {
+        routes::files::get_thumbnail_v2(client, &arg, None, None)
+    })
+    .await?;
+    let metadata = response.result.file_metadata.ok_or_else(|| {
+        Error::UnexpectedResponse("thumbnail response has no file metadata".to_owned())
+    })?;
+    let mut body = response
+        .body
+        .ok_or_else(|| Error::UnexpectedResponse("thumbnail response has no body".to_owned()))?;
+    let mut data = vec![];
+    body.read_to_end(&mut data)
+        .await
+        .map_err(|e| Error::HttpClient(Box::new(e)))?;
+    Ok(Thumbnail { metadata, data })
+}
@@ -0,0 +1,234 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Updating a small file in place, safely in the face of concurrent changes.
+//!
+//! [`update_with`] reads a file, passes its content to a function which returns the new content,
+//! and writes that back, but only if nothing else changed the file in the meantime: it's uploaded
+//! with [`WriteMode::Update`] and the revision that was read, and `strict_conflict`. If the file
+//! did change, the whole cycle is repeated with the new content. This makes it possible to use
+//! files like JSON manifests for coordination between several clients, without losing updates.
+//!
+//! Each upload is given its own `client_modified` time, so that an upload which was retried after
+//! a transient error, and conflicted with its own earlier attempt, can be told apart from another
+//! client writing the same content.
+//!
+//! The whole file is held in memory, and it's uploaded in a single request, so it can't be larger
+//! than 150 MiB.
+
+use super::content_hash::content_hash;
+use super::{Sleeper, routes, with_retry, with_retry_counted};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    DownloadArg, DownloadError, FileMetadata, LookupError, UploadArg, UploadError,
+    UploadWriteFailed, WriteError, WriteMode,
+};
+use futures::AsyncReadExt;
+
+/// Options controlling how [`update_with`] updates a file.
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    max_conflicts: u32,
+    create: bool,
+    max_retries: u32,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            max_conflicts: 5,
+            create: true,
+            max_retries: 3,
+        }
+    }
+}
+
+impl UpdateOptions {
+    /// How many times to read the file again and retry the update when it was changed by something
+    /// else in the meantime, before giving up. Defaults to 5.
+    pub fn with_max_conflicts(mut self, value: u32) -> Self {
+        self.max_conflicts = value;
+        self
+    }
+
+    /// Whether to create the file if it doesn't exist, in which case the update function is given
+    /// `None`. Otherwise, updating a file which doesn't exist fails with a not found error.
+    /// Defaults to true.
+    pub fn with_create(mut self, value: bool) -> Self {
+        self.create = value;
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+}
+
+/// An error updating a file.
+#[derive(thiserror::Error, Debug)]
+pub enum UpdateError {
+    /// Reading the file failed.
+    #[error("error downloading file: {0}")]
+    Download(#[source] Error<DownloadError>),
+
+    /// Writing the file failed, other than because it was changed in the meantime.
+    #[error("error uploading file: {0}")]
+    Upload(#[source] Error<UploadError>),
+
+    /// The file was changed by something else every time it was about to be written.
+    #[error("file kept being changed; gave up after {0} conflicts")]
+    TooManyConflicts(u32),
+}
+
+if_feature! { "sync_routes",
+    /// Update a file by applying a function to its content, using a sync HTTP client. The function
+    /// is given `None` if the file doesn't exist yet. It may be called more than once, if the file
+    /// is changed by something else while it's being updated.
+    ///
+    /// Returns the metadata of the file as written. If the function returns the content unchanged,
+    /// nothing is written, and the existing metadata is returned.
+    pub fn update_with(
+        client: &impl crate::client_trait::UserAuthClient,
+        path: &str,
+        options: &UpdateOptions,
+        f: impl FnMut(Option<&[u8]>) -> Vec<u8>,
+    ) -> Result<FileMetadata, UpdateError> {
+        super::block_on_sync(update_with_impl(client, path, options, f, Sleeper::Blocking))
+    }
+}
+
+/// Update a file by applying a function to its content. The function is given `None` if the file
+/// doesn't exist yet. It may be called more than once, if the file is changed by something else
+/// while it's being updated.
+///
+/// Returns the metadata of the file as written. If the function returns the content unchanged,
+/// nothing is written, and the existing metadata is returned.
+pub async fn update_with_async(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &UpdateOptions,
+    f: impl FnMut(Option<&[u8]>) -> Vec<u8>,
+) -> Result<FileMetadata, UpdateError> {
+    update_with_impl(client, path, options, f, Sleeper::Async).await
+}
+
+async fn update_with_impl(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &UpdateOptions,
+    mut f: impl FnMut(Option<&[u8]>) -> Vec<u8>,
+    sleeper: Sleeper,
+) -> Result<FileMetadata, UpdateError> {
+    let mut conflicts = 0;
+    let mut conflicted = false;
+    // The content hash and client_modified time of the last upload, if it conflicted after being
+    // retried.
+    let mut retried: Option<(String, String)> = None;
+    loop {
+        let current = match (read(client, path, options, sleeper).await?, retried.take()) {
+            // An upload which was retried after a transient error can conflict with itself, if an
+            // earlier attempt actually went through. The file has our write then, so don't apply
+            // the function to it again. Another client can write the same content too, so the
+            // time we gave is compared as well.
+            (Some((metadata, _)), Some((hash, time)))
+                if metadata.content_hash.as_ref() == Some(&hash)
+                    && metadata.client_modified == time =>
+            {
+                return Ok(metadata);
+            }
+            (current, _) => current,
+        };
+        if conflicted {
+            if conflicts == options.max_conflicts {
+                return Err(UpdateError::TooManyConflicts(conflicts));
+            }
+            conflicts += 1;
+            debug!("{path} was changed while updating it; retrying ({conflicts})");
+        }
+
+        let new = f(current.as_ref().map(|(_, data)| data.as_slice()));
+        let mode = match current {
+            Some((metadata, data)) if data == new => return Ok(metadata),
+            Some((metadata, _)) => WriteMode::Update(metadata.rev),
+            None => WriteMode::Add,
+        };
+
+        let hash = content_hash(&new);
+        let time = unique_timestamp();
+        let arg = UploadArg::new(path.to_owned())
+            .with_mode(mode)
+            .with_client_modified(time.clone())
+            .with_strict_conflict(true)
+            .with_content_hash(hash.clone());
+        let new = bytes::Bytes::from(new);
+        let (result, retries) = with_retry_counted(options.max_retries, sleeper, || {
+            routes::files::upload(client, &arg, new.clone())
+        })
+        .await;
+        match result {
+            Ok(metadata) => return Ok(metadata),
+            Err(Error::Api(UploadError::Path(UploadWriteFailed {
+                reason: WriteError::Conflict(_),
+                ..
+            }))) => {
+                conflicted = true;
+                if retries > 0 {
+                    retried = Some((hash, time));
+                }
+            }
+            Err(e) => return Err(UpdateError::Upload(e)),
+        }
+    }
+}
+
+/// A `client_modified` time for an upload: the current time, but at least a second after the one
+/// given to any earlier upload by this process, so that they can be told apart.
+fn unique_timestamp() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, SystemTime};
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let secs = LAST
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .map_or(now, |last| now.max(last + 1));
+    super::dropbox_timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Read the whole file and its metadata, or `None` if it doesn't exist and may be created.
+async fn read(
+    client: &impl UserAuthClient,
+    path: &str,
+    options: &UpdateOptions,
+    sleeper: Sleeper,
+) -> Result<Option<(FileMetadata, Vec<u8>)>, UpdateError> {
+    let arg = DownloadArg::new(path.to_owned());
+    let response = match with_retry(options.max_retries, sleeper, || {
+        routes::files::download(client, &arg, None, None)
+    })
+    .await
+    {
+        Ok(response) => response,
+        Err(Error::Api(DownloadError::Path(LookupError::NotFound))) if options.create => {
+            return Ok(None);
+        }
+        Err(e) => return Err(UpdateError::Download(e)),
+    };
+    let mut body = response.body.ok_or_else(|| {
+        UpdateError::Download(Error::UnexpectedResponse(
+            "download response has no body".to_owned(),
+        ))
+    })?;
+    let mut data = Vec::with_capacity(response.result.size as usize);
+    body.read_to_end(&mut data)
+        .await
+        .map_err(|e| UpdateError::Download(Error::HttpClient(Box::new(e))))?;
+    Ok(Some((response.result, data)))
+}
@@ -0,0 +1,513 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Uploading a whole local directory tree.
+//!
+//! [`upload_directory`] mirrors a local directory into a Dropbox folder:
+//!
+//! 1. The existing contents of the destination are listed, so that files which are already there
+//!    with the same content can be skipped.
+//! 2. The folder structure is created up front with
+//!    [`create_folder_batch`](crate::files::create_folder_batch), rather than one folder at a
+//!    time.
+//! 3. Files are uploaded concurrently, each the way [`upload_file`](super::upload_file) does it:
+//!    small files are sent in a single [`upload`](crate::files::upload) request, and larger ones
+//!    through an [`UploadSession`](super::upload_session::UploadSession).
+//!
+//! The outcome for each file is returned in a [`DirectoryUploadReport`].
+//!
+//! Symlinks to files are followed, but symlinks to folders are skipped, as they could form a cycle,
+//! and so are symlinks which don't point to anything. Files whose metadata can't be read are
+//! reported as failed, without stopping the others.
+
+use super::upload_file::{Existing, UploadFileError, UploadFileOptions, UploadFileOutcome};
+use super::upload_session::UploadOptions;
+use super::{JOB_POLL_INTERVAL, Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::dbx_async::{PollArg, PollError};
+use crate::types::files::{
+    CreateFolderBatchArg, CreateFolderBatchJobStatus, CreateFolderBatchLaunch,
+    CreateFolderBatchResultEntry, CreateFolderEntryError, FileMetadata, ListFolderArg,
+    ListFolderContinueArg, ListFolderContinueError, ListFolderError, LookupError, Metadata,
+    WriteConflictError, WriteError, WriteMode,
+};
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The most folders which can be created in a single call to `create_folder_batch`. This is a
+/// Dropbox limit.
+const MAX_FOLDER_BATCH: usize = 10_000;
+
+/// Options controlling how [`upload_directory`] uploads files.
+#[derive(Debug, Clone)]
+pub struct DirectoryUploadOptions {
+    parallelism: usize,
+    max_retries: u32,
+    /// How each file is uploaded.
+    file: UploadFileOptions,
+}
+
+impl Default for DirectoryUploadOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            max_retries: 3,
+            file: UploadFileOptions::default()
+                .with_skip_unchanged(true)
+                .with_session_threshold(32 * 1024 * 1024),
+        }
+    }
+}
+
+impl DirectoryUploadOptions {
+    /// How many files to upload at the same time. Defaults to 4.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// What to do if a file already exists at the destination. Defaults to [`WriteMode::Add`],
+    /// which never overwrites anything.
+    pub fn with_mode(mut self, value: WriteMode) -> Self {
+        self.file = self.file.with_mode(value);
+        self
+    }
+
+    /// Whether to rename files which conflict with existing ones, instead of failing. Defaults to
+    /// false.
+    pub fn with_autorename(mut self, value: bool) -> Self {
+        self.file = self.file.with_autorename(value);
+        self
+    }
+
+    /// Whether to skip files which already exist at the destination with the same content.
+    /// Defaults to true.
+    pub fn with_skip_unchanged(mut self, value: bool) -> Self {
+        self.file = self.file.with_skip_unchanged(value);
+        self
+    }
+
+    /// Files larger than this many bytes are uploaded through an upload session, and smaller ones
+    /// in a single request. Values are clamped to at most 150 MiB, the most a single request can
+    /// send. Defaults to 32 MiB.
+    pub fn with_session_threshold(mut self, value: u64) -> Self {
+        self.file = self.file.with_session_threshold(value);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self.file = self.file.with_max_retries(value);
+        self
+    }
+
+    /// Options for files which are uploaded through an upload session.
+    pub fn with_upload_options(mut self, value: UploadOptions) -> Self {
+        self.file = self.file.with_upload_options(value);
+        self
+    }
+}
+
+/// An error which stopped a directory upload from going ahead at all.
+#[derive(thiserror::Error, Debug)]
+pub enum DirectoryUploadError {
+    /// Reading the local directory tree failed.
+    #[error("error reading local directory: {0}")]
+    Io(#[from] io::Error),
+
+    /// Starting to list the destination folder, with `list_folder`, failed.
+    #[error("error listing destination folder: {0}")]
+    ListFolder(#[source] Error<ListFolderError>),
+
+    /// Getting the next page of the destination folder's listing, with `list_folder/continue`,
+    /// failed.
+    #[error("error listing destination folder: {0}")]
+    ListFolderContinue(#[source] Error<ListFolderContinueError>),
+
+    /// Creating the folder structure failed as a whole. Failures of individual folders are
+    /// reported in [`DirectoryUploadReport::folder_failures`] instead.
+    #[error("error creating folders: {0}")]
+    CreateFolders(#[source] Error<PollError>),
+}
+
+/// What happened to a single file.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // the success cases are by far the most common
+pub enum FileOutcome {
+    /// The file was uploaded.
+    Uploaded(FileMetadata),
+
+    /// The file already existed at the destination with the same content, so it was skipped.
+    Unchanged(FileMetadata),
+
+    /// Uploading the file failed.
+    Failed(UploadFileError),
+}
+
+impl From<Result<UploadFileOutcome, UploadFileError>> for FileOutcome {
+    fn from(result: Result<UploadFileOutcome, UploadFileError>) -> Self {
+        match result {
+            Ok(UploadFileOutcome::Uploaded(metadata)) => Self::Uploaded(metadata),
+            Ok(UploadFileOutcome::Unchanged(metadata)) => Self::Unchanged(metadata),
+            Err(e) => Self::Failed(e),
+        }
+    }
+}
+
+/// What happened to a single file, and where it came from and went to.
+#[derive(Debug)]
+pub struct FileReport {
+    /// The path of the local file.
+    pub local_path: PathBuf,
+
+    /// The path the file was uploaded to. If it was renamed due to a conflict, the path it ended
+    /// up at is in the metadata in `outcome`.
+    pub dropbox_path: String,
+
+    /// What happened to the file.
+    pub outcome: FileOutcome,
+}
+
+/// The outcome of uploading a directory.
+#[derive(Debug, Default)]
+pub struct DirectoryUploadReport {
+    /// What happened to each file, in the order of their local paths.
+    pub files: Vec<FileReport>,
+
+    /// Folders which couldn't be created, and why. Files in them probably failed to upload too.
+    pub folder_failures: Vec<(String, CreateFolderEntryError)>,
+}
+
+if_feature! { "sync_routes",
+    /// Upload the contents of a local directory, recursively, into a folder on Dropbox, using a
+    /// sync HTTP client. The folder is created if it doesn't exist.
+    pub fn upload_directory(
+        client: &impl crate::client_trait::UserAuthClient,
+        local: &Path,
+        dropbox_path: &str,
+        options: &DirectoryUploadOptions,
+    ) -> Result<DirectoryUploadReport, DirectoryUploadError> {
+        let plan = super::block_on_sync(Plan::new(
+            client,
+            local,
+            dropbox_path,
+            options,
+            Sleeper::Blocking,
+        ))?;
+        let outcomes = super::parallel_map(
+            plan.to_upload(),
+            options.parallelism,
+            |(file, remote)| {
+                super::upload_file::upload_file_to(
+                    client,
+                    &file.local_path,
+                    &file.dropbox_path,
+                    &options.file,
+                    Existing::Known(remote),
+                )
+            },
+        );
+        Ok(plan.into_report(outcomes))
+    }
+}
+
+/// Upload the contents of a local directory, recursively, into a folder on Dropbox. The folder is
+/// created if it doesn't exist.
+///
+/// Note that the local file system is accessed synchronously.
+pub async fn upload_directory_async(
+    client: &impl UserAuthClient,
+    local: &Path,
+    dropbox_path: &str,
+    options: &DirectoryUploadOptions,
+) -> Result<DirectoryUploadReport, DirectoryUploadError> {
+    let plan = Plan::new(client, local, dropbox_path, options, Sleeper::Async).await?;
+    let outcomes = futures::stream::iter(plan.to_upload())
+        .map(|(file, remote)| {
+            super::upload_file::upload_file_to_async(
+                client,
+                &file.local_path,
+                &file.dropbox_path,
+                &options.file,
+                Existing::Known(remote),
+            )
+        })
+        .buffered(options.parallelism)
+        .collect::<Vec<_>>()
+        .await;
+    Ok(plan.into_report(outcomes))
+}
+
+/// A local file to upload.
+#[derive(Debug)]
+struct LocalFile {
+    local_path: PathBuf,
+    dropbox_path: String,
+}
+
+/// What needs to be uploaded, after the folders have been created.
+struct Plan {
+    files: Vec<LocalFile>,
+    /// Local files which couldn't be looked at, with their Dropbox paths.
+    unreadable: Vec<(PathBuf, String, io::Error)>,
+    /// Files which exist at the destination already, by lowercased path.
+    remote: HashMap<String, FileMetadata>,
+    folder_failures: Vec<(String, CreateFolderEntryError)>,
+}
+
+impl Plan {
+    /// Walk the local tree, list the destination, and create any missing folders.
+    async fn new(
+        client: &impl UserAuthClient,
+        local: &Path,
+        dropbox_path: &str,
+        options: &DirectoryUploadOptions,
+        sleeper: Sleeper,
+    ) -> Result<Self, DirectoryUploadError> {
+        let root = dropbox_path.trim_end_matches('/');
+        let mut folders = vec![root.to_owned()];
+        let mut files = vec![];
+        let mut unreadable = vec![];
+        walk(local, root, &mut folders, &mut files, &mut unreadable)?;
+
+        let (remote, remote_folders) = list_remote(client, root, options, sleeper).await?;
+
+        // Creating a folder creates its parents too, so only the deepest ones need creating.
+        let parents = folders
+            .iter()
+            .filter_map(|path| {
+                path.rsplit_once('/')
+                    .map(|(parent, _)| parent.to_lowercase())
+            })
+            .collect::<HashSet<_>>();
+        let to_create = folders
+            .into_iter()
+            .filter(|path| {
+                let lower = path.to_lowercase();
+                !path.is_empty() && !parents.contains(&lower) && !remote_folders.contains(&lower)
+            })
+            .collect::<Vec<_>>();
+        let folder_failures = create_folders(client, to_create, options, sleeper).await?;
+
+        Ok(Self {
+            files,
+            unreadable,
+            remote,
+            folder_failures,
+        })
+    }
+
+    /// Each file to upload, along with the existing file at its destination, if any.
+    fn to_upload(&self) -> Vec<(&LocalFile, Option<&FileMetadata>)> {
+        self.files
+            .iter()
+            .map(|file| (file, self.remote.get(&file.dropbox_path.to_lowercase())))
+            .collect()
+    }
+
+    fn into_report(
+        self,
+        outcomes: Vec<Result<UploadFileOutcome, UploadFileError>>,
+    ) -> DirectoryUploadReport {
+        let mut files = self
+            .files
+            .into_iter()
+            .zip(outcomes)
+            .map(|(file, outcome)| FileReport {
+                local_path: file.local_path,
+                dropbox_path: file.dropbox_path,
+                outcome: outcome.into(),
+            })
+            .collect::<Vec<_>>();
+        if !self.unreadable.is_empty() {
+            files.extend(
+                self.unreadable
+                    .into_iter()
+                    .map(|(local_path, dropbox_path, e)| FileReport {
+                        local_path,
+                        dropbox_path,
+                        outcome: FileOutcome::Failed(UploadFileError::Io(e)),
+                    }),
+            );
+            files.sort_by(|a, b| a.local_path.cmp(&b.local_path));
+        }
+        DirectoryUploadReport {
+            files,
+            folder_failures: self.folder_failures,
+        }
+    }
+}
+
+/// Recursively collect the folders and files under `local`, with their Dropbox paths under
+/// `dropbox_path`. Entries whose metadata can't be read go in `unreadable`.
+fn walk(
+    local: &Path,
+    dropbox_path: &str,
+    folders: &mut Vec<String>,
+    files: &mut Vec<LocalFile>,
+    unreadable: &mut Vec<(PathBuf, String, io::Error)>,
+) -> io::Result<()> {
+    let mut entries = std::fs::read_dir(local)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("file name is not valid UTF-8: {:?}", entry.path()),
+            )
+        })?;
+        let path = format!("{dropbox_path}/{name}");
+        let is_symlink = entry.file_type()?.is_symlink();
+        let metadata = if is_symlink {
+            // This follows the link.
+            match std::fs::metadata(entry.path()) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    warn!("skipping broken symlink {:?}: {e}", entry.path());
+                    continue;
+                }
+            }
+        } else {
+            match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    unreadable.push((entry.path(), path, e));
+                    continue;
+                }
+            }
+        };
+        if metadata.is_dir() {
+            // A symlink to a folder could point back up the tree, so don't go into it.
+            if is_symlink {
+                warn!("skipping symlink to folder {:?}", entry.path());
+                continue;
+            }
+            folders.push(path.clone());
+            walk(&entry.path(), &path, folders, files, unreadable)?;
+        } else if metadata.is_file() {
+            files.push(LocalFile {
+                local_path: entry.path(),
+                dropbox_path: path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// List everything under the destination, returning the files and the folders, both by lowercased
+/// path. The destination not existing is not an error.
+async fn list_remote(
+    client: &impl UserAuthClient,
+    root: &str,
+    options: &DirectoryUploadOptions,
+    sleeper: Sleeper,
+) -> Result<(HashMap<String, FileMetadata>, HashSet<String>), DirectoryUploadError> {
+    let mut files = HashMap::new();
+    let mut folders = HashSet::new();
+    let arg = ListFolderArg::new(root.to_owned()).with_recursive(true);
+    let mut result = match with_retry(options.max_retries, sleeper, || {
+        routes::files::list_folder(client, &arg)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(Error::Api(ListFolderError::Path(LookupError::NotFound))) => {
+            return Ok((files, folders));
+        }
+        Err(e) => return Err(DirectoryUploadError::ListFolder(e)),
+    };
+    loop {
+        for entry in result.entries {
+            match entry {
+                Metadata::File(file) => {
+                    if let Some(path) = file.path_lower.clone() {
+                        files.insert(path, file);
+                    }
+                }
+                Metadata::Folder(folder) => folders.extend(folder.path_lower),
+                Metadata::Deleted(_) => (),
+            }
+        }
+        if !result.has_more {
+            return Ok((files, folders));
+        }
+        let arg = ListFolderContinueArg::new(result.cursor);
+        result = with_retry(options.max_retries, sleeper, || {
+            routes::files::list_folder_continue(client, &arg)
+        })
+        .await
+        .map_err(DirectoryUploadError::ListFolderContinue)?;
+    }
+}
+
+/// Create the given folders in batches, returning the ones which failed. Folders which already
+/// exist are not failures.
+async fn create_folders(
+    client: &impl UserAuthClient,
+    paths: Vec<String>,
+    options: &DirectoryUploadOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<(String, CreateFolderEntryError)>, DirectoryUploadError> {
+    let mut failures = vec![];
+    for batch in paths.chunks(MAX_FOLDER_BATCH) {
+        let arg = CreateFolderBatchArg::new(batch.to_vec());
+        let launch = with_retry(options.max_retries, sleeper, || {
+            routes::files::create_folder_batch(client, &arg)
+        })
+        .await
+        .map_err(|e| DirectoryUploadError::CreateFolders(e.typed()))?;
+        let result = match launch {
+            CreateFolderBatchLaunch::Complete(result) => result,
+            CreateFolderBatchLaunch::AsyncJobId(id) => {
+                let arg = PollArg::new(id);
+                loop {
+                    sleeper.sleep(JOB_POLL_INTERVAL).await;
+                    match with_retry(options.max_retries, sleeper, || {
+                        routes::files::create_folder_batch_check(client, &arg)
+                    })
+                    .await
+                    .map_err(DirectoryUploadError::CreateFolders)?
+                    {
+                        CreateFolderBatchJobStatus::InProgress => (),
+                        CreateFolderBatchJobStatus::Complete(result) => break result,
+                        CreateFolderBatchJobStatus::Failed(e) => {
+                            return Err(DirectoryUploadError::CreateFolders(
+                                Error::UnexpectedResponse(format!(
+                                    "create_folder_batch job failed: {e}"
+                                )),
+                            ));
+                        }
+                        CreateFolderBatchJobStatus::Other => {
+                            return Err(DirectoryUploadError::CreateFolders(
+                                Error::UnexpectedResponse(
+                                    "unknown create_folder_batch job status".to_owned(),
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+            CreateFolderBatchLaunch::Other => {
+                return Err(DirectoryUploadError::CreateFolders(
+                    Error::UnexpectedResponse("unknown create_folder_batch result".to_owned()),
+                ));
+            }
+        };
+        for (path, entry) in batch.iter().zip(result.entries) {
+            match entry {
+                CreateFolderBatchResultEntry::Success(_)
+                | CreateFolderBatchResultEntry::Failure(CreateFolderEntryError::Path(
+                    WriteError::Conflict(WriteConflictError::Folder),
+                )) => (),
+                CreateFolderBatchResultEntry::Failure(e) => failures.push((path.clone(), e)),
+            }
+        }
+    }
+    Ok(failures)
+}
@@ -0,0 +1,340 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Uploading a single local file.
+//!
+//! [`upload_file`] picks the right way to upload a file based on its size: files up to 150 MiB are
+//! sent in a single [`upload`](crate::files::upload) request, and larger ones through an
+//! [`UploadSession`]. The file's modification time is kept as its `client_modified` time, and its
+//! content hash is sent along with it either way, so that the server checks it before committing.
+//! Optionally, the upload is skipped if the file is already there with the same content.
+
+use super::content_hash::ContentHasher;
+use super::upload_session::{UploadOptions, UploadSession, UploadSessionError};
+use super::{MAX_SINGLE_UPLOAD, Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, FileMetadata, GetMetadataArg, GetMetadataError, LookupError, Metadata, UploadArg,
+    UploadError, UploadSessionStartError, WriteMode,
+};
+use bytes::Bytes;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+/// Options controlling how [`upload_file`] uploads a file.
+#[derive(Debug, Clone)]
+pub struct UploadFileOptions {
+    mode: WriteMode,
+    autorename: bool,
+    skip_unchanged: bool,
+    session_threshold: u64,
+    max_retries: u32,
+    upload: UploadOptions,
+}
+
+impl Default for UploadFileOptions {
+    fn default() -> Self {
+        Self {
+            mode: WriteMode::Add,
+            autorename: false,
+            skip_unchanged: false,
+            session_threshold: MAX_SINGLE_UPLOAD,
+            max_retries: 3,
+            upload: UploadOptions::default(),
+        }
+    }
+}
+
+impl UploadFileOptions {
+    /// What to do if a file already exists at the destination. Defaults to [`WriteMode::Add`],
+    /// which never overwrites anything.
+    pub fn with_mode(mut self, value: WriteMode) -> Self {
+        self.mode = value;
+        self
+    }
+
+    /// Whether to rename the file if it conflicts with an existing one, instead of failing.
+    /// Defaults to false.
+    pub fn with_autorename(mut self, value: bool) -> Self {
+        self.autorename = value;
+        self
+    }
+
+    /// Whether to skip the upload if a file with the same content already exists at the
+    /// destination. This costs an extra request, and reading the local file an extra time if the
+    /// remote file has the same size. Defaults to false.
+    pub fn with_skip_unchanged(mut self, value: bool) -> Self {
+        self.skip_unchanged = value;
+        self
+    }
+
+    /// Files larger than this many bytes are uploaded through an upload session, and smaller ones
+    /// in a single request. Values are clamped to at most 150 MiB, the most a single request can
+    /// send, which is also the default.
+    pub fn with_session_threshold(mut self, value: u64) -> Self {
+        self.session_threshold = value.min(MAX_SINGLE_UPLOAD);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// Options for files which are uploaded through an upload session.
+    pub fn with_upload_options(mut self, value: UploadOptions) -> Self {
+        self.upload = value;
+        self
+    }
+}
+
+/// An error uploading a file.
+#[derive(thiserror::Error, Debug)]
+pub enum UploadFileError {
+    /// Reading the local file failed.
+    #[error("error reading local file: {0}")]
+    Io(#[from] io::Error),
+
+    /// Looking up the existing file at the destination failed.
+    #[error("error getting metadata of existing file: {0}")]
+    GetMetadata(#[source] Error<GetMetadataError>),
+
+    /// Uploading the file in a single request failed.
+    #[error("error uploading file: {0}")]
+    Upload(#[source] Error<UploadError>),
+
+    /// Starting an upload session for the file failed.
+    #[error("error starting upload session: {0}")]
+    StartSession(#[source] Error<UploadSessionStartError>),
+
+    /// Uploading the file through an upload session failed.
+    #[error("error uploading file: {0}")]
+    Session(#[source] UploadSessionError),
+}
+
+/// What happened to the file.
+#[derive(Debug, Clone)]
+pub enum UploadFileOutcome {
+    /// The file was uploaded.
+    Uploaded(FileMetadata),
+
+    /// The file already existed at the destination with the same content, so it was skipped.
+    Unchanged(FileMetadata),
+}
+
+impl UploadFileOutcome {
+    /// The metadata of the file on Dropbox, whether it was uploaded or not.
+    pub fn metadata(&self) -> &FileMetadata {
+        match self {
+            Self::Uploaded(metadata) | Self::Unchanged(metadata) => metadata,
+        }
+    }
+}
+
+/// What's known about the file at the destination before uploading, for skipping unchanged files.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Existing<'a> {
+    /// Nothing yet; it's looked up if need be.
+    Unknown,
+    /// It was looked up already, along with others: this is the file there, if any.
+    Known(Option<&'a FileMetadata>),
+}
+
+if_feature! { "sync_routes",
+    /// Upload a local file to the given path on Dropbox, using a sync HTTP client.
+    pub fn upload_file(
+        client: &impl crate::client_trait::UserAuthClient,
+        local_path: impl AsRef<Path>,
+        dropbox_path: &str,
+        options: &UploadFileOptions,
+    ) -> Result<UploadFileOutcome, UploadFileError> {
+        upload_file_to(client, local_path.as_ref(), dropbox_path, options, Existing::Unknown)
+    }
+
+    /// Like [`upload_file`], given what's at the destination already.
+    pub(crate) fn upload_file_to(
+        client: &impl crate::client_trait::UserAuthClient,
+        local_path: &Path,
+        dropbox_path: &str,
+        options: &UploadFileOptions,
+        existing: Existing<'_>,
+    ) -> Result<UploadFileOutcome, UploadFileError> {
+        let local = LocalFile::open(local_path)?;
+        if let Some(metadata) = super::block_on_sync(unchanged(
+            client,
+            &local,
+            dropbox_path,
+            existing,
+            options,
+            Sleeper::Blocking,
+        ))? {
+            return Ok(UploadFileOutcome::Unchanged(metadata));
+        }
+        let commit = local.commit_info(dropbox_path, options);
+        let metadata = if local.size <= options.session_threshold {
+            super::block_on_sync(upload_small(client, local, commit, options, Sleeper::Blocking))?
+        } else {
+            UploadSession::start(client, options.upload.clone())
+                .map_err(UploadFileError::StartSession)?
+                .upload(client, local.file, commit)
+                .map_err(UploadFileError::Session)?
+        };
+        Ok(UploadFileOutcome::Uploaded(metadata))
+    }
+}
+
+/// Upload a local file to the given path on Dropbox.
+///
+/// Note that the local file is read synchronously.
+pub async fn upload_file_async(
+    client: &impl UserAuthClient,
+    local_path: impl AsRef<Path>,
+    dropbox_path: &str,
+    options: &UploadFileOptions,
+) -> Result<UploadFileOutcome, UploadFileError> {
+    upload_file_to_async(
+        client,
+        local_path.as_ref(),
+        dropbox_path,
+        options,
+        Existing::Unknown,
+    )
+    .await
+}
+
+/// Like [`upload_file_async`], given what's at the destination already.
+pub(crate) async fn upload_file_to_async(
+    client: &impl UserAuthClient,
+    local_path: &Path,
+    dropbox_path: &str,
+    options: &UploadFileOptions,
+    existing: Existing<'_>,
+) -> Result<UploadFileOutcome, UploadFileError> {
+    let local = LocalFile::open(local_path)?;
+    let sleeper = Sleeper::Async;
+    if let Some(metadata) =
+        unchanged(client, &local, dropbox_path, existing, options, sleeper).await?
+    {
+        return Ok(UploadFileOutcome::Unchanged(metadata));
+    }
+    let commit = local.commit_info(dropbox_path, options);
+    let metadata = if local.size <= options.session_threshold {
+        upload_small(client, local, commit, options, sleeper).await?
+    } else {
+        UploadSession::start_async(client, options.upload.clone())
+            .await
+            .map_err(UploadFileError::StartSession)?
+            .upload_async(client, futures::io::AllowStdIo::new(local.file), commit)
+            .await
+            .map_err(UploadFileError::Session)?
+    };
+    Ok(UploadFileOutcome::Uploaded(metadata))
+}
+
+/// The local file being uploaded.
+struct LocalFile {
+    file: File,
+    size: u64,
+    client_modified: Option<String>,
+}
+
+impl LocalFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} is not a file"),
+            ));
+        }
+        Ok(Self {
+            file,
+            size: metadata.len(),
+            // Not every platform records modification times.
+            client_modified: metadata.modified().ok().map(super::dropbox_timestamp),
+        })
+    }
+
+    fn commit_info(&self, dropbox_path: &str, options: &UploadFileOptions) -> CommitInfo {
+        let commit = CommitInfo::new(dropbox_path.to_owned())
+            .with_mode(options.mode.clone())
+            .with_autorename(options.autorename);
+        match &self.client_modified {
+            Some(time) => commit.with_client_modified(time.clone()),
+            None => commit,
+        }
+    }
+}
+
+/// If skipping unchanged files is enabled, and the file at the destination has the same content
+/// as the local one, return its metadata. The destination is looked up unless it's known already.
+async fn unchanged(
+    client: &impl UserAuthClient,
+    local: &LocalFile,
+    dropbox_path: &str,
+    existing: Existing<'_>,
+    options: &UploadFileOptions,
+    sleeper: Sleeper,
+) -> Result<Option<FileMetadata>, UploadFileError> {
+    if !options.skip_unchanged {
+        return Ok(None);
+    }
+    let remote = match existing {
+        Existing::Known(Some(remote)) => remote.clone(),
+        Existing::Known(None) => return Ok(None),
+        Existing::Unknown => {
+            let arg = GetMetadataArg::new(dropbox_path.to_owned());
+            match with_retry(options.max_retries, sleeper, || {
+                routes::files::get_metadata(client, &arg)
+            })
+            .await
+            {
+                Ok(Metadata::File(remote)) => remote,
+                Ok(_) | Err(Error::Api(GetMetadataError::Path(LookupError::NotFound))) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(UploadFileError::GetMetadata(e)),
+            }
+        }
+    };
+    if remote.size != local.size {
+        return Ok(None);
+    }
+    let Some(remote_hash) = &remote.content_hash else {
+        return Ok(None);
+    };
+    let mut hasher = ContentHasher::new();
+    io::copy(&mut &local.file, &mut hasher)?;
+    (&local.file).rewind()?;
+    Ok((hasher.finish() == *remote_hash).then_some(remote))
+}
+
+/// Upload a file in a single request, which the server checks against its content hash.
+async fn upload_small(
+    client: &impl UserAuthClient,
+    mut local: LocalFile,
+    commit: CommitInfo,
+    options: &UploadFileOptions,
+    sleeper: Sleeper,
+) -> Result<FileMetadata, UploadFileError> {
+    let mut data = Vec::with_capacity(local.size as usize);
+    local.file.read_to_end(&mut data)?;
+    let mut hasher = ContentHasher::new();
+    hasher.update(&data);
+    let mut arg = UploadArg::new(commit.path)
+        .with_mode(commit.mode)
+        .with_autorename(commit.autorename)
+        .with_content_hash(hasher.finish());
+    arg.client_modified = commit.client_modified;
+    let data = Bytes::from(data);
+    with_retry(options.max_retries, sleeper, || {
+        routes::files::upload(client, &arg, data.clone())
+    })
+    .await
+    .map_err(UploadFileError::Upload)
+}
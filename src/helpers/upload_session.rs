@@ -0,0 +1,477 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Parallel, resumable uploads of large files using upload sessions.
+//!
+//! A single [`upload`](crate::files::upload) request can't be larger than 150 MiB. Larger files
+//! need to go through an upload session: the file is sent in pieces using
+//! [`upload_session_append_v2`](crate::files::upload_session_append_v2), and then committed with
+//! [`upload_session_finish`](crate::files::upload_session_finish).
+//!
+//! [`UploadSession`] takes care of the details: it uses a *concurrent* upload session so that
+//! blocks can be sent in parallel, retries blocks which fail with transient errors, reports
+//! progress, and keeps track of how much of the file has been completely uploaded so that an
+//! interrupted upload can be resumed later, even from a different process, using a
+//! [`ResumeState`].
+//!
+//! An upload which starts from the beginning of the source also computes its content hash on the
+//! way, and sends it when committing, so the server refuses to commit a file whose content didn't
+//! arrive intact.
+//!
+//! See `examples/large-file-upload.rs` for a complete example.
+
+use super::content_hash::ContentHasher;
+use super::{Sleeper, routes, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, FileMetadata, UploadSessionAppendArg, UploadSessionAppendError,
+    UploadSessionCursor, UploadSessionFinishArg, UploadSessionFinishError, UploadSessionStartArg,
+    UploadSessionStartError, UploadSessionType,
+};
+use bytes::Bytes;
+use futures::{AsyncRead, AsyncReadExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The size of a block. This is a Dropbox constant, not adjustable.
+///
+/// All requests to a concurrent upload session, except for the last one, must be an integer
+/// multiple of this size.
+pub const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The most blocks which fit in a single request, which can't be larger than 150 MiB.
+const MAX_BLOCKS_PER_REQUEST: usize = 37;
+
+/// Options controlling how an [`UploadSession`] uploads data.
+#[derive(Clone)]
+pub struct UploadOptions {
+    parallelism: usize,
+    blocks_per_request: usize,
+    max_retries: u32,
+    progress: Option<Arc<dyn Fn(u64) + Send + Sync>>,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 8,
+            blocks_per_request: 2,
+            max_retries: 3,
+            progress: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for UploadOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadOptions")
+            .field("parallelism", &self.parallelism)
+            .field("blocks_per_request", &self.blocks_per_request)
+            .field("max_retries", &self.max_retries)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl UploadOptions {
+    /// How many requests to have in flight at the same time. Each one holds a buffer of
+    /// `blocks_per_request` blocks in memory. Defaults to 8.
+    pub fn with_parallelism(mut self, value: usize) -> Self {
+        self.parallelism = value.max(1);
+        self
+    }
+
+    /// How many [`BLOCK_SIZE`] blocks to send in each request. Sending more than one per request
+    /// reduces the number of requests needed and can help avoid running into rate limits. Values
+    /// are clamped to between 1 and 37, because a request can't be larger than 150 MiB. Defaults
+    /// to 2.
+    pub fn with_blocks_per_request(mut self, value: usize) -> Self {
+        self.blocks_per_request = value.clamp(1, MAX_BLOCKS_PER_REQUEST);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error (a server error or an
+    /// error from the HTTP client) before giving up. Requests which are rate-limited are always
+    /// retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function to call whenever a request completes, with the offset up to which the source
+    /// has been uploaded so far. Because requests complete out of order, this counts all bytes
+    /// sent, not only the contiguous prefix recorded in [`ResumeState::offset`].
+    pub fn with_progress(mut self, f: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    pub(crate) fn chunk_size(&self) -> usize {
+        BLOCK_SIZE * self.blocks_per_request
+    }
+}
+
+/// Everything needed to resume an interrupted upload, possibly from a different process.
+///
+/// This can be serialized with serde, and passed to [`UploadSession::resume`] later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeState {
+    /// The upload session ID.
+    pub session_id: String,
+
+    /// The offset up to which the source has been completely uploaded. When resuming, the source
+    /// must be positioned at this offset.
+    pub offset: u64,
+}
+
+/// An error that occurred while uploading through an [`UploadSession`].
+///
+/// When this is returned, [`UploadSession::resume_state`] says where the upload can be resumed
+/// from.
+#[derive(thiserror::Error, Debug)]
+pub enum UploadSessionError {
+    /// Reading from the source failed.
+    #[error("error reading upload source: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Appending data to the session failed, even after retrying.
+    #[error("error appending to upload session: {0}")]
+    Append(#[source] Error<UploadSessionAppendError>),
+
+    /// Committing the uploaded file failed, even after retrying.
+    #[error("error finishing upload session: {0}")]
+    Finish(#[source] Error<UploadSessionFinishError>),
+}
+
+/// A concurrent upload session, and the state of an upload through it.
+#[derive(Debug)]
+pub struct UploadSession {
+    session_id: String,
+    start_offset: u64,
+    options: UploadOptions,
+    bytes_transferred: AtomicU64,
+    completion: Mutex<CompletionTracker>,
+}
+
+impl UploadSession {
+    if_feature! { "sync_routes",
+        /// Start a new upload session, using a sync HTTP client.
+        pub fn start(
+            client: &impl crate::client_trait::UserAuthClient,
+            options: UploadOptions,
+        ) -> Result<Self, Error<UploadSessionStartError>> {
+            super::block_on_sync(Self::start_impl(client, options, Sleeper::Blocking))
+        }
+    }
+
+    /// Start a new upload session.
+    pub async fn start_async(
+        client: &impl UserAuthClient,
+        options: UploadOptions,
+    ) -> Result<Self, Error<UploadSessionStartError>> {
+        Self::start_impl(client, options, Sleeper::Async).await
+    }
+
+    pub(crate) async fn start_impl(
+        client: &impl UserAuthClient,
+        options: UploadOptions,
+        sleeper: Sleeper,
+    ) -> Result<Self, Error<UploadSessionStartError>> {
+        let arg = UploadSessionStartArg::default().with_session_type(UploadSessionType::Concurrent);
+        let result = with_retry(options.max_retries, sleeper, || {
+            routes::files::upload_session_start(client, &arg, Bytes::new())
+        })
+        .await?;
+        debug!("started upload session {}", result.session_id);
+        Ok(Self::resume(
+            ResumeState {
+                session_id: result.session_id,
+                offset: 0,
+            },
+            options,
+        ))
+    }
+
+    /// Resume a previously interrupted upload session. No request is made until data is uploaded.
+    pub fn resume(state: ResumeState, options: UploadOptions) -> Self {
+        Self {
+            session_id: state.session_id,
+            start_offset: state.offset,
+            options,
+            bytes_transferred: AtomicU64::new(0),
+            completion: Mutex::new(CompletionTracker::resume_from(state.offset)),
+        }
+    }
+
+    /// The upload session ID.
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Where the upload can be resumed from if it gets interrupted.
+    pub fn resume_state(&self) -> ResumeState {
+        ResumeState {
+            session_id: self.session_id.clone(),
+            offset: self.completion.lock().unwrap().complete_up_to,
+        }
+    }
+
+    if_feature! { "sync_routes",
+        /// Upload the contents of `source` and commit them as a file, using a sync HTTP client.
+        ///
+        /// The source is read sequentially on the calling thread, and requests are sent from a
+        /// pool of worker threads. If the session was resumed, `source` must be positioned at
+        /// [`ResumeState::offset`] already.
+        pub fn upload(
+            &self,
+            client: &impl crate::client_trait::UserAuthClient,
+            mut source: impl std::io::Read,
+            commit: CommitInfo,
+        ) -> Result<FileMetadata, UploadSessionError> {
+            use std::io::Read;
+            use std::sync::mpsc;
+
+            let chunk_size = self.options.chunk_size();
+            // The content hash can only be worked out if the whole source is read.
+            let mut hasher = (self.start_offset == 0).then(ContentHasher::new);
+            let (last_offset, last_data) = std::thread::scope(|s| {
+                let (tx, rx) = mpsc::sync_channel::<(u64, Vec<u8>)>(self.options.parallelism);
+                let rx = Arc::new(Mutex::new(rx));
+                let workers = (0..self.options.parallelism)
+                    .map(|_| {
+                        // Each worker holds a reference to the receiver, so if they all fail, the
+                        // sender finds out and stops reading the source.
+                        let rx = Arc::clone(&rx);
+                        s.spawn(move || loop {
+                            let Ok((offset, data)) = rx.lock().unwrap().recv() else {
+                                return Ok::<_, UploadSessionError>(());
+                            };
+                            super::block_on_sync(self.append(
+                                client,
+                                offset,
+                                Bytes::from(data),
+                                false,
+                                Sleeper::Blocking,
+                            ))
+                            .map_err(UploadSessionError::Append)?;
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                drop(rx);
+
+                let mut offset = self.start_offset;
+                let mut last = None;
+                loop {
+                    let mut data = Vec::with_capacity(chunk_size);
+                    (&mut source).take(chunk_size as u64).read_to_end(&mut data)?;
+                    if let Some(hasher) = &mut hasher {
+                        hasher.update(&data);
+                    }
+                    if data.len() < chunk_size {
+                        // Only the last request is allowed to not be a multiple of BLOCK_SIZE, and
+                        // once the session is closed by it, it can't be resumed. Hold onto it
+                        // until everything else is uploaded.
+                        last = Some((offset, data));
+                        break;
+                    }
+                    let len = data.len() as u64;
+                    if tx.send((offset, data)).is_err() {
+                        break;
+                    }
+                    offset += len;
+                }
+                drop(tx);
+
+                for worker in workers {
+                    worker
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+                }
+                Ok::<_, UploadSessionError>(
+                    last.expect("workers only stop early when they return an error"),
+                )
+            })?;
+
+            super::block_on_sync(self.finish(
+                client,
+                last_offset,
+                Bytes::from(last_data),
+                commit,
+                hasher.map(ContentHasher::finish),
+                Sleeper::Blocking,
+            ))
+        }
+    }
+
+    /// Upload the contents of `source` and commit them as a file.
+    ///
+    /// Up to [`UploadOptions::with_parallelism`] requests are sent concurrently. If the session
+    /// was resumed, `source` must be positioned at [`ResumeState::offset`] already.
+    pub async fn upload_async(
+        &self,
+        client: &impl UserAuthClient,
+        source: impl AsyncRead + Unpin,
+        commit: CommitInfo,
+    ) -> Result<FileMetadata, UploadSessionError> {
+        let chunk_size = self.options.chunk_size();
+        let last = Mutex::new(None);
+        // See the comment in the sync version.
+        let mut hasher = (self.start_offset == 0).then(ContentHasher::new);
+
+        let chunks =
+            futures::stream::try_unfold(Some((source, self.start_offset)), |state| async move {
+                let Some((mut source, offset)) = state else {
+                    return Ok(None);
+                };
+                let mut data = Vec::with_capacity(chunk_size);
+                (&mut source)
+                    .take(chunk_size as u64)
+                    .read_to_end(&mut data)
+                    .await?;
+                let next = if data.len() == chunk_size {
+                    Some((source, offset + chunk_size as u64))
+                } else {
+                    None
+                };
+                Ok::<_, UploadSessionError>(Some(((offset, Bytes::from(data)), next)))
+            });
+
+        chunks
+            .map_ok(|(offset, data)| {
+                // Chunks come through here in order, even though they're uploaded out of order.
+                if let Some(hasher) = &mut hasher {
+                    hasher.update(&data);
+                }
+                let last = &last;
+                async move {
+                    if data.len() < chunk_size {
+                        // See the comment in the sync version.
+                        *last.lock().unwrap() = Some((offset, data));
+                        return Ok(());
+                    }
+                    self.append(client, offset, data, false, Sleeper::Async)
+                        .await
+                        .map_err(UploadSessionError::Append)
+                }
+            })
+            .try_buffer_unordered(self.options.parallelism)
+            .try_collect::<()>()
+            .await?;
+
+        let (last_offset, last_data) = last
+            .into_inner()
+            .unwrap()
+            .expect("the source always ends with a short chunk");
+        self.finish(
+            client,
+            last_offset,
+            last_data,
+            commit,
+            hasher.map(ContentHasher::finish),
+            Sleeper::Async,
+        )
+        .await
+    }
+
+    /// Append data at the given offset of the file, retrying as needed, and record it as done.
+    pub(crate) async fn append(
+        &self,
+        client: &impl UserAuthClient,
+        offset: u64,
+        data: Bytes,
+        close: bool,
+        sleeper: Sleeper,
+    ) -> Result<(), Error<UploadSessionAppendError>> {
+        let arg =
+            UploadSessionAppendArg::new(UploadSessionCursor::new(self.session_id.clone(), offset))
+                .with_close(close);
+        with_retry(self.options.max_retries, sleeper, || {
+            routes::files::upload_session_append_v2(client, &arg, data.clone())
+        })
+        .await?;
+
+        let len = data.len() as u64;
+        self.completion.lock().unwrap().complete_block(offset, len);
+        let transferred = self.bytes_transferred.fetch_add(len, Ordering::SeqCst) + len;
+        if let Some(progress) = &self.options.progress {
+            progress(self.start_offset + transferred);
+        }
+        Ok(())
+    }
+
+    /// Close the session with the final piece of data, and commit the file. If the content hash of
+    /// the whole file is given, the server checks it before committing.
+    pub(crate) async fn finish(
+        &self,
+        client: &impl UserAuthClient,
+        offset: u64,
+        data: Bytes,
+        commit: CommitInfo,
+        content_hash: Option<String>,
+        sleeper: Sleeper,
+    ) -> Result<FileMetadata, UploadSessionError> {
+        let total_len = offset + data.len() as u64;
+        debug!(
+            "closing session {} at {offset} with {}-byte block",
+            self.session_id,
+            data.len()
+        );
+        match self.append(client, offset, data, true, sleeper).await {
+            Ok(()) => (),
+            // If we're resuming a session which was already closed, but failed to commit, this is
+            // expected, and it's fine to just go ahead and commit.
+            Err(Error::Api(UploadSessionAppendError::Closed)) => (),
+            Err(e) => return Err(UploadSessionError::Append(e)),
+        }
+
+        let mut arg = UploadSessionFinishArg::new(
+            UploadSessionCursor::new(self.session_id.clone(), total_len),
+            commit,
+        );
+        arg.content_hash = content_hash;
+        with_retry(self.options.max_retries, sleeper, || {
+            routes::files::upload_session_finish(client, &arg, Bytes::new())
+        })
+        .await
+        .map_err(UploadSessionError::Finish)
+    }
+}
+
+/// Because blocks can be uploaded out of order, if an error is encountered when uploading a given
+/// block, that is not necessarily the correct place to resume uploading from next time: there may
+/// be gaps before that block.
+///
+/// This struct is for keeping track of what offset the file has been completely uploaded to.
+#[derive(Debug, Default)]
+struct CompletionTracker {
+    complete_up_to: u64,
+    uploaded_blocks: HashMap<u64, u64>,
+}
+
+impl CompletionTracker {
+    /// Make a new CompletionTracker that assumes everything up to the given offset is complete.
+    fn resume_from(complete_up_to: u64) -> Self {
+        Self {
+            complete_up_to,
+            uploaded_blocks: HashMap::new(),
+        }
+    }
+
+    /// Mark a block as completely uploaded.
+    fn complete_block(&mut self, block_offset: u64, block_len: u64) {
+        if block_offset == self.complete_up_to {
+            // Advance the cursor.
+            self.complete_up_to += block_len;
+
+            // Also look if we can advance it further still.
+            while let Some(len) = self.uploaded_blocks.remove(&self.complete_up_to) {
+                self.complete_up_to += len;
+            }
+        } else {
+            // This block isn't at the low-water mark; there's a gap behind it. Save it for later.
+            self.uploaded_blocks.insert(block_offset, block_len);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Streaming data into a Dropbox file as it is produced.
+//!
+//! [`DropboxWriter`] and [`AsyncDropboxWriter`] implement [`Write`](std::io::Write) and
+//! [`AsyncWrite`] respectively, so that output such as an archive or a database dump can be written
+//! straight to Dropbox without a temporary file, and without knowing its size upfront.
+//!
+//! Written data is buffered until a whole chunk is ready, which is then sent as part of an upload
+//! session. When the writer is closed, the rest of the data is sent and the file is committed. A
+//! file which fits in a single chunk is uploaded in one request instead. The content hash of
+//! everything written is sent along with the commit, so the server rejects the file if any of it
+//! got corrupted along the way.
+
+use super::content_hash::ContentHasher;
+use super::fs::io_error;
+use super::upload_session::BLOCK_SIZE;
+use super::{Sleeper, routes, with_retry};
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{
+    CommitInfo, FileMetadata, UploadArg, UploadSessionAppendArg, UploadSessionCursor,
+    UploadSessionFinishArg, UploadSessionStartArg,
+};
+use bytes::Bytes;
+use futures::AsyncWrite;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+/// The most blocks which fit in a single request, which can't be larger than 150 MiB.
+const MAX_BLOCKS_PER_REQUEST: usize = 37;
+
+/// Options for a [`DropboxWriter`] or [`AsyncDropboxWriter`].
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    blocks_per_request: usize,
+    max_retries: u32,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            blocks_per_request: 2,
+            max_retries: 3,
+        }
+    }
+}
+
+impl WriterOptions {
+    /// How many [`BLOCK_SIZE`] blocks of data to buffer before sending them. This is how much
+    /// memory the writer uses, and files up to this size are uploaded in a single request. Values
+    /// are clamped to between 1 and 37, because a request can't be larger than 150 MiB. Defaults
+    /// to 2.
+    pub fn with_blocks_per_request(mut self, value: usize) -> Self {
+        self.blocks_per_request = value.clamp(1, MAX_BLOCKS_PER_REQUEST);
+        self
+    }
+
+    /// How many times to retry a request which fails with a transient error. Rate-limited
+    /// requests are always retried after the delay the server asks for. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    fn chunk_size(&self) -> usize {
+        BLOCK_SIZE * self.blocks_per_request
+    }
+}
+
+/// Send a chunk of a file being written, starting an upload session if there isn't one yet, and
+/// return the updated session cursor.
+async fn send_chunk(
+    client: &impl UserAuthClient,
+    session: Option<UploadSessionCursor>,
+    data: Bytes,
+    max_retries: u32,
+    sleeper: Sleeper,
+) -> io::Result<UploadSessionCursor> {
+    let len = data.len() as u64;
+    match session {
+        None => {
+            let arg = UploadSessionStartArg::default();
+            let result = with_retry(max_retries, sleeper, || {
+                routes::files::upload_session_start(client, &arg, data.clone())
+            })
+            .await
+            .map_err(io_error)?;
+            Ok(UploadSessionCursor::new(result.session_id, len))
+        }
+        Some(cursor) => {
+            let arg = UploadSessionAppendArg::new(cursor.clone());
+            with_retry(max_retries, sleeper, || {
+                routes::files::upload_session_append_v2(client, &arg, data.clone())
+            })
+            .await
+            .map_err(io_error)?;
+            Ok(UploadSessionCursor::new(
+                cursor.session_id,
+                cursor.offset + len,
+            ))
+        }
+    }
+}
+
+/// Commit a file being written, with the last of its data. If all of it fit in one chunk, it's
+/// uploaded in a single request.
+async fn commit(
+    client: &impl UserAuthClient,
+    commit: CommitInfo,
+    session: Option<UploadSessionCursor>,
+    data: Bytes,
+    content_hash: String,
+    max_retries: u32,
+    sleeper: Sleeper,
+) -> io::Result<FileMetadata> {
+    match session {
+        None => {
+            let arg = UploadArg {
+                path: commit.path,
+                mode: commit.mode,
+                autorename: commit.autorename,
+                client_modified: commit.client_modified,
+                mute: commit.mute,
+                property_groups: commit.property_groups,
+                strict_conflict: commit.strict_conflict,
+                content_hash: Some(content_hash),
+            };
+            with_retry(max_retries, sleeper, || {
+                routes::files::upload(client, &arg, data.clone())
+            })
+            .await
+            .map_err(io_error)
+        }
+        Some(cursor) => {
+            let arg = UploadSessionFinishArg::new(cursor, commit).with_content_hash(content_hash);
+            with_retry(max_retries, sleeper, || {
+                routes::files::upload_session_finish(client, &arg, data.clone())
+            })
+            .await
+            .map_err(io_error)
+        }
+    }
+}
+
+/// The state of a file being written, shared by the sync and async writers.
+#[derive(Debug)]
+struct Upload {
+    commit: CommitInfo,
+    options: WriterOptions,
+    buffer: Vec<u8>,
+    hasher: ContentHasher,
+    /// The upload session, once the file has outgrown a single chunk.
+    session: Option<UploadSessionCursor>,
+}
+
+impl Upload {
+    fn new(commit: CommitInfo, options: WriterOptions) -> Self {
+        Self {
+            commit,
+            options,
+            buffer: Vec::new(),
+            hasher: ContentHasher::new(),
+            session: None,
+        }
+    }
+
+    /// Buffer as much of the data as fits in the current chunk.
+    fn buffer(&mut self, data: &[u8]) -> usize {
+        let n = data
+            .len()
+            .min(self.options.chunk_size() - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..n]);
+        self.hasher.update(&data[..n]);
+        n
+    }
+
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= self.options.chunk_size()
+    }
+
+    /// Take the buffered data to send it.
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.buffer))
+    }
+
+    /// Put data which failed to send back at the start of the buffer, so that it's sent again by
+    /// the next attempt.
+    fn restore(&mut self, data: Bytes) {
+        let mut buffer = data.to_vec();
+        buffer.append(&mut self.buffer);
+        self.buffer = buffer;
+    }
+}
+
+if_feature! { "sync_routes",
+    /// A [`Write`](io::Write) sink which uploads to a Dropbox file, using a sync HTTP client.
+    ///
+    /// Data is sent in chunks as it is written, and the file is committed when the writer is
+    /// closed with [`close`](Self::close). If it's dropped without being closed, nothing is
+    /// committed, and the upload session is left to expire on its own.
+    #[derive(Debug)]
+    pub struct DropboxWriter<'a, C: crate::client_trait::UserAuthClient> {
+        client: &'a C,
+        upload: Upload,
+        closed: bool,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<'a, C: crate::client_trait::UserAuthClient> DropboxWriter<'a, C> {
+    /// Start writing a file, which is committed as described by `commit` once it's closed.
+    pub fn new(client: &'a C, commit: CommitInfo, options: WriterOptions) -> Self {
+        Self {
+            client,
+            upload: Upload::new(commit, options),
+            closed: false,
+        }
+    }
+
+    /// Commit the file, and return its metadata.
+    pub fn close(mut self) -> io::Result<FileMetadata> {
+        self.closed = true;
+        let data = self.upload.take();
+        super::block_on_sync(commit(
+            self.client,
+            self.upload.commit.clone(),
+            self.upload.session.take(),
+            data,
+            self.upload.hasher.clone().finish(),
+            self.upload.options.max_retries,
+            Sleeper::Blocking,
+        ))
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> io::Write for DropboxWriter<'_, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed {
+            return Err(io::Error::other("file has already been committed"));
+        }
+        if self.upload.is_full() {
+            let data = self.upload.take();
+            match super::block_on_sync(send_chunk(
+                self.client,
+                self.upload.session.clone(),
+                data.clone(),
+                self.upload.options.max_retries,
+                Sleeper::Blocking,
+            )) {
+                Ok(cursor) => self.upload.session = Some(cursor),
+                Err(e) => {
+                    self.upload.restore(data);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.upload.buffer(buf))
+    }
+
+    /// Nothing is visible on Dropbox until the file is closed, so this does nothing.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<C: crate::client_trait::UserAuthClient> Drop for DropboxWriter<'_, C> {
+    fn drop(&mut self) {
+        // Committing here would hide any error, and could commit a file which is incomplete
+        // because its writer bailed out early, so the upload is abandoned instead.
+        if !self.closed {
+            warn!(
+                "writer for {} dropped without being closed; the file was not committed",
+                self.upload.commit.path
+            );
+        }
+    }
+}
+
+/// What a request made by an [`AsyncDropboxWriter`] accomplished.
+#[allow(clippy::large_enum_variant)] // there's only ever one of these at a time
+enum Sent {
+    Chunk(UploadSessionCursor),
+    Committed(FileMetadata),
+}
+
+type PendingRequest = Pin<Box<dyn Future<Output = io::Result<Sent>> + Send>>;
+
+/// An [`AsyncWrite`] sink which uploads to a Dropbox file.
+///
+/// Data is sent in chunks as it is written, and the file is committed when the writer is closed,
+/// using [`close`](Self::close) or [`AsyncWriteExt::close`](futures::AsyncWriteExt::close). If it's
+/// dropped without being closed, nothing is committed, and the upload session is left to expire on
+/// its own.
+pub struct AsyncDropboxWriter<C> {
+    client: Arc<C>,
+    upload: Upload,
+    /// The request in progress, and the data it's sending.
+    pending: Option<(PendingRequest, Bytes)>,
+    metadata: Option<FileMetadata>,
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncDropboxWriter<C> {
+    /// Start writing a file, which is committed as described by `commit` once it's closed.
+    pub fn new(client: Arc<C>, commit: CommitInfo, options: WriterOptions) -> Self {
+        Self {
+            client,
+            upload: Upload::new(commit, options),
+            pending: None,
+            metadata: None,
+        }
+    }
+
+    /// Commit the file, and return its metadata.
+    pub async fn close(mut self) -> io::Result<FileMetadata> {
+        futures::AsyncWriteExt::close(&mut self).await?;
+        Ok(self
+            .metadata
+            .clone()
+            .expect("file should have been committed"))
+    }
+
+    /// The metadata of the file, once it has been committed.
+    pub fn metadata(&self) -> Option<&FileMetadata> {
+        self.metadata.as_ref()
+    }
+
+    fn start(&mut self, last: bool) {
+        let client = Arc::clone(&self.client);
+        let data = self.upload.take();
+        let session = self.upload.session.clone();
+        let max_retries = self.upload.options.max_retries;
+        let body = data.clone();
+        let send: PendingRequest = if last {
+            let info = self.upload.commit.clone();
+            let content_hash = self.upload.hasher.clone().finish();
+            Box::pin(async move {
+                commit(
+                    client.as_ref(),
+                    info,
+                    session,
+                    body,
+                    content_hash,
+                    max_retries,
+                    Sleeper::Async,
+                )
+                .await
+                .map(Sent::Committed)
+            })
+        } else {
+            Box::pin(async move {
+                send_chunk(client.as_ref(), session, body, max_retries, Sleeper::Async)
+                    .await
+                    .map(Sent::Chunk)
+            })
+        };
+        self.pending = Some((send, data));
+    }
+
+    /// Wait for the request in progress, if any, to finish.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some((send, _)) = &mut self.pending {
+            let result = ready!(send.as_mut().poll(cx));
+            let (_, data) = self.pending.take().expect("request should be pending");
+            match result {
+                Ok(Sent::Chunk(cursor)) => self.upload.session = Some(cursor),
+                Ok(Sent::Committed(metadata)) => self.metadata = Some(metadata),
+                Err(e) => {
+                    self.upload.restore(data);
+                    return Poll::Ready(Err(e));
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<C: UserAuthClient + Send + 'static> AsyncWrite for AsyncDropboxWriter<C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.metadata.is_some() {
+            return Poll::Ready(Err(io::Error::other("file has already been committed")));
+        }
+        loop {
+            ready!(this.poll_pending(cx))?;
+            if !this.upload.is_full() {
+                return Poll::Ready(Ok(this.upload.buffer(buf)));
+            }
+            this.start(false);
+        }
+    }
+
+    /// Nothing is visible on Dropbox until the file is closed, so this only waits for any chunk
+    /// being sent.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_pending(cx))?;
+            if this.metadata.is_some() {
+                return Poll::Ready(Ok(()));
+            }
+            this.start(true);
+        }
+    }
+}
+
+impl<C> Drop for AsyncDropboxWriter<C> {
+    fn drop(&mut self) {
+        // Like the sync writer, this can't commit here, so the upload is abandoned.
+        if self.metadata.is_none() {
+            warn!(
+                "writer for {} dropped without being closed; the file was not committed",
+                self.upload.commit.path
+            );
+        }
+    }
+}
@@ -0,0 +1,710 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Reading zip archives as they're downloaded, such as folders from `files/download_zip`.
+//!
+//! [`AsyncZipReader`] and [`ZipReader`] read an archive front to back, using the local header in
+//! front of each entry rather than the central directory at the end, so entries can be read while
+//! the archive is still arriving, without buffering it anywhere. [`download_folder`] and
+//! [`download_folder_async`] use them to download a folder (up to Dropbox's 20 GB limit for zip
+//! downloads) and extract it into a local directory in one pass.
+//!
+//! Entries may be stored or compressed with deflate, which covers the archives Dropbox creates.
+//! Stored entries need to have their size in the local header, since there's no way to find where
+//! they end otherwise.
+//!
+//! Each file is extracted into a temporary file next to its destination, and only renamed into
+//! place once its checksum and size have been checked, so an entry which is corrupt or cut short
+//! doesn't leave a partial file behind.
+
+use super::download_file::TempFile;
+use super::{BlockingSpawnFn, Sleeper, routes, run_blocking, with_retry};
+use crate::Error;
+use crate::async_client_trait::UserAuthClient;
+use crate::types::files::{DownloadZipArg, DownloadZipError};
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use futures::{AsyncRead, AsyncReadExt};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const LOCAL_HEADER_LEN: usize = 30;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// How much of the archive to read at a time.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Options controlling how a folder is downloaded and extracted.
+#[derive(Clone)]
+pub struct ZipOptions {
+    overwrite: bool,
+    max_retries: u32,
+    spawner: Option<Arc<BlockingSpawnFn>>,
+}
+
+impl Default for ZipOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: false,
+            max_retries: 3,
+            spawner: None,
+        }
+    }
+}
+
+impl fmt::Debug for ZipOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZipOptions")
+            .field("overwrite", &self.overwrite)
+            .field("max_retries", &self.max_retries)
+            .field("spawner", &self.spawner.is_some())
+            .finish()
+    }
+}
+
+impl ZipOptions {
+    /// Whether to replace local files which already exist. Otherwise, extracting fails if the
+    /// archive contains a file which already exists. Defaults to false.
+    pub fn with_overwrite(mut self, value: bool) -> Self {
+        self.overwrite = value;
+        self
+    }
+
+    /// How many times to retry starting the download if it fails with a transient error (a server
+    /// error or an error from the HTTP client) before giving up. Requests which are rate-limited
+    /// are always retried after the delay the server asks for. Errors while the archive is being
+    /// read aren't retried. Defaults to 3.
+    pub fn with_max_retries(mut self, value: u32) -> Self {
+        self.max_retries = value;
+        self
+    }
+
+    /// A function which runs a blocking closure on a thread where that's allowed, such as
+    /// `|f| { tokio::task::spawn_blocking(f); }`. [`extract_async`] and [`download_folder_async`]
+    /// create folders and write files through it, so that extracting doesn't block the task
+    /// reading the archive. The sync functions don't use it.
+    pub fn with_blocking_spawner(
+        mut self,
+        value: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static,
+    ) -> Self {
+        self.spawner = Some(Arc::new(value));
+        self
+    }
+}
+
+/// An error downloading or extracting a zip archive.
+#[derive(thiserror::Error, Debug)]
+pub enum ZipError {
+    /// Starting the download failed.
+    #[error("error downloading folder: {0}")]
+    Download(#[source] Error<DownloadZipError>),
+
+    /// Reading the archive failed, or it isn't a valid zip archive.
+    #[error("error reading zip archive: {0}")]
+    Read(#[source] io::Error),
+
+    /// An entry in the archive has a path which would put it outside the destination directory.
+    #[error("zip entry {0:?} would be extracted outside of the destination directory")]
+    UnsafePath(String),
+
+    /// Writing an entry to a local file failed.
+    #[error("error writing {path:?}: {source}")]
+    Write {
+        /// The local path being written.
+        path: PathBuf,
+        /// The error writing it.
+        #[source]
+        source: io::Error,
+    },
+}
+
+if_feature! { "sync_routes",
+    /// Download a folder as a zip archive using a sync HTTP client, and extract it into `dest`,
+    /// which is created if it doesn't exist. The archive contains the folder itself, so its
+    /// contents end up in a subdirectory of `dest` with the folder's name.
+    ///
+    /// Returns the local paths of the files extracted, in the order they were in the archive. If
+    /// this fails, anything extracted up to that point is left in place.
+    pub fn download_folder(
+        client: &impl crate::client_trait::UserAuthClient,
+        path: &str,
+        dest: impl AsRef<Path>,
+        options: &ZipOptions,
+    ) -> Result<Vec<PathBuf>, ZipError> {
+        super::block_on_sync(download_folder_impl(
+            client,
+            path,
+            dest.as_ref(),
+            options,
+            Sleeper::Blocking,
+        ))
+    }
+
+    /// Extract a zip archive read from a sync reader into `dest`, which is created if it doesn't
+    /// exist.
+    ///
+    /// Returns the local paths of the files extracted, in the order they were in the archive. If
+    /// this fails, anything extracted up to that point is left in place.
+    pub fn extract(
+        reader: impl io::Read + Unpin,
+        dest: impl AsRef<Path>,
+        options: &ZipOptions,
+    ) -> Result<Vec<PathBuf>, ZipError> {
+        let mut reader = AsyncZipReader::new(futures::io::AllowStdIo::new(reader));
+        super::block_on_sync(extract_impl(&mut reader, dest.as_ref(), options, Sleeper::Blocking))
+    }
+}
+
+/// Download a folder as a zip archive, and extract it into `dest`, which is created if it doesn't
+/// exist. The archive contains the folder itself, so its contents end up in a subdirectory of
+/// `dest` with the folder's name.
+///
+/// Returns the local paths of the files extracted, in the order they were in the archive. If this
+/// fails, anything extracted up to that point is left in place.
+///
+/// Local files are written using the [blocking spawner](ZipOptions::with_blocking_spawner), if
+/// one was given.
+pub async fn download_folder_async(
+    client: &impl UserAuthClient,
+    path: &str,
+    dest: impl AsRef<Path>,
+    options: &ZipOptions,
+) -> Result<Vec<PathBuf>, ZipError> {
+    download_folder_impl(client, path, dest.as_ref(), options, Sleeper::Async).await
+}
+
+/// Extract a zip archive into `dest`, which is created if it doesn't exist.
+///
+/// Returns the local paths of the files extracted, in the order they were in the archive. If this
+/// fails, anything extracted up to that point is left in place.
+///
+/// Local files are written using the [blocking spawner](ZipOptions::with_blocking_spawner), if
+/// one was given.
+pub async fn extract_async(
+    reader: impl AsyncRead + Unpin,
+    dest: impl AsRef<Path>,
+    options: &ZipOptions,
+) -> Result<Vec<PathBuf>, ZipError> {
+    let mut reader = AsyncZipReader::new(reader);
+    extract_impl(&mut reader, dest.as_ref(), options, Sleeper::Async).await
+}
+
+async fn download_folder_impl(
+    client: &impl UserAuthClient,
+    path: &str,
+    dest: &Path,
+    options: &ZipOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<PathBuf>, ZipError> {
+    let arg = DownloadZipArg::new(path.to_owned());
+    let response = with_retry(options.max_retries, sleeper, || {
+        routes::files::download_zip(client, &arg, None, None)
+    })
+    .await
+    .map_err(ZipError::Download)?;
+    let body = response.body.ok_or_else(|| {
+        ZipError::Download(Error::UnexpectedResponse(
+            "download response has no body".to_owned(),
+        ))
+    })?;
+    extract_impl(&mut AsyncZipReader::new(body), dest, options, sleeper).await
+}
+
+async fn extract_impl<R: AsyncRead + Unpin>(
+    reader: &mut AsyncZipReader<R>,
+    dest: &Path,
+    options: &ZipOptions,
+    sleeper: Sleeper,
+) -> Result<Vec<PathBuf>, ZipError> {
+    let write_error = |path: &Path| {
+        let path = path.to_owned();
+        move |source| ZipError::Write { path, source }
+    };
+    let spawner = options.spawner.as_deref();
+
+    let dir = dest.to_owned();
+    run_blocking(sleeper, spawner, move || fs::create_dir_all(dir))
+        .await
+        .map_err(write_error(dest))?;
+    let mut files = Vec::new();
+    let mut buf = vec![0; BUFFER_SIZE];
+    while let Some(mut entry) = reader.next_entry().await.map_err(ZipError::Read)? {
+        let path = super::local_path(dest, entry.path())
+            .ok_or_else(|| ZipError::UnsafePath(entry.path().to_owned()))?;
+        let (destination, is_dir, overwrite) = (path.clone(), entry.is_dir(), options.overwrite);
+        let temp = run_blocking(sleeper, spawner, move || {
+            if is_dir {
+                fs::create_dir_all(&destination)?;
+                return Ok(None);
+            }
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if !overwrite && fs::symlink_metadata(&destination).is_ok() {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            TempFile::create(&destination).map(Some)
+        })
+        .await
+        .map_err(write_error(&path))?;
+        let Some(mut temp) = temp else {
+            continue;
+        };
+
+        // The temporary file is deleted when it's dropped, if the entry turns out to be corrupt.
+        loop {
+            let n = entry.read(&mut buf).await.map_err(ZipError::Read)?;
+            if n == 0 {
+                break;
+            }
+            (temp, buf) = run_blocking(sleeper, spawner, move || {
+                temp.write_all(&buf[..n])?;
+                io::Result::Ok((temp, buf))
+            })
+            .await
+            .map_err(write_error(&path))?;
+        }
+        run_blocking(sleeper, spawner, move || temp.finish())
+            .await
+            .map_err(write_error(&path))?;
+        debug!("extracted {}", entry.path());
+        files.push(path);
+    }
+    Ok(files)
+}
+
+if_feature! { "sync_routes",
+    /// Reads the entries of a zip archive one at a time from a sync reader, as they arrive.
+    #[derive(Debug)]
+    pub struct ZipReader<R: io::Read + Unpin> {
+        inner: AsyncZipReader<futures::io::AllowStdIo<R>>,
+    }
+
+    /// An entry of a zip archive being read by a [`ZipReader`]. Reading from it gives the entry's
+    /// uncompressed content.
+    #[derive(Debug)]
+    pub struct ZipEntry<'a, R: io::Read + Unpin> {
+        inner: AsyncZipEntry<'a, futures::io::AllowStdIo<R>>,
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<R: io::Read + Unpin> ZipReader<R> {
+    /// Start reading an archive.
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: AsyncZipReader::new(futures::io::AllowStdIo::new(reader)),
+        }
+    }
+
+    /// Get the next entry, skipping over any of the previous one which wasn't read, or `None` at
+    /// the end of the archive.
+    pub fn next_entry(&mut self) -> io::Result<Option<ZipEntry<'_, R>>> {
+        Ok(super::block_on_sync(self.inner.next_entry())?.map(|inner| ZipEntry { inner }))
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<R: io::Read + Unpin> ZipEntry<'_, R> {
+    /// The path of the entry within the archive, using `/` as the separator.
+    pub fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    /// Whether the entry is a directory rather than a file.
+    pub fn is_dir(&self) -> bool {
+        self.inner.is_dir()
+    }
+
+    /// The uncompressed size of the entry, if it's known yet. See [`AsyncZipEntry::size`].
+    pub fn size(&self) -> Option<u64> {
+        self.inner.size()
+    }
+}
+
+#[cfg(feature = "sync_routes")]
+impl<R: io::Read + Unpin> io::Read for ZipEntry<'_, R> {
+    /// Fails with [`io::ErrorKind::InvalidData`] at the end of the entry if its checksum or size
+    /// don't match the archive.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        super::block_on_sync(self.inner.read(buf))
+    }
+}
+
+/// Reads the entries of a zip archive one at a time, as they arrive.
+#[derive(Debug)]
+pub struct AsyncZipReader<R> {
+    input: Input<R>,
+    entry: Option<Entry>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncZipReader<R> {
+    /// Start reading an archive.
+    pub fn new(reader: R) -> Self {
+        Self {
+            input: Input {
+                reader,
+                buf: vec![0; BUFFER_SIZE],
+                pos: 0,
+                end: 0,
+                eof: false,
+            },
+            entry: None,
+            done: false,
+        }
+    }
+
+    /// Get the next entry, skipping over any of the previous one which wasn't read, or `None` at
+    /// the end of the archive.
+    pub async fn next_entry(&mut self) -> io::Result<Option<AsyncZipEntry<'_, R>>> {
+        if let Some(mut entry) = self.entry.take() {
+            let mut sink = vec![0; BUFFER_SIZE];
+            while futures::future::poll_fn(|cx| entry.poll_read(&mut self.input, cx, &mut sink))
+                .await?
+                > 0
+            {}
+        }
+        if self.done {
+            return Ok(None);
+        }
+
+        self.input.ensure(4).await?;
+        match read_u32(self.input.available()) {
+            LOCAL_HEADER => (),
+            CENTRAL_HEADER | END_OF_CENTRAL_DIRECTORY | ZIP64_END_OF_CENTRAL_DIRECTORY => {
+                self.done = true;
+                return Ok(None);
+            }
+            _ => return Err(invalid_data("expected a zip local file header")),
+        }
+        self.input.ensure(LOCAL_HEADER_LEN).await?;
+        let fixed = &self.input.available()[..LOCAL_HEADER_LEN];
+        let flags = read_u16(&fixed[6..]);
+        let method = read_u16(&fixed[8..]);
+        let crc = read_u32(&fixed[14..]);
+        let mut compressed_size = u64::from(read_u32(&fixed[18..]));
+        let mut size = u64::from(read_u32(&fixed[22..]));
+        let name_len = usize::from(read_u16(&fixed[26..]));
+        let extra_len = usize::from(read_u16(&fixed[28..]));
+
+        let header_len = LOCAL_HEADER_LEN + name_len + extra_len;
+        self.input.ensure(header_len).await?;
+        let header = &self.input.available()[..header_len];
+        let path = String::from_utf8_lossy(&header[LOCAL_HEADER_LEN..][..name_len]).into_owned();
+        let mut extra = &header[LOCAL_HEADER_LEN + name_len..];
+        let mut zip64 = false;
+        while extra.len() >= 4 {
+            let id = read_u16(extra);
+            let len = usize::from(read_u16(&extra[2..])).min(extra.len() - 4);
+            let mut data = &extra[4..][..len];
+            if id == ZIP64_EXTRA_FIELD {
+                zip64 = true;
+                // The 64-bit sizes are only present for the ones which didn't fit in the header.
+                if size == u64::from(u32::MAX) && data.len() >= 8 {
+                    size = read_u64(data);
+                    data = &data[8..];
+                }
+                if compressed_size == u64::from(u32::MAX) && data.len() >= 8 {
+                    compressed_size = read_u64(data);
+                }
+            }
+            extra = &extra[4 + len..];
+        }
+        self.input.pos += header_len;
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            return Err(unsupported(format!("{path} is encrypted")));
+        }
+        let decoder = match method {
+            METHOD_STORED => Decoder::Stored {
+                remaining: compressed_size,
+            },
+            METHOD_DEFLATED => Decoder::Deflated {
+                inflater: Decompress::new(false),
+                ended: false,
+            },
+            _ => {
+                return Err(unsupported(format!(
+                    "{path} uses unsupported compression method {method}"
+                )));
+            }
+        };
+        let has_descriptor = flags & FLAG_DATA_DESCRIPTOR != 0;
+        self.entry = Some(Entry {
+            path,
+            expected: (!has_descriptor).then_some((crc, compressed_size, size)),
+            zip64,
+            decoder,
+            crc: Crc::new(),
+            read: 0,
+            consumed: 0,
+            finished: false,
+        });
+        Ok(Some(AsyncZipEntry { reader: self }))
+    }
+}
+
+/// An entry of a zip archive being read by an [`AsyncZipReader`]. Reading from it gives the
+/// entry's uncompressed content.
+#[derive(Debug)]
+pub struct AsyncZipEntry<'a, R> {
+    reader: &'a mut AsyncZipReader<R>,
+}
+
+impl<R> AsyncZipEntry<'_, R> {
+    fn entry(&self) -> &Entry {
+        self.reader.entry.as_ref().expect("entry is being read")
+    }
+
+    /// The path of the entry within the archive, using `/` as the separator.
+    pub fn path(&self) -> &str {
+        &self.entry().path
+    }
+
+    /// Whether the entry is a directory rather than a file.
+    pub fn is_dir(&self) -> bool {
+        self.path().ends_with('/')
+    }
+
+    /// The uncompressed size of the entry, if it's known yet. Archives which are created as
+    /// they're streamed, like the ones from `files/download_zip`, usually only give the size after
+    /// the entry's data, so it's only known once the entry has been read to the end.
+    pub fn size(&self) -> Option<u64> {
+        self.entry().expected.map(|(_, _, size)| size)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncZipEntry<'_, R> {
+    /// Fails with [`io::ErrorKind::InvalidData`] at the end of the entry if its checksum or size
+    /// don't match the archive.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let reader = &mut *self.get_mut().reader;
+        let entry = reader.entry.as_mut().expect("entry is being read");
+        entry.poll_read(&mut reader.input, cx, buf)
+    }
+}
+
+/// The archive being read, and a buffer of what's been read from it but not used yet.
+#[derive(Debug)]
+struct Input<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> Input<R> {
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..self.end]
+    }
+
+    /// Read more of the archive into the buffer. Returns false if it has ended.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        if self.eof {
+            return Poll::Ready(Ok(false));
+        }
+        if self.pos > 0 {
+            self.buf.copy_within(self.pos..self.end, 0);
+            self.end -= self.pos;
+            self.pos = 0;
+        }
+        if self.end == self.buf.len() {
+            self.buf.resize(self.buf.len() * 2, 0);
+        }
+        let n = ready!(Pin::new(&mut self.reader).poll_read(cx, &mut self.buf[self.end..]))?;
+        self.end += n;
+        self.eof = n == 0;
+        Poll::Ready(Ok(n > 0))
+    }
+
+    /// Make sure at least `n` bytes are buffered.
+    fn poll_ensure(&mut self, cx: &mut Context<'_>, n: usize) -> Poll<io::Result<()>> {
+        while self.end - self.pos < n {
+            if !ready!(self.poll_fill(cx))? {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    async fn ensure(&mut self, n: usize) -> io::Result<()> {
+        futures::future::poll_fn(|cx| self.poll_ensure(cx, n)).await
+    }
+}
+
+/// The state of the entry being read.
+#[derive(Debug)]
+struct Entry {
+    path: String,
+    /// The CRC-32, compressed size, and uncompressed size from the local header, unless they're
+    /// in a data descriptor after the data instead.
+    expected: Option<(u32, u64, u64)>,
+    /// Whether the sizes in the data descriptor are 64-bit.
+    zip64: bool,
+    decoder: Decoder,
+    crc: Crc,
+    /// How many uncompressed bytes have been read.
+    read: u64,
+    /// How many compressed bytes have been read.
+    consumed: u64,
+    /// Whether all the data has been read, and checked.
+    finished: bool,
+}
+
+#[derive(Debug)]
+enum Decoder {
+    Stored { remaining: u64 },
+    Deflated { inflater: Decompress, ended: bool },
+}
+
+impl Entry {
+    fn poll_read<R: AsyncRead + Unpin>(
+        &mut self,
+        input: &mut Input<R>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.finished || buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = loop {
+            match &mut self.decoder {
+                Decoder::Stored { remaining: 0 } | Decoder::Deflated { ended: true, .. } => {
+                    break 0;
+                }
+                Decoder::Stored { remaining } => {
+                    if input.available().is_empty() && !ready!(input.poll_fill(cx))? {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    let n = input
+                        .available()
+                        .len()
+                        .min(buf.len())
+                        .min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                    buf[..n].copy_from_slice(&input.available()[..n]);
+                    input.pos += n;
+                    *remaining -= n as u64;
+                    self.consumed += n as u64;
+                    break n;
+                }
+                Decoder::Deflated { inflater, ended } => {
+                    let (total_in, total_out) = (inflater.total_in(), inflater.total_out());
+                    let status = inflater
+                        .decompress(input.available(), buf, FlushDecompress::None)
+                        .map_err(|e| invalid_data(format!("{}: {e}", self.path)))?;
+                    let consumed = inflater.total_in() - total_in;
+                    let produced = (inflater.total_out() - total_out) as usize;
+                    input.pos += consumed as usize;
+                    self.consumed += consumed;
+                    *ended = status == Status::StreamEnd;
+                    if produced > 0 || *ended {
+                        break produced;
+                    }
+                    if consumed == 0 && !ready!(input.poll_fill(cx))? {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                }
+            }
+        };
+        if n > 0 {
+            self.crc.update(&buf[..n]);
+            self.read += n as u64;
+            return Poll::Ready(Ok(n));
+        }
+        ready!(self.poll_finish(input, cx))?;
+        self.finished = true;
+        Poll::Ready(Ok(0))
+    }
+
+    /// Read the data descriptor, if there is one, and check the data against it or the header.
+    fn poll_finish<R: AsyncRead + Unpin>(
+        &mut self,
+        input: &mut Input<R>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let (crc, compressed_size, size) = match self.expected {
+            Some(expected) => expected,
+            None => {
+                // The signature is optional.
+                ready!(input.poll_ensure(cx, 4))?;
+                let start = if read_u32(input.available()) == DATA_DESCRIPTOR {
+                    4
+                } else {
+                    0
+                };
+                let zip64 = self.zip64;
+                let width = if zip64 { 8 } else { 4 };
+                ready!(input.poll_ensure(cx, start + 4 + 2 * width))?;
+                let descriptor = &input.available()[start..];
+                let read_size = |data: &[u8]| {
+                    if zip64 {
+                        read_u64(data)
+                    } else {
+                        u64::from(read_u32(data))
+                    }
+                };
+                let expected = (
+                    read_u32(descriptor),
+                    read_size(&descriptor[4..]),
+                    read_size(&descriptor[4 + width..]),
+                );
+                input.pos += start + 4 + 2 * width;
+                self.expected = Some(expected);
+                expected
+            }
+        };
+        if crc != self.crc.sum() {
+            return Poll::Ready(Err(invalid_data(format!(
+                "{}: checksum mismatch",
+                self.path
+            ))));
+        }
+        if size != self.read || compressed_size != self.consumed {
+            return Poll::Ready(Err(invalid_data(format!(
+                "{}: expected {size} bytes ({compressed_size} compressed), got {} ({} compressed)",
+                self.path, self.read, self.consumed,
+            ))));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    u16::from_le_bytes([data[0], data[1]])
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    u64::from_le_bytes(data[..8].try_into().unwrap())
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn unsupported(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
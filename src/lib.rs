@@ -53,8 +53,12 @@ pub mod async_client_trait;
 
 mod client_helpers;
 
+pub mod helpers;
+
 pub mod oauth2;
 
+pub mod validation;
+
 // You need to run the Stone generator to create this module.
 #[rustfmt::skip]
 mod generated;
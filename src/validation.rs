@@ -0,0 +1,209 @@
+// Copyright (c) 2019-2025 Dropbox, Inc.
+
+//! Client-side checking of the constraints the API specification puts on argument fields, like
+//! string patterns, lengths, and numeric bounds.
+//!
+//! These are the building blocks of the [`Validate`] implementations which the code generator
+//! emits for types with constraints. Nothing in the SDK validates arguments on its own; a value can
+//! be checked by calling [`Validate::validate`] on it before it's sent.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{LazyLock, Mutex};
+
+/// A value which doesn't meet the constraints of its type.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid value for {field}: {reason}")]
+pub struct ValidationError {
+    /// The field which is invalid, as a path from the value being validated, like
+    /// `commit.path` or `entries[2].path`.
+    pub field: String,
+
+    /// What is wrong with the field's value.
+    pub reason: String,
+}
+
+impl ValidationError {
+    /// Create an error for the given field.
+    pub fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Make the field of this error relative to the field of an enclosing value.
+    pub fn within(mut self, parent: &str) -> Self {
+        if self.field.is_empty() {
+            self.field = parent.to_owned();
+        } else if self.field.starts_with('[') {
+            self.field = format!("{parent}{}", self.field);
+        } else {
+            self.field = format!("{parent}.{}", self.field);
+        }
+        self
+    }
+}
+
+/// A type whose values can be checked against the constraints of the API specification.
+pub trait Validate {
+    /// Check this value and everything in it, returning the first constraint violation found.
+    fn validate(&self) -> Result<(), ValidationError>;
+}
+
+macro_rules! impl_validate_unconstrained {
+    ($($t:ty),*) => {
+        $(
+            impl Validate for $t {
+                fn validate(&self) -> Result<(), ValidationError> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_validate_unconstrained!(
+    (),
+    bool,
+    u8,
+    u32,
+    u64,
+    i32,
+    i64,
+    f32,
+    f64,
+    String,
+    serde_json::Value
+);
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        match self {
+            Some(value) => value.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Validate> Validate for Box<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        (**self).validate()
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        for (i, item) in self.iter().enumerate() {
+            item.validate().map_err(|e| e.within(&format!("[{i}]")))?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V: Validate> Validate for HashMap<K, V> {
+    fn validate(&self) -> Result<(), ValidationError> {
+        for value in self.values() {
+            value.validate()?;
+        }
+        Ok(())
+    }
+}
+
+// The functions below are called by the generated `Validate` implementations.
+
+/// Check that a string's length in characters is within the given bounds.
+#[doc(hidden)]
+pub fn check_string_length(
+    field: &str,
+    value: &str,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Result<(), ValidationError> {
+    check_length(field, value.chars().count(), min, max, "characters")
+}
+
+/// Check that a list's length is within the given bounds.
+#[doc(hidden)]
+pub fn check_list_length<T>(
+    field: &str,
+    value: &[T],
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Result<(), ValidationError> {
+    check_length(field, value.len(), min, max, "items")
+}
+
+fn check_length(
+    field: &str,
+    len: usize,
+    min: Option<usize>,
+    max: Option<usize>,
+    unit: &str,
+) -> Result<(), ValidationError> {
+    if let Some(min) = min.filter(|&min| len < min) {
+        return Err(ValidationError::new(
+            field,
+            format!("{len} {unit} is fewer than the minimum of {min}"),
+        ));
+    }
+    if let Some(max) = max.filter(|&max| len > max) {
+        return Err(ValidationError::new(
+            field,
+            format!("{len} {unit} is more than the maximum of {max}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a number is within the given bounds.
+#[doc(hidden)]
+pub fn check_range<T: PartialOrd + Display>(
+    field: &str,
+    value: T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), ValidationError> {
+    if let Some(min) = min.filter(|min| value < *min) {
+        return Err(ValidationError::new(
+            field,
+            format!("{value} is less than the minimum of {min}"),
+        ));
+    }
+    if let Some(max) = max.filter(|max| value > *max) {
+        return Err(ValidationError::new(
+            field,
+            format!("{value} is greater than the maximum of {max}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Compiled patterns, by their source in the specification. Patterns using syntax the `regex` crate
+/// doesn't support, like look-around, are `None` and aren't checked.
+static PATTERNS: LazyLock<Mutex<HashMap<&'static str, Option<Regex>>>> =
+    LazyLock::new(Default::default);
+
+/// Check that the whole of a string matches a pattern from the specification.
+#[doc(hidden)]
+pub fn check_pattern(
+    field: &str,
+    value: &str,
+    pattern: &'static str,
+) -> Result<(), ValidationError> {
+    let regex = PATTERNS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(pattern)
+        .or_insert_with(|| Regex::new(&format!("^(?:{pattern})$")).ok())
+        .clone();
+    if regex.is_none_or(|regex| regex.is_match(value)) {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            field,
+            format!("{value:?} does not match the pattern {pattern}"),
+        ))
+    }
+}
@@ -0,0 +1,208 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{CommitInfo, UploadSessionFinishError};
+use dropbox_sdk::helpers::batch_upload::{
+    BatchUploadEntry, BatchUploadOptions, BatchUploadResult, upload_batch,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, response};
+
+/// A client which implements just enough of the batch upload routes to check what was sent.
+#[derive(Default)]
+struct BatchClient {
+    /// Uploaded data, by session ID.
+    sessions: Mutex<HashMap<String, Vec<u8>>>,
+    /// How many times each path has been committed.
+    commits: Mutex<HashMap<String, u32>>,
+    /// Whether committing `/contended` again after lock contention fails as a whole.
+    fail_retry: bool,
+}
+
+impl HttpClient for BatchClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        match request.url.as_str() {
+            "https://api.dropboxapi.com/2/files/upload_session/start_batch" => {
+                let arg: serde_json::Value = serde_json::from_slice(body)?;
+                let n = arg["num_sessions"].as_u64().unwrap();
+                assert!(n <= 1000);
+                let mut sessions = self.sessions.lock().unwrap();
+                let base = sessions.len();
+                let ids = (0..n as usize)
+                    .map(|i| format!("s{}", base + i))
+                    .collect::<Vec<_>>();
+                for id in &ids {
+                    sessions.insert(id.clone(), vec![]);
+                }
+                Ok(response(
+                    200,
+                    serde_json::json!({ "session_ids": ids }).to_string(),
+                ))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/append_v2" => {
+                let arg: serde_json::Value = serde_json::from_str(&request.arg.unwrap())?;
+                assert_eq!(arg["close"], true);
+                if body == b"fail" {
+                    return Ok(response(
+                        409,
+                        r#"{"error":{".tag":"too_large"},"error_summary":"too_large/"}"#,
+                    ));
+                }
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                self.sessions
+                    .lock()
+                    .unwrap()
+                    .get_mut(id)
+                    .unwrap()
+                    .extend_from_slice(body);
+                Ok(response(200, "null"))
+            }
+            "https://api.dropboxapi.com/2/files/upload_session/finish_batch_v2" => {
+                let arg: serde_json::Value = serde_json::from_slice(body)?;
+                let sessions = self.sessions.lock().unwrap();
+                let mut commits = self.commits.lock().unwrap();
+                if self.fail_retry && commits.contains_key("/contended") {
+                    return Ok(response(400, "bad request"));
+                }
+                let entries = arg["entries"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|entry| {
+                        let path = entry["commit"]["path"].as_str().unwrap();
+                        let data = &sessions[entry["cursor"]["session_id"].as_str().unwrap()];
+                        assert_eq!(entry["cursor"]["offset"], data.len());
+                        let count = commits.entry(path.to_owned()).or_default();
+                        *count += 1;
+                        match path {
+                            "/contended" if *count < 3 => serde_json::json!({
+                                ".tag": "failure",
+                                "failure": {".tag": "too_many_write_operations"},
+                            }),
+                            "/bad" => serde_json::json!({
+                                ".tag": "failure",
+                                "failure": {".tag": "encryption_not_supported"},
+                            }),
+                            _ => serde_json::json!({
+                                ".tag": "success",
+                                "name": &path[1..],
+                                "id": format!("id:{path}"),
+                                "client_modified": "2020-01-01T00:00:00Z",
+                                "server_modified": "2020-01-01T00:00:00Z",
+                                "rev": "0123456789",
+                                "size": data.len(),
+                                "path_display": path,
+                            }),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Ok(response(
+                    200,
+                    serde_json::json!({ "entries": entries }).to_string(),
+                ))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for BatchClient {}
+
+#[test]
+fn test_upload_batch() {
+    let client = BatchClient::default();
+    let mut entries = (0..1500)
+        .map(|i| {
+            BatchUploadEntry::new(
+                CommitInfo::new(format!("/file{i}")),
+                format!("contents of file {i}").into_bytes(),
+            )
+        })
+        .collect::<Vec<_>>();
+    entries.push(BatchUploadEntry::new(
+        CommitInfo::new("/contended".to_owned()),
+        &b"contended"[..],
+    ));
+    entries.push(BatchUploadEntry::new(
+        CommitInfo::new("/bad".to_owned()),
+        &b"bad"[..],
+    ));
+    entries.push(BatchUploadEntry::new(
+        CommitInfo::new("/fail".to_owned()),
+        &b"fail"[..],
+    ));
+
+    let options = BatchUploadOptions::default().with_parallelism(4);
+    let results = upload_batch(&client, entries, &options).unwrap();
+    assert_eq!(results.len(), 1503);
+
+    for (i, result) in results[..1500].iter().enumerate() {
+        match result {
+            BatchUploadResult::Success(metadata) => {
+                assert_eq!(metadata.name, format!("file{i}"));
+                assert_eq!(metadata.size, format!("contents of file {i}").len() as u64);
+            }
+            other => panic!("unexpected result for file {i}: {other:?}"),
+        }
+    }
+
+    // This one failed twice due to lock contention and was retried.
+    assert!(matches!(&results[1500], BatchUploadResult::Success(m) if m.name == "contended"));
+    assert_eq!(client.commits.lock().unwrap()["/contended"], 3);
+
+    assert!(matches!(
+        results[1501],
+        BatchUploadResult::FinishFailed(UploadSessionFinishError::EncryptionNotSupported)
+    ));
+    assert_eq!(client.commits.lock().unwrap()["/bad"], 1);
+
+    assert!(matches!(
+        results[1502],
+        BatchUploadResult::AppendFailed(dropbox_sdk::Error::Api(_))
+    ));
+    assert!(!client.commits.lock().unwrap().contains_key("/fail"));
+}
+
+#[test]
+fn test_upload_batch_error() {
+    let client = BatchClient {
+        fail_retry: true,
+        ..BatchClient::default()
+    };
+    let mut entries = (0..1001)
+        .map(|i| BatchUploadEntry::new(CommitInfo::new(format!("/file{i}")), &b"data"[..]))
+        .collect::<Vec<_>>();
+    entries.push(BatchUploadEntry::new(
+        CommitInfo::new("/contended".to_owned()),
+        &b"contended"[..],
+    ));
+    entries.push(BatchUploadEntry::new(
+        CommitInfo::new("/other".to_owned()),
+        &b"other"[..],
+    ));
+
+    let err = upload_batch(&client, entries, &BatchUploadOptions::default()).unwrap_err();
+    assert!(matches!(err.source, dropbox_sdk::Error::BadRequest(_)));
+
+    // The first batch, and the entries committed in the first attempt at the second batch, are
+    // reported; retrying the contended entry failed, so it wasn't committed.
+    assert_eq!(err.results.len(), 1003);
+    assert!(
+        err.results[..1001]
+            .iter()
+            .all(|result| matches!(result, Some(BatchUploadResult::Success(_))))
+    );
+    assert!(err.results[1001].is_none());
+    assert!(matches!(&err.results[1002], Some(BatchUploadResult::Success(m)) if m.name == "other"));
+}
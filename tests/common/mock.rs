@@ -0,0 +1,79 @@
+//! Building blocks for mock HTTP clients, which answer requests in-process so that the helpers can
+//! be tested without a Dropbox account.
+//!
+//! Each test's client implements `HttpClient` with its own fake server behind `execute`, using
+//! [`Request`] as its request type and the functions here to build its responses.
+
+use dropbox_sdk::client_trait::{HttpRequest, HttpRequestResultRaw};
+use std::io::Cursor;
+
+/// A request made to a mock client, with the headers the tests look at.
+#[derive(Debug, Default)]
+pub struct Request {
+    pub url: String,
+
+    /// The `Dropbox-API-Arg` header, which holds the argument of upload and download requests.
+    pub arg: Option<String>,
+
+    /// The `Range` header.
+    pub range: Option<String>,
+
+    /// The `Dropbox-API-Select-User` header, naming the member a team client acts as.
+    pub member: Option<String>,
+}
+
+impl Request {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// The route requested, like `files/upload`.
+    pub fn route(&self) -> &str {
+        self.url.split_once("/2/").unwrap().1
+    }
+}
+
+impl HttpRequest for Request {
+    fn set_header(mut self, name: &str, value: &str) -> Self {
+        match name {
+            "Dropbox-API-Arg" => self.arg = Some(value.to_owned()),
+            "Range" => self.range = Some(value.to_owned()),
+            "Dropbox-API-Select-User" => self.member = Some(value.to_owned()),
+            _ => (),
+        }
+        self
+    }
+}
+
+/// A response with the given status and body.
+pub fn response(status: u16, body: impl Into<Vec<u8>>) -> HttpRequestResultRaw {
+    content_response(status, None, body)
+}
+
+/// A response with a JSON body, like the result of an RPC route, or an error.
+pub fn json_response(status: u16, body: serde_json::Value) -> HttpRequestResultRaw {
+    response(status, body.to_string())
+}
+
+/// An error response from a route, with the given JSON error, like
+/// `{".tag":"path","path":{".tag":"not_found"}}`.
+pub fn api_error(error: &str) -> HttpRequestResultRaw {
+    response(409, format!(r#"{{"error":{error}}}"#))
+}
+
+/// A response to a download route, with its result in the `Dropbox-API-Result` header.
+pub fn content_response(
+    status: u16,
+    result_header: Option<String>,
+    body: impl Into<Vec<u8>>,
+) -> HttpRequestResultRaw {
+    HttpRequestResultRaw {
+        status,
+        result_header,
+        content_length: None,
+        body: Box::new(Cursor::new(body.into())),
+    }
+}
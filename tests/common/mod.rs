@@ -1,3 +1,6 @@
+// Each test crate includes this module, and uses only some of what's in it.
+#![allow(dead_code)]
+
 use dropbox_sdk::Error::Api;
 use dropbox_sdk::client_trait::UserAuthClient;
 use dropbox_sdk::files;
@@ -6,6 +9,8 @@ use std::thread;
 use std::time::Duration;
 use threadpool::ThreadPool;
 
+pub mod mock;
+
 pub fn create_files(
     client: Arc<impl UserAuthClient + Send + Sync + 'static>,
     path: &'static str,
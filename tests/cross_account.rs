@@ -0,0 +1,275 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::Metadata;
+use dropbox_sdk::helpers::cross_account::{
+    CrossAccountCopyError, CrossAccountCopyOptions, copy_between_accounts,
+    copy_between_accounts_async,
+};
+use dropbox_sdk::helpers::member::{AsyncMemberClient, MemberClient};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, json_response};
+
+/// A team client for a team whose member `dbmid:src` has this tree:
+///
+/// ```text
+/// /doc.txt
+/// /big/            (too big to copy in one go)
+/// /big/a.txt
+/// /big/sub/
+/// ```
+///
+/// and whose member `dbmid:dst` has whatever has been copied to them.
+#[derive(Default)]
+struct TeamClient {
+    /// Paths in the destination member's Dropbox, with a trailing slash for folders.
+    copied: Mutex<BTreeSet<String>>,
+    /// Whether the next copy reference saved is rejected.
+    reject_next: Mutex<bool>,
+    /// Each request's route and the member it was made as.
+    requests: Mutex<Vec<(String, String)>>,
+}
+
+fn metadata(path: &str) -> serde_json::Value {
+    let name = path.rsplit('/').next().unwrap();
+    if name.contains('.') {
+        serde_json::json!({
+            ".tag": "file",
+            "name": name,
+            "id": format!("id:{path}"),
+            "client_modified": "2020-01-01T00:00:00Z",
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": 1,
+            "path_lower": path,
+            "path_display": path,
+        })
+    } else {
+        serde_json::json!({
+            ".tag": "folder",
+            "name": name,
+            "id": format!("id:{path}"),
+            "path_lower": path,
+            "path_display": path,
+        })
+    }
+}
+
+impl HttpClient for TeamClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let route = request.route().to_owned();
+        let member = request.member.unwrap();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((route.clone(), member.clone()));
+        match route.as_str() {
+            "files/copy_reference/get" => {
+                assert_eq!(member, "dbmid:src");
+                let path = arg["path"].as_str().unwrap();
+                if !["/doc.txt", "/big", "/big/a.txt", "/big/sub"].contains(&path) {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "path", "path": {".tag": "not_found"}}}),
+                    ));
+                }
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "metadata": metadata(path),
+                        "copy_reference": format!("ref:{path}"),
+                        "expires": "2030-01-01T00:00:00Z",
+                    }),
+                ))
+            }
+            "files/copy_reference/save" => {
+                assert_eq!(member, "dbmid:dst");
+                if std::mem::take(&mut *self.reject_next.lock().unwrap()) {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "invalid_copy_reference"}}),
+                    ));
+                }
+                let source = arg["copy_reference"]
+                    .as_str()
+                    .unwrap()
+                    .strip_prefix("ref:")
+                    .unwrap();
+                if source == "/big" {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "too_many_files"}}),
+                    ));
+                }
+                let path = arg["path"].as_str().unwrap();
+                let mut copied = self.copied.lock().unwrap();
+                if source.contains('.') {
+                    copied.insert(path.to_owned());
+                } else {
+                    copied.insert(format!("{path}/"));
+                }
+                Ok(json_response(
+                    200,
+                    serde_json::json!({ "metadata": metadata(path) }),
+                ))
+            }
+            "files/create_folder_v2" => {
+                assert_eq!(member, "dbmid:dst");
+                let path = arg["path"].as_str().unwrap();
+                self.copied.lock().unwrap().insert(format!("{path}/"));
+                Ok(json_response(
+                    200,
+                    serde_json::json!({ "metadata": metadata(path) }),
+                ))
+            }
+            "files/list_folder" => {
+                assert_eq!(member, "dbmid:src");
+                assert_eq!(arg["path"], "/big");
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "entries": [metadata("/big/a.txt")],
+                        "cursor": "c",
+                        "has_more": true,
+                    }),
+                ))
+            }
+            "files/list_folder/continue" => {
+                assert_eq!(arg["cursor"], "c");
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "entries": [metadata("/big/sub")],
+                        "cursor": "d",
+                        "has_more": false,
+                    }),
+                ))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl TeamAuthClient for TeamClient {}
+
+fn member<'a>(client: &'a TeamClient, id: &str) -> MemberClient<'a, TeamClient> {
+    MemberClient::new(client, TeamSelect::User(id.to_owned()))
+}
+
+#[test]
+fn test_copy_file() {
+    let client = TeamClient::default();
+    let result = copy_between_accounts(
+        &member(&client, "dbmid:src"),
+        "/doc.txt",
+        &member(&client, "dbmid:dst"),
+        "/copied.txt",
+        &CrossAccountCopyOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(result, Metadata::File(_)));
+    assert_eq!(
+        client.copied.lock().unwrap().iter().collect::<Vec<_>>(),
+        vec!["/copied.txt"]
+    );
+    assert_eq!(
+        *client.requests.lock().unwrap(),
+        vec![
+            (
+                "files/copy_reference/get".to_owned(),
+                "dbmid:src".to_owned()
+            ),
+            (
+                "files/copy_reference/save".to_owned(),
+                "dbmid:dst".to_owned()
+            ),
+        ]
+    );
+
+    // A rejected reference is replaced with a new one.
+    *client.reject_next.lock().unwrap() = true;
+    client.requests.lock().unwrap().clear();
+    copy_between_accounts(
+        &member(&client, "dbmid:src"),
+        "/doc.txt",
+        &member(&client, "dbmid:dst"),
+        "/again.txt",
+        &CrossAccountCopyOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(client.requests.lock().unwrap().len(), 4);
+    assert!(client.copied.lock().unwrap().contains("/again.txt"));
+}
+
+#[test]
+fn test_copy_big_folder() {
+    let client = TeamClient::default();
+    let result = copy_between_accounts(
+        &member(&client, "dbmid:src"),
+        "/big",
+        &member(&client, "dbmid:dst"),
+        "/Copy",
+        &CrossAccountCopyOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(result, Metadata::Folder(_)));
+    assert_eq!(
+        client.copied.lock().unwrap().iter().collect::<Vec<_>>(),
+        vec!["/Copy/", "/Copy/a.txt", "/Copy/sub/"]
+    );
+}
+
+#[test]
+fn test_error() {
+    let client = TeamClient::default();
+    let err = copy_between_accounts(
+        &member(&client, "dbmid:src"),
+        "/missing.txt",
+        &member(&client, "dbmid:dst"),
+        "/copied.txt",
+        &CrossAccountCopyOptions::default(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, CrossAccountCopyError::GetReference { path, .. } if path == "/missing.txt"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = Arc::new(TeamClient::default());
+    let source = AsyncMemberClient::new(
+        Arc::clone(&client),
+        TeamSelect::User("dbmid:src".to_owned()),
+    );
+    let destination = AsyncMemberClient::new(
+        Arc::clone(&client),
+        TeamSelect::User("dbmid:dst".to_owned()),
+    );
+    copy_between_accounts_async(
+        &source,
+        "/big",
+        &destination,
+        "/big",
+        &CrossAccountCopyOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        client.copied.lock().unwrap().iter().collect::<Vec<_>>(),
+        vec!["/big/", "/big/a.txt", "/big/sub/"]
+    );
+}
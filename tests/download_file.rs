@@ -0,0 +1,237 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::download_file::{
+    DownloadFileError, DownloadFileOptions, DownloadFileOutcome, download_to_file,
+    download_to_file_async,
+};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+mod common;
+use common::mock::{Request, json_response};
+
+/// A client which serves a single file, `/src.bin`.
+struct FileClient {
+    data: Vec<u8>,
+    /// Whether to report the wrong content hash for the file.
+    corrupt: bool,
+    /// The path or revision of each download.
+    downloads: Mutex<Vec<String>>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl FileClient {
+    fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            corrupt: false,
+            downloads: Mutex::default(),
+            requests: Mutex::default(),
+        }
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        let hash = if self.corrupt {
+            content_hash(b"something else")
+        } else {
+            content_hash(&self.data)
+        };
+        serde_json::json!({
+            ".tag": "file",
+            "name": "src.bin",
+            "id": "id:src",
+            "client_modified": "2020-09-13T12:26:40Z",
+            "server_modified": "2021-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": self.data.len(),
+            "path_lower": "/src.bin",
+            "path_display": "/src.bin",
+            "content_hash": hash,
+        })
+    }
+}
+
+impl HttpClient for FileClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let route = request.route();
+        self.requests.lock().unwrap().push(route.to_owned());
+        match route {
+            "files/get_metadata" => {
+                assert_eq!(arg["path"], "/src.bin");
+                Ok(json_response(200, self.metadata()))
+            }
+            "files/download" => {
+                let path = arg["path"].as_str().unwrap();
+                assert!(path == "/src.bin" || path == "rev:0123456789a", "{path}");
+                self.downloads.lock().unwrap().push(path.to_owned());
+                Ok(HttpRequestResultRaw {
+                    status: 200,
+                    result_header: Some(self.metadata().to_string()),
+                    content_length: Some(self.data.len() as u64),
+                    body: Box::new(Cursor::new(self.data.clone())),
+                })
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for FileClient {}
+
+/// A directory in the temp directory which is deleted when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// The names of the files in the directory.
+    fn files(&self) -> Vec<String> {
+        let mut names = std::fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn big_data() -> Vec<u8> {
+    (0..100_000).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_download() {
+    let dir = TempDir::new("download-file");
+    let dest = dir.0.join("dest.bin");
+    std::fs::write(&dest, b"old content").unwrap();
+    let client = FileClient::new(big_data());
+    let outcome =
+        download_to_file(&client, "/src.bin", &dest, &DownloadFileOptions::default()).unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Downloaded(_)));
+    assert_eq!(outcome.metadata().size, 100_000);
+    assert_eq!(std::fs::read(&dest).unwrap(), big_data());
+    assert_eq!(
+        std::fs::metadata(&dest).unwrap().modified().unwrap(),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    );
+    assert_eq!(dir.files(), vec!["dest.bin"]);
+    assert_eq!(*client.downloads.lock().unwrap(), vec!["/src.bin"]);
+}
+
+#[test]
+fn test_hash_mismatch() {
+    let dir = TempDir::new("download-file-corrupt");
+    let dest = dir.0.join("dest.bin");
+    std::fs::write(&dest, b"old content").unwrap();
+    let client = FileClient {
+        corrupt: true,
+        ..FileClient::new(big_data())
+    };
+    let err =
+        download_to_file(&client, "/src.bin", &dest, &DownloadFileOptions::default()).unwrap_err();
+    assert!(
+        matches!(err, DownloadFileError::HashMismatch { .. }),
+        "{err}"
+    );
+
+    // The destination is untouched, and the temporary file is gone.
+    assert_eq!(std::fs::read(&dest).unwrap(), b"old content");
+    assert_eq!(dir.files(), vec!["dest.bin"]);
+}
+
+#[test]
+fn test_skip_unchanged() {
+    let dir = TempDir::new("download-file-unchanged");
+    let dest = dir.0.join("dest.bin");
+    std::fs::write(&dest, big_data()).unwrap();
+    let client = FileClient::new(big_data());
+    let options = DownloadFileOptions::default().with_skip_unchanged(true);
+    let outcome = download_to_file(&client, "/src.bin", &dest, &options).unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Unchanged(_)));
+    assert_eq!(*client.requests.lock().unwrap(), vec!["files/get_metadata"]);
+
+    // A local file with different content is replaced, with the revision which was looked up.
+    let mut changed = big_data();
+    changed[500] ^= 1;
+    std::fs::write(&dest, changed).unwrap();
+    let outcome = download_to_file(&client, "/src.bin", &dest, &options).unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Downloaded(_)));
+    assert_eq!(std::fs::read(&dest).unwrap(), big_data());
+    assert_eq!(*client.downloads.lock().unwrap(), vec!["rev:0123456789a"]);
+}
+
+#[tokio::test]
+async fn test_async() {
+    let dir = TempDir::new("download-file-async");
+    let dest = dir.0.join("dest.bin");
+    let client = Arc::new(FileClient::new(big_data()));
+    let options = DownloadFileOptions::default().with_skip_unchanged(true);
+    let outcome = download_to_file_async(Arc::clone(&client), "/src.bin", &dest, &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Downloaded(_)));
+    assert_eq!(std::fs::read(&dest).unwrap(), big_data());
+
+    let outcome = download_to_file_async(client, "/src.bin", &dest, &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Unchanged(_)));
+    assert_eq!(dir.files(), vec!["dest.bin"]);
+}
+
+#[tokio::test]
+async fn test_async_blocking_spawner() {
+    let dir = TempDir::new("download-file-spawner");
+    let dest = dir.0.join("dest.bin");
+    let client = Arc::new(FileClient::new(big_data()));
+    let spawned = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let options = DownloadFileOptions::default()
+        .with_skip_unchanged(true)
+        .with_blocking_spawner({
+            let spawned = Arc::clone(&spawned);
+            move |f| {
+                spawned.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                tokio::task::spawn_blocking(f);
+            }
+        });
+    let outcome = download_to_file_async(Arc::clone(&client), "/src.bin", &dest, &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Downloaded(_)));
+    assert_eq!(std::fs::read(&dest).unwrap(), big_data());
+    // Checking the local file, creating the temporary file, writing at least once, and renaming.
+    assert!(spawned.load(std::sync::atomic::Ordering::Relaxed) >= 4);
+
+    let outcome = download_to_file_async(client, "/src.bin", &dest, &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, DownloadFileOutcome::Unchanged(_)));
+    assert_eq!(dir.files(), vec!["dest.bin"]);
+}
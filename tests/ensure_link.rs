@@ -0,0 +1,471 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::ensure_link::{
+    EnsureSharedLinkError, EnsureSharedLinkOptions, EnsureSharedLinkOutcome, SharedLinkChange,
+    ensure_shared_link, ensure_shared_link_async,
+};
+use dropbox_sdk::sharing::{LinkAudience, RequestedLinkAccessLevel, SharedLinkSettings};
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response};
+
+const URL: &str = "https://www.dropbox.com/scl/fi/abc/report.pdf";
+
+/// The settings of the link to `/report.pdf`.
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    audience: String,
+    /// The audience a team policy forces, whatever the link asks for.
+    forced_audience: Option<String>,
+    access: String,
+    expires: Option<String>,
+    require_password: bool,
+    password: Option<String>,
+    allow_download: bool,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            audience: "public".to_owned(),
+            forced_audience: None,
+            access: "viewer".to_owned(),
+            expires: None,
+            require_password: false,
+            password: None,
+            allow_download: true,
+        }
+    }
+}
+
+impl Link {
+    fn metadata(&self) -> serde_json::Value {
+        let mut metadata = serde_json::json!({
+            ".tag": "file",
+            "url": URL,
+            "name": "report.pdf",
+            "path_lower": "/report.pdf",
+            "link_permissions": {
+                "can_revoke": true,
+                "visibility_policies": [],
+                "can_set_expiry": true,
+                "can_remove_expiry": true,
+                "allow_download": self.allow_download,
+                "can_allow_download": true,
+                "can_disallow_download": true,
+                "allow_comments": false,
+                "team_restricts_comments": false,
+                "effective_audience": {".tag": self.audience},
+                "link_access_level": {".tag": self.access},
+                "require_password": self.require_password,
+            },
+            "client_modified": "2020-01-01T00:00:00Z",
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": 1,
+        });
+        if let Some(expires) = &self.expires {
+            metadata["expires"] = serde_json::json!(expires);
+        }
+        if let Some(forced) = &self.forced_audience {
+            let permissions = &mut metadata["link_permissions"];
+            permissions["effective_audience"] = serde_json::json!({".tag": forced});
+            permissions["audience_options"] = ["public", "team", "no_one"]
+                .iter()
+                .map(|audience| {
+                    serde_json::json!({
+                        "audience": {".tag": audience},
+                        "allowed": audience == forced,
+                    })
+                })
+                .collect();
+            let requested = match self.audience.as_str() {
+                "team" => "team_only",
+                other => other,
+            };
+            permissions["requested_visibility"] = serde_json::json!({".tag": requested});
+        }
+        metadata
+    }
+
+    fn apply(&mut self, settings: &serde_json::Value) {
+        if let Some(audience) = settings["audience"][".tag"].as_str() {
+            self.audience = audience.to_owned();
+        }
+        if let Some(access) = settings["access"][".tag"].as_str() {
+            self.access = access.to_owned();
+        }
+        if let Some(expires) = settings["expires"].as_str() {
+            self.expires = Some(expires.to_owned());
+        }
+        if let Some(require) = settings["require_password"].as_bool() {
+            self.require_password = require;
+        }
+        if let Some(password) = settings["link_password"].as_str() {
+            self.require_password = true;
+            self.password = Some(password.to_owned());
+        }
+        if let Some(allow) = settings["allow_download"].as_bool() {
+            self.allow_download = allow;
+        }
+    }
+}
+
+/// A client for an account with a file `/report.pdf`, which might have a shared link.
+#[derive(Default)]
+struct LinkClient {
+    link: Mutex<Option<Link>>,
+    /// Whether to leave the existing link's metadata out of `shared_link_already_exists` errors.
+    hide_existing: bool,
+    requests: Mutex<Vec<String>>,
+    /// The arguments of each call to `sharing/modify_shared_link_settings`.
+    modified: Mutex<Vec<serde_json::Value>>,
+}
+
+impl LinkClient {
+    fn with_link(link: Link) -> Self {
+        Self {
+            link: Mutex::new(Some(link)),
+            ..Self::default()
+        }
+    }
+}
+
+impl HttpClient for LinkClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let route = request.route();
+        self.requests.lock().unwrap().push(route.to_owned());
+        let mut link = self.link.lock().unwrap();
+        match route {
+            "sharing/create_shared_link_with_settings" => {
+                assert_eq!(arg["path"], "/report.pdf");
+                if let Some(link) = &*link {
+                    let error = if self.hide_existing {
+                        serde_json::json!({".tag": "shared_link_already_exists"})
+                    } else {
+                        serde_json::json!({
+                            ".tag": "shared_link_already_exists",
+                            "shared_link_already_exists": {
+                                ".tag": "metadata",
+                                "metadata": link.metadata(),
+                            },
+                        })
+                    };
+                    return Ok(json_response(409, serde_json::json!({ "error": error })));
+                }
+                let mut new = Link::default();
+                new.apply(&arg["settings"]);
+                let metadata = new.metadata();
+                *link = Some(new);
+                Ok(json_response(200, metadata))
+            }
+            "sharing/list_shared_links" => {
+                assert_eq!(arg["path"], "/report.pdf");
+                assert_eq!(arg["direct_only"], true);
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "links": link.iter().map(Link::metadata).collect::<Vec<_>>(),
+                        "has_more": false,
+                    }),
+                ))
+            }
+            "sharing/modify_shared_link_settings" => {
+                assert_eq!(arg["url"], URL);
+                let link = link.as_mut().unwrap();
+                link.apply(&arg["settings"]);
+                if arg["remove_expiration"] == true {
+                    link.expires = None;
+                }
+                self.modified.lock().unwrap().push(arg);
+                Ok(json_response(200, link.metadata()))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for LinkClient {}
+
+fn settings() -> SharedLinkSettings {
+    SharedLinkSettings::default()
+        .with_audience(LinkAudience::Team)
+        .with_access(RequestedLinkAccessLevel::Viewer)
+        .with_expires("2030-01-01T00:00:00Z".to_owned())
+        .with_allow_download(false)
+}
+
+#[test]
+fn test_create_then_unchanged() {
+    let client = LinkClient::default();
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings(),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(outcome, EnsureSharedLinkOutcome::Created(_)));
+    assert_eq!(
+        *client.link.lock().unwrap(),
+        Some(Link {
+            audience: "team".to_owned(),
+            expires: Some("2030-01-01T00:00:00Z".to_owned()),
+            allow_download: false,
+            ..Link::default()
+        })
+    );
+
+    // Asking again changes nothing.
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings(),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(outcome, EnsureSharedLinkOutcome::Unchanged(_)));
+    assert!(client.modified.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_reconcile() {
+    let client = LinkClient::with_link(Link {
+        access: "editor".to_owned(),
+        expires: Some("2025-01-01T00:00:00Z".to_owned()),
+        ..Link::default()
+    });
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings().with_link_password("hunter2".to_owned()),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    let EnsureSharedLinkOutcome::Updated { changes, .. } = outcome else {
+        panic!("{outcome:?}");
+    };
+    assert_eq!(
+        changes,
+        vec![
+            SharedLinkChange::Expiry,
+            SharedLinkChange::Audience,
+            SharedLinkChange::Password,
+            SharedLinkChange::Access,
+            SharedLinkChange::AllowDownload,
+        ]
+    );
+    assert_eq!(
+        *client.link.lock().unwrap(),
+        Some(Link {
+            audience: "team".to_owned(),
+            expires: Some("2030-01-01T00:00:00Z".to_owned()),
+            require_password: true,
+            password: Some("hunter2".to_owned()),
+            allow_download: false,
+            ..Link::default()
+        })
+    );
+
+    // Only what differs is sent.
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &SharedLinkSettings::default().with_audience(LinkAudience::Public),
+        &EnsureSharedLinkOptions::default().with_remove_expiration(true),
+    )
+    .unwrap();
+    assert!(matches!(
+        &outcome,
+        EnsureSharedLinkOutcome::Updated { changes, .. }
+            if *changes == [SharedLinkChange::Expiry, SharedLinkChange::Audience]
+    ));
+    let modified = client.modified.lock().unwrap();
+    assert_eq!(
+        modified[1],
+        serde_json::json!({
+            "url": URL,
+            "settings": {"audience": {".tag": "public"}},
+            "remove_expiration": true,
+        })
+    );
+    assert_eq!(client.link.lock().unwrap().as_ref().unwrap().expires, None);
+}
+
+#[test]
+fn test_password() {
+    let client = LinkClient::with_link(Link::default());
+    let settings = SharedLinkSettings::default().with_link_password("hunter2".to_owned());
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings,
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(
+        &outcome,
+        EnsureSharedLinkOutcome::Updated { changes, .. }
+            if *changes == [SharedLinkChange::Password]
+    ));
+
+    // The link requires a password now, so it's left alone.
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings.clone().with_link_password("swordfish".to_owned()),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(outcome, EnsureSharedLinkOutcome::Unchanged(_)));
+    assert_eq!(client.modified.lock().unwrap().len(), 1);
+
+    // Unless it's asked to be reset.
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings.with_link_password("swordfish".to_owned()),
+        &EnsureSharedLinkOptions::default().with_reset_password(true),
+    )
+    .unwrap();
+    assert!(matches!(
+        &outcome,
+        EnsureSharedLinkOutcome::Updated { changes, .. }
+            if *changes == [SharedLinkChange::Password]
+    ));
+    assert_eq!(
+        client
+            .link
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .password
+            .as_deref(),
+        Some("swordfish")
+    );
+}
+
+#[test]
+fn test_audience_forced_by_policy() {
+    let client = LinkClient::with_link(Link {
+        forced_audience: Some("team".to_owned()),
+        ..Link::default()
+    });
+
+    // The link asks for the audience already, the one the policy forces is its audience already,
+    // or the policy doesn't allow it.
+    for audience in [
+        LinkAudience::Public,
+        LinkAudience::Team,
+        LinkAudience::NoOne,
+    ] {
+        let outcome = ensure_shared_link(
+            &client,
+            "/report.pdf",
+            &SharedLinkSettings::default().with_audience(audience),
+            &EnsureSharedLinkOptions::default(),
+        )
+        .unwrap();
+        assert!(
+            matches!(outcome, EnsureSharedLinkOutcome::Unchanged(_)),
+            "{outcome:?}"
+        );
+    }
+    assert!(client.modified.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_existing_link_not_in_error() {
+    let client = LinkClient {
+        hide_existing: true,
+        ..LinkClient::with_link(Link::default())
+    };
+    let outcome = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &SharedLinkSettings::default().with_require_password(false),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap();
+    assert!(matches!(outcome, EnsureSharedLinkOutcome::Unchanged(_)));
+    assert_eq!(
+        *client.requests.lock().unwrap(),
+        vec![
+            "sharing/create_shared_link_with_settings",
+            "sharing/list_shared_links"
+        ]
+    );
+}
+
+#[test]
+fn test_error() {
+    let client = SettingsErrorClient;
+    let err = ensure_shared_link(
+        &client,
+        "/report.pdf",
+        &settings(),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, EnsureSharedLinkError::Create { path, .. } if path == "/report.pdf"),
+        "{err}"
+    );
+}
+
+/// A client which rejects every link's settings.
+struct SettingsErrorClient;
+
+impl HttpClient for SettingsErrorClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        _request: Self::Request,
+        _body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        Ok(json_response(
+            409,
+            serde_json::json!({"error": {
+                ".tag": "settings_error",
+                "settings_error": {".tag": "invalid_settings"},
+            }}),
+        ))
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for SettingsErrorClient {}
+
+#[tokio::test]
+async fn test_async() {
+    let client = LinkClient::with_link(Link::default());
+    let outcome = ensure_shared_link_async(
+        &client,
+        "/report.pdf",
+        &SharedLinkSettings::default().with_allow_download(false),
+        &EnsureSharedLinkOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        &outcome,
+        EnsureSharedLinkOutcome::Updated { changes, .. }
+            if *changes == [SharedLinkChange::AllowDownload]
+    ));
+}
@@ -0,0 +1,396 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::Metadata;
+use dropbox_sdk::helpers::fs::{AsyncDropboxFs, DropboxFs, FsOptions};
+use futures::{AsyncReadExt, AsyncWriteExt};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, api_error, content_response, json_response};
+
+/// A client which keeps a Dropbox in memory, implementing the routes used by `DropboxFs`.
+#[derive(Default)]
+struct MemoryClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Files and folders, by lowercased path.
+    entries: BTreeMap<String, Entry>,
+    /// Data uploaded to each upload session so far.
+    sessions: HashMap<String, Vec<u8>>,
+    next_rev: u64,
+    /// The routes called, in order.
+    calls: Vec<String>,
+}
+
+struct Entry {
+    path: String,
+    /// The content of a file, or `None` for a folder.
+    data: Option<Vec<u8>>,
+    rev: String,
+}
+
+impl Entry {
+    fn json(&self) -> serde_json::Value {
+        let name = self.path.rsplit('/').next().unwrap();
+        match &self.data {
+            Some(data) => serde_json::json!({
+                ".tag": "file",
+                "name": name,
+                "id": format!("id:{}", self.rev),
+                "client_modified": "2020-01-01T00:00:00Z",
+                "server_modified": "2020-01-01T00:00:00Z",
+                "rev": self.rev,
+                "size": data.len(),
+                "path_lower": self.path.to_lowercase(),
+                "path_display": self.path,
+            }),
+            None => serde_json::json!({
+                ".tag": "folder",
+                "name": name,
+                "id": format!("id:{}", self.rev),
+                "path_lower": self.path.to_lowercase(),
+                "path_display": self.path,
+            }),
+        }
+    }
+}
+
+impl State {
+    fn insert(&mut self, path: &str, data: Option<Vec<u8>>) -> &Entry {
+        self.next_rev += 1;
+        let entry = Entry {
+            path: path.to_owned(),
+            data,
+            rev: format!("{:09x}", self.next_rev),
+        };
+        let key = path.to_lowercase();
+        self.entries.insert(key.clone(), entry);
+        &self.entries[&key]
+    }
+
+    /// The keys of an entry and everything under it.
+    fn subtree(&self, path: &str) -> Vec<String> {
+        let path = path.to_lowercase();
+        let prefix = format!("{path}/");
+        self.entries
+            .keys()
+            .filter(|key| **key == path || key.starts_with(&prefix))
+            .cloned()
+            .collect()
+    }
+
+    fn children(&self, path: &str) -> Vec<serde_json::Value> {
+        let prefix = format!("{}/", path.to_lowercase());
+        self.entries
+            .iter()
+            .filter(|(key, _)| {
+                key.strip_prefix(&prefix)
+                    .is_some_and(|rest| !rest.contains('/'))
+            })
+            .map(|(_, entry)| entry.json())
+            .collect()
+    }
+
+    fn list_page(&self, path: &str, offset: usize) -> serde_json::Value {
+        let children = self.children(path);
+        let end = children.len().min(offset + 2);
+        serde_json::json!({
+            "entries": children[offset..end],
+            "cursor": format!("{path}|{end}"),
+            "has_more": end < children.len(),
+        })
+    }
+
+    /// Move or copy an entry and everything under it.
+    fn relocate(&mut self, from: &str, to: &str, keep: bool) -> Result<serde_json::Value, String> {
+        if self.entries.contains_key(&to.to_lowercase()) {
+            return Err(
+                r#"{".tag":"to","to":{".tag":"conflict","conflict":{".tag":"file"}}}"#.to_owned(),
+            );
+        }
+        let keys = self.subtree(from);
+        if keys.is_empty() {
+            return Err(r#"{".tag":"from_lookup","from_lookup":{".tag":"not_found"}}"#.to_owned());
+        }
+        for key in keys {
+            let entry = if keep {
+                let entry = &self.entries[&key];
+                (entry.path.clone(), entry.data.clone())
+            } else {
+                let entry = self.entries.remove(&key).unwrap();
+                (entry.path, entry.data)
+            };
+            let path = format!("{to}{}", &entry.0[from.len()..]);
+            self.insert(&path, entry.1);
+        }
+        Ok(self.entries[&to.to_lowercase()].json())
+    }
+}
+
+fn ok(value: serde_json::Value) -> HttpRequestResultRaw {
+    json_response(200, value)
+}
+
+const NOT_FOUND: &str = r#"{".tag":"path","path":{".tag":"not_found"}}"#;
+
+impl HttpClient for MemoryClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let route = request.route().to_owned();
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(route.clone());
+        let path = arg["path"].as_str().unwrap_or_default();
+        Ok(match route.as_str() {
+            "files/get_metadata" => match state.entries.get(&path.to_lowercase()) {
+                Some(entry) => ok(entry.json()),
+                None => api_error(NOT_FOUND),
+            },
+            "files/list_folder" => {
+                if !state.entries.contains_key(&path.to_lowercase()) {
+                    return Ok(api_error(NOT_FOUND));
+                }
+                ok(state.list_page(path, 0))
+            }
+            "files/list_folder/continue" => {
+                let (path, offset) = arg["cursor"].as_str().unwrap().split_once('|').unwrap();
+                ok(state.list_page(path, offset.parse().unwrap()))
+            }
+            "files/download" => {
+                let rev = path.strip_prefix("rev:").unwrap();
+                let entry = state.entries.values().find(|e| e.rev == rev).unwrap();
+                let data = entry.data.as_ref().unwrap();
+                let range = request.range.unwrap();
+                let (start, end) = range
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                let (start, end) = (start.parse().unwrap(), end.parse::<usize>().unwrap() + 1);
+                content_response(
+                    206,
+                    Some(entry.json().to_string()),
+                    data[start..end].to_vec(),
+                )
+            }
+            "files/upload" => {
+                assert_eq!(arg["mode"][".tag"], "overwrite");
+                ok(state.insert(path, Some(body.to_vec())).json())
+            }
+            "files/upload_session/start" => {
+                let id = format!("s{}", state.sessions.len());
+                state.sessions.insert(id.clone(), body.to_vec());
+                ok(serde_json::json!({ "session_id": id }))
+            }
+            "files/upload_session/append_v2" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let session = state.sessions.get_mut(id).unwrap();
+                assert_eq!(arg["cursor"]["offset"], session.len());
+                session.extend_from_slice(body);
+                ok(serde_json::Value::Null)
+            }
+            "files/upload_session/finish" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let mut data = state.sessions.remove(id).unwrap();
+                assert_eq!(arg["cursor"]["offset"], data.len());
+                data.extend_from_slice(body);
+                let path = arg["commit"]["path"].as_str().unwrap();
+                ok(state.insert(path, Some(data)).json())
+            }
+            "files/move_v2" | "files/copy_v2" => {
+                let from = arg["from_path"].as_str().unwrap();
+                let to = arg["to_path"].as_str().unwrap();
+                match state.relocate(from, to, route == "files/copy_v2") {
+                    Ok(metadata) => ok(serde_json::json!({ "metadata": metadata })),
+                    Err(error) => api_error(&error),
+                }
+            }
+            "files/delete_v2" => {
+                let metadata = state.entries[&path.to_lowercase()].json();
+                for key in state.subtree(path) {
+                    state.entries.remove(&key);
+                }
+                ok(serde_json::json!({ "metadata": metadata }))
+            }
+            "files/create_folder_v2" => {
+                if state.entries.contains_key(&path.to_lowercase()) {
+                    return Ok(api_error(
+                        r#"{".tag":"path","path":{".tag":"conflict","conflict":{".tag":"folder"}}}"#,
+                    ));
+                }
+                let mut parent = String::new();
+                for segment in path.split('/').skip(1) {
+                    parent = format!("{parent}/{segment}");
+                    if !state.entries.contains_key(&parent.to_lowercase()) {
+                        state.insert(&parent, None);
+                    }
+                }
+                let mut metadata = state.entries[&path.to_lowercase()].json();
+                metadata.as_object_mut().unwrap().remove(".tag");
+                ok(serde_json::json!({ "metadata": metadata }))
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MemoryClient {}
+
+impl MemoryClient {
+    fn calls(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+}
+
+fn names(entries: &[Metadata]) -> Vec<&str> {
+    entries
+        .iter()
+        .map(|entry| match entry {
+            Metadata::File(file) => file.name.as_str(),
+            Metadata::Folder(folder) => folder.name.as_str(),
+            Metadata::Deleted(deleted) => deleted.name.as_str(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_write_list_and_read() {
+    let client = MemoryClient::default();
+    let fs = DropboxFs::new(&client, FsOptions::default());
+
+    fs.create_dir_all("/Docs/sub").unwrap();
+    fs.create_dir_all("/Docs/sub/").unwrap();
+    assert_eq!(
+        client.calls(),
+        vec!["files/create_folder_v2", "files/create_folder_v2"]
+    );
+
+    // A small file is uploaded in one request when it's closed.
+    let mut writer = fs.create("/Docs/a.txt").unwrap();
+    writer.write_all(b"hello").unwrap();
+    assert!(client.calls().is_empty());
+    assert_eq!(writer.close().unwrap().size, 5);
+    assert_eq!(client.calls(), vec!["files/upload"]);
+
+    // A larger one goes through an upload session as it's written.
+    let big = (0..20_000_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let mut writer = fs.create("/Docs/big.bin").unwrap();
+    writer.write_all(&big).unwrap();
+    assert_eq!(writer.close().unwrap().size, big.len() as u64);
+    assert_eq!(
+        client.calls(),
+        vec![
+            "files/upload_session/start",
+            "files/upload_session/append_v2",
+            "files/upload_session/finish",
+        ]
+    );
+
+    let entries = fs.read_dir("/docs").unwrap();
+    assert_eq!(names(&entries), vec!["a.txt", "big.bin", "sub"]);
+
+    let mut data = vec![];
+    fs.open("/docs/BIG.bin")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert!(data == big);
+}
+
+#[test]
+fn test_rename_copy_and_remove() {
+    let client = MemoryClient::default();
+    let fs = DropboxFs::new(&client, FsOptions::default());
+    fs.create_dir_all("/a/b").unwrap();
+    fs.create("/a/b/f").unwrap().close().unwrap();
+
+    fs.rename("/a/b", "/a/c").unwrap();
+    fs.copy("/a/c/f", "/a/g").unwrap();
+    assert_eq!(names(&fs.read_dir("/a").unwrap()), vec!["c", "g"]);
+    assert!(matches!(fs.metadata("/a/c/f").unwrap(), Metadata::File(_)));
+
+    fs.remove_file("/a/g").unwrap();
+    fs.remove_dir_all("/a/c").unwrap();
+    assert!(fs.read_dir("/a").unwrap().is_empty());
+}
+
+fn kind<T>(result: io::Result<T>) -> io::ErrorKind {
+    result.map(|_| ()).unwrap_err().kind()
+}
+
+#[test]
+fn test_error_kinds() {
+    let client = MemoryClient::default();
+    let fs = DropboxFs::new(&client, FsOptions::default());
+    fs.create_dir_all("/dir").unwrap();
+    fs.create("/dir/f").unwrap().close().unwrap();
+    fs.create("/g").unwrap().close().unwrap();
+
+    assert_eq!(kind(fs.metadata("/missing")), io::ErrorKind::NotFound);
+    assert_eq!(kind(fs.read_dir("/missing")), io::ErrorKind::NotFound);
+    assert_eq!(kind(fs.metadata("no-slash")), io::ErrorKind::InvalidInput);
+    assert_eq!(kind(fs.remove_file("/dir")), io::ErrorKind::IsADirectory);
+    assert_eq!(
+        kind(fs.remove_dir_all("/dir/f")),
+        io::ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        kind(fs.rename("/g", "/dir/f")),
+        io::ErrorKind::AlreadyExists
+    );
+    assert_eq!(kind(fs.copy("/nope", "/x")), io::ErrorKind::NotFound);
+
+    // The API error is kept inside.
+    let error = fs.metadata("/missing").unwrap_err();
+    let inner = error
+        .into_inner()
+        .unwrap()
+        .downcast::<dropbox_sdk::Error<dropbox_sdk::files::GetMetadataError>>()
+        .unwrap();
+    assert!(matches!(*inner, dropbox_sdk::Error::Api(_)));
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = Arc::new(MemoryClient::default());
+    let fs = AsyncDropboxFs::new(Arc::clone(&client), FsOptions::default());
+    fs.create_dir_all("/dir").await.unwrap();
+
+    let data = (0..9_000_000).map(|i| (i % 13) as u8).collect::<Vec<_>>();
+    let mut writer = fs.create("/dir/f").unwrap();
+    writer.write_all(&data).await.unwrap();
+    let metadata = writer.close().await.unwrap();
+    assert_eq!(metadata.size, data.len() as u64);
+
+    let mut read = vec![];
+    fs.open("/dir/f")
+        .await
+        .unwrap()
+        .read_to_end(&mut read)
+        .await
+        .unwrap();
+    assert!(read == data);
+
+    assert_eq!(names(&fs.read_dir("/dir").await.unwrap()), vec!["f"]);
+    fs.remove_dir_all("/dir").await.unwrap();
+    assert_eq!(
+        fs.metadata("/dir").await.unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+}
@@ -0,0 +1,359 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::link_policy::{
+    LinkAction, LinkActionError, LinkFix, LinkPolicy, LinkPolicyOptions, LinkPolicyPlanError,
+    LinkRisk, enforce_link_policy, enforce_link_policy_async, plan_link_policy,
+    plan_link_policy_async,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod common;
+use common::mock::{Request, json_response};
+
+const DAY: Duration = Duration::from_secs(86_400);
+
+/// A team client for a team with these members, listed in two pages:
+///
+/// - `dbmid:a`, with a public file link which never expires, to a file last modified in 2020,
+///   and, on a second page of links, a safe file link and a public folder link
+/// - `dbmid:b`, who has been invited but hasn't joined
+/// - `dbmid:c`, with a team-only file link which never expires
+#[derive(Default)]
+struct TeamClient {
+    /// Whether listing `dbmid:c`'s links fails.
+    fail_links: bool,
+    /// Whether revoking links fails.
+    fail_revoke: bool,
+    /// Each change request's member, route and argument.
+    changes: Mutex<Vec<(String, String, serde_json::Value)>>,
+}
+
+fn team_member(id: &str, status: &str) -> serde_json::Value {
+    let name = id.trim_start_matches("dbmid:");
+    serde_json::json!({
+        "profile": {
+            "team_member_id": id,
+            "email": format!("{name}@example.com"),
+            "email_verified": true,
+            "status": {".tag": status},
+            "name": {
+                "given_name": name,
+                "surname": name,
+                "familiar_name": name,
+                "display_name": name,
+                "abbreviated_name": name,
+            },
+            "membership_type": {".tag": "full"},
+            "groups": [],
+            "member_folder_id": "1",
+            "root_folder_id": "2",
+        },
+    })
+}
+
+fn link(
+    kind: &str,
+    name: &str,
+    audience: &str,
+    expires: Option<&str>,
+    modified: &str,
+) -> serde_json::Value {
+    let mut link = serde_json::json!({
+        ".tag": kind,
+        "url": format!("https://www.dropbox.com/scl/{name}"),
+        "name": name,
+        "path_lower": format!("/{name}"),
+        "link_permissions": {
+            "can_revoke": true,
+            "visibility_policies": [],
+            "can_set_expiry": true,
+            "can_remove_expiry": true,
+            "allow_download": true,
+            "can_allow_download": true,
+            "can_disallow_download": true,
+            "allow_comments": false,
+            "team_restricts_comments": false,
+            "effective_audience": {".tag": audience},
+            "link_access_level": {".tag": "viewer"},
+        },
+    });
+    if kind == "file" {
+        link["client_modified"] = serde_json::json!(modified);
+        link["server_modified"] = serde_json::json!(modified);
+        link["rev"] = serde_json::json!("0123456789a");
+        link["size"] = serde_json::json!(1);
+    }
+    if let Some(expires) = expires {
+        link["expires"] = serde_json::json!(expires);
+    }
+    link
+}
+
+impl HttpClient for TeamClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let route = request.route();
+        let member = request.member.clone().unwrap_or_default();
+        match route {
+            "team/members/list_v2" => Ok(json_response(
+                200,
+                serde_json::json!({
+                    "members": [
+                        team_member("dbmid:a", "active"),
+                        team_member("dbmid:b", "invited"),
+                    ],
+                    "cursor": "more",
+                    "has_more": true,
+                }),
+            )),
+            "team/members/list/continue_v2" => {
+                assert_eq!(arg["cursor"], "more");
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "members": [team_member("dbmid:c", "active")],
+                        "cursor": "done",
+                        "has_more": false,
+                    }),
+                ))
+            }
+            "sharing/list_shared_links" => match (member.as_str(), arg["cursor"].as_str()) {
+                ("dbmid:a", None) => Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "links": [link("file", "old.txt", "public", None, "2020-01-01T00:00:00Z")],
+                        "has_more": true,
+                        "cursor": "links",
+                    }),
+                )),
+                ("dbmid:a", Some("links")) => Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "links": [
+                            link(
+                                "file",
+                                "safe.txt",
+                                "team",
+                                Some("2999-01-01T00:00:00Z"),
+                                "2999-01-01T00:00:00Z",
+                            ),
+                            link("folder", "Photos", "public", Some("2999-01-01T00:00:00Z"), ""),
+                        ],
+                        "has_more": false,
+                    }),
+                )),
+                ("dbmid:c", None) if self.fail_links => Ok(json_response(
+                    409,
+                    serde_json::json!({"error": {".tag": "reset"}}),
+                )),
+                ("dbmid:c", None) => Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "links": [link("file", "new.txt", "team", None, "2999-01-01T00:00:00Z")],
+                        "has_more": false,
+                    }),
+                )),
+                other => panic!("unexpected links request {other:?}"),
+            },
+            "sharing/modify_shared_link_settings" | "sharing/revoke_shared_link" => {
+                self.changes
+                    .lock()
+                    .unwrap()
+                    .push((member, route.to_owned(), arg));
+                if route == "sharing/revoke_shared_link" {
+                    if self.fail_revoke {
+                        return Ok(json_response(
+                            409,
+                            serde_json::json!({"error": {".tag": "shared_link_access_denied"}}),
+                        ));
+                    }
+                    return Ok(json_response(200, serde_json::Value::Null));
+                }
+                Ok(json_response(
+                    200,
+                    link("folder", "Photos", "team", Some("2999-01-01T00:00:00Z"), ""),
+                ))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl TeamAuthClient for TeamClient {}
+
+fn policy() -> LinkPolicy {
+    LinkPolicy::default()
+        .with_public_links(LinkFix::RestrictToTeam)
+        .with_links_without_expiry(LinkFix::Expire(30 * DAY))
+        .with_links_to_unmodified_files(365 * DAY, LinkFix::Revoke)
+}
+
+#[test]
+fn test_plan() {
+    let client = TeamClient::default();
+    let plan = plan_link_policy(&client, &policy(), &LinkPolicyOptions::default()).unwrap();
+    let lines = plan.to_string();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{plan}");
+    assert_eq!(
+        lines[0],
+        "a@example.com https://www.dropbox.com/scl/old.txt (public, no expiry, stale): revoke"
+    );
+    assert_eq!(
+        lines[1],
+        "a@example.com https://www.dropbox.com/scl/Photos (public): restrict to team"
+    );
+    assert!(
+        lines[2].starts_with(
+            "c@example.com https://www.dropbox.com/scl/new.txt (no expiry): expire at "
+        ),
+        "{plan}"
+    );
+    assert_eq!(plan.findings[2].team_member_id, "dbmid:c");
+    assert_eq!(plan.findings[2].risks, [LinkRisk::NoExpiry]);
+    assert!(client.changes.lock().unwrap().is_empty());
+
+    // Without rules, nothing needs fixing.
+    let plan = plan_link_policy(
+        &client,
+        &LinkPolicy::default(),
+        &LinkPolicyOptions::default(),
+    )
+    .unwrap();
+    assert!(plan.findings.is_empty(), "{plan}");
+}
+
+#[test]
+fn test_enforce() {
+    let client = TeamClient::default();
+    let logged = Arc::new(AtomicUsize::new(0));
+    let options = LinkPolicyOptions::default().with_progress({
+        let logged = Arc::clone(&logged);
+        move |_| {
+            logged.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let plan = plan_link_policy(&client, &policy(), &options).unwrap();
+    let LinkAction::Modify {
+        expires: Some(expires),
+        ..
+    } = plan.findings[2].action.clone()
+    else {
+        panic!("{plan}");
+    };
+    let report = enforce_link_policy(&client, plan, &options);
+    assert_eq!(report.actions.len(), 3);
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(logged.load(Ordering::SeqCst), 3);
+
+    let mut changes = client.changes.lock().unwrap().clone();
+    changes.sort_by(|a, b| a.2["url"].as_str().cmp(&b.2["url"].as_str()));
+    assert_eq!(
+        changes,
+        vec![
+            (
+                "dbmid:a".to_owned(),
+                "sharing/modify_shared_link_settings".to_owned(),
+                serde_json::json!({
+                    "url": "https://www.dropbox.com/scl/Photos",
+                    "settings": {"audience": {".tag": "team"}},
+                }),
+            ),
+            (
+                "dbmid:c".to_owned(),
+                "sharing/modify_shared_link_settings".to_owned(),
+                serde_json::json!({
+                    "url": "https://www.dropbox.com/scl/new.txt",
+                    "settings": {"expires": expires},
+                }),
+            ),
+            (
+                "dbmid:a".to_owned(),
+                "sharing/revoke_shared_link".to_owned(),
+                serde_json::json!({"url": "https://www.dropbox.com/scl/old.txt"}),
+            ),
+        ]
+    );
+
+    let mut log = Vec::new();
+    report.write_audit_log(&mut log).unwrap();
+    let log = String::from_utf8(log).unwrap();
+    let records = log
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        records[0],
+        serde_json::json!({
+            "time": report.actions[0].time,
+            "team_member_id": "dbmid:a",
+            "email": "a@example.com",
+            "url": "https://www.dropbox.com/scl/old.txt",
+            "path": "/old.txt",
+            "risks": ["public", "no expiry", "stale"],
+            "action": "revoke",
+            "error": null,
+        })
+    );
+}
+
+#[test]
+fn test_errors() {
+    let client = TeamClient {
+        fail_links: true,
+        ..TeamClient::default()
+    };
+    let err = plan_link_policy(&client, &policy(), &LinkPolicyOptions::default()).unwrap_err();
+    assert!(
+        matches!(&err, LinkPolicyPlanError::ListLinks { team_member_id, .. } if team_member_id == "dbmid:c"),
+        "{err}"
+    );
+
+    // A failed action is reported, and doesn't stop the others.
+    let client = TeamClient {
+        fail_revoke: true,
+        ..TeamClient::default()
+    };
+    let options = LinkPolicyOptions::default().with_parallelism(1);
+    let plan = plan_link_policy(&client, &policy(), &options).unwrap();
+    let report = enforce_link_policy(&client, plan, &options);
+    let failures = report.failures().collect::<Vec<_>>();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].finding.action, LinkAction::Revoke);
+    assert!(matches!(
+        failures[0].outcome,
+        Err(LinkActionError::Revoke(dropbox_sdk::Error::Api(_)))
+    ));
+    assert_eq!(client.changes.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = Arc::new(TeamClient::default());
+    let policy = LinkPolicy::default().with_public_links(LinkFix::Revoke);
+    let options = LinkPolicyOptions::default();
+    let plan = plan_link_policy_async(&client, &policy, &options)
+        .await
+        .unwrap();
+    assert_eq!(plan.findings.len(), 2);
+    assert!(
+        plan.findings
+            .iter()
+            .all(|finding| finding.action == LinkAction::Revoke)
+    );
+    let report = enforce_link_policy_async(&client, plan, &options).await;
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(client.changes.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,260 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{LockFileError, LookupError};
+use dropbox_sdk::helpers::lock::{
+    AsyncFileLockGuard, FileLockGuard, LockError, LockOptions, get_lock_info,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod common;
+use common::mock::{Request, json_response};
+
+const ME: &str = "dbid:me";
+const OTHER: &str = "dbid:other";
+
+/// A client which keeps track of file locks. `/missing` doesn't exist; any other path does.
+#[derive(Default)]
+struct MockClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The account holding the lock on each locked path.
+    locks: HashMap<String, &'static str>,
+    /// How many lock requests have been made.
+    lock_requests: usize,
+    /// After how many lock requests to release the locks held by the other account.
+    other_releases_after: Option<usize>,
+}
+
+impl State {
+    fn locked_by(&self, account: &str) -> Vec<&str> {
+        let mut paths = self
+            .locks
+            .iter()
+            .filter(|(_, holder)| **holder == account)
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+}
+
+fn lock(holder: &str) -> serde_json::Value {
+    serde_json::json!({
+        "content": {
+            ".tag": "single_user",
+            "created": "2020-01-01T00:00:00Z",
+            "lock_holder_account_id": holder,
+        }
+    })
+}
+
+fn metadata(path: &str, holder: Option<&str>) -> serde_json::Value {
+    let mut metadata = serde_json::json!({
+        ".tag": "file",
+        "name": path.trim_start_matches('/'),
+        "id": "id:1",
+        "client_modified": "2020-01-01T00:00:00Z",
+        "server_modified": "2020-01-01T00:00:00Z",
+        "rev": "000000001",
+        "size": 1,
+        "path_lower": path,
+        "path_display": path,
+    });
+    if let Some(holder) = holder {
+        metadata["file_lock_info"] = serde_json::json!({
+            "is_lockholder": holder == ME,
+            "lockholder_account_id": holder,
+        });
+    }
+    metadata
+}
+
+fn failure(error: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ ".tag": "failure", "failure": error })
+}
+
+fn conflict(holder: &str) -> serde_json::Value {
+    failure(serde_json::json!({ ".tag": "lock_conflict", "lock": lock(holder) }))
+}
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let mut state = self.state.lock().unwrap();
+        let route = request.route();
+        if route == "files/lock_file_batch" {
+            state.lock_requests += 1;
+            if state.other_releases_after == Some(state.lock_requests) {
+                state.locks.retain(|_, holder| *holder != OTHER);
+            }
+        }
+        let entries = arg["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                let path = entry["path"].as_str().unwrap();
+                if path == "/missing" {
+                    return failure(serde_json::json!({
+                        ".tag": "path_lookup",
+                        "path_lookup": { ".tag": "not_found" },
+                    }));
+                }
+                let holder = state.locks.get(path).copied();
+                match route {
+                    "files/lock_file_batch" | "files/unlock_file_batch"
+                        if holder == Some(OTHER) =>
+                    {
+                        return conflict(OTHER);
+                    }
+                    "files/lock_file_batch" => {
+                        state.locks.insert(path.to_owned(), ME);
+                    }
+                    "files/unlock_file_batch" => {
+                        state.locks.remove(path);
+                    }
+                    "files/get_file_lock_batch" => (),
+                    other => panic!("unexpected request to {other}"),
+                }
+                let holder = state.locks.get(path).copied();
+                serde_json::json!({
+                    ".tag": "success",
+                    "metadata": metadata(path, holder),
+                    "lock": lock(holder.unwrap_or(ME)),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json_response(
+            200,
+            serde_json::json!({ "entries": entries }),
+        ))
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+fn paths(paths: &[&str]) -> Vec<String> {
+    paths.iter().map(|path| path.to_string()).collect()
+}
+
+#[test]
+fn test_lock() {
+    let client = MockClient::default();
+    let options = LockOptions::default();
+
+    let guard = FileLockGuard::acquire(&client, paths(&["/a", "/b"]), options.clone()).unwrap();
+    assert_eq!(guard.metadata().len(), 2);
+    assert_eq!(client.state.lock().unwrap().locked_by(ME), vec!["/a", "/b"]);
+
+    let info = get_lock_info(&client, paths(&["/a", "/c", "/missing"]), &options).unwrap();
+    assert_eq!(
+        info[0].as_ref().unwrap().as_ref().unwrap().is_lockholder,
+        Some(true)
+    );
+    assert_eq!(info[1], Ok(None));
+    assert!(matches!(
+        info[2],
+        Err(LockFileError::PathLookup(LookupError::NotFound))
+    ));
+
+    guard.release().unwrap();
+    assert!(client.state.lock().unwrap().locks.is_empty());
+
+    // Dropping the guard unlocks the files too.
+    {
+        let _guard = FileLockGuard::acquire(&client, paths(&["/c"]), options).unwrap();
+        assert_eq!(client.state.lock().unwrap().locked_by(ME), vec!["/c"]);
+    }
+    assert!(client.state.lock().unwrap().locks.is_empty());
+}
+
+#[test]
+fn test_conflict() {
+    let client = MockClient::default();
+    client
+        .state
+        .lock()
+        .unwrap()
+        .locks
+        .insert("/b".to_owned(), OTHER);
+
+    // None of the files are left locked if some of them can't be.
+    let options = LockOptions::default();
+    let err = FileLockGuard::acquire(&client, paths(&["/a", "/b"]), options).unwrap_err();
+    let LockError::Entries(failures) = err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, "/b");
+    assert_eq!(failures[0].holder().unwrap().lock_holder_account_id, OTHER);
+    assert!(client.state.lock().unwrap().locked_by(ME).is_empty());
+
+    // Files which don't exist aren't waited for.
+    let options = LockOptions::default()
+        .with_wait(Duration::from_secs(60))
+        .with_retry_interval(Duration::from_millis(1));
+    let err =
+        FileLockGuard::acquire(&client, paths(&["/b", "/missing"]), options.clone()).unwrap_err();
+    assert!(
+        matches!(&err, LockError::Entries(failures) if failures.len() == 2),
+        "{err}"
+    );
+    assert_eq!(client.state.lock().unwrap().lock_requests, 2);
+
+    // Files locked by someone else are, until they're unlocked.
+    client.state.lock().unwrap().other_releases_after = Some(5);
+    let guard = FileLockGuard::acquire(&client, paths(&["/a", "/b"]), options).unwrap();
+    assert_eq!(client.state.lock().unwrap().lock_requests, 5);
+    assert_eq!(client.state.lock().unwrap().locked_by(ME), vec!["/a", "/b"]);
+    drop(guard);
+    assert!(client.state.lock().unwrap().locks.is_empty());
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = Arc::new(MockClient::default());
+    let guard = AsyncFileLockGuard::acquire(client.clone(), paths(&["/a"]), LockOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(client.state.lock().unwrap().locked_by(ME), vec!["/a"]);
+    guard.release().await.unwrap();
+    assert!(client.state.lock().unwrap().locks.is_empty());
+
+    // Without a spawner, dropping the guard can't unlock the files.
+    let guard = AsyncFileLockGuard::acquire(client.clone(), paths(&["/a"]), LockOptions::default())
+        .await
+        .unwrap();
+    drop(guard);
+    assert_eq!(client.state.lock().unwrap().locked_by(ME), vec!["/a"]);
+    client.state.lock().unwrap().locks.clear();
+
+    let tasks = Arc::new(Mutex::new(Vec::new()));
+    let options = LockOptions::default().with_spawner({
+        let tasks = tasks.clone();
+        move |f| tasks.lock().unwrap().push(tokio::spawn(f))
+    });
+    let guard = AsyncFileLockGuard::acquire(client.clone(), paths(&["/a", "/b"]), options)
+        .await
+        .unwrap();
+    drop(guard);
+    let tasks = std::mem::take(&mut *tasks.lock().unwrap());
+    assert_eq!(tasks.len(), 1);
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert!(client.state.lock().unwrap().locks.is_empty());
+}
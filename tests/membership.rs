@@ -0,0 +1,321 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::membership::{
+    ChangeError, FolderMember, FolderMembershipOptions, MembershipChange, MembershipPlanError,
+    execute_folder_membership, execute_folder_membership_async, plan_folder_membership,
+    plan_folder_membership_async,
+};
+use dropbox_sdk::sharing::{AccessLevel, MemberSelector};
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response};
+
+/// A client for an account which can manage shared folder `sf:1`, whose members are:
+///
+/// - `dbid:owner`, its owner
+/// - `dbid:a`, a viewer
+/// - `dbid:b`, an editor
+/// - `dbid:c`, a viewer through a parent folder
+/// - group `g:1`, a viewer through a parent folder
+/// - `Old@example.com`, invited as a viewer (listed on a second page)
+#[derive(Default)]
+struct FolderClient {
+    /// Each change request's route and argument.
+    changes: Mutex<Vec<(String, serde_json::Value)>>,
+}
+
+fn user(id: &str, access: &str, inherited: bool) -> serde_json::Value {
+    serde_json::json!({
+        "access_type": {".tag": access},
+        "user": {
+            "account_id": id,
+            "email": format!("{}@example.com", id.trim_start_matches("dbid:")),
+            "display_name": id,
+            "same_team": true,
+        },
+        "is_inherited": inherited,
+    })
+}
+
+impl HttpClient for FolderClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let route = request.route();
+        match route {
+            "sharing/list_folder_members" => {
+                if arg["shared_folder_id"] != "sf:1" {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "invalid_id"}}),
+                    ));
+                }
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "users": [
+                            user("dbid:owner", "owner", false),
+                            user("dbid:a", "viewer", false),
+                            user("dbid:b", "editor", false),
+                            user("dbid:c", "viewer", true),
+                        ],
+                        "groups": [{
+                            "access_type": {".tag": "viewer"},
+                            "group": {
+                                "group_name": "Everyone",
+                                "group_id": "g:1",
+                                "group_management_type": {".tag": "company_managed"},
+                                "group_type": {".tag": "team"},
+                                "is_member": true,
+                                "is_owner": false,
+                                "same_team": true,
+                            },
+                            "is_inherited": true,
+                        }],
+                        "invitees": [],
+                        "cursor": "more",
+                    }),
+                ))
+            }
+            "sharing/list_folder_members/continue" => {
+                assert_eq!(arg["cursor"], "more");
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "users": [],
+                        "groups": [],
+                        "invitees": [{
+                            "access_type": {".tag": "viewer"},
+                            "invitee": {".tag": "email", "email": "Old@example.com"},
+                            "is_inherited": false,
+                        }],
+                    }),
+                ))
+            }
+            "sharing/add_folder_member" => {
+                self.changes
+                    .lock()
+                    .unwrap()
+                    .push((route.to_owned(), arg.clone()));
+                if arg["members"][0]["member"]["email"] == "bad@example.com" {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "cant_share_outside_team"}}),
+                    ));
+                }
+                Ok(json_response(200, serde_json::Value::Null))
+            }
+            "sharing/update_folder_member" => {
+                self.changes
+                    .lock()
+                    .unwrap()
+                    .push((route.to_owned(), arg.clone()));
+                Ok(json_response(200, serde_json::json!({})))
+            }
+            "sharing/remove_folder_member" => {
+                self.changes
+                    .lock()
+                    .unwrap()
+                    .push((route.to_owned(), arg.clone()));
+                Ok(json_response(
+                    200,
+                    serde_json::json!({".tag": "async_job_id", "async_job_id": "job"}),
+                ))
+            }
+            "sharing/check_remove_member_job_status" => {
+                assert_eq!(arg["async_job_id"], "job");
+                Ok(json_response(200, serde_json::json!({".tag": "complete"})))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for FolderClient {}
+
+fn desired() -> Vec<FolderMember> {
+    vec![
+        FolderMember::new(
+            MemberSelector::DropboxId("dbid:a".to_owned()),
+            AccessLevel::Editor,
+        ),
+        FolderMember::new(
+            MemberSelector::DropboxId("dbid:c".to_owned()),
+            AccessLevel::Editor,
+        ),
+        FolderMember::new(
+            MemberSelector::Email("old@example.com".to_owned()),
+            AccessLevel::Viewer,
+        ),
+        FolderMember::new(
+            MemberSelector::Email("new@example.com".to_owned()),
+            AccessLevel::Editor,
+        ),
+    ]
+}
+
+#[test]
+fn test_plan() {
+    let client = FolderClient::default();
+    let plan = plan_folder_membership(
+        &client,
+        "sf:1",
+        &desired(),
+        &FolderMembershipOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.to_string(),
+        "add dbid:c as editor\n\
+         add new@example.com as editor\n\
+         change dbid:a from viewer to editor\n\
+         remove dbid:b (editor)\n"
+    );
+    assert!(client.changes.lock().unwrap().is_empty());
+
+    // Members who are already as they should be need no changes.
+    let desired = vec![
+        FolderMember::new(
+            MemberSelector::DropboxId("dbid:a".to_owned()),
+            AccessLevel::Viewer,
+        ),
+        FolderMember::new(
+            MemberSelector::Email("b@example.com".to_owned()),
+            AccessLevel::Editor,
+        ),
+        FolderMember::new(
+            MemberSelector::DropboxId("dbid:c".to_owned()),
+            AccessLevel::Viewer,
+        ),
+        FolderMember::new(
+            MemberSelector::Email("old@example.com".to_owned()),
+            AccessLevel::Viewer,
+        ),
+        // Less than the group inherits, which can't be taken away.
+        FolderMember::new(
+            MemberSelector::DropboxId("g:1".to_owned()),
+            AccessLevel::ViewerNoComment,
+        ),
+    ];
+    let plan = plan_folder_membership(
+        &client,
+        "sf:1",
+        &desired,
+        &FolderMembershipOptions::default(),
+    )
+    .unwrap();
+    assert!(plan.changes.is_empty(), "{plan}");
+}
+
+#[test]
+fn test_execute() {
+    let client = FolderClient::default();
+    let mut desired = desired();
+    desired.push(FolderMember::new(
+        MemberSelector::Email("bad@example.com".to_owned()),
+        AccessLevel::Viewer,
+    ));
+    let options = FolderMembershipOptions::default()
+        .with_quiet(true)
+        .with_custom_message("Welcome");
+    let plan = plan_folder_membership(&client, "sf:1", &desired, &options).unwrap();
+    let report = execute_folder_membership(&client, plan, &options);
+    assert_eq!(report.changes.len(), 5);
+
+    // The failure is reported, and doesn't stop the other changes.
+    let failures = report.failures().collect::<Vec<_>>();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(
+        failures[0].change.member(),
+        &MemberSelector::Email("bad@example.com".to_owned())
+    );
+    assert!(matches!(
+        failures[0].outcome,
+        Err(ChangeError::Add(dropbox_sdk::Error::Api(_)))
+    ));
+
+    let changes = client.changes.lock().unwrap();
+    let routes = changes
+        .iter()
+        .map(|(route, _)| route.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        vec![
+            "sharing/add_folder_member",
+            "sharing/add_folder_member",
+            "sharing/add_folder_member",
+            "sharing/update_folder_member",
+            "sharing/remove_folder_member",
+        ]
+    );
+    assert_eq!(
+        changes[1].1,
+        serde_json::json!({
+            "shared_folder_id": "sf:1",
+            "members": [{
+                "member": {".tag": "email", "email": "new@example.com"},
+                "access_level": {".tag": "editor"},
+            }],
+            "quiet": true,
+            "custom_message": "Welcome",
+        })
+    );
+    assert_eq!(
+        changes[3].1,
+        serde_json::json!({
+            "shared_folder_id": "sf:1",
+            "member": {".tag": "dropbox_id", "dropbox_id": "dbid:a"},
+            "access_level": {".tag": "editor"},
+        })
+    );
+    assert_eq!(
+        changes[4].1,
+        serde_json::json!({
+            "shared_folder_id": "sf:1",
+            "member": {".tag": "dropbox_id", "dropbox_id": "dbid:b"},
+            "leave_a_copy": false,
+        })
+    );
+}
+
+#[test]
+fn test_plan_error() {
+    let client = FolderClient::default();
+    let err = plan_folder_membership(
+        &client,
+        "sf:2",
+        &desired(),
+        &FolderMembershipOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, MembershipPlanError::ListMembers(_)), "{err}");
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = FolderClient::default();
+    let options = FolderMembershipOptions::default();
+    let plan = plan_folder_membership_async(&client, "sf:1", &[], &options)
+        .await
+        .unwrap();
+    // Everyone with direct access except the owner is removed: dbid:a, dbid:b and the invitee.
+    assert_eq!(plan.changes.len(), 3);
+    assert!(
+        plan.changes
+            .iter()
+            .all(|change| matches!(change, MembershipChange::Remove { .. }))
+    );
+    let report = execute_folder_membership_async(&client, plan, &options).await;
+    assert_eq!(report.failures().count(), 0);
+    assert_eq!(client.changes.lock().unwrap().len(), 3);
+}
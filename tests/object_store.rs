@@ -0,0 +1,560 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::object_store::{DropboxObjectStore, ObjectStoreOptions};
+use dropbox_sdk::helpers::path::DropboxPath;
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, ObjectStore, ObjectStoreExt, PutMode, PutMultipartOptions, PutOptions,
+    PutPayload, UpdateVersion,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, api_error, content_response, json_response};
+
+/// A client which keeps files in memory, implementing the routes used by `DropboxObjectStore`.
+#[derive(Default)]
+struct MemoryClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Files and folders, by lowercased path.
+    entries: BTreeMap<String, Entry>,
+    /// The pieces of each concurrent upload session, by offset.
+    sessions: HashMap<String, BTreeMap<u64, Vec<u8>>>,
+    next_rev: u64,
+}
+
+struct Entry {
+    path: String,
+    /// The content of a file, or `None` for a folder.
+    data: Option<Vec<u8>>,
+    rev: String,
+}
+
+impl Entry {
+    fn json(&self) -> serde_json::Value {
+        let name = self.path.rsplit('/').next().unwrap();
+        match &self.data {
+            Some(data) => serde_json::json!({
+                ".tag": "file",
+                "name": name,
+                "id": format!("id:{}", self.rev),
+                "client_modified": "2020-01-01T00:00:00Z",
+                "server_modified": "2020-01-01T00:00:00Z",
+                "rev": self.rev,
+                "size": data.len(),
+                "path_lower": self.path.to_lowercase(),
+                "path_display": self.path,
+                "content_hash": content_hash(data),
+            }),
+            None => serde_json::json!({
+                ".tag": "folder",
+                "name": name,
+                "id": format!("id:{}", self.rev),
+                "path_lower": self.path.to_lowercase(),
+                "path_display": self.path,
+            }),
+        }
+    }
+}
+
+impl State {
+    /// Write a file, creating its parent folders.
+    fn write(&mut self, path: &str, data: Vec<u8>) -> serde_json::Value {
+        let mut parent = String::new();
+        let segments = path.split('/').skip(1).collect::<Vec<_>>();
+        for segment in &segments[..segments.len() - 1] {
+            parent = format!("{parent}/{segment}");
+            if !self.entries.contains_key(&parent.to_lowercase()) {
+                self.insert(&parent, None);
+            }
+        }
+        self.insert(path, Some(data))
+    }
+
+    fn insert(&mut self, path: &str, data: Option<Vec<u8>>) -> serde_json::Value {
+        self.next_rev += 1;
+        let entry = Entry {
+            path: path.to_owned(),
+            data,
+            rev: format!("{:09x}", self.next_rev),
+        };
+        let json = entry.json();
+        self.entries.insert(path.to_lowercase(), entry);
+        json
+    }
+
+    fn find(&self, path: &str) -> Option<&Entry> {
+        match path.strip_prefix("rev:") {
+            Some(rev) => self.entries.values().find(|entry| entry.rev == rev),
+            None => self.entries.get(&path.to_lowercase()),
+        }
+    }
+
+    fn list_page(&self, path: &str, recursive: bool, offset: usize) -> serde_json::Value {
+        let prefix = format!("{}/", path.to_lowercase());
+        let children = self
+            .entries
+            .iter()
+            .filter(|(key, _)| {
+                key.strip_prefix(&prefix)
+                    .is_some_and(|rest| recursive || !rest.contains('/'))
+            })
+            .map(|(_, entry)| entry.json())
+            .collect::<Vec<_>>();
+        let end = children.len().min(offset + 2);
+        serde_json::json!({
+            "entries": children[offset..end],
+            "cursor": format!("{path}|{recursive}|{end}"),
+            "has_more": end < children.len(),
+        })
+    }
+
+    /// Check a write mode against the existing file, returning the API error for a conflict.
+    fn check_mode(&self, path: &str, mode: &serde_json::Value) -> Option<String> {
+        let existing = self.entries.get(&path.to_lowercase());
+        let conflict = match mode[".tag"].as_str().unwrap_or("add") {
+            "add" => existing.is_some(),
+            "update" => existing.is_none_or(|e| e.rev != mode["update"]),
+            _ => false,
+        };
+        conflict.then(|| r#"{".tag":"conflict","conflict":{".tag":"file"}}"#.to_owned())
+    }
+}
+
+fn ok(value: serde_json::Value) -> HttpRequestResultRaw {
+    json_response(200, value)
+}
+
+const NOT_FOUND: &str = r#"{".tag":"path","path":{".tag":"not_found"}}"#;
+
+impl HttpClient for MemoryClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let route = request.route();
+        let mut state = self.state.lock().unwrap();
+        let path = arg["path"].as_str().unwrap_or_default();
+        Ok(match route {
+            "files/get_metadata" => match state.find(path) {
+                Some(entry) => ok(entry.json()),
+                None => api_error(NOT_FOUND),
+            },
+            "files/list_folder" => {
+                if !path.is_empty() && !state.entries.contains_key(&path.to_lowercase()) {
+                    return Ok(api_error(NOT_FOUND));
+                }
+                ok(state.list_page(path, arg["recursive"] == true, 0))
+            }
+            "files/list_folder/continue" => {
+                let cursor = arg["cursor"].as_str().unwrap();
+                let mut parts = cursor.split('|');
+                let (path, recursive, offset) = (
+                    parts.next().unwrap(),
+                    parts.next().unwrap(),
+                    parts.next().unwrap(),
+                );
+                ok(state.list_page(path, recursive == "true", offset.parse().unwrap()))
+            }
+            "files/download" => {
+                let Some(entry) = state.find(path) else {
+                    return Ok(api_error(NOT_FOUND));
+                };
+                let data = entry.data.as_ref().unwrap();
+                let len = data.len();
+                let (status, range) = match request.range {
+                    None => (200, 0..len),
+                    Some(range) => {
+                        let (start, end) = range
+                            .strip_prefix("bytes=")
+                            .unwrap()
+                            .split_once('-')
+                            .unwrap();
+                        let range = match (start.parse::<usize>(), end.parse::<usize>()) {
+                            (Ok(start), Ok(end)) => start..len.min(end + 1),
+                            (Ok(start), Err(_)) => start..len,
+                            (Err(_), Ok(suffix)) => len.saturating_sub(suffix)..len,
+                            _ => panic!("bad range {range}"),
+                        };
+                        (206, range)
+                    }
+                };
+                content_response(status, Some(entry.json().to_string()), data[range].to_vec())
+            }
+            "files/upload" => {
+                assert_eq!(arg["strict_conflict"], true);
+                if let Some(reason) = state.check_mode(path, &arg["mode"]) {
+                    return Ok(api_error(&format!(
+                        r#"{{".tag":"path","reason":{reason},"upload_session_id":"u"}}"#
+                    )));
+                }
+                ok(state.write(path, body.to_vec()))
+            }
+            "files/upload_session/start" => {
+                assert_eq!(arg["session_type"][".tag"], "concurrent");
+                assert!(body.is_empty());
+                let id = format!("s{}", state.sessions.len());
+                state.sessions.insert(id.clone(), BTreeMap::new());
+                ok(serde_json::json!({ "session_id": id }))
+            }
+            "files/upload_session/append_v2" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let offset = arg["cursor"]["offset"].as_u64().unwrap();
+                if arg["close"] != true {
+                    assert_eq!(body.len() % (4 * 1024 * 1024), 0);
+                }
+                state
+                    .sessions
+                    .get_mut(id)
+                    .unwrap()
+                    .insert(offset, body.to_vec());
+                ok(serde_json::Value::Null)
+            }
+            "files/upload_session/finish" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let mut data = vec![];
+                for (offset, piece) in state.sessions.remove(id).unwrap() {
+                    assert_eq!(offset, data.len() as u64);
+                    data.extend(piece);
+                }
+                assert_eq!(arg["cursor"]["offset"], data.len());
+                assert_eq!(arg["commit"]["strict_conflict"], true);
+                let path = arg["commit"]["path"].as_str().unwrap();
+                if let Some(reason) = state.check_mode(path, &arg["commit"]["mode"]) {
+                    return Ok(api_error(&format!(r#"{{".tag":"path","path":{reason}}}"#)));
+                }
+                ok(state.write(path, data))
+            }
+            "files/copy_v2" | "files/move_v2" => {
+                let from = arg["from_path"].as_str().unwrap();
+                let to = arg["to_path"].as_str().unwrap();
+                if state.entries.contains_key(&to.to_lowercase()) {
+                    return Ok(api_error(
+                        r#"{".tag":"to","to":{".tag":"conflict","conflict":{".tag":"file"}}}"#,
+                    ));
+                }
+                let data = state.entries[&from.to_lowercase()].data.clone().unwrap();
+                if route == "files/move_v2" {
+                    state.entries.remove(&from.to_lowercase());
+                }
+                ok(serde_json::json!({ "metadata": state.write(to, data) }))
+            }
+            "files/delete_v2" => {
+                let entry = &state.entries[&path.to_lowercase()];
+                assert_eq!(arg["parent_rev"], entry.rev);
+                let metadata = entry.json();
+                state.entries.remove(&path.to_lowercase());
+                ok(serde_json::json!({ "metadata": metadata }))
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MemoryClient {}
+
+fn store() -> (Arc<MemoryClient>, DropboxObjectStore<MemoryClient>) {
+    let client = Arc::new(MemoryClient::default());
+    let store = DropboxObjectStore::new(
+        Arc::clone(&client),
+        DropboxPath::parse("/Pipelines").unwrap(),
+        ObjectStoreOptions::default(),
+    )
+    .unwrap();
+    (client, store)
+}
+
+#[test]
+fn test_root_must_be_a_path() {
+    let client = Arc::new(MemoryClient::default());
+    for root in ["id:a4ayc_80_OEAAAAAAAAAYa", "ns:1234/Pipelines"] {
+        let result = DropboxObjectStore::new(
+            Arc::clone(&client),
+            DropboxPath::parse(root).unwrap(),
+            ObjectStoreOptions::default(),
+        );
+        assert!(
+            matches!(result, Err(object_store::Error::Generic { .. })),
+            "{root}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_put_and_get() {
+    let (client, store) = store();
+    let location = Path::from("data/a.txt");
+    let put = store
+        .put(&location, PutPayload::from_static(b"hello, world"))
+        .await
+        .unwrap();
+    assert_eq!(
+        put.e_tag.as_deref(),
+        Some(content_hash(b"hello, world").as_str())
+    );
+    assert!(
+        client
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key("/pipelines/data/a.txt")
+    );
+
+    let result = store.get(&location).await.unwrap();
+    assert_eq!(result.meta.size, 12);
+    assert_eq!(result.meta.e_tag, put.e_tag);
+    assert_eq!(result.meta.version, put.version);
+    assert_eq!(result.range, 0..12);
+    assert_eq!(result.bytes().await.unwrap().as_ref(), b"hello, world");
+
+    assert_eq!(
+        store.get_range(&location, 7..100).await.unwrap().as_ref(),
+        b"world"
+    );
+    let options = GetOptions::new().with_range(Some(GetRange::Suffix(5)));
+    let result = store.get_opts(&location, options).await.unwrap();
+    assert_eq!(result.range, 7..12);
+    assert_eq!(result.bytes().await.unwrap().as_ref(), b"world");
+
+    let head = store.head(&location).await.unwrap();
+    assert_eq!(head.location, location);
+    assert_eq!(head.last_modified.to_rfc3339(), "2020-01-01T00:00:00+00:00");
+
+    // A version is read by its revision.
+    let second = store
+        .put(&location, PutPayload::from_static(b"changed"))
+        .await
+        .unwrap();
+    let options = GetOptions::new().with_version(second.version);
+    let result = store.get_opts(&location, options).await.unwrap();
+    assert_eq!(result.bytes().await.unwrap().as_ref(), b"changed");
+
+    assert!(matches!(
+        store.get(&Path::from("missing")).await,
+        Err(object_store::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        store.head(&Path::from("data")).await,
+        Err(object_store::Error::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_conditional_requests() {
+    let (_client, store) = store();
+    let location = Path::from("a");
+    let first = store
+        .put(&location, PutPayload::from_static(b"1"))
+        .await
+        .unwrap();
+
+    let create = PutOptions::from(PutMode::Create);
+    assert!(matches!(
+        store
+            .put_opts(&location, PutPayload::from_static(b"2"), create)
+            .await,
+        Err(object_store::Error::AlreadyExists { .. })
+    ));
+
+    let update = |version: UpdateVersion| PutOptions::from(PutMode::Update(version));
+    let second = store
+        .put_opts(
+            &location,
+            PutPayload::from_static(b"2"),
+            update(UpdateVersion::from(first.clone())),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        store
+            .put_opts(
+                &location,
+                PutPayload::from_static(b"3"),
+                update(UpdateVersion::from(first.clone())),
+            )
+            .await,
+        Err(object_store::Error::Precondition { .. })
+    ));
+    let by_e_tag = UpdateVersion {
+        e_tag: second.e_tag.clone(),
+        version: None,
+    };
+    store
+        .put_opts(&location, PutPayload::from_static(b"3"), update(by_e_tag))
+        .await
+        .unwrap();
+    assert!(matches!(
+        store
+            .put_opts(
+                &Path::from("new"),
+                PutPayload::from_static(b"3"),
+                update(UpdateVersion::from(second.clone())),
+            )
+            .await,
+        Err(object_store::Error::Precondition { .. })
+    ));
+
+    let options = GetOptions::new().with_if_none_match(Some(content_hash(b"3")));
+    assert!(matches!(
+        store.get_opts(&location, options).await,
+        Err(object_store::Error::NotModified { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_list() {
+    let (_client, store) = store();
+    for name in ["x/1", "x/2", "x/y/3", "z"] {
+        store
+            .put(&Path::from(name), PutPayload::from_static(b"."))
+            .await
+            .unwrap();
+    }
+
+    let mut all = store
+        .list(None)
+        .map_ok(|meta| meta.location.to_string())
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    all.sort();
+    assert_eq!(all, vec!["x/1", "x/2", "x/y/3", "z"]);
+
+    let under_x = store
+        .list(Some(&Path::from("x")))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(under_x.len(), 3);
+    let missing = store
+        .list(Some(&Path::from("nope")))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert!(missing.is_empty());
+
+    let result = store.list_with_delimiter(None).await.unwrap();
+    assert_eq!(result.common_prefixes, vec![Path::from("x")]);
+    assert_eq!(result.objects.len(), 1);
+    assert_eq!(result.objects[0].location, Path::from("z"));
+
+    let result = store
+        .list_with_delimiter(Some(&Path::from("x")))
+        .await
+        .unwrap();
+    assert_eq!(result.common_prefixes, vec![Path::from("x/y")]);
+    assert_eq!(result.objects.len(), 2);
+}
+
+#[tokio::test]
+async fn test_copy_rename_and_delete() {
+    let (client, store) = store();
+    let (a, b, c) = (Path::from("a"), Path::from("b"), Path::from("c"));
+    store.put(&a, PutPayload::from_static(b"a")).await.unwrap();
+    store.put(&b, PutPayload::from_static(b"b")).await.unwrap();
+
+    assert!(matches!(
+        store.copy_if_not_exists(&a, &b).await,
+        Err(object_store::Error::AlreadyExists { .. })
+    ));
+    store.copy(&a, &b).await.unwrap();
+    assert_eq!(
+        store.get(&b).await.unwrap().bytes().await.unwrap().as_ref(),
+        b"a"
+    );
+
+    store.rename(&b, &c).await.unwrap();
+    assert!(store.head(&b).await.is_err());
+    assert!(store.head(&c).await.is_ok());
+
+    store.delete(&c).await.unwrap();
+    store.delete(&c).await.unwrap();
+    assert!(store.head(&c).await.is_err());
+
+    // Deleting a folder's location doesn't delete what's in it.
+    store
+        .put(&Path::from("dir/f"), PutPayload::from_static(b"f"))
+        .await
+        .unwrap();
+    store.delete(&Path::from("dir")).await.unwrap();
+    assert!(
+        client
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key("/pipelines/dir/f")
+    );
+}
+
+#[tokio::test]
+async fn test_multipart() {
+    let (_client, store) = store();
+    let location = Path::from("big.bin");
+    let data = (0..11_000_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+    let mut upload = store.put_multipart(&location).await.unwrap();
+    let parts = data
+        .chunks(5_000_000)
+        .map(|chunk| upload.put_part(PutPayload::from(chunk.to_vec())))
+        .collect::<Vec<_>>();
+    // Parts can finish in any order.
+    for part in parts.into_iter().rev() {
+        part.await.unwrap();
+    }
+    let result = upload.complete().await.unwrap();
+    assert_eq!(result.e_tag, Some(content_hash(&data)));
+
+    let read = store.get(&location).await.unwrap().bytes().await.unwrap();
+    assert!(read == data);
+
+    // A put mode can be given in the options' extensions.
+    let multipart = |mode: PutMode| {
+        let mut opts = PutMultipartOptions::default();
+        opts.extensions.insert(mode);
+        let store = &store;
+        let location = &location;
+        async move {
+            let mut upload = store.put_multipart_opts(location, opts).await?;
+            upload.put_part(PutPayload::from_static(b"new")).await?;
+            upload.complete().await
+        }
+    };
+    assert!(matches!(
+        multipart(PutMode::Create).await,
+        Err(object_store::Error::AlreadyExists { .. })
+    ));
+    let stale = UpdateVersion {
+        e_tag: None,
+        version: Some("0000000000000".to_owned()),
+    };
+    assert!(matches!(
+        multipart(PutMode::Update(stale)).await,
+        Err(object_store::Error::Precondition { .. })
+    ));
+    let current = UpdateVersion {
+        e_tag: result.e_tag,
+        version: None,
+    };
+    multipart(PutMode::Update(current)).await.unwrap();
+    let read = store.get(&location).await.unwrap().bytes().await.unwrap();
+    assert_eq!(read.as_ref(), b"new");
+}
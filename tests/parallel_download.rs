@@ -0,0 +1,207 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::content_hash::{ContentHasher, HASH_BLOCK_SIZE, content_hash};
+use dropbox_sdk::helpers::parallel_download::{
+    ParallelDownloadError, ParallelDownloadOptions, download_parallel, download_parallel_async,
+};
+use std::fs::File;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, json_response};
+
+/// A client which serves a single file, by metadata and by range.
+struct FileClient {
+    data: Vec<u8>,
+    content_hash: String,
+    /// The range of each download request.
+    ranges: Mutex<Vec<String>>,
+}
+
+impl FileClient {
+    fn new(len: usize) -> Self {
+        let data = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        Self {
+            content_hash: content_hash(&data),
+            data,
+            ranges: Mutex::new(vec![]),
+        }
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        serde_json::json!({
+            ".tag": "file",
+            "name": "f",
+            "id": "id:f",
+            "client_modified": "2020-01-01T00:00:00Z",
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": self.data.len(),
+            "path_display": "/f",
+            "content_hash": self.content_hash,
+        })
+    }
+}
+
+impl HttpClient for FileClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        match request.url.as_str() {
+            "https://api.dropboxapi.com/2/files/get_metadata" => {
+                let arg: serde_json::Value = serde_json::from_slice(body)?;
+                assert_eq!(arg["path"], "/f");
+                Ok(json_response(200, self.metadata()))
+            }
+            "https://content.dropboxapi.com/2/files/download" => {
+                let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+                assert_eq!(arg["path"], "rev:0123456789a");
+                let range = request.range.unwrap();
+                let (start, end) = range
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                let (start, end) = (
+                    start.parse::<usize>().unwrap(),
+                    end.parse::<usize>().unwrap(),
+                );
+                self.ranges.lock().unwrap().push(range);
+                Ok(HttpRequestResultRaw {
+                    status: 206,
+                    result_header: Some(self.metadata().to_string()),
+                    content_length: Some((end + 1 - start) as u64),
+                    body: Box::new(Cursor::new(self.data[start..=end].to_vec())),
+                })
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for FileClient {}
+
+/// A file in the temp directory which is deleted when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id())))
+    }
+
+    fn create(&self) -> File {
+        File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.0)
+            .unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn test_content_hash() {
+    // The hash of nothing is the hash of no block hashes, i.e. the SHA-256 of an empty string.
+    assert_eq!(
+        content_hash(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+
+    // Feeding the content in arbitrary pieces gives the same result.
+    let data = (0..HASH_BLOCK_SIZE * 2 + 5)
+        .map(|i| (i % 7) as u8)
+        .collect::<Vec<_>>();
+    let mut hasher = ContentHasher::new();
+    for chunk in data.chunks(1_000_003) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finish(), content_hash(&data));
+    assert_ne!(
+        content_hash(&data[..HASH_BLOCK_SIZE]),
+        content_hash(&data[..HASH_BLOCK_SIZE + 1])
+    );
+}
+
+#[test]
+fn test_download_parallel() {
+    let client = FileClient::new(HASH_BLOCK_SIZE * 5 + 123);
+    let temp = TempFile::new("parallel-download");
+    let file = temp.create();
+    let progress = Arc::new(AtomicU64::new(0));
+    let options = ParallelDownloadOptions::default()
+        .with_parallelism(3)
+        .with_blocks_per_range(2)
+        .with_progress({
+            let progress = Arc::clone(&progress);
+            move |n| {
+                progress.fetch_max(n, Ordering::SeqCst);
+            }
+        });
+
+    let metadata = download_parallel(&client, "/f", &file, &options).unwrap();
+    assert_eq!(metadata.size, client.data.len() as u64);
+    assert_eq!(std::fs::read(&temp.0).unwrap(), client.data);
+    assert_eq!(progress.load(Ordering::SeqCst), client.data.len() as u64);
+
+    let mut ranges = client.ranges.lock().unwrap().clone();
+    ranges.sort();
+    let b = HASH_BLOCK_SIZE;
+    let mut expected = vec![
+        format!("bytes=0-{}", b * 2 - 1),
+        format!("bytes={}-{}", b * 2, b * 4 - 1),
+        format!("bytes={}-{}", b * 4, b * 5 + 122),
+    ];
+    expected.sort();
+    assert_eq!(ranges, expected);
+}
+
+#[test]
+fn test_download_parallel_hash_mismatch() {
+    let mut client = FileClient::new(1000);
+    client.content_hash = content_hash(b"something else");
+    let temp = TempFile::new("parallel-download-mismatch");
+    let file = temp.create();
+
+    match download_parallel(&client, "/f", &file, &ParallelDownloadOptions::default()) {
+        Err(ParallelDownloadError::HashMismatch { expected, actual }) => {
+            assert_eq!(expected, client.content_hash);
+            assert_eq!(actual, content_hash(&client.data));
+        }
+        other => panic!("unexpected result {other:?}"),
+    }
+
+    // It can be told not to check.
+    let options = ParallelDownloadOptions::default().with_verify(false);
+    download_parallel(&client, "/f", &file, &options).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_parallel_async() {
+    let client = Arc::new(FileClient::new(HASH_BLOCK_SIZE * 3 + 1));
+    let temp = TempFile::new("parallel-download-async");
+    let file = temp.create();
+    let options = ParallelDownloadOptions::default().with_blocks_per_range(1);
+
+    download_parallel_async(Arc::clone(&client), "/f", &file, &options)
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&temp.0).unwrap(), client.data);
+    assert_eq!(client.ranges.lock().unwrap().len(), 4);
+}
@@ -0,0 +1,115 @@
+use dropbox_sdk::helpers::path::{DropboxPath, PathError, PathKind};
+use std::collections::HashSet;
+
+#[test]
+fn test_parse_and_normalize() {
+    let path = DropboxPath::parse("/Photos//2024/./trip/../").unwrap();
+    assert_eq!(path.as_str(), "/Photos/2024");
+    assert_eq!(path.kind(), PathKind::Path);
+    assert_eq!(path.to_lower(), "/photos/2024");
+
+    assert!(DropboxPath::parse("").unwrap().is_root());
+    assert!(DropboxPath::parse("/").unwrap().is_root());
+    assert_eq!(DropboxPath::parse("/").unwrap(), DropboxPath::root());
+
+    assert!(matches!(
+        DropboxPath::parse("Photos"),
+        Err(PathError::MissingLeadingSlash(_))
+    ));
+    assert!(matches!(
+        DropboxPath::parse("/a/../.."),
+        Err(PathError::EscapesRoot(_))
+    ));
+    assert!(matches!(
+        DropboxPath::parse("/a\nb"),
+        Err(PathError::InvalidCharacter(_, '\n'))
+    ));
+    assert!(matches!(
+        DropboxPath::parse(&format!("/{}", "x".repeat(256))),
+        Err(PathError::ComponentTooLong(_))
+    ));
+}
+
+#[test]
+fn test_special_forms() {
+    let id = DropboxPath::parse("id:a4ayc_80_OEAAAAAAAAAYa").unwrap();
+    assert_eq!(id.kind(), PathKind::Id);
+    assert_eq!(id.parent(), None);
+    assert_eq!(id.file_name(), None);
+    let child = id.join("sub/./beach.jpg").unwrap();
+    assert_eq!(child.as_str(), "id:a4ayc_80_OEAAAAAAAAAYa/sub/beach.jpg");
+    assert_eq!(child.parent().unwrap().parent().unwrap(), id);
+    assert!(matches!(id.join(".."), Err(PathError::EscapesRoot(_))));
+
+    let ns = DropboxPath::parse("ns:1234/Shared//docs/").unwrap();
+    assert_eq!(ns.kind(), PathKind::Namespace);
+    assert_eq!(ns.as_str(), "ns:1234/Shared/docs");
+    assert!(matches!(
+        DropboxPath::parse("ns:abc"),
+        Err(PathError::InvalidId(_))
+    ));
+
+    let rev = DropboxPath::parse("rev:a1c10ce0dd78").unwrap();
+    assert_eq!(rev.kind(), PathKind::Rev);
+    assert_eq!(rev.parent(), None);
+    assert!(matches!(rev.join("x"), Err(PathError::JoinOntoRev(_))));
+    assert!(matches!(
+        DropboxPath::parse("rev:xyz"),
+        Err(PathError::InvalidId(_))
+    ));
+    assert!(matches!(
+        DropboxPath::parse("id:"),
+        Err(PathError::InvalidId(_))
+    ));
+}
+
+#[test]
+fn test_join_parent_file_name() {
+    let dir = DropboxPath::root().join("Photos").unwrap();
+    assert_eq!(dir.as_str(), "/Photos");
+    let file = dir.join("2024/beach.jpg").unwrap();
+    assert_eq!(file.as_str(), "/Photos/2024/beach.jpg");
+    assert_eq!(file.file_name(), Some("beach.jpg"));
+    assert_eq!(
+        file.segments().collect::<Vec<_>>(),
+        vec!["Photos", "2024", "beach.jpg"]
+    );
+    assert_eq!(file.parent().unwrap().as_str(), "/Photos/2024");
+    assert_eq!(dir.parent().unwrap(), DropboxPath::root());
+    assert_eq!(DropboxPath::root().parent(), None);
+    assert_eq!(dir.join("../Music").unwrap().as_str(), "/Music");
+}
+
+#[test]
+fn test_case_insensitive() {
+    let a = DropboxPath::parse("/Photos/Beach.JPG").unwrap();
+    let b: DropboxPath = "/photos/beach.jpg".parse().unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a.to_string(), "/Photos/Beach.JPG");
+    let set = [a, b].into_iter().collect::<HashSet<_>>();
+    assert_eq!(set.len(), 1);
+
+    // IDs are case-sensitive; only the path after them isn't.
+    let id = DropboxPath::parse("id:a4ayc_80_OEAAAAAAAAAYa/Sub").unwrap();
+    assert_eq!(
+        id,
+        DropboxPath::parse("id:a4ayc_80_OEAAAAAAAAAYa/sub").unwrap()
+    );
+    assert_ne!(
+        id,
+        DropboxPath::parse("id:a4ayc_80_oeaaaaaaaaaaya/Sub").unwrap()
+    );
+    assert_eq!(id.to_lower(), "id:a4ayc_80_OEAAAAAAAAAYa/sub");
+    assert_ne!(
+        DropboxPath::parse("rev:a1c10ce0dd78").unwrap(),
+        DropboxPath::parse("rev:A1C10CE0DD78").unwrap()
+    );
+}
+
+#[test]
+#[cfg(feature = "dbx_files")]
+fn test_into_arg() {
+    let path = DropboxPath::parse("/a/b.txt").unwrap();
+    let arg = dropbox_sdk::files::DownloadArg::new(path.into());
+    assert_eq!(arg.path, "/a/b.txt");
+}
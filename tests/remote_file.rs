@@ -0,0 +1,161 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{GetMetadataError, LookupError};
+use dropbox_sdk::helpers::remote_file::{AsyncRemoteFile, RemoteFile, RemoteFileOptions};
+use futures::{AsyncReadExt, AsyncSeekExt};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, content_response, response};
+
+/// A client which serves a single file at `/f`, and a folder at `/dir`.
+struct FileClient {
+    data: Vec<u8>,
+    /// The range of each download request.
+    ranges: Mutex<Vec<(usize, usize)>>,
+}
+
+impl FileClient {
+    fn new(len: usize) -> Self {
+        Self {
+            data: (0..len).map(|i| (i % 251) as u8).collect(),
+            ranges: Mutex::new(vec![]),
+        }
+    }
+
+    fn metadata(&self) -> String {
+        serde_json::json!({
+            ".tag": "file",
+            "name": "f",
+            "id": "id:f",
+            "client_modified": "2020-01-01T00:00:00Z",
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": self.data.len(),
+            "path_display": "/f",
+        })
+        .to_string()
+    }
+
+    fn ranges(&self) -> Vec<(usize, usize)> {
+        std::mem::take(&mut *self.ranges.lock().unwrap())
+    }
+}
+
+impl HttpClient for FileClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        match request.url.as_str() {
+            "https://api.dropboxapi.com/2/files/get_metadata" => {
+                let arg: serde_json::Value = serde_json::from_slice(body)?;
+                Ok(match arg["path"].as_str().unwrap() {
+                    "/f" => response(200, self.metadata()),
+                    "/dir" => response(
+                        200,
+                        r#"{".tag":"folder","name":"dir","id":"id:dir","path_display":"/dir"}"#,
+                    ),
+                    other => panic!("unexpected path {other}"),
+                })
+            }
+            "https://content.dropboxapi.com/2/files/download" => {
+                let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+                assert_eq!(arg["path"], "rev:0123456789a");
+                let range = request.range.unwrap();
+                let (start, end) = range
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                let (start, end) = (start.parse().unwrap(), end.parse::<usize>().unwrap() + 1);
+                self.ranges.lock().unwrap().push((start, end));
+                Ok(content_response(
+                    206,
+                    Some(self.metadata()),
+                    self.data[start..end].to_vec(),
+                ))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for FileClient {}
+
+#[test]
+fn test_read_and_seek() {
+    let client = FileClient::new(10_000);
+    let options = RemoteFileOptions::default()
+        .with_block_size(1000)
+        .with_read_ahead(3)
+        .with_cache_blocks(4);
+    let mut file = RemoteFile::open(&client, "/f", options).unwrap();
+    assert_eq!(file.metadata().size, 10_000);
+    assert!(client.ranges().is_empty(), "nothing is fetched on open");
+
+    // Reading the first block reads ahead by two more blocks.
+    let mut buf = [0; 100];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(buf[..], client.data[..100]);
+    assert_eq!(client.ranges(), vec![(0, 3000)]);
+
+    // Reading within those blocks doesn't need another request.
+    file.seek(SeekFrom::Start(2500)).unwrap();
+    let mut buf = [0; 500];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(buf[..], client.data[2500..3000]);
+    assert!(client.ranges().is_empty());
+
+    // Reading near the end only reads as far as the end.
+    assert_eq!(file.seek(SeekFrom::End(-1500)).unwrap(), 8500);
+    let mut rest = vec![];
+    file.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, client.data[8500..]);
+    assert_eq!(client.ranges(), vec![(8000, 10_000)]);
+
+    // The read-ahead stops at blocks which are already cached.
+    file.seek(SeekFrom::Start(6000)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(client.ranges(), vec![(6000, 8000)]);
+
+    // Only the four most recently used blocks are kept.
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(client.ranges(), vec![(0, 3000)]);
+
+    assert!(file.seek(SeekFrom::Current(-1000)).is_err());
+}
+
+#[test]
+fn test_open_folder() {
+    let client = FileClient::new(0);
+    match RemoteFile::open(&client, "/dir", RemoteFileOptions::default()) {
+        Err(dropbox_sdk::Error::Api(GetMetadataError::Path(LookupError::NotFile))) => (),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_read_and_seek_async() {
+    let client = Arc::new(FileClient::new(5000));
+    let options = RemoteFileOptions::default()
+        .with_block_size(1000)
+        .with_read_ahead(2);
+    let mut file = AsyncRemoteFile::open(Arc::clone(&client), "/f", options)
+        .await
+        .unwrap();
+
+    file.seek(SeekFrom::Start(1500)).await.unwrap();
+    let mut buf = vec![];
+    file.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, client.data[1500..]);
+    assert_eq!(client.ranges(), vec![(1000, 3000), (3000, 5000)]);
+}
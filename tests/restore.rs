@@ -0,0 +1,351 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::restore::{
+    ActionError, ActionOutcome, RestoreAction, RestoreOptions, execute_restore,
+    execute_restore_async, plan_restore, plan_restore_async,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+mod common;
+use common::mock::{Request, api_error, json_response};
+
+/// 2021-06-01T00:00:00Z
+fn restore_time() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_622_505_600)
+}
+
+/// A client which keeps the revision history of files in memory.
+#[derive(Default)]
+struct MockClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Files by lowercased path.
+    files: BTreeMap<String, History>,
+    /// Deleted folders, by path.
+    deleted_folders: Vec<String>,
+    next_rev: u64,
+    /// How many times revisions were listed, for each path.
+    revision_lookups: BTreeMap<String, usize>,
+}
+
+struct History {
+    path: String,
+    /// Revisions, oldest first: (rev, server_modified, content).
+    revisions: Vec<(String, String, String)>,
+    deleted: Option<String>,
+}
+
+impl History {
+    fn metadata(&self, i: usize) -> serde_json::Value {
+        let (rev, modified, content) = &self.revisions[i];
+        serde_json::json!({
+            "name": self.path.rsplit('/').next().unwrap(),
+            "id": "id:1",
+            "client_modified": modified,
+            "server_modified": modified,
+            "rev": rev,
+            "size": content.len(),
+            "content_hash": format!("hash of {content}"),
+            "path_lower": self.path.to_lowercase(),
+            "path_display": self.path,
+        })
+    }
+
+    fn content(&self) -> Option<&str> {
+        match self.deleted {
+            Some(_) => None,
+            None => Some(&self.revisions.last().unwrap().2),
+        }
+    }
+}
+
+impl State {
+    fn add_revision(&mut self, path: &str, modified: &str, content: &str) -> String {
+        self.next_rev += 1;
+        let rev = format!("{:09x}", self.next_rev);
+        let history = self
+            .files
+            .entry(path.to_lowercase())
+            .or_insert_with(|| History {
+                path: path.to_owned(),
+                revisions: vec![],
+                deleted: None,
+            });
+        history
+            .revisions
+            .push((rev.clone(), modified.to_owned(), content.to_owned()));
+        history.deleted = None;
+        rev
+    }
+
+    fn delete(&mut self, path: &str, when: &str) {
+        self.files.get_mut(&path.to_lowercase()).unwrap().deleted = Some(when.to_owned());
+    }
+
+    fn content(&self, path: &str) -> Option<&str> {
+        self.files[&path.to_lowercase()].content()
+    }
+}
+
+fn ok(value: serde_json::Value) -> HttpRequestResultRaw {
+    json_response(200, value)
+}
+
+/// When files are restored.
+const NOW: &str = "2030-01-01T00:00:00Z";
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let mut state = self.state.lock().unwrap();
+        let path = arg["path"].as_str().unwrap_or_default();
+        Ok(match request.route() {
+            "files/list_folder" => {
+                assert_eq!(arg["recursive"], true);
+                assert_eq!(arg["include_deleted"], true);
+                let mut entries = state
+                    .files
+                    .values()
+                    .map(|history| match history.deleted {
+                        Some(_) => serde_json::json!({
+                            ".tag": "deleted",
+                            "name": history.path.rsplit('/').next().unwrap(),
+                            "path_lower": history.path.to_lowercase(),
+                            "path_display": history.path,
+                        }),
+                        None => {
+                            let mut metadata = history.metadata(history.revisions.len() - 1);
+                            metadata[".tag"] = "file".into();
+                            metadata
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                for folder in &state.deleted_folders {
+                    entries.push(serde_json::json!({
+                        ".tag": "deleted",
+                        "name": folder.rsplit('/').next().unwrap(),
+                        "path_lower": folder.to_lowercase(),
+                        "path_display": folder,
+                    }));
+                }
+                ok(serde_json::json!({
+                    "entries": entries,
+                    "cursor": "c",
+                    "has_more": false,
+                }))
+            }
+            "files/list_revisions" => {
+                *state.revision_lookups.entry(path.to_owned()).or_default() += 1;
+                let Some(history) = state.files.get(&path.to_lowercase()) else {
+                    return Ok(api_error(r#"{".tag":"path","path":{".tag":"not_file"}}"#));
+                };
+                let limit = arg["limit"].as_u64().unwrap() as usize;
+                let mut end = history.revisions.len();
+                if let Some(before) = arg["before_rev"].as_str() {
+                    end = history
+                        .revisions
+                        .iter()
+                        .position(|(rev, ..)| rev == before)
+                        .unwrap();
+                }
+                let start = end.saturating_sub(limit);
+                let entries = (start..end)
+                    .rev()
+                    .map(|i| history.metadata(i))
+                    .collect::<Vec<_>>();
+                ok(serde_json::json!({
+                    "is_deleted": history.deleted.is_some(),
+                    "server_deleted": history.deleted,
+                    "entries": entries,
+                    "has_more": start > 0,
+                }))
+            }
+            "files/restore" => {
+                let rev = arg["rev"].as_str().unwrap();
+                let history = &state.files[&path.to_lowercase()];
+                let content = history
+                    .revisions
+                    .iter()
+                    .find(|(r, ..)| r == rev)
+                    .unwrap()
+                    .2
+                    .clone();
+                state.add_revision(path, NOW, &content);
+                let history = &state.files[&path.to_lowercase()];
+                ok(history.metadata(history.revisions.len() - 1))
+            }
+            "files/delete_v2" => {
+                let history = &state.files[&path.to_lowercase()];
+                if arg["parent_rev"] != history.revisions.last().unwrap().0 {
+                    return Ok(api_error(
+                        r#"{".tag":"path_write","path_write":{".tag":"conflict","conflict":{".tag":"file"}}}"#,
+                    ));
+                }
+                let mut metadata = history.metadata(history.revisions.len() - 1);
+                metadata[".tag"] = "file".into();
+                state.delete(path, NOW);
+                ok(serde_json::json!({ "metadata": metadata }))
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+/// A folder hit by something which encrypted, deleted and created files after the restore time.
+fn damaged_folder() -> MockClient {
+    let client = MockClient::default();
+    let mut state = client.state.lock().unwrap();
+    state.add_revision("/Docs/a.txt", "2021-01-01T00:00:00Z", "a");
+    state.add_revision("/Docs/a.txt", "2021-07-01T00:00:00Z", "encrypted a");
+    state.add_revision("/Docs/b.txt", "2021-02-01T00:00:00Z", "b");
+    state.add_revision("/Docs/c.txt", "2021-03-01T00:00:00Z", "c");
+    state.delete("/Docs/c.txt", "2021-08-01T00:00:00Z");
+    state.add_revision("/Docs/d.txt", "2021-03-01T00:00:00Z", "d");
+    state.delete("/Docs/d.txt", "2021-04-01T00:00:00Z");
+    state.add_revision("/Docs/new.txt", "2021-09-01T00:00:00Z", "ransom note");
+    // The revision current at the restore time is beyond the first page of revisions.
+    state.add_revision("/Docs/Sub/e.txt", "2021-05-31T23:59:59Z", "e");
+    for i in 1..=150 {
+        let modified = format!("2021-06-01T00:{:02}:{:02}Z", i / 60, i % 60);
+        state.add_revision("/Docs/Sub/e.txt", &modified, &format!("e{i}"));
+    }
+    state.deleted_folders.push("/Docs/Old".to_owned());
+    drop(state);
+    client
+}
+
+#[test]
+fn test_plan_and_restore() {
+    let client = damaged_folder();
+    let options = RestoreOptions::default();
+    let plan = plan_restore(&client, "/Docs", restore_time(), &options).unwrap();
+    assert_eq!(plan.time, "2021-06-01T00:00:00Z");
+
+    // Planning is a dry run.
+    assert_eq!(
+        client.state.lock().unwrap().content("/Docs/a.txt"),
+        Some("encrypted a")
+    );
+
+    let actions = plan
+        .actions
+        .iter()
+        .map(|action| match action {
+            RestoreAction::Revert { path, revision } => {
+                format!("revert {path} to {}", revision.server_modified)
+            }
+            RestoreAction::Undelete { path, revision } => {
+                format!("undelete {path} at {}", revision.server_modified)
+            }
+            RestoreAction::Delete { path, .. } => format!("delete {path}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        actions,
+        vec![
+            "revert /Docs/a.txt to 2021-01-01T00:00:00Z",
+            "undelete /Docs/c.txt at 2021-03-01T00:00:00Z",
+            "revert /Docs/Sub/e.txt to 2021-05-31T23:59:59Z",
+        ]
+    );
+    {
+        // Files unchanged since the time aren't looked up.
+        let state = client.state.lock().unwrap();
+        assert!(!state.revision_lookups.contains_key("/Docs/b.txt"));
+        assert_eq!(state.revision_lookups["/Docs/Sub/e.txt"], 2);
+    }
+
+    let done = Arc::new(AtomicUsize::new(0));
+    let options = options.with_progress({
+        let done = Arc::clone(&done);
+        move |_| {
+            done.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let report = execute_restore(&client, plan, &options);
+    assert_eq!(done.load(Ordering::SeqCst), 3);
+    assert!(
+        report
+            .actions
+            .iter()
+            .all(|report| matches!(report.outcome, ActionOutcome::Restored(_)))
+    );
+
+    let state = client.state.lock().unwrap();
+    assert_eq!(state.content("/Docs/a.txt"), Some("a"));
+    assert_eq!(state.content("/Docs/b.txt"), Some("b"));
+    assert_eq!(state.content("/Docs/c.txt"), Some("c"));
+    assert_eq!(state.content("/Docs/d.txt"), None);
+    assert_eq!(state.content("/Docs/new.txt"), Some("ransom note"));
+    assert_eq!(state.content("/Docs/Sub/e.txt"), Some("e"));
+}
+
+#[test]
+fn test_delete_new_files() {
+    let client = damaged_folder();
+    let options = RestoreOptions::default().with_delete_new_files(true);
+    let plan = plan_restore(&client, "/Docs", restore_time(), &options).unwrap();
+    assert!(matches!(
+        plan.actions[2],
+        RestoreAction::Delete { ref path, .. } if path == "/Docs/new.txt"
+    ));
+
+    // A new file which changes after the plan is made isn't deleted.
+    let mut stale = plan.clone();
+    client
+        .state
+        .lock()
+        .unwrap()
+        .add_revision("/Docs/new.txt", NOW, "changed");
+    stale
+        .actions
+        .retain(|action| action.path() == "/Docs/new.txt");
+    let report = execute_restore(&client, stale, &options);
+    assert!(matches!(
+        report.actions[0].outcome,
+        ActionOutcome::Failed(ActionError::Delete(_))
+    ));
+    assert_eq!(
+        client.state.lock().unwrap().content("/Docs/new.txt"),
+        Some("changed")
+    );
+
+    let plan = plan_restore(&client, "/Docs", restore_time(), &options).unwrap();
+    execute_restore(&client, plan, &options);
+    assert_eq!(client.state.lock().unwrap().content("/Docs/new.txt"), None);
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = damaged_folder();
+    let options = RestoreOptions::default();
+    let plan = plan_restore_async(&client, "/Docs", restore_time(), &options)
+        .await
+        .unwrap();
+    assert_eq!(plan.actions.len(), 3);
+    let report = execute_restore_async(&client, plan, &options).await;
+    assert_eq!(report.actions.len(), 3);
+
+    // Restoring again has nothing left to do.
+    let plan = plan_restore_async(&client, "/Docs", restore_time(), &options)
+        .await
+        .unwrap();
+    assert!(plan.actions.is_empty());
+}
@@ -0,0 +1,201 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::DownloadArg;
+use dropbox_sdk::helpers::download::{AsyncResumableDownload, DownloadOptions, ResumableDownload};
+use futures::AsyncReadExt;
+use std::io::{self, Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::Request;
+
+const REV: &str = "0123456789a";
+
+/// A client which serves a single file, but only a limited number of bytes per request before the
+/// connection drops.
+struct DownloadClient {
+    data: Vec<u8>,
+    /// How many bytes each response delivers before failing.
+    per_request: usize,
+    /// Whether responses fail with an error, or just end early.
+    fail_with_error: bool,
+    /// The path and range of each request made.
+    requests: Mutex<Vec<(String, Option<String>)>>,
+}
+
+impl DownloadClient {
+    fn new(len: usize, per_request: usize, fail_with_error: bool) -> Self {
+        Self {
+            data: (0..len).map(|i| (i % 251) as u8).collect(),
+            per_request,
+            fail_with_error,
+            requests: Mutex::new(vec![]),
+        }
+    }
+}
+
+/// A reader which fails every time.
+struct ConnectionReset;
+
+impl Read for ConnectionReset {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::ConnectionReset.into())
+    }
+}
+
+impl HttpClient for DownloadClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        _body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        assert_eq!(
+            request.url,
+            "https://content.dropboxapi.com/2/files/download"
+        );
+        let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+        let path = arg["path"].as_str().unwrap().to_owned();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((path, request.range.clone()));
+
+        let len = self.data.len();
+        let (start, end) = match request.range.as_deref() {
+            None => (0, len),
+            Some(range) => {
+                let (start, end) = range
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                match (start.parse::<usize>(), end.parse::<usize>()) {
+                    (Ok(start), Ok(end)) => (start, (end + 1).min(len)),
+                    (Ok(start), Err(_)) => (start, len),
+                    (Err(_), Ok(suffix)) => (len - suffix, len),
+                    _ => panic!("bad range {range}"),
+                }
+            }
+        };
+
+        let delivered = &self.data[start..end.min(start + self.per_request)];
+        let body: Box<dyn Read + Send> = if self.fail_with_error && delivered.len() < end - start {
+            Box::new(Cursor::new(delivered.to_vec()).chain(ConnectionReset))
+        } else {
+            Box::new(Cursor::new(delivered.to_vec()))
+        };
+        Ok(HttpRequestResultRaw {
+            status: if request.range.is_some() { 206 } else { 200 },
+            result_header: Some(
+                serde_json::json!({
+                    "name": "f",
+                    "id": "id:f",
+                    "client_modified": "2020-01-01T00:00:00Z",
+                    "server_modified": "2020-01-01T00:00:00Z",
+                    "rev": REV,
+                    "size": len,
+                    "path_display": "/f",
+                })
+                .to_string(),
+            ),
+            content_length: Some((end - start) as u64),
+            body,
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for DownloadClient {}
+
+#[test]
+fn test_reconnect_after_error() {
+    let client = DownloadClient::new(100_000, 30_000, true);
+    let mut download = ResumableDownload::new(
+        &client,
+        &DownloadArg::new("/f".to_owned()),
+        None,
+        None,
+        DownloadOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(download.metadata().rev, REV);
+
+    let mut buf = vec![];
+    download.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, client.data);
+    assert_eq!(download.position(), 100_000);
+
+    // After the first request, the rest are pinned to the revision and pick up where the last one
+    // left off.
+    let requests = client.requests.lock().unwrap();
+    assert_eq!(
+        *requests,
+        vec![
+            ("/f".to_owned(), None),
+            (format!("rev:{REV}"), Some("bytes=30000-99999".to_owned())),
+            (format!("rev:{REV}"), Some("bytes=60000-99999".to_owned())),
+            (format!("rev:{REV}"), Some("bytes=90000-99999".to_owned())),
+        ]
+    );
+}
+
+#[test]
+fn test_reconnect_after_early_eof_with_range() {
+    let client = DownloadClient::new(100_000, 30_000, false);
+    let mut download = ResumableDownload::new(
+        &client,
+        &DownloadArg::new("/f".to_owned()),
+        Some(10_000),
+        Some(79_999),
+        DownloadOptions::default(),
+    )
+    .unwrap();
+
+    let mut buf = vec![];
+    download.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, &client.data[10_000..80_000]);
+    assert_eq!(
+        client.requests.lock().unwrap().last().unwrap().1.as_deref(),
+        Some("bytes=70000-79999")
+    );
+}
+
+#[test]
+fn test_gives_up() {
+    // No progress is made after the first request, so it eventually gives up.
+    let client = DownloadClient::new(100, 0, true);
+    let mut download = ResumableDownload::new(
+        &client,
+        &DownloadArg::new("/f".to_owned()),
+        None,
+        None,
+        DownloadOptions::default().with_max_reconnects(2),
+    )
+    .unwrap();
+    let err = download.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(client.requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_reconnect_async() {
+    let client = Arc::new(DownloadClient::new(100_000, 40_000, true));
+    let mut download = AsyncResumableDownload::new(
+        Arc::clone(&client),
+        &DownloadArg::new("/f".to_owned()),
+        None,
+        Some(50_000),
+        DownloadOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let mut buf = vec![];
+    download.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, &client.data[50_000..]);
+    assert_eq!(client.requests.lock().unwrap().len(), 2);
+}
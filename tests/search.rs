@@ -0,0 +1,201 @@
+use dropbox_sdk::Error;
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{
+    FileCategory, HighlightSpan, LookupError, Metadata, SearchError, SearchMatchTypeV2,
+};
+use dropbox_sdk::helpers::search::{Highlighted, SearchQuery};
+use futures::TryStreamExt;
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response, response};
+
+/// A client which finds `/docs/report0.txt` up to `/docs/report{count - 1}.txt` for any search,
+/// except in `/missing`, which doesn't exist. The first page also has a result of an unknown type.
+struct MockClient {
+    count: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The argument to the first request.
+    search: Option<serde_json::Value>,
+    requests: usize,
+}
+
+impl MockClient {
+    fn new(count: usize) -> Self {
+        Self {
+            count,
+            state: Mutex::default(),
+        }
+    }
+}
+
+fn hit(i: usize, highlights: bool) -> serde_json::Value {
+    let name = format!("report{i}.txt");
+    let mut hit = serde_json::json!({
+        "metadata": {
+            ".tag": "metadata",
+            "metadata": {
+                ".tag": "file",
+                "name": name,
+                "id": format!("id:{i}"),
+                "client_modified": "2020-01-01T00:00:00Z",
+                "server_modified": "2020-01-01T00:00:00Z",
+                "rev": "000000001",
+                "size": 1,
+                "path_lower": format!("/docs/{name}"),
+                "path_display": format!("/docs/{name}"),
+            },
+        },
+        "match_type": { ".tag": "filename" },
+    });
+    if highlights {
+        hit["highlight_spans"] = serde_json::json!([
+            { "highlight_str": "rep", "is_highlighted": true },
+            { "highlight_str": "ort", "is_highlighted": true },
+            { "highlight_str": format!("{i}.txt"), "is_highlighted": false },
+        ]);
+    }
+    hit
+}
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(&self, request: Self::Request, body: &[u8]) -> Result<HttpRequestResultRaw, Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        let start = match request.route() {
+            "files/search_v2" => {
+                if arg["options"]["path"] == "/missing" {
+                    let body = r#"{"error":{".tag":"path","path":{".tag":"not_found"}}}"#;
+                    return Ok(response(409, body));
+                }
+                state.search = Some(arg);
+                0
+            }
+            "files/search/continue_v2" => arg["cursor"].as_str().unwrap().parse().unwrap(),
+            other => panic!("unexpected request to {other}"),
+        };
+        let search = state.search.as_ref().unwrap();
+        let page_size = search["options"]["max_results"].as_u64().unwrap_or(100) as usize;
+        let highlights = search["match_field_options"]["include_highlights"] == true;
+        let end = (start + page_size).min(self.count);
+        let mut matches = (start..end).map(|i| hit(i, highlights)).collect::<Vec<_>>();
+        if start == 0 {
+            matches.insert(1, serde_json::json!({ "metadata": { ".tag": "unknown" } }));
+        }
+        let body = serde_json::json!({
+            "matches": matches,
+            "has_more": end < self.count,
+            "cursor": end.to_string(),
+        });
+        Ok(json_response(200, body))
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+fn path(metadata: &Metadata) -> &str {
+    match metadata {
+        Metadata::File(file) => file.path_display.as_deref().unwrap(),
+        _ => panic!("unexpected metadata {metadata:?}"),
+    }
+}
+
+#[test]
+fn test_search() {
+    let client = MockClient::new(250);
+    let query = SearchQuery::new("report")
+        .with_path("/docs")
+        .with_category(FileCategory::Document)
+        .with_extension(".txt")
+        .with_extension("md")
+        .with_filename_only(true)
+        .with_page_size(100);
+    let hits = query.run(&client).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(hits.len(), 250);
+    assert_eq!(client.state.lock().unwrap().requests, 3);
+    for (i, hit) in hits.iter().enumerate() {
+        assert_eq!(path(&hit.metadata), format!("/docs/report{i}.txt"));
+    }
+    assert_eq!(hits[0].match_type, Some(SearchMatchTypeV2::Filename));
+    let highlights = hits[7].highlights.as_ref().unwrap();
+    assert_eq!(highlights.text(), "report7.txt");
+    assert_eq!(highlights.annotate("[", "]"), "[report]7.txt");
+
+    let state = client.state.lock().unwrap();
+    let arg = state.search.as_ref().unwrap();
+    assert_eq!(arg["query"], "report");
+    assert_eq!(arg["options"]["path"], "/docs");
+    assert_eq!(
+        arg["options"]["file_categories"],
+        serde_json::json!([{ ".tag": "document" }])
+    );
+    assert_eq!(
+        arg["options"]["file_extensions"],
+        serde_json::json!(["txt", "md"])
+    );
+    assert_eq!(arg["options"]["filename_only"], true);
+    assert_eq!(arg["match_field_options"]["include_highlights"], true);
+}
+
+#[test]
+fn test_error() {
+    let client = MockClient::new(10);
+    let query = SearchQuery::new("report").with_path("/missing");
+    let mut results = query.run(&client);
+    assert!(matches!(
+        results.next(),
+        Some(Err(Error::Api(SearchError::Path(LookupError::NotFound))))
+    ));
+    assert!(results.next().is_none());
+    assert_eq!(client.state.lock().unwrap().requests, 1);
+}
+
+#[test]
+fn test_highlighted() {
+    let highlighted = Highlighted::from_spans(vec![
+        HighlightSpan::new("Quarterly ".to_owned(), false),
+        HighlightSpan::new("bud".to_owned(), true),
+        HighlightSpan::new("get".to_owned(), true),
+        HighlightSpan::new(" and ".to_owned(), false),
+        HighlightSpan::new(String::new(), true),
+        HighlightSpan::new("budget".to_owned(), true),
+    ]);
+    assert_eq!(highlighted.to_string(), "Quarterly budget and budget");
+    assert_eq!(highlighted.ranges(), &[10..16, 21..27]);
+    assert_eq!(
+        highlighted.highlighted().collect::<Vec<_>>(),
+        vec!["budget", "budget"]
+    );
+    assert_eq!(
+        highlighted.annotate("<mark>", "</mark>"),
+        "Quarterly <mark>budget</mark> and <mark>budget</mark>"
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = MockClient::new(30);
+    let query = SearchQuery::new("report")
+        .with_page_size(20)
+        .with_highlights(false);
+    let hits = query
+        .run_async(&client)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 30);
+    assert!(hits.iter().all(|hit| hit.highlights.is_none()));
+    assert_eq!(path(&hits[29].metadata), "/docs/report29.txt");
+    assert_eq!(client.state.lock().unwrap().requests, 2);
+}
@@ -0,0 +1,313 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::shared_link::{
+    SharedLinkCrawlError, SharedLinkOptions, download_shared_link, download_shared_link_app_auth,
+    download_shared_link_async, list_shared_link,
+};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response};
+
+const URL: &str = "https://www.dropbox.com/scl/fo/abc/xyz";
+
+/// A client which can see a shared link with the password `hunter2` to a folder with this tree:
+///
+/// ```text
+/// /a.txt
+/// /Docs/b.pdf
+/// /Docs/Old/c.pdf
+/// /tmp/d.txt
+/// ```
+///
+/// The root folder is listed in two pages.
+struct LinkClient {
+    /// Each request's route and the folder or file it was for.
+    requests: Mutex<Vec<(String, String)>>,
+}
+
+impl LinkClient {
+    fn new() -> Self {
+        Self {
+            requests: Mutex::default(),
+        }
+    }
+
+    fn requests(&self, route: &str) -> Vec<String> {
+        let mut paths = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(r, _)| r == route)
+            .map(|(_, path)| path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+}
+
+fn contents(path: &str) -> String {
+    format!("contents of {path}")
+}
+
+fn entry(path: &str) -> serde_json::Value {
+    let name = path.rsplit('/').next().unwrap();
+    if name.contains('.') {
+        serde_json::json!({
+            ".tag": "file",
+            "name": name,
+            "id": format!("id:{path}"),
+            "client_modified": "2020-01-01T00:00:00Z",
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": "0123456789a",
+            "size": contents(path).len(),
+            "content_hash": content_hash(contents(path).as_bytes()),
+        })
+    } else {
+        serde_json::json!({
+            ".tag": "folder",
+            "name": name,
+            "id": format!("id:{path}"),
+        })
+    }
+}
+
+fn page(entries: &[&str], cursor: &str, has_more: bool) -> HttpRequestResultRaw {
+    json_response(
+        200,
+        serde_json::json!({
+            "entries": entries.iter().map(|path| entry(path)).collect::<Vec<_>>(),
+            "cursor": cursor,
+            "has_more": has_more,
+        }),
+    )
+}
+
+impl HttpClient for LinkClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let route = request.route().to_owned();
+        match route.as_str() {
+            "files/list_folder" => {
+                assert_eq!(arg["shared_link"]["url"], URL);
+                assert_eq!(arg["shared_link"]["password"], "hunter2");
+                let path = arg["path"].as_str().unwrap();
+                self.requests
+                    .lock()
+                    .unwrap()
+                    .push((route.clone(), path.to_owned()));
+                Ok(match path {
+                    "" => page(&["/a.txt", "/Docs"], "root", true),
+                    "/Docs" => page(&["/Docs/b.pdf", "/Docs/Old"], "", false),
+                    "/Docs/Old" => page(&["/Docs/Old/c.pdf"], "", false),
+                    "/tmp" => page(&["/tmp/d.txt"], "", false),
+                    other => panic!("unexpected folder {other}"),
+                })
+            }
+            "files/list_folder/continue" => {
+                assert_eq!(arg["cursor"], "root");
+                Ok(page(&["/tmp"], "", false))
+            }
+            "sharing/get_shared_link_file" => {
+                assert_eq!(arg["url"], URL);
+                assert_eq!(arg["link_password"], "hunter2");
+                let path = arg["path"].as_str().unwrap();
+                self.requests
+                    .lock()
+                    .unwrap()
+                    .push((route.clone(), path.to_owned()));
+                if path == "/tmp/d.txt" {
+                    return Ok(json_response(
+                        409,
+                        serde_json::json!({"error": {".tag": "shared_link_access_denied"}}),
+                    ));
+                }
+                let name = path.rsplit('/').next().unwrap();
+                let metadata = serde_json::json!({
+                    ".tag": "file",
+                    "url": URL,
+                    "name": name,
+                    "link_permissions": {
+                        "can_revoke": false,
+                        "visibility_policies": [],
+                        "can_set_expiry": false,
+                        "can_remove_expiry": false,
+                        "allow_download": true,
+                        "can_allow_download": false,
+                        "can_disallow_download": false,
+                        "allow_comments": false,
+                        "team_restricts_comments": false,
+                    },
+                    "client_modified": "2020-01-01T00:00:00Z",
+                    "server_modified": "2020-01-01T00:00:00Z",
+                    "rev": "0123456789a",
+                    "size": contents(path).len(),
+                });
+                Ok(HttpRequestResultRaw {
+                    status: 200,
+                    result_header: Some(metadata.to_string()),
+                    content_length: Some(contents(path).len() as u64),
+                    body: Box::new(Cursor::new(contents(path).into_bytes())),
+                })
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for LinkClient {}
+
+impl AppAuthClient for LinkClient {}
+
+/// A directory in the temp directory which is deleted when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn options() -> SharedLinkOptions {
+    SharedLinkOptions::default().with_password("hunter2")
+}
+
+#[test]
+fn test_list() {
+    let client = LinkClient::new();
+    let files = list_shared_link(&client, URL, &options()).unwrap();
+    assert_eq!(
+        files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+        vec!["Docs/Old/c.pdf", "Docs/b.pdf", "a.txt", "tmp/d.txt"]
+    );
+    assert_eq!(files[2].metadata.name, "a.txt");
+}
+
+#[test]
+fn test_filters() {
+    let client = LinkClient::new();
+    let files = list_shared_link(
+        &client,
+        URL,
+        &options().with_include("**/*.PDF").with_exclude("docs/old"),
+    )
+    .unwrap();
+    assert_eq!(
+        files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+        vec!["Docs/b.pdf"]
+    );
+    // The excluded folder isn't listed.
+    assert_eq!(
+        client.requests("files/list_folder"),
+        vec!["", "/Docs", "/tmp"]
+    );
+
+    // `*` doesn't match across folders, but `**/` can match none.
+    let client = LinkClient::new();
+    let files = list_shared_link(&client, URL, &options().with_include("**/*.txt")).unwrap();
+    assert_eq!(
+        files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+        vec!["a.txt", "tmp/d.txt"]
+    );
+    let files = list_shared_link(&client, URL, &options().with_include("*.txt")).unwrap();
+    assert_eq!(
+        files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+        vec!["a.txt"]
+    );
+}
+
+#[test]
+fn test_download() {
+    let dir = TempDir::new("shared-link-download");
+    let client = LinkClient::new();
+    let paths = download_shared_link(
+        &client,
+        URL,
+        &dir.0,
+        &options().with_exclude("tmp").with_parallelism(2),
+    )
+    .unwrap();
+    assert_eq!(
+        paths,
+        vec![
+            dir.0.join("Docs/Old/c.pdf"),
+            dir.0.join("Docs/b.pdf"),
+            dir.0.join("a.txt"),
+        ]
+    );
+    for (path, local) in ["/Docs/Old/c.pdf", "/Docs/b.pdf", "/a.txt"]
+        .iter()
+        .zip(&paths)
+    {
+        assert_eq!(std::fs::read_to_string(local).unwrap(), contents(path));
+    }
+    assert_eq!(
+        client.requests("sharing/get_shared_link_file"),
+        vec!["/Docs/Old/c.pdf", "/Docs/b.pdf", "/a.txt"]
+    );
+
+    let err = download_shared_link_app_auth(&client, URL, &dir.0, &options()).unwrap_err();
+    assert!(
+        matches!(&err, SharedLinkCrawlError::Download { path, .. } if path == "tmp/d.txt"),
+        "{err}"
+    );
+    assert!(!dir.0.join("tmp/d.txt").exists());
+}
+
+#[tokio::test]
+async fn test_async() {
+    let dir = TempDir::new("shared-link-async");
+    let client = LinkClient::new();
+    let paths = download_shared_link_async(&client, URL, &dir.0, &options().with_include("a.txt"))
+        .await
+        .unwrap();
+    assert_eq!(paths, vec![dir.0.join("a.txt")]);
+    assert_eq!(
+        std::fs::read_to_string(&paths[0]).unwrap(),
+        contents("/a.txt")
+    );
+
+    // The files can be written on tokio's blocking threads instead.
+    let dir = TempDir::new("shared-link-async-spawner");
+    let options = options()
+        .with_include("**/*.pdf")
+        .with_blocking_spawner(|f| {
+            tokio::task::spawn_blocking(f);
+        });
+    let paths = download_shared_link_async(&client, URL, &dir.0, &options)
+        .await
+        .unwrap();
+    assert_eq!(paths.len(), 2);
+    for path in paths {
+        let name = path.strip_prefix(&dir.0).unwrap().to_str().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            contents(&format!("/{}", name.replace('\\', "/")))
+        );
+    }
+}
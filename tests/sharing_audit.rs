@@ -0,0 +1,370 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::sharing_audit::{
+    Grantee, SharedItemKind, SharingAuditError, SharingAuditOptions, audit_sharing,
+    audit_sharing_async,
+};
+use dropbox_sdk::sharing::AccessLevel;
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response};
+
+/// A client for an account which is a member of two shared folders, `sf:1` (mounted, listed in two
+/// pages) and `sf:2` (being unmounted, so listed as both mounted and mountable), has been sent the
+/// file `id:f`, and has a public link to `/Notes.txt`.
+#[derive(Default)]
+struct SharingClient {
+    /// Whether listing `sf:2`'s members fails.
+    fail_members: bool,
+    requests: Mutex<Vec<String>>,
+}
+
+fn policy() -> serde_json::Value {
+    serde_json::json!({
+        "acl_update_policy": {".tag": "owner"},
+        "shared_link_policy": {".tag": "anyone"},
+    })
+}
+
+fn folder(id: &str, name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "access_type": {".tag": "editor"},
+        "is_inside_team_folder": false,
+        "is_team_folder": false,
+        "name": name,
+        "policy": policy(),
+        "preview_url": "https://www.dropbox.com/preview",
+        "shared_folder_id": id,
+        "time_invited": "2020-01-01T00:00:00Z",
+        "path_display": format!("/{name}"),
+    })
+}
+
+fn user(id: &str, access: &str, inherited: bool, same_team: bool) -> serde_json::Value {
+    serde_json::json!({
+        "access_type": {".tag": access},
+        "user": {
+            "account_id": id,
+            "email": format!("{id}@example.com"),
+            "display_name": format!("User {id}"),
+            "same_team": same_team,
+        },
+        "is_inherited": inherited,
+    })
+}
+
+fn folders(entries: &[serde_json::Value], cursor: Option<&str>) -> HttpRequestResultRaw {
+    let mut body = serde_json::json!({ "entries": entries });
+    if let Some(cursor) = cursor {
+        body["cursor"] = serde_json::json!(cursor);
+    }
+    json_response(200, body)
+}
+
+fn members(
+    users: &[serde_json::Value],
+    groups: &[serde_json::Value],
+    invitees: &[serde_json::Value],
+    cursor: Option<&str>,
+) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "users": users,
+        "groups": groups,
+        "invitees": invitees,
+    });
+    if let Some(cursor) = cursor {
+        body["cursor"] = serde_json::json!(cursor);
+    }
+    body
+}
+
+impl HttpClient for SharingClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_slice(body)?;
+        let route = request.route();
+        self.requests.lock().unwrap().push(route.to_owned());
+        match route {
+            "sharing/list_folders" => Ok(folders(&[folder("sf:1", "Team")], Some("more"))),
+            "sharing/list_folders/continue" => {
+                assert_eq!(arg["cursor"], "more");
+                Ok(folders(&[folder("sf:2", "Old")], None))
+            }
+            "sharing/list_mountable_folders" => Ok(folders(&[folder("sf:2", "Old")], None)),
+            "sharing/list_folder_members" => match arg["shared_folder_id"].as_str().unwrap() {
+                "sf:1" => Ok(json_response(
+                    200,
+                    members(
+                        &[user("dbid:me", "owner", false, true)],
+                        &[serde_json::json!({
+                            "access_type": {".tag": "viewer"},
+                            "group": {
+                                "group_name": "=HYPERLINK(\"https://example.com\",\"Partners\")",
+                                "group_id": "g:1",
+                                "group_management_type": {".tag": "user_managed"},
+                                "group_type": {".tag": "user_managed"},
+                                "is_member": false,
+                                "is_owner": false,
+                                "same_team": false,
+                            },
+                            "is_inherited": false,
+                        })],
+                        &[],
+                        Some("members"),
+                    ),
+                )),
+                "sf:2" if self.fail_members => Ok(json_response(
+                    409,
+                    serde_json::json!({"error": {".tag": "not_a_member"}}),
+                )),
+                "sf:2" => Ok(json_response(
+                    200,
+                    members(&[user("dbid:x", "viewer", true, false)], &[], &[], None),
+                )),
+                other => panic!("unexpected folder {other}"),
+            },
+            "sharing/list_folder_members/continue" => {
+                assert_eq!(arg["cursor"], "members");
+                Ok(json_response(
+                    200,
+                    members(
+                        &[],
+                        &[],
+                        &[serde_json::json!({
+                            "access_type": {".tag": "editor"},
+                            "invitee": {".tag": "email", "email": "new@example.com"},
+                            "is_inherited": false,
+                        })],
+                        None,
+                    ),
+                ))
+            }
+            "sharing/list_received_files" => Ok(json_response(
+                200,
+                serde_json::json!({
+                    "entries": [{
+                        "id": "id:f",
+                        "name": "Plan, v2.docx",
+                        "policy": policy(),
+                        "preview_url": "https://www.dropbox.com/preview",
+                    }],
+                }),
+            )),
+            "sharing/list_file_members/batch" => {
+                assert_eq!(arg["files"], serde_json::json!(["id:f"]));
+                Ok(json_response(
+                    200,
+                    serde_json::json!([{
+                        "file": "id:f",
+                        "result": {
+                            ".tag": "result",
+                            "members": members(
+                                &[user("dbid:y", "owner", false, false)],
+                                &[],
+                                &[],
+                                Some("file members"),
+                            ),
+                            "member_count": 2,
+                        },
+                    }]),
+                ))
+            }
+            "sharing/list_file_members/continue" => {
+                assert_eq!(arg["cursor"], "file members");
+                Ok(json_response(
+                    200,
+                    members(&[user("dbid:me", "viewer", false, true)], &[], &[], None),
+                ))
+            }
+            "sharing/list_shared_links" => Ok(json_response(
+                200,
+                serde_json::json!({
+                    "links": [{
+                        ".tag": "file",
+                        "url": "https://www.dropbox.com/scl/fi/abc/Notes.txt",
+                        "name": "Notes.txt",
+                        "id": "id:n",
+                        "path_lower": "/notes.txt",
+                        "link_permissions": {
+                            "can_revoke": true,
+                            "visibility_policies": [],
+                            "can_set_expiry": true,
+                            "can_remove_expiry": true,
+                            "allow_download": true,
+                            "can_allow_download": true,
+                            "can_disallow_download": true,
+                            "allow_comments": false,
+                            "team_restricts_comments": false,
+                            "effective_audience": {".tag": "public"},
+                            "link_access_level": {".tag": "viewer"},
+                        },
+                        "client_modified": "2020-01-01T00:00:00Z",
+                        "server_modified": "2020-01-01T00:00:00Z",
+                        "rev": "0123456789a",
+                        "size": 1,
+                    }],
+                    "has_more": false,
+                }),
+            )),
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for SharingClient {}
+
+#[test]
+fn test_report() {
+    let client = SharingClient::default();
+    let report = audit_sharing(&client, &SharingAuditOptions::default()).unwrap();
+    let summary = report
+        .entries
+        .iter()
+        .map(|entry| {
+            let grantee = match &entry.grantee {
+                Grantee::User { account_id, .. } => account_id.as_str(),
+                Grantee::Group { name, .. } => name.as_str(),
+                Grantee::Invitee { email } => email.as_str(),
+                Grantee::Link { url, .. } => url.as_str(),
+            };
+            (
+                entry.kind,
+                entry.id.as_str(),
+                grantee,
+                entry.inherited,
+                entry.external,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (SharedItemKind::Folder, "sf:1", "dbid:me", false, false),
+            (
+                SharedItemKind::Folder,
+                "sf:1",
+                "=HYPERLINK(\"https://example.com\",\"Partners\")",
+                false,
+                true,
+            ),
+            (
+                SharedItemKind::Folder,
+                "sf:1",
+                "new@example.com",
+                false,
+                true
+            ),
+            (SharedItemKind::Folder, "sf:2", "dbid:x", true, true),
+            (SharedItemKind::File, "id:f", "dbid:y", false, true),
+            (SharedItemKind::File, "id:f", "dbid:me", false, false),
+            (
+                SharedItemKind::File,
+                "id:n",
+                "https://www.dropbox.com/scl/fi/abc/Notes.txt",
+                false,
+                true
+            ),
+        ]
+    );
+    assert_eq!(report.entries[0].access, AccessLevel::Owner);
+    assert_eq!(report.entries[6].access, AccessLevel::Viewer);
+    assert_eq!(report.external().count(), 5);
+    // sf:2 is only listed once, though it's in both lists.
+    assert_eq!(
+        client
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|route| *route == "sharing/list_folder_members")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_export() {
+    let client = SharingClient::default();
+    let report = audit_sharing(&client, &SharingAuditOptions::default()).unwrap();
+
+    let mut csv = Vec::new();
+    report.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(
+        lines[0],
+        "kind,id,name,path,grantee_type,grantee_id,grantee_name,grantee_email,access,inherited,external"
+    );
+    assert_eq!(
+        lines[1],
+        "folder,sf:1,Team,/Team,user,dbid:me,User dbid:me,dbid:me@example.com,owner,false,false"
+    );
+    // Names which a spreadsheet would take as a formula are escaped.
+    assert_eq!(
+        lines[2],
+        "folder,sf:1,Team,/Team,group,g:1,\"'=HYPERLINK(\"\"https://example.com\"\",\"\"Partners\"\")\",,viewer,false,true"
+    );
+    assert_eq!(
+        lines[5],
+        "file,id:f,\"Plan, v2.docx\",,user,dbid:y,User dbid:y,dbid:y@example.com,owner,false,true"
+    );
+    assert_eq!(
+        lines[7],
+        "file,id:n,Notes.txt,/notes.txt,link,https://www.dropbox.com/scl/fi/abc/Notes.txt,public,,viewer,false,true"
+    );
+
+    let mut json = Vec::new();
+    report.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 7);
+    assert_eq!(
+        json[2],
+        serde_json::json!({
+            "kind": "folder",
+            "id": "sf:1",
+            "name": "Team",
+            "path": "/Team",
+            "grantee_type": "invitee",
+            "grantee_id": "new@example.com",
+            "grantee_name": "",
+            "grantee_email": "new@example.com",
+            "access": "editor",
+            "inherited": false,
+            "external": true,
+        })
+    );
+    assert_eq!(json[4]["path"], serde_json::Value::Null);
+}
+
+#[test]
+fn test_error() {
+    let client = SharingClient {
+        fail_members: true,
+        ..SharingClient::default()
+    };
+    let err = audit_sharing(&client, &SharingAuditOptions::default()).unwrap_err();
+    assert!(
+        matches!(&err, SharingAuditError::FolderMembers { shared_folder_id, .. } if shared_folder_id == "sf:2"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = SharingClient::default();
+    let report = audit_sharing_async(&client, &SharingAuditOptions::default().with_parallelism(1))
+        .await
+        .unwrap();
+    assert_eq!(report.entries.len(), 7);
+    assert_eq!(report.entries[3].id, "sf:2");
+}
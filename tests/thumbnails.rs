@@ -0,0 +1,190 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use dropbox_sdk::Error;
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{LookupError, ThumbnailFormat, ThumbnailV2Error};
+use dropbox_sdk::helpers::thumbnails::{ThumbnailOptions, get_thumbnails, get_thumbnails_async};
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, content_response, json_response, response};
+
+/// A client which makes up thumbnails for any path. A few paths behave specially:
+///  * `/missing.jpg` doesn't exist.
+///  * `/flaky.jpg` fails with a conversion error when requested in a batch.
+///  * `/garbled.jpg` has bad base64 data when requested in a batch.
+#[derive(Default)]
+struct MockClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// How many more batch requests should fail with a server error.
+    failing_batches: usize,
+    /// The size of each batch requested, in order.
+    batches: Vec<usize>,
+    /// The paths requested one at a time.
+    singles: Vec<String>,
+}
+
+fn metadata(path: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": path.trim_start_matches('/'),
+        "id": "id:1",
+        "client_modified": "2020-01-01T00:00:00Z",
+        "server_modified": "2020-01-01T00:00:00Z",
+        "rev": "000000001",
+        "size": 1000,
+        "path_lower": path.to_lowercase(),
+        "path_display": path,
+    })
+}
+
+fn thumbnail(path: &str, format: &serde_json::Value) -> Vec<u8> {
+    format!("{}:{path}", format[".tag"].as_str().unwrap_or("jpeg")).into_bytes()
+}
+
+const NOT_FOUND: &str = r#"{".tag":"path","path":{".tag":"not_found"}}"#;
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(&self, request: Self::Request, body: &[u8]) -> Result<HttpRequestResultRaw, Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let mut state = self.state.lock().unwrap();
+        Ok(match request.route() {
+            "files/get_thumbnail_batch" => {
+                let entries = arg["entries"].as_array().unwrap();
+                assert!(entries.len() <= 25);
+                state.batches.push(entries.len());
+                if state.failing_batches > 0 {
+                    state.failing_batches -= 1;
+                    return Ok(response(500, "oops"));
+                }
+                let results = entries
+                    .iter()
+                    .map(|entry| {
+                        let path = entry["path"].as_str().unwrap();
+                        match path {
+                            "/missing.jpg" => serde_json::json!({
+                                ".tag": "failure",
+                                "failure": serde_json::from_str::<serde_json::Value>(NOT_FOUND)
+                                    .unwrap(),
+                            }),
+                            "/flaky.jpg" => serde_json::json!({
+                                ".tag": "failure",
+                                "failure": { ".tag": "conversion_error" },
+                            }),
+                            _ => {
+                                let mut data = STANDARD.encode(thumbnail(path, &entry["format"]));
+                                if path == "/garbled.jpg" {
+                                    data.push('!');
+                                }
+                                serde_json::json!({
+                                    ".tag": "success",
+                                    "metadata": metadata(path),
+                                    "thumbnail": data,
+                                })
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                json_response(200, serde_json::json!({ "entries": results }))
+            }
+            "files/get_thumbnail_v2" => {
+                let path = arg["resource"]["path"].as_str().unwrap();
+                state.singles.push(path.to_owned());
+                if path == "/missing.jpg" {
+                    let body = format!(r#"{{"error":{NOT_FOUND}}}"#);
+                    return Ok(response(409, body));
+                }
+                let result = serde_json::json!({ "file_metadata": metadata(path) });
+                content_response(
+                    200,
+                    Some(result.to_string()),
+                    thumbnail(path, &arg["format"]),
+                )
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+#[test]
+fn test_batches() {
+    let client = MockClient::default();
+    let mut paths = (0..57).map(|i| format!("/img{i}.jpg")).collect::<Vec<_>>();
+    paths[3] = "/missing.jpg".to_owned();
+    paths[30] = "/flaky.jpg".to_owned();
+    paths[56] = "/garbled.jpg".to_owned();
+
+    let options = ThumbnailOptions::default().with_format(ThumbnailFormat::Png);
+    let results = get_thumbnails(&client, paths.clone(), &options);
+    assert_eq!(results.len(), paths.len());
+    for (path, result) in paths.iter().zip(&results) {
+        match result {
+            Ok(thumbnail) => {
+                assert_eq!(thumbnail.data, format!("png:{path}").into_bytes());
+                assert_eq!(thumbnail.metadata.path_display.as_ref(), Some(path));
+            }
+            Err(Error::Api(ThumbnailV2Error::Path(LookupError::NotFound))) => {
+                assert_eq!(path, "/missing.jpg")
+            }
+            Err(e) => panic!("unexpected error for {path}: {e}"),
+        }
+    }
+
+    // Only the entries which might succeed on their own are tried again.
+    let mut state = client.state.lock().unwrap();
+    state.batches.sort();
+    assert_eq!(state.batches, vec![7, 25, 25]);
+    state.singles.sort();
+    assert_eq!(state.singles, vec!["/flaky.jpg", "/garbled.jpg"]);
+}
+
+#[test]
+fn test_failed_batch() {
+    let client = MockClient::default();
+    client.state.lock().unwrap().failing_batches = 1;
+    let paths = vec!["/a.jpg".to_owned(), "/missing.jpg".to_owned()];
+    let options = ThumbnailOptions::default().with_max_retries(0);
+    let results = get_thumbnails(&client, paths, &options);
+
+    // Every entry of the batch is tried again on its own.
+    assert_eq!(results[0].as_ref().unwrap().data, b"jpeg:/a.jpg");
+    assert!(matches!(
+        results[1],
+        Err(Error::Api(ThumbnailV2Error::Path(LookupError::NotFound)))
+    ));
+    assert_eq!(
+        client.state.lock().unwrap().singles,
+        vec!["/a.jpg", "/missing.jpg"]
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = MockClient::default();
+    let paths = (0..30).map(|i| format!("/img{i}.jpg")).collect::<Vec<_>>();
+    let results = get_thumbnails_async(&client, paths.clone(), &ThumbnailOptions::default()).await;
+    let data = results
+        .into_iter()
+        .map(|result| result.unwrap().data)
+        .collect::<Vec<_>>();
+    let expected = paths
+        .iter()
+        .map(|path| format!("jpeg:{path}").into_bytes())
+        .collect::<Vec<_>>();
+    assert_eq!(data, expected);
+    assert!(client.state.lock().unwrap().singles.is_empty());
+}
@@ -0,0 +1,239 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::update::{UpdateError, UpdateOptions, update_with, update_with_async};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, api_error, content_response, json_response, response};
+
+/// A client which keeps files in memory, and can simulate another client changing a file right
+/// after it's been downloaded. Clients made with [`MockClient::connect`] share the same files.
+#[derive(Default)]
+struct MockClient {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockClient {
+    /// Another client for the same account.
+    fn connect(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Files by path: (rev, content, client_modified).
+    files: HashMap<String, (u64, Vec<u8>, String)>,
+    next_rev: u64,
+    /// How many more downloads should be followed by a concurrent change to the file.
+    interfere: usize,
+    /// How many more uploads should be written, but fail with a server error as if the response
+    /// was lost.
+    lose_response: usize,
+    uploads: usize,
+}
+
+impl State {
+    fn write(&mut self, path: &str, content: Vec<u8>) -> serde_json::Value {
+        self.write_at(path, content, "2020-01-01T00:00:00Z")
+    }
+
+    fn write_at(&mut self, path: &str, content: Vec<u8>, time: &str) -> serde_json::Value {
+        self.next_rev += 1;
+        let file = (self.next_rev, content, time.to_owned());
+        self.files.insert(path.to_owned(), file);
+        self.metadata(path)
+    }
+
+    fn metadata(&self, path: &str) -> serde_json::Value {
+        let (rev, content, time) = &self.files[path];
+        serde_json::json!({
+            "name": path.rsplit('/').next().unwrap(),
+            "id": "id:1",
+            "client_modified": time,
+            "server_modified": "2020-01-01T00:00:00Z",
+            "rev": format!("{rev:09x}"),
+            "size": content.len(),
+            "path_lower": path,
+            "path_display": path,
+            "content_hash": content_hash(content),
+        })
+    }
+
+    fn content(&self, path: &str) -> String {
+        String::from_utf8(self.files[path].1.clone()).unwrap()
+    }
+}
+
+const CONFLICT: &str = r#"{".tag":"path","reason":{".tag":"conflict","conflict":{".tag":"file"}},"upload_session_id":"u"}"#;
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+        let path = arg["path"].as_str().unwrap();
+        let mut state = self.state.lock().unwrap();
+        Ok(match request.route() {
+            "files/download" => {
+                if !state.files.contains_key(path) {
+                    return Ok(api_error(r#"{".tag":"path","path":{".tag":"not_found"}}"#));
+                }
+                let response = content_response(
+                    200,
+                    Some(state.metadata(path).to_string()),
+                    state.files[path].1.clone(),
+                );
+                if state.interfere > 0 {
+                    state.interfere -= 1;
+                    let content = format!("{}0", state.content(path));
+                    state.write(path, content.into_bytes());
+                }
+                response
+            }
+            "files/upload" => {
+                state.uploads += 1;
+                assert_eq!(arg["strict_conflict"], true);
+                assert_eq!(arg["content_hash"], content_hash(body));
+                let current = state.files.get(path).map(|(rev, ..)| format!("{rev:09x}"));
+                let expected = match arg["mode"][".tag"].as_str().unwrap_or("add") {
+                    "add" => None,
+                    "update" => Some(arg["mode"]["update"].as_str().unwrap().to_owned()),
+                    other => panic!("unexpected mode {other}"),
+                };
+                if current != expected {
+                    return Ok(api_error(CONFLICT));
+                }
+                let time = arg["client_modified"].as_str().unwrap();
+                let metadata = state.write_at(path, body.to_vec(), time);
+                if state.lose_response > 0 {
+                    state.lose_response -= 1;
+                    return Ok(response(500, "oops"));
+                }
+                json_response(200, metadata)
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+/// Add a digit to a file, which starts out empty.
+fn append_one(old: Option<&[u8]>) -> Vec<u8> {
+    let mut new = old.unwrap_or_default().to_vec();
+    new.push(b'1');
+    new
+}
+
+#[test]
+fn test_update() {
+    let client = MockClient::default();
+    let options = UpdateOptions::default();
+
+    // The file is created if it doesn't exist.
+    let metadata = update_with(&client, "/state", &options, append_one).unwrap();
+    assert_eq!(metadata.size, 1);
+    let metadata = update_with(&client, "/state", &options, append_one).unwrap();
+    assert_eq!(metadata.rev, "000000002");
+    assert_eq!(client.state.lock().unwrap().content("/state"), "11");
+
+    // Nothing is written if the content doesn't change.
+    let metadata = update_with(&client, "/state", &options, |old| old.unwrap().to_vec()).unwrap();
+    assert_eq!(metadata.rev, "000000002");
+    assert_eq!(client.state.lock().unwrap().uploads, 2);
+
+    let options = options.with_create(false);
+    let err = update_with(&client, "/other", &options, append_one).unwrap_err();
+    assert!(matches!(err, UpdateError::Download(_)), "{err}");
+}
+
+#[test]
+fn test_conflicts() {
+    let client = MockClient::default();
+    let options = UpdateOptions::default().with_max_conflicts(2);
+    update_with(&client, "/state", &options, append_one).unwrap();
+
+    // Changes made by something else in the meantime aren't lost.
+    client.state.lock().unwrap().interfere = 2;
+    let mut calls = 0;
+    update_with(&client, "/state", &options, |old| {
+        calls += 1;
+        append_one(old)
+    })
+    .unwrap();
+    assert_eq!(calls, 3);
+    assert_eq!(client.state.lock().unwrap().content("/state"), "1001");
+
+    client.state.lock().unwrap().interfere = 3;
+    let err = update_with(&client, "/state", &options, append_one).unwrap_err();
+    assert!(matches!(err, UpdateError::TooManyConflicts(2)), "{err}");
+}
+
+#[test]
+fn test_retried_upload() {
+    let client = MockClient::default();
+    client.state.lock().unwrap().write("/state", b"1".to_vec());
+
+    // The first upload goes through, but its response is lost, so it's retried and conflicts with
+    // itself. That isn't mistaken for a change made by something else.
+    client.state.lock().unwrap().lose_response = 1;
+    let mut calls = 0;
+    let metadata = update_with(&client, "/state", &UpdateOptions::default(), |old| {
+        calls += 1;
+        append_one(old)
+    })
+    .unwrap();
+    assert_eq!(calls, 1);
+    assert_eq!(metadata.size, 2);
+    assert_eq!(client.state.lock().unwrap().content("/state"), "11");
+    assert_eq!(client.state.lock().unwrap().uploads, 2);
+}
+
+#[test]
+fn test_concurrent_clients() {
+    // Two clients increment the same counter at once. They often compute the same new value from
+    // the same old one, and only one of them can write it; the other one must try again rather
+    // than take the other's write for its own.
+    let client = MockClient::default();
+    let increment = |old: Option<&[u8]>| {
+        let n: u32 = old.map_or(0, |old| std::str::from_utf8(old).unwrap().parse().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        (n + 1).to_string().into_bytes()
+    };
+    let options = UpdateOptions::default().with_max_conflicts(100);
+    std::thread::scope(|scope| {
+        for client in [client.connect(), client.connect()] {
+            let options = &options;
+            scope.spawn(move || {
+                for _ in 0..20 {
+                    update_with(&client, "/counter", options, increment).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(client.state.lock().unwrap().content("/counter"), "40");
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = MockClient::default();
+    client.state.lock().unwrap().write("/state", b"1".to_vec());
+    client.state.lock().unwrap().interfere = 1;
+    let metadata = update_with_async(&client, "/state", &UpdateOptions::default(), append_one)
+        .await
+        .unwrap();
+    assert_eq!(metadata.size, 3);
+    assert_eq!(client.state.lock().unwrap().content("/state"), "101");
+}
@@ -0,0 +1,261 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::WriteMode;
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::upload_directory::{
+    DirectoryUploadOptions, FileOutcome, upload_directory,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+mod common;
+use common::mock::{Request, json_response, response};
+
+/// A client which implements just enough of the routes used by `upload_directory` to check what
+/// was sent.
+#[derive(Default)]
+struct DirectoryClient {
+    /// Folders created, in order.
+    folders: Mutex<Vec<String>>,
+    /// How many times the folder creation job has been checked.
+    checks: Mutex<u32>,
+    /// Files committed, by path, along with whether they went through an upload session.
+    files: Mutex<HashMap<String, (Vec<u8>, bool)>>,
+    /// Data appended to upload sessions, by session ID and offset.
+    sessions: Mutex<HashMap<String, BTreeMap<u64, Vec<u8>>>>,
+}
+
+fn file_metadata(path: &str, data: &[u8]) -> serde_json::Value {
+    serde_json::json!({
+        ".tag": "file",
+        "name": path.rsplit('/').next().unwrap(),
+        "id": format!("id:{path}"),
+        "client_modified": "2020-01-01T00:00:00Z",
+        "server_modified": "2020-01-01T00:00:00Z",
+        "rev": "0123456789a",
+        "size": data.len(),
+        "path_lower": path.to_lowercase(),
+        "path_display": path,
+        "content_hash": content_hash(data),
+    })
+}
+
+impl HttpClient for DirectoryClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        match request.url.as_str() {
+            "https://api.dropboxapi.com/2/files/list_folder" => {
+                assert_eq!(arg["path"], "/dest");
+                assert_eq!(arg["recursive"], true);
+                let entries = vec![
+                    serde_json::json!({
+                        ".tag": "folder",
+                        "name": "dest",
+                        "id": "id:dest",
+                        "path_lower": "/dest",
+                        "path_display": "/dest",
+                    }),
+                    file_metadata("/dest/Same.txt", b"same"),
+                    file_metadata("/dest/changed.txt", b"before"),
+                ];
+                Ok(json_response(
+                    200,
+                    serde_json::json!({
+                        "entries": entries,
+                        "cursor": "c",
+                        "has_more": false,
+                    }),
+                ))
+            }
+            "https://api.dropboxapi.com/2/files/create_folder_batch" => {
+                let mut folders = self.folders.lock().unwrap();
+                for path in arg["paths"].as_array().unwrap() {
+                    folders.push(path.as_str().unwrap().to_owned());
+                }
+                Ok(response(
+                    200,
+                    r#"{".tag":"async_job_id","async_job_id":"job"}"#,
+                ))
+            }
+            "https://api.dropboxapi.com/2/files/create_folder_batch/check" => {
+                assert_eq!(arg["async_job_id"], "job");
+                let mut checks = self.checks.lock().unwrap();
+                *checks += 1;
+                if *checks == 1 {
+                    return Ok(response(200, r#"{".tag":"in_progress"}"#));
+                }
+                let entries = self
+                    .folders
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|path| {
+                        if path.ends_with("/exists") {
+                            serde_json::json!({
+                                ".tag": "failure",
+                                "failure": {
+                                    ".tag": "path",
+                                    "path": {".tag": "conflict", "conflict": {".tag": "folder"}},
+                                },
+                            })
+                        } else {
+                            serde_json::json!({
+                                ".tag": "success",
+                                "metadata": {"name": "x", "id": "id:x", "path_display": path},
+                            })
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json_response(
+                    200,
+                    serde_json::json!({".tag": "complete", "entries": entries}),
+                ))
+            }
+            "https://content.dropboxapi.com/2/files/upload" => {
+                assert_eq!(arg["mode"][".tag"], "overwrite");
+                let path = arg["path"].as_str().unwrap();
+                self.files
+                    .lock()
+                    .unwrap()
+                    .insert(path.to_owned(), (body.to_vec(), false));
+                Ok(json_response(200, file_metadata(path, body)))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/start" => {
+                let mut sessions = self.sessions.lock().unwrap();
+                let id = format!("s{}", sessions.len());
+                sessions.insert(id.clone(), BTreeMap::new());
+                Ok(json_response(200, serde_json::json!({ "session_id": id })))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/append_v2" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let offset = arg["cursor"]["offset"].as_u64().unwrap();
+                self.sessions
+                    .lock()
+                    .unwrap()
+                    .get_mut(id)
+                    .unwrap()
+                    .insert(offset, body.to_vec());
+                Ok(response(200, "null"))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/finish" => {
+                assert_eq!(arg["commit"]["mode"][".tag"], "overwrite");
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let data = self.sessions.lock().unwrap()[id]
+                    .values()
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>();
+                let path = arg["commit"]["path"].as_str().unwrap();
+                let metadata = file_metadata(path, &data);
+                self.files
+                    .lock()
+                    .unwrap()
+                    .insert(path.to_owned(), (data, true));
+                Ok(json_response(200, metadata))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for DirectoryClient {}
+
+/// A directory in the temp directory which is deleted when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) {
+        let path = self.0.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_upload_directory() {
+    let dir = TempDir::new("upload-directory");
+    dir.write("a.txt", b"hello");
+    dir.write("same.txt", b"same");
+    dir.write("changed.txt", b"after");
+    dir.write("sub/b.txt", b"world");
+    let big = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    dir.write("sub/deep/big.bin", &big);
+    std::fs::create_dir_all(dir.0.join("empty")).unwrap();
+    std::fs::create_dir_all(dir.0.join("exists")).unwrap();
+    // A symlink back up the tree isn't followed, and a broken one is skipped.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&dir.0, dir.0.join("sub/loop")).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(dir.0.join("missing"), dir.0.join("broken")).unwrap();
+
+    let client = DirectoryClient::default();
+    let options = DirectoryUploadOptions::default()
+        .with_mode(WriteMode::Overwrite)
+        .with_session_threshold(1000);
+    let report = upload_directory(&client, &dir.0, "/dest/", &options).unwrap();
+
+    // Only the deepest folders need to be created; existing folders aren't failures.
+    assert_eq!(
+        *client.folders.lock().unwrap(),
+        vec!["/dest/empty", "/dest/exists", "/dest/sub/deep"]
+    );
+    assert_eq!(*client.checks.lock().unwrap(), 2);
+    assert!(report.folder_failures.is_empty());
+
+    let outcomes = report
+        .files
+        .iter()
+        .map(|file| {
+            let outcome = match &file.outcome {
+                FileOutcome::Uploaded(_) => "uploaded",
+                FileOutcome::Unchanged(_) => "unchanged",
+                FileOutcome::Failed(e) => panic!("{} failed: {e}", file.dropbox_path),
+            };
+            (file.dropbox_path.as_str(), outcome)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("/dest/a.txt", "uploaded"),
+            ("/dest/changed.txt", "uploaded"),
+            ("/dest/same.txt", "unchanged"),
+            ("/dest/sub/b.txt", "uploaded"),
+            ("/dest/sub/deep/big.bin", "uploaded"),
+        ]
+    );
+    assert_eq!(report.files[0].local_path, dir.0.join("a.txt"));
+
+    let files = client.files.lock().unwrap();
+    assert_eq!(files.len(), 4);
+    assert_eq!(files["/dest/a.txt"], (b"hello".to_vec(), false));
+    assert_eq!(files["/dest/changed.txt"], (b"after".to_vec(), false));
+    assert_eq!(files["/dest/sub/b.txt"], (b"world".to_vec(), false));
+    assert_eq!(files["/dest/sub/deep/big.bin"], (big, true));
+}
@@ -0,0 +1,257 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{UploadSessionFinishError, WriteMode};
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::upload_file::{
+    UploadFileError, UploadFileOptions, UploadFileOutcome, upload_file, upload_file_async,
+};
+use dropbox_sdk::helpers::upload_session::UploadSessionError;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+mod common;
+use common::mock::{Request, json_response, response};
+
+/// A client which stores a single file, `/dest.bin`, and records how it was uploaded.
+#[derive(Default)]
+struct FileClient {
+    /// The file's content, if it exists.
+    file: Mutex<Option<Vec<u8>>>,
+    /// The argument to each `files/upload` request, and the commit of each finished session.
+    commits: Mutex<Vec<serde_json::Value>>,
+    /// Data appended to the upload session, by offset.
+    session: Mutex<BTreeMap<u64, Vec<u8>>>,
+    /// Whether the next upload fails with a server error.
+    fail_next: Mutex<bool>,
+    /// Whether data appended to the session gets corrupted on the way.
+    corrupt: bool,
+    requests: Mutex<Vec<String>>,
+}
+
+fn file_metadata(data: &[u8]) -> serde_json::Value {
+    serde_json::json!({
+        ".tag": "file",
+        "name": "dest.bin",
+        "id": "id:dest",
+        "client_modified": "2020-01-01T00:00:00Z",
+        "server_modified": "2020-01-01T00:00:00Z",
+        "rev": "0123456789a",
+        "size": data.len(),
+        "path_lower": "/dest.bin",
+        "path_display": "/dest.bin",
+        "content_hash": content_hash(data),
+    })
+}
+
+impl HttpClient for FileClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = match &request.arg {
+            Some(arg) => serde_json::from_str(arg)?,
+            None => serde_json::from_slice(body)?,
+        };
+        let route = request.route();
+        self.requests.lock().unwrap().push(route.to_owned());
+        match route {
+            "files/get_metadata" => {
+                assert_eq!(arg["path"], "/dest.bin");
+                match &*self.file.lock().unwrap() {
+                    Some(data) => Ok(json_response(200, file_metadata(data))),
+                    None => Ok(response(
+                        409,
+                        r#"{"error":{".tag":"path","path":{".tag":"not_found"}}}"#,
+                    )),
+                }
+            }
+            "files/upload" => {
+                if std::mem::take(&mut *self.fail_next.lock().unwrap()) {
+                    return Ok(response(500, "oops"));
+                }
+                assert_eq!(arg["content_hash"], content_hash(body));
+                self.commits.lock().unwrap().push(arg);
+                *self.file.lock().unwrap() = Some(body.to_vec());
+                Ok(json_response(200, file_metadata(body)))
+            }
+            "files/upload_session/start" => Ok(response(200, r#"{"session_id":"s"}"#)),
+            "files/upload_session/append_v2" => {
+                let offset = arg["cursor"]["offset"].as_u64().unwrap();
+                let mut body = body.to_vec();
+                if self.corrupt && offset == 0 {
+                    body[0] ^= 1;
+                }
+                self.session.lock().unwrap().insert(offset, body);
+                Ok(response(200, "null"))
+            }
+            "files/upload_session/finish" => {
+                let mut data = std::mem::take(&mut *self.session.lock().unwrap())
+                    .into_values()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                data.extend(body);
+                if arg["content_hash"] != content_hash(&data) {
+                    return Ok(response(
+                        409,
+                        r#"{"error":{".tag":"content_hash_mismatch"}}"#,
+                    ));
+                }
+                let metadata = file_metadata(&data);
+                self.commits.lock().unwrap().push(arg["commit"].clone());
+                *self.file.lock().unwrap() = Some(data);
+                Ok(json_response(200, metadata))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for FileClient {}
+
+/// A file in the temp directory which is deleted when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, data: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+            .unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn big_data() -> Vec<u8> {
+    (0..100_000).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_single_request() {
+    let file = TempFile::new("upload-file-single", b"hello, world");
+    let client = FileClient::default();
+    *client.fail_next.lock().unwrap() = true;
+    let options = UploadFileOptions::default().with_mode(WriteMode::Overwrite);
+    let outcome = upload_file(&client, &file.0, "/dest.bin", &options).unwrap();
+    assert!(matches!(outcome, UploadFileOutcome::Uploaded(_)));
+    assert_eq!(outcome.metadata().size, 12);
+
+    // The server error was retried.
+    assert_eq!(
+        *client.requests.lock().unwrap(),
+        vec!["files/upload", "files/upload"]
+    );
+    let commits = client.commits.lock().unwrap();
+    assert_eq!(commits[0]["mode"][".tag"], "overwrite");
+    assert_eq!(commits[0]["client_modified"], "2020-09-13T12:26:40Z");
+    assert_eq!(
+        client.file.lock().unwrap().as_deref(),
+        Some(&b"hello, world"[..])
+    );
+}
+
+#[test]
+fn test_session() {
+    let file = TempFile::new("upload-file-session", &big_data());
+    let client = FileClient::default();
+    let options = UploadFileOptions::default().with_session_threshold(1000);
+    let outcome = upload_file(&client, &file.0, "/dest.bin", &options).unwrap();
+    assert_eq!(
+        outcome.metadata().content_hash,
+        Some(content_hash(&big_data()))
+    );
+    assert!(
+        !client
+            .requests
+            .lock()
+            .unwrap()
+            .contains(&"files/upload".to_owned())
+    );
+    let commits = client.commits.lock().unwrap();
+    assert_eq!(commits[0]["path"], "/dest.bin");
+    // The default mode, add, isn't sent.
+    assert!(commits[0].get("mode").is_none());
+    assert_eq!(commits[0]["client_modified"], "2020-09-13T12:26:40Z");
+    assert_eq!(
+        client.file.lock().unwrap().as_deref(),
+        Some(&big_data()[..])
+    );
+
+    // The server doesn't commit a session whose content doesn't match the hash sent with it.
+    let client = FileClient {
+        corrupt: true,
+        ..FileClient::default()
+    };
+    let err = upload_file(&client, &file.0, "/dest.bin", &options).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            UploadFileError::Session(UploadSessionError::Finish(dropbox_sdk::Error::Api(
+                UploadSessionFinishError::ContentHashMismatch
+            )))
+        ),
+        "{err}"
+    );
+    assert!(client.file.lock().unwrap().is_none());
+}
+
+#[test]
+fn test_skip_unchanged() {
+    let file = TempFile::new("upload-file-unchanged", &big_data());
+    let client = FileClient::default();
+    *client.file.lock().unwrap() = Some(big_data());
+    let options = UploadFileOptions::default().with_skip_unchanged(true);
+    let outcome = upload_file(&client, &file.0, "/dest.bin", &options).unwrap();
+    assert!(matches!(outcome, UploadFileOutcome::Unchanged(_)));
+    assert_eq!(*client.requests.lock().unwrap(), vec!["files/get_metadata"]);
+
+    // A file with the same size but different content is uploaded, from the start.
+    let mut changed = big_data();
+    changed[500] ^= 1;
+    *client.file.lock().unwrap() = Some(changed);
+    let outcome = upload_file(&client, &file.0, "/dest.bin", &options).unwrap();
+    assert!(matches!(outcome, UploadFileOutcome::Uploaded(_)));
+    assert_eq!(
+        client.file.lock().unwrap().as_deref(),
+        Some(&big_data()[..])
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let file = TempFile::new("upload-file-async", &big_data());
+    let client = FileClient::default();
+    let options = UploadFileOptions::default()
+        .with_skip_unchanged(true)
+        .with_session_threshold(1000);
+    let outcome = upload_file_async(&client, &file.0, "/dest.bin", &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, UploadFileOutcome::Uploaded(_)));
+    assert_eq!(
+        client.file.lock().unwrap().as_deref(),
+        Some(&big_data()[..])
+    );
+
+    let outcome = upload_file_async(&client, &file.0, "/dest.bin", &options)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, UploadFileOutcome::Unchanged(_)));
+}
@@ -0,0 +1,215 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::CommitInfo;
+use dropbox_sdk::helpers::upload_session::{
+    BLOCK_SIZE, ResumeState, UploadOptions, UploadSession, UploadSessionError,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, response};
+
+/// A client which implements just enough of the upload session routes to check what was sent.
+#[derive(Default)]
+struct UploadSessionClient {
+    /// Appended data, by offset.
+    appended: Mutex<BTreeMap<u64, Vec<u8>>>,
+    closed: Mutex<bool>,
+    committed_len: Mutex<Option<u64>>,
+    /// Whether the finish request included a content hash.
+    sent_hash: Mutex<Option<bool>>,
+    /// Appends at this offset fail.
+    fail_at: Option<u64>,
+}
+
+impl HttpClient for UploadSessionClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+        match request.url.as_str() {
+            "https://content.dropboxapi.com/2/files/upload_session/start" => {
+                assert_eq!(arg["session_type"][".tag"], "concurrent");
+                Ok(response(200, r#"{"session_id":"sid"}"#.to_owned()))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/append_v2" => {
+                assert_eq!(arg["cursor"]["session_id"], "sid");
+                let offset = arg["cursor"]["offset"].as_u64().unwrap();
+                if self.fail_at == Some(offset) {
+                    return Ok(response(
+                        409,
+                        r#"{"error":{".tag":"too_large"},"error_summary":"too_large/"}"#.to_owned(),
+                    ));
+                }
+                let mut closed = self.closed.lock().unwrap();
+                assert!(!*closed, "append after session was closed");
+                if arg["close"] == true {
+                    *closed = true;
+                } else {
+                    assert_eq!(body.len() % BLOCK_SIZE, 0, "uneven block size");
+                }
+                self.appended.lock().unwrap().insert(offset, body.to_vec());
+                Ok(response(200, "null".to_owned()))
+            }
+            "https://content.dropboxapi.com/2/files/upload_session/finish" => {
+                assert!(
+                    *self.closed.lock().unwrap(),
+                    "finish before session was closed"
+                );
+                let len = arg["cursor"]["offset"].as_u64().unwrap();
+                *self.committed_len.lock().unwrap() = Some(len);
+                *self.sent_hash.lock().unwrap() = Some(arg["content_hash"].is_string());
+                Ok(response(
+                    200,
+                    format!(
+                        r#"{{"name":"f","id":"id:f","client_modified":"2020-01-01T00:00:00Z",
+                        "server_modified":"2020-01-01T00:00:00Z","rev":"0123456789",
+                        "size":{len},"path_display":{}}}"#,
+                        arg["commit"]["path"]
+                    ),
+                ))
+            }
+            other => panic!("unexpected request to {other}"),
+        }
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for UploadSessionClient {}
+
+impl UploadSessionClient {
+    /// Put the appended pieces back together.
+    fn uploaded(&self) -> Vec<u8> {
+        let mut data = vec![];
+        for (offset, chunk) in self.appended.lock().unwrap().iter() {
+            assert_eq!(*offset, data.len() as u64, "gap in uploaded data");
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+}
+
+fn source_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_upload() {
+    let client = UploadSessionClient::default();
+    let data = source_data(BLOCK_SIZE * 7 + 1234);
+    let progress = Arc::new(AtomicU64::new(0));
+    let options = UploadOptions::default()
+        .with_parallelism(3)
+        .with_blocks_per_request(2)
+        .with_progress({
+            let progress = Arc::clone(&progress);
+            move |n| {
+                progress.fetch_max(n, Ordering::SeqCst);
+            }
+        });
+
+    let session = UploadSession::start(&client, options).unwrap();
+    assert_eq!(session.session_id(), "sid");
+    let metadata = session
+        .upload(&client, &data[..], CommitInfo::new("/f".to_owned()))
+        .unwrap();
+
+    assert_eq!(metadata.size, data.len() as u64);
+    assert_eq!(metadata.path_display.as_deref(), Some("/f"));
+    assert_eq!(
+        *client.committed_len.lock().unwrap(),
+        Some(data.len() as u64)
+    );
+    assert_eq!(client.uploaded(), data);
+    assert_eq!(*client.sent_hash.lock().unwrap(), Some(true));
+    assert_eq!(progress.load(Ordering::SeqCst), data.len() as u64);
+    assert_eq!(session.resume_state().offset, data.len() as u64);
+}
+
+#[test]
+fn test_upload_exact_multiple() {
+    // When the source is an exact multiple of the request size, the session gets closed with an
+    // empty request at the end.
+    let client = UploadSessionClient::default();
+    let data = source_data(BLOCK_SIZE * 4);
+    let session = UploadSession::start(&client, UploadOptions::default()).unwrap();
+    session
+        .upload(&client, &data[..], CommitInfo::new("/f".to_owned()))
+        .unwrap();
+    assert_eq!(client.uploaded(), data);
+    assert_eq!(client.appended.lock().unwrap()[&(data.len() as u64)], b"");
+}
+
+#[test]
+fn test_upload_failure_and_resume() {
+    let data = source_data(BLOCK_SIZE * 6 + 1);
+    let client = UploadSessionClient {
+        fail_at: Some(BLOCK_SIZE as u64 * 2),
+        ..Default::default()
+    };
+    let options = UploadOptions::default()
+        .with_parallelism(1)
+        .with_blocks_per_request(1);
+    let session = UploadSession::start(&client, options.clone()).unwrap();
+    match session.upload(&client, &data[..], CommitInfo::new("/f".to_owned())) {
+        Err(UploadSessionError::Append(dropbox_sdk::Error::Api(_))) => (),
+        other => panic!("unexpected result {other:?}"),
+    }
+    assert!(!*client.closed.lock().unwrap());
+
+    // Everything before the failed block is known to be complete, and nothing after it.
+    let state = session.resume_state();
+    assert_eq!(
+        state,
+        ResumeState {
+            session_id: "sid".to_owned(),
+            offset: BLOCK_SIZE as u64 * 2,
+        }
+    );
+
+    // The state can be persisted and used to pick up where it left off.
+    let state: ResumeState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+    let client = UploadSessionClient {
+        appended: Mutex::new(std::mem::take(&mut *client.appended.lock().unwrap())),
+        ..Default::default()
+    };
+    let session = UploadSession::resume(state.clone(), options);
+    session
+        .upload(
+            &client,
+            &data[state.offset as usize..],
+            CommitInfo::new("/f".to_owned()),
+        )
+        .unwrap();
+    assert_eq!(client.uploaded(), data);
+    // Only part of the data went through this session, so it can't be hashed.
+    assert_eq!(*client.sent_hash.lock().unwrap(), Some(false));
+}
+
+#[tokio::test]
+async fn test_upload_async() {
+    let client = UploadSessionClient::default();
+    let data = source_data(BLOCK_SIZE * 5 + 17);
+    let options = UploadOptions::default()
+        .with_parallelism(4)
+        .with_blocks_per_request(1);
+    let session = UploadSession::start_async(&client, options).await.unwrap();
+    let metadata = session
+        .upload_async(
+            &client,
+            futures::io::Cursor::new(&data),
+            CommitInfo::new("/f".to_owned()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(metadata.size, data.len() as u64);
+    assert_eq!(client.uploaded(), data);
+}
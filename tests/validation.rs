@@ -0,0 +1,53 @@
+use dropbox_sdk::validation::{
+    Validate, ValidationError, check_list_length, check_pattern, check_range, check_string_length,
+};
+
+/// A type with a constrained field, implementing `Validate` the way generated types do.
+struct Entry {
+    path: String,
+}
+
+impl Validate for Entry {
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_pattern("path", &self.path, r"(/(.|[\r\n])*)?|id:.*")
+    }
+}
+
+#[test]
+fn test_checks() {
+    assert!(check_string_length("s", "héllo", Some(1), Some(5)).is_ok());
+    assert_eq!(
+        check_string_length("s", "héllo!", None, Some(5))
+            .unwrap_err()
+            .to_string(),
+        "invalid value for s: 6 characters is more than the maximum of 5"
+    );
+    assert!(check_list_length("l", &[1, 2], Some(3), None).is_err());
+    assert!(check_range("n", 1000u32, Some(1), Some(1000)).is_ok());
+    assert!(check_range("n", -0.5f64, Some(0.0), Some(1.0)).is_err());
+}
+
+#[test]
+fn test_pattern_matches_whole_string() {
+    assert!(check_pattern("p", "id:abc", "id:.*").is_ok());
+    assert!(check_pattern("p", "xid:abc", "id:.*").is_err());
+    assert!(check_pattern("p", "abc", "a|abc").is_ok());
+}
+
+#[test]
+fn test_nested_field_names() {
+    let entries = vec![
+        Entry {
+            path: "/a".to_owned(),
+        },
+        Entry {
+            path: "a".to_owned(),
+        },
+    ];
+    let e = entries.validate().unwrap_err().within("entries");
+    assert_eq!(e.field, "entries[1].path");
+    assert_eq!(
+        e.to_string(),
+        r#"invalid value for entries[1].path: "a" does not match the pattern (/(.|[\r\n])*)?|id:.*"#
+    );
+}
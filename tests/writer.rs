@@ -0,0 +1,238 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::files::{CommitInfo, WriteMode};
+use dropbox_sdk::helpers::content_hash::content_hash;
+use dropbox_sdk::helpers::writer::{AsyncDropboxWriter, DropboxWriter, WriterOptions};
+use futures::AsyncWriteExt;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::mock::{Request, json_response, response};
+
+/// A client which implements the upload routes, keeping the committed files in memory.
+#[derive(Default)]
+struct MockClient {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Data uploaded to each upload session so far.
+    sessions: HashMap<String, Vec<u8>>,
+    /// The arguments each file was committed with, and its content.
+    files: HashMap<String, (serde_json::Value, Vec<u8>)>,
+    /// Fail the next append request.
+    fail_append: bool,
+    /// The routes called, in order.
+    calls: Vec<String>,
+}
+
+fn file_metadata(path: &str, data: &[u8]) -> HttpRequestResultRaw {
+    let value = serde_json::json!({
+        "name": path.rsplit('/').next().unwrap(),
+        "id": "id:1",
+        "client_modified": "2020-01-01T00:00:00Z",
+        "server_modified": "2020-01-01T00:00:00Z",
+        "rev": "000000001",
+        "size": data.len(),
+        "path_lower": path.to_lowercase(),
+        "path_display": path,
+        "content_hash": content_hash(data),
+    });
+    json_response(200, value)
+}
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+        let route = request.route().to_owned();
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(route.clone());
+        Ok(match route.as_str() {
+            "files/upload" => {
+                let path = arg["path"].as_str().unwrap().to_owned();
+                assert_eq!(arg["content_hash"], content_hash(body));
+                let response = file_metadata(&path, body);
+                state.files.insert(path, (arg, body.to_vec()));
+                response
+            }
+            "files/upload_session/start" => {
+                let id = format!("s{}", state.sessions.len());
+                state.sessions.insert(id.clone(), body.to_vec());
+                json_response(200, serde_json::json!({ "session_id": id }))
+            }
+            "files/upload_session/append_v2" => {
+                if std::mem::take(&mut state.fail_append) {
+                    return Ok(response(409, r#"{"error":{".tag":"not_found"}}"#));
+                }
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let session = state.sessions.get_mut(id).unwrap();
+                assert_eq!(arg["cursor"]["offset"], session.len());
+                session.extend_from_slice(body);
+                response(200, "null")
+            }
+            "files/upload_session/finish" => {
+                let id = arg["cursor"]["session_id"].as_str().unwrap();
+                let mut data = state.sessions.remove(id).unwrap();
+                assert_eq!(arg["cursor"]["offset"], data.len());
+                data.extend_from_slice(body);
+                assert_eq!(arg["content_hash"], content_hash(&data));
+                let path = arg["commit"]["path"].as_str().unwrap().to_owned();
+                let response = file_metadata(&path, &data);
+                state.files.insert(path, (arg["commit"].clone(), data));
+                response
+            }
+            other => panic!("unexpected request to {other}"),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+impl MockClient {
+    fn calls(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+
+    /// The arguments a file was committed with, and its content.
+    fn file(&self, path: &str) -> (serde_json::Value, Vec<u8>) {
+        self.state.lock().unwrap().files[path].clone()
+    }
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_small_file() {
+    let client = MockClient::default();
+    let commit = CommitInfo::new("/notes.txt".to_owned())
+        .with_autorename(true)
+        .with_mute(true);
+    let mut writer = DropboxWriter::new(&client, commit, WriterOptions::default());
+    writer.write_all(b"hello, ").unwrap();
+    writer.write_all(b"world").unwrap();
+    assert!(client.calls().is_empty());
+
+    // All of it fits in one chunk, so it's uploaded in a single request.
+    let metadata = writer.close().unwrap();
+    assert_eq!(metadata.size, 12);
+    assert_eq!(client.calls(), vec!["files/upload"]);
+    let (arg, content) = client.file("/notes.txt");
+    assert_eq!(content, b"hello, world");
+    assert_eq!(arg["autorename"], true);
+    assert_eq!(arg["mute"], true);
+}
+
+#[test]
+fn test_streaming() {
+    let client = MockClient::default();
+    let commit = CommitInfo::new("/dump.sql".to_owned()).with_mode(WriteMode::Overwrite);
+    let options = WriterOptions::default().with_blocks_per_request(1);
+    let mut writer = DropboxWriter::new(&client, commit, options);
+
+    // Data is sent as it's written, a chunk at a time.
+    let expected = data(10_000_000);
+    io::copy(&mut Cursor::new(&expected), &mut writer).unwrap();
+    assert_eq!(
+        client.calls(),
+        vec![
+            "files/upload_session/start",
+            "files/upload_session/append_v2"
+        ]
+    );
+
+    let metadata = writer.close().unwrap();
+    assert_eq!(metadata.size, expected.len() as u64);
+    assert_eq!(client.calls(), vec!["files/upload_session/finish"]);
+    let (commit, content) = client.file("/dump.sql");
+    assert!(content == expected);
+    assert_eq!(commit["mode"][".tag"], "overwrite");
+}
+
+#[test]
+fn test_failed_chunk_is_resent() {
+    let client = MockClient::default();
+    let commit = CommitInfo::new("/export.csv".to_owned());
+    let options = WriterOptions::default().with_blocks_per_request(1);
+    let mut writer = DropboxWriter::new(&client, commit, options);
+
+    let expected = data(9_000_000);
+    writer.write_all(&expected[..5_000_000]).unwrap();
+    let n = 5_000_000 + writer.write(&expected[5_000_000..]).unwrap();
+    client.state.lock().unwrap().fail_append = true;
+    let err = writer.write(&expected[n..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+
+    // The chunk which failed is sent again by the next write.
+    writer.write_all(&expected[n..]).unwrap();
+    writer.close().unwrap();
+    assert!(client.file("/export.csv").1 == expected);
+}
+
+#[test]
+fn test_drop_abandons() {
+    let client = MockClient::default();
+    let options = WriterOptions::default().with_blocks_per_request(1);
+    let mut writer = DropboxWriter::new(&client, CommitInfo::new("/log.txt".to_owned()), options);
+    writer.write_all(&data(5_000_000)).unwrap();
+    drop(writer);
+
+    // The upload session was started, but never finished.
+    assert_eq!(client.calls(), vec!["files/upload_session/start"]);
+    assert!(client.state.lock().unwrap().files.is_empty());
+}
+
+#[tokio::test]
+async fn test_async_drop_abandons() {
+    let client = Arc::new(MockClient::default());
+    let options = WriterOptions::default().with_blocks_per_request(1);
+    let mut writer = AsyncDropboxWriter::new(
+        Arc::clone(&client),
+        CommitInfo::new("/log.txt".to_owned()),
+        options,
+    );
+    writer.write_all(&data(5_000_000)).await.unwrap();
+    drop(writer);
+
+    assert_eq!(client.calls(), vec!["files/upload_session/start"]);
+    assert!(client.state.lock().unwrap().files.is_empty());
+}
+
+#[tokio::test]
+async fn test_async() {
+    let client = Arc::new(MockClient::default());
+    let options = WriterOptions::default().with_blocks_per_request(1);
+    let mut writer = AsyncDropboxWriter::new(
+        Arc::clone(&client),
+        CommitInfo::new("/archive.tar".to_owned()),
+        options,
+    );
+
+    let expected = data(9_000_000);
+    writer.write_all(&expected).await.unwrap();
+    assert!(writer.metadata().is_none());
+    let metadata = writer.close().await.unwrap();
+    assert_eq!(metadata.size, expected.len() as u64);
+    assert_eq!(
+        client.calls(),
+        vec![
+            "files/upload_session/start",
+            "files/upload_session/append_v2",
+            "files/upload_session/finish"
+        ]
+    );
+    assert!(client.file("/archive.tar").1 == expected);
+}
@@ -0,0 +1,342 @@
+use dropbox_sdk::client_trait::*;
+use dropbox_sdk::helpers::zip::{
+    ZipError, ZipOptions, ZipReader, download_folder, extract, extract_async,
+};
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+use std::io::{self, Cursor, Read, Write};
+use std::path::PathBuf;
+
+mod common;
+use common::mock::Request;
+
+/// How an entry is written to a test archive.
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    /// Stored, with the sizes in the local header.
+    Stored,
+    /// Compressed, with the sizes in a data descriptor after the data, like a streamed archive.
+    Streamed,
+    /// Compressed, with 64-bit sizes in a zip64 extra field and a zip64 data descriptor.
+    Zip64,
+}
+
+/// Build a zip archive. Only the local headers are meaningful to the reader, so the central
+/// directory is left out, leaving just the end of central directory record.
+fn archive(entries: &[(&str, &[u8], Layout)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for &(name, data, layout) in entries {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let compressed = match layout {
+            Layout::Stored => data.to_vec(),
+            Layout::Streamed | Layout::Zip64 => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        };
+        let (flags, method): (u16, u16) = match layout {
+            Layout::Stored => (0, 0),
+            Layout::Streamed | Layout::Zip64 => (1 << 3 | 1 << 11, 8),
+        };
+        out.extend(0x04034b50u32.to_le_bytes());
+        out.extend(45u16.to_le_bytes());
+        out.extend(flags.to_le_bytes());
+        out.extend(method.to_le_bytes());
+        out.extend([0; 4]); // modification time and date
+        match layout {
+            Layout::Stored => {
+                out.extend(crc.sum().to_le_bytes());
+                out.extend((data.len() as u32).to_le_bytes());
+                out.extend((data.len() as u32).to_le_bytes());
+            }
+            Layout::Streamed => out.extend([0; 12]),
+            Layout::Zip64 => {
+                out.extend([0; 4]);
+                out.extend([0xff; 8]);
+            }
+        }
+        out.extend((name.len() as u16).to_le_bytes());
+        let extra_len: u16 = if layout == Layout::Zip64 { 20 } else { 0 };
+        out.extend(extra_len.to_le_bytes());
+        out.extend(name.as_bytes());
+        if layout == Layout::Zip64 {
+            out.extend(1u16.to_le_bytes());
+            out.extend(16u16.to_le_bytes());
+            out.extend([0; 16]); // the sizes are in the data descriptor
+        }
+        out.extend(&compressed);
+        match layout {
+            Layout::Stored => (),
+            Layout::Streamed => {
+                out.extend(0x08074b50u32.to_le_bytes());
+                out.extend(crc.sum().to_le_bytes());
+                out.extend((compressed.len() as u32).to_le_bytes());
+                out.extend((data.len() as u32).to_le_bytes());
+            }
+            Layout::Zip64 => {
+                // Without the optional signature.
+                out.extend(crc.sum().to_le_bytes());
+                out.extend((compressed.len() as u64).to_le_bytes());
+                out.extend((data.len() as u64).to_le_bytes());
+            }
+        }
+    }
+    out.extend(0x06054b50u32.to_le_bytes());
+    out.extend([0; 18]);
+    out
+}
+
+/// A reader which returns a few bytes at a time, to exercise the reader's buffering.
+struct Trickle<R>(R);
+
+impl<R: Read> Read for Trickle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(7);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+fn big_data() -> Vec<u8> {
+    (0..300_000u32)
+        .flat_map(|i| (i % 251).to_le_bytes())
+        .collect()
+}
+
+fn sample() -> Vec<u8> {
+    archive(&[
+        ("folder/", b"", Layout::Stored),
+        ("folder/a.txt", b"hello, world", Layout::Streamed),
+        ("folder/skipped.bin", &big_data(), Layout::Streamed),
+        ("folder/sub/b.bin", &big_data(), Layout::Stored),
+        ("folder/sub/c.bin", &big_data(), Layout::Zip64),
+    ])
+}
+
+#[test]
+fn test_entries() {
+    let mut reader = ZipReader::new(Trickle(Cursor::new(sample())));
+    let mut entries = Vec::new();
+    while let Some(mut entry) = reader.next_entry().unwrap() {
+        let size = entry.size();
+        let mut data = Vec::new();
+        // Leave one entry unread, to check that it's skipped over.
+        if !entry.path().contains("skipped") {
+            entry.read_to_end(&mut data).unwrap();
+        }
+        entries.push((entry.path().to_owned(), entry.is_dir(), size, data));
+        if entry.path() == "folder/a.txt" {
+            // The size is in the data descriptor, so it's known once the entry has been read.
+            assert_eq!(entry.size(), Some(12));
+        }
+    }
+
+    let names = entries
+        .iter()
+        .map(|(name, ..)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "folder/",
+            "folder/a.txt",
+            "folder/skipped.bin",
+            "folder/sub/b.bin",
+            "folder/sub/c.bin",
+        ]
+    );
+    assert!(entries[0].1);
+    assert_eq!(entries[1].3, b"hello, world");
+    assert_eq!(entries[1].2, None);
+    assert!(entries[2].3.is_empty());
+    assert_eq!(entries[3].2, Some(1_200_000));
+    assert_eq!(entries[3].3, big_data());
+    assert_eq!(entries[4].3, big_data());
+    assert!(reader.next_entry().unwrap().is_none());
+}
+
+#[test]
+fn test_corrupt() {
+    // A changed byte in a stored entry is caught by the checksum, once the entry has been read.
+    let mut data = archive(&[("a.txt", b"hello, world", Layout::Stored)]);
+    data[30 + 5] ^= 1;
+    let mut reader = ZipReader::new(Cursor::new(data));
+    let mut entry = reader.next_entry().unwrap().unwrap();
+    let err = entry.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // So is an archive which ends early.
+    let mut data = archive(&[("a.bin", &big_data(), Layout::Streamed)]);
+    data.truncate(data.len() / 2);
+    let mut reader = ZipReader::new(Cursor::new(data));
+    let mut entry = reader.next_entry().unwrap().unwrap();
+    let err = entry.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut reader = ZipReader::new(Cursor::new(b"not a zip file".to_vec()));
+    let err = reader.next_entry().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+/// A directory in the temp directory which is deleted when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dropbox-sdk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_unsafe_path() {
+    let dir = TempDir::new("zip-unsafe");
+    for name in [
+        "../evil.txt",
+        "a/../../evil.txt",
+        "/etc/evil.txt",
+        "a\\..\\evil.txt",
+    ] {
+        let data = archive(&[
+            ("ok.txt", b"ok", Layout::Stored),
+            (name, b"evil", Layout::Stored),
+        ]);
+        let err =
+            extract(Cursor::new(data), dir.0.join("out"), &ZipOptions::default()).unwrap_err();
+        assert!(
+            matches!(&err, ZipError::UnsafePath(path) if path == name),
+            "{err}"
+        );
+        std::fs::remove_dir_all(&dir.0).unwrap();
+    }
+    assert!(!std::env::temp_dir().join("evil.txt").exists());
+}
+
+#[test]
+fn test_extract_corrupt() {
+    let dir = TempDir::new("zip-corrupt");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    std::fs::write(dir.0.join("b.txt"), b"old").unwrap();
+    let mut data = archive(&[
+        ("a.txt", b"fine", Layout::Stored),
+        ("b.txt", b"hello, world", Layout::Stored),
+    ]);
+    // Change a byte of b.txt's data, after a.txt's entry and b.txt's header.
+    data[(30 + 5 + 4) + (30 + 5) + 3] ^= 1;
+    let options = ZipOptions::default().with_overwrite(true);
+    let err = extract(Cursor::new(data), &dir.0, &options).unwrap_err();
+    assert!(
+        matches!(&err, ZipError::Read(e) if e.kind() == io::ErrorKind::InvalidData),
+        "{err}"
+    );
+
+    // The corrupt entry didn't replace the existing file, or leave anything else behind.
+    assert_eq!(std::fs::read(dir.0.join("b.txt")).unwrap(), b"old");
+    let mut names = std::fs::read_dir(&dir.0)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+}
+
+#[derive(Default)]
+struct MockClient;
+
+impl HttpClient for MockClient {
+    type Request = Request;
+
+    fn execute(
+        &self,
+        request: Self::Request,
+        _body: &[u8],
+    ) -> Result<HttpRequestResultRaw, dropbox_sdk::Error> {
+        let arg: serde_json::Value = serde_json::from_str(request.arg.as_deref().unwrap())?;
+        assert_eq!(arg["path"], "/folder");
+        let result = serde_json::json!({
+            "metadata": {
+                "name": "folder",
+                "id": "id:1",
+                "path_lower": "/folder",
+                "path_display": "/folder",
+            }
+        });
+        Ok(HttpRequestResultRaw {
+            status: 200,
+            result_header: Some(result.to_string()),
+            content_length: None,
+            body: Box::new(Trickle(Cursor::new(sample()))),
+        })
+    }
+
+    fn new_request(&self, url: &str) -> Self::Request {
+        assert!(url.ends_with("/files/download_zip"), "{url}");
+        Request::new(url)
+    }
+}
+
+impl UserAuthClient for MockClient {}
+
+#[test]
+fn test_download_folder() {
+    let dir = TempDir::new("zip-download");
+    let files = download_folder(&MockClient, "/folder", &dir.0, &ZipOptions::default()).unwrap();
+    assert_eq!(files.len(), 4);
+    assert_eq!(
+        std::fs::read(dir.0.join("folder/a.txt")).unwrap(),
+        b"hello, world"
+    );
+    assert_eq!(
+        std::fs::read(dir.0.join("folder/sub/c.bin")).unwrap(),
+        big_data()
+    );
+
+    // Existing files are only replaced if asked to.
+    std::fs::write(dir.0.join("folder/a.txt"), b"changed").unwrap();
+    let err = download_folder(&MockClient, "/folder", &dir.0, &ZipOptions::default()).unwrap_err();
+    assert!(
+        matches!(&err, ZipError::Write { source, .. } if source.kind() == io::ErrorKind::AlreadyExists),
+        "{err}"
+    );
+    let options = ZipOptions::default().with_overwrite(true);
+    download_folder(&MockClient, "/folder", &dir.0, &options).unwrap();
+    assert_eq!(
+        std::fs::read(dir.0.join("folder/a.txt")).unwrap(),
+        b"hello, world"
+    );
+}
+
+#[tokio::test]
+async fn test_async() {
+    let dir = TempDir::new("zip-async");
+    let reader = futures::io::Cursor::new(sample());
+    let files = extract_async(reader, &dir.0, &ZipOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(files[0], dir.0.join("folder/a.txt"));
+    assert_eq!(std::fs::read(&files[2]).unwrap(), big_data());
+}
+
+#[tokio::test]
+async fn test_async_blocking_spawner() {
+    let dir = TempDir::new("zip-async-spawner");
+    let reader = futures::io::Cursor::new(sample());
+    let options = ZipOptions::default().with_blocking_spawner(|f| {
+        tokio::task::spawn_blocking(f);
+    });
+    let files = extract_async(reader, &dir.0, &options).await.unwrap();
+    assert_eq!(files.len(), 4);
+    assert_eq!(
+        std::fs::read(dir.0.join("folder/a.txt")).unwrap(),
+        b"hello, world"
+    );
+    assert_eq!(std::fs::read(&files[3]).unwrap(), big_data());
+}